@@ -0,0 +1,64 @@
+//! Fixes what the bootstrapper trusts at build time, see `src/trust.rs`. A bad value
+//! fails the build rather than the user's install.
+
+use std::env;
+
+#[path = "src/signature/key.rs"]
+mod key;
+
+/// The manifest fetched when neither `--manifest-url` nor the config file names one.
+const DEFAULT_MANIFEST_URL: &str = "https://raw.githubusercontent.com/syringeefy/Xenith/refs/heads/main/installer.json";
+
+fn main() {
+    for var in ["PARADISE_MANIFEST_URL", "PARADISE_SIGNING_PUBKEY", "PARADISE_ALLOW_UNSIGNED"] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+
+    let manifest_url = var("PARADISE_MANIFEST_URL").unwrap_or_else(|| DEFAULT_MANIFEST_URL.to_string());
+    if let Err(e) = check_manifest_url(&manifest_url) {
+        fail("PARADISE_MANIFEST_URL", &e);
+    }
+
+    let signing_key = var("PARADISE_SIGNING_PUBKEY").map(|key| key::key_line(&key).to_string());
+    if let Some(Err(e)) = signing_key.as_deref().map(key::parse) {
+        fail("PARADISE_SIGNING_PUBKEY", e);
+    }
+
+    // A build with a key refuses unsigned releases unless told otherwise, or a tampered
+    // manifest would only have to leave out the signature.
+    let allow_unsigned = match var("PARADISE_ALLOW_UNSIGNED").map(|value| value.to_lowercase()).as_deref() {
+        None => signing_key.is_none(),
+        Some("1" | "true" | "yes") => true,
+        Some("0" | "false" | "no") => false,
+        Some(other) => fail("PARADISE_ALLOW_UNSIGNED", &format!("expected true or false, got {:?}", other)),
+    };
+    if !allow_unsigned && signing_key.is_none() {
+        fail("PARADISE_ALLOW_UNSIGNED", "refusing unsigned releases needs PARADISE_SIGNING_PUBKEY");
+    }
+
+    println!("cargo:rustc-env=PARADISE_TRUSTED_MANIFEST_URL={}", manifest_url);
+    println!("cargo:rustc-env=PARADISE_TRUSTED_SIGNING_PUBKEY={}", signing_key.unwrap_or_default());
+    println!("cargo:rustc-env=PARADISE_TRUSTED_ALLOW_UNSIGNED={}", allow_unsigned);
+}
+
+/// The variable's value; unset and blank are the same.
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+fn fail(var: &str, message: &str) -> ! {
+    eprintln!("error: {} is invalid: {}", var, message);
+    std::process::exit(1);
+}
+
+/// An http or https URL with a host; the manifest fetched without any other setting.
+fn check_manifest_url(url: &str) -> Result<(), String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| format!("{} must be an http or https URL", url))?;
+    match rest.split(['/', '?', '#']).next() {
+        Some(host) if !host.is_empty() && !host.contains(char::is_whitespace) => Ok(()),
+        _ => Err(format!("{} has no host", url)),
+    }
+}
@@ -0,0 +1,65 @@
+use clap::ValueEnum;
+use std::fmt;
+use windows::Win32::System::SystemInformation::{
+    IMAGE_FILE_MACHINE, IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, IsWow64Process2};
+
+/// CPU architectures a release can be built for, named as in the manifest's `targets`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Arch {
+    #[default]
+    #[value(name = "x86_64")]
+    X86_64,
+    #[value(name = "aarch64")]
+    Aarch64,
+}
+
+impl Arch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+        }
+    }
+
+    /// The architecture this bootstrapper was built for.
+    fn of_build() -> Self {
+        if cfg!(target_arch = "aarch64") {
+            Arch::Aarch64
+        } else {
+            Arch::X86_64
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The machine's native architecture. An x64 bootstrapper running under emulation on an
+/// ARM64 machine still gets `Aarch64`.
+pub fn detect() -> Arch {
+    let mut process = IMAGE_FILE_MACHINE::default();
+    let mut native = IMAGE_FILE_MACHINE::default();
+
+    // IsWow64Process2 needs Windows 10 1709 or later.
+    if let Err(e) = unsafe { IsWow64Process2(GetCurrentProcess(), &mut process, Some(&mut native)) } {
+        log::debug!("IsWow64Process2 failed, assuming the build architecture: {}", e);
+        return Arch::of_build();
+    }
+
+    if native == IMAGE_FILE_MACHINE_ARM64 {
+        Arch::Aarch64
+    } else if native == IMAGE_FILE_MACHINE_AMD64 {
+        Arch::X86_64
+    } else {
+        log::warn!(
+            "Unknown native machine type {:#06x}, assuming the build architecture",
+            native.0
+        );
+        Arch::of_build()
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// A fresh backup location next to `target_dir`, e.g. `appfolder.backup-20240101T120000`.
+///
+/// A `-N` counter is appended when a backup with the same timestamp already exists.
+pub fn new_backup_path(target_dir: &Path) -> PathBuf {
+    let base = format!(
+        "{}{}",
+        prefix(target_dir),
+        chrono::Local::now().format(TIMESTAMP_FORMAT)
+    );
+
+    let mut candidate = target_dir.with_file_name(&base);
+    let mut counter = 1;
+    while candidate.exists() {
+        candidate = target_dir.with_file_name(format!("{}-{}", base, counter));
+        counter += 1;
+    }
+    candidate
+}
+
+/// Backups of `target_dir`, oldest first. Only directories matching the backup naming pattern are listed.
+pub fn list_backups(target_dir: &Path) -> Result<Vec<PathBuf>> {
+    let Some(parent) = target_dir.parent() else {
+        return Ok(Vec::new());
+    };
+    if !parent.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = prefix(target_dir);
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(parent).context("Failed to list backups")? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(key) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(parse_suffix)
+        else {
+            continue;
+        };
+        if entry.file_type()?.is_dir() {
+            backups.push((key, entry.path()));
+        }
+    }
+
+    backups.sort();
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Deletes all but the newest `keep` backups of `target_dir` and returns what was removed.
+pub fn prune_backups(target_dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let backups = list_backups(target_dir)?;
+    let excess = backups.len().saturating_sub(keep);
+
+    let mut removed = Vec::new();
+    for backup in backups.into_iter().take(excess) {
+        fs::remove_dir_all(&backup)
+            .with_context(|| format!("Failed to remove old backup {}", backup.display()))?;
+        log::info!("Pruned old backup {:?}", backup);
+        removed.push(backup);
+    }
+    Ok(removed)
+}
+
+fn prefix(target_dir: &Path) -> String {
+    let name = target_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!("{}.backup-", name)
+}
+
+/// Parses `<timestamp>` or `<timestamp>-<counter>` into a sortable key.
+fn parse_suffix(suffix: &str) -> Option<(NaiveDateTime, u32)> {
+    let (timestamp, counter) = match suffix.split_once('-') {
+        Some((timestamp, counter)) => (timestamp, counter.parse().ok()?),
+        None => (suffix, 0),
+    };
+    let timestamp = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    Some((timestamp, counter))
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::disk::format_bytes;
+use crate::paths;
+use crate::verify::compute_sha256;
+
+/// Default cap on the space cached release zips may take (5 GiB).
+pub const DEFAULT_MAX_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// `<cache>\downloads`, where verified release zips are kept as `<sha256>.zip`.
+pub fn dir() -> Result<PathBuf> {
+    Ok(paths::cache_dir()?.join("downloads"))
+}
+
+/// Where the release zip with `sha256` is cached, or `None` when the hash is not hex.
+pub fn entry_path(sha256: &str) -> Result<Option<PathBuf>> {
+    if sha256.is_empty() || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    Ok(Some(dir()?.join(format!("{}.zip", sha256.to_lowercase()))))
+}
+
+/// The cached zip for `sha256`, if there is one and it still hashes to `sha256`.
+///
+/// An entry that no longer verifies is deleted.
+pub fn lookup(sha256: &str) -> Option<PathBuf> {
+    let path = entry_path(sha256).ok()??;
+    if !path.is_file() {
+        return None;
+    }
+
+    log::info!("Found cached release {:?}, verifying it", path);
+    match compute_sha256(&path) {
+        Ok(computed) if computed.eq_ignore_ascii_case(sha256) => {
+            // Marks the entry as recently used, so pruning removes others first.
+            if let Err(e) = fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+            {
+                log::debug!("Failed to update cache entry time: {}", e);
+            }
+            Some(path)
+        }
+        Ok(computed) => {
+            log::warn!("Cached release {:?} hashes to {}, discarding it", path, computed);
+            let _ = fs::remove_file(&path);
+            None
+        }
+        Err(e) => {
+            log::warn!("Failed to verify cached release {:?}: {:#}", path, e);
+            None
+        }
+    }
+}
+
+/// Moves a verified release zip into the cache, then prunes the cache to `max_size`.
+pub fn store(zip_path: &Path, sha256: &str, max_size: u64) -> Result<()> {
+    let Some(path) = entry_path(sha256)? else {
+        anyhow::bail!("Release hash {:?} cannot name a cache entry", sha256);
+    };
+
+    let size = fs::metadata(zip_path)
+        .context("Failed to read the downloaded release")?
+        .len();
+    if size > max_size {
+        log::info!(
+            "Release ({}) is larger than the cache limit ({}), not caching it",
+            format_bytes(size),
+            format_bytes(max_size)
+        );
+        fs::remove_file(zip_path).context("Failed to remove downloaded release")?;
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir()?).context("Failed to create download cache directory")?;
+    fs::rename(zip_path, &path).context("Failed to move release into the download cache")?;
+    log::info!("Cached release as {:?}", path);
+
+    prune(max_size, &path)
+}
+
+/// Cached zips, least recently used first.
+fn entries() -> Result<Vec<(PathBuf, u64)>> {
+    let dir = dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&dir).context("Failed to read download cache directory")? {
+        let entry = entry.context("Failed to read download cache directory")?;
+        let metadata = entry.metadata().context("Failed to read cache entry")?;
+        if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((entry.path(), metadata.len(), modified));
+        }
+    }
+
+    entries.sort_by_key(|(_, _, modified)| *modified);
+    Ok(entries
+        .into_iter()
+        .map(|(path, size, _)| (path, size))
+        .collect())
+}
+
+/// Removes the least recently used entries other than `keep` until the cache fits `max_size`.
+fn prune(max_size: u64, keep: &Path) -> Result<()> {
+    let entries = entries()?;
+    let mut total: u64 = entries.iter().map(|(_, size)| size).sum();
+
+    for (path, size) in entries {
+        if total <= max_size {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+        fs::remove_file(&path).context("Failed to remove cache entry")?;
+        log::info!("Evicted cached release {:?} ({})", path, format_bytes(size));
+        total -= size;
+    }
+
+    Ok(())
+}
+
+/// Number of cached files and the space they take.
+pub fn usage() -> Result<(usize, u64)> {
+    let entries = entries()?;
+    Ok((entries.len(), entries.iter().map(|(_, size)| size).sum()))
+}
+
+/// Deletes every cached zip, returning how many files and bytes were freed.
+pub fn clean() -> Result<(usize, u64)> {
+    let mut freed = (0, 0);
+    for (path, size) in entries()? {
+        fs::remove_file(&path).context("Failed to remove cache entry")?;
+        log::info!("Removed cached release {:?}", path);
+        freed.0 += 1;
+        freed.1 += size;
+    }
+    Ok(freed)
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::{InstallError, EXIT_CANCELLED};
+use crate::strings;
+
+static ARMED: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the Ctrl+C handler.
+///
+/// Outside of the install pipeline Ctrl+C exits right away. While the pipeline is armed
+/// the first Ctrl+C only requests cancellation so temp files and backups can be cleaned
+/// up; a second one force-exits.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if !ARMED.load(Ordering::SeqCst) || CANCELLED.swap(true, Ordering::SeqCst) {
+            log::warn!("Installation cancelled by user, exiting immediately");
+            std::process::exit(EXIT_CANCELLED);
+        }
+
+        log::warn!("Cancellation requested, stopping after the current step");
+        eprintln!("{}", strings::text("cancel.requested"));
+    })?;
+
+    Ok(())
+}
+
+/// Keeps Ctrl+C from killing the process until the guard is dropped.
+pub struct ArmGuard;
+
+pub fn arm() -> ArmGuard {
+    ARMED.store(true, Ordering::SeqCst);
+    ArmGuard
+}
+
+impl Drop for ArmGuard {
+    fn drop(&mut self) {
+        ARMED.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Fails with [`InstallError::Cancelled`] once Ctrl+C has been pressed.
+pub fn check() -> Result<()> {
+    if is_cancelled() {
+        return Err(InstallError::Cancelled.into());
+    }
+    Ok(())
+}
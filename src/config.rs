@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cli::{InstallMode, LogLevel};
+use crate::paths;
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// User defaults stored in `%LOCALAPPDATA%\paradise\config.toml`.
+///
+/// Unknown keys are ignored so files written by newer bootstrappers still load.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub install_mode: Option<InstallMode>,
+    pub install_dir: Option<PathBuf>,
+    pub manifest_url: Option<String>,
+    pub log_level: Option<LogLevel>,
+    /// Download rate cap such as "2M"; see `--limit-rate`.
+    pub limit_rate: Option<String>,
+    /// Download cache size cap such as "10G"; see `--cache-max-size`.
+    pub cache_max_size: Option<String>,
+    /// Installed size as a multiple of the zip size; see `--expansion-factor`.
+    pub expansion_factor: Option<f64>,
+    pub shortcuts: ShortcutPreferences,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShortcutPreferences {
+    pub desktop: Option<bool>,
+    pub install_dir: Option<bool>,
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(CONFIG_FILE))
+    }
+
+    /// Loads the config file, returning defaults when it does not exist.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let config = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {:?}", path))?;
+
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create config directory")?;
+        }
+
+        let text = toml::to_string_pretty(self).context("Failed to serialize config")?;
+        fs::write(&path, text)
+            .with_context(|| format!("Failed to write config file {:?}", path))?;
+
+        log::info!("Config saved to {:?}", path);
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use reqwest::{StatusCode, Url};
+use std::error::Error as StdError;
+use std::io;
+
+use crate::http::{AuthError, StatusError};
+use crate::pinning::PinMismatch;
+use crate::strings;
+
+/// What kind of network trouble an error comes from, as far as the user can act on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkErrorKind {
+    Dns,
+    Tls,
+    CertificatePin,
+    ConnectionRefused,
+    Timeout,
+    ProxyAuth,
+    AuthMissing,
+    AuthRejected,
+    /// Any other 4xx answer.
+    ClientError,
+    /// A 5xx answer.
+    ServerError,
+}
+
+impl NetworkErrorKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            NetworkErrorKind::Dns => "dns",
+            NetworkErrorKind::Tls => "tls",
+            NetworkErrorKind::CertificatePin => "certificate_pin",
+            NetworkErrorKind::ConnectionRefused => "connection_refused",
+            NetworkErrorKind::Timeout => "timeout",
+            NetworkErrorKind::ProxyAuth => "proxy_auth",
+            NetworkErrorKind::AuthMissing => "auth_missing",
+            NetworkErrorKind::AuthRejected => "auth_rejected",
+            NetworkErrorKind::ClientError => "http_client_error",
+            NetworkErrorKind::ServerError => "http_server_error",
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            NetworkErrorKind::Dns => 10,
+            NetworkErrorKind::Tls | NetworkErrorKind::CertificatePin => 11,
+            NetworkErrorKind::ConnectionRefused => 12,
+            NetworkErrorKind::Timeout => 13,
+            NetworkErrorKind::ProxyAuth => 14,
+            NetworkErrorKind::AuthMissing
+            | NetworkErrorKind::AuthRejected
+            | NetworkErrorKind::ClientError
+            | NetworkErrorKind::ServerError => 15,
+        }
+    }
+}
+
+/// A classified network failure, with what the console shows instead of the error chain.
+#[derive(Debug, Clone)]
+pub struct NetworkProblem {
+    pub kind: NetworkErrorKind,
+    /// Host of the failed request, when the error names it.
+    pub host: String,
+    pub status: Option<StatusCode>,
+}
+
+impl NetworkProblem {
+    /// One line saying what went wrong.
+    pub fn message(&self) -> String {
+        let status = self
+            .status
+            .map(|status| status.to_string())
+            .unwrap_or_default();
+        strings::format(self.keys().0, &[&self.host, &status])
+    }
+
+    /// What the user can try next.
+    pub fn suggestion(&self) -> String {
+        strings::format(self.keys().1, &[&self.host])
+    }
+
+    /// Keys of the message and the suggestion in [`crate::strings`].
+    fn keys(&self) -> (&'static str, &'static str) {
+        match self.kind {
+            NetworkErrorKind::Dns => ("net.dns", "net.dns.hint"),
+            NetworkErrorKind::Tls => ("net.tls", "net.tls.hint"),
+            NetworkErrorKind::CertificatePin => ("net.pin", "net.pin.hint"),
+            NetworkErrorKind::ConnectionRefused => ("net.refused", "net.refused.hint"),
+            NetworkErrorKind::Timeout => ("net.timeout", "net.timeout.hint"),
+            NetworkErrorKind::ProxyAuth => ("net.proxy_auth", "net.proxy_auth.hint"),
+            NetworkErrorKind::AuthMissing => ("net.auth_missing", "net.auth_missing.hint"),
+            NetworkErrorKind::AuthRejected => ("net.auth_rejected", "net.auth_rejected.hint"),
+            NetworkErrorKind::ClientError => ("net.client_error", "net.client_error.hint"),
+            NetworkErrorKind::ServerError => ("net.server_error", "net.server_error.hint"),
+        }
+    }
+}
+
+/// Looks through the chain of `err` for a network failure it can name.
+pub fn classify(err: &anyhow::Error) -> Option<NetworkProblem> {
+    let mut host = None;
+    let mut found = None;
+
+    for cause in err.chain() {
+        if host.is_none() {
+            host = url_of(cause);
+        }
+        if found.is_none() {
+            found = kind_of(cause);
+        }
+    }
+
+    let (kind, status) = found?;
+    Some(NetworkProblem {
+        kind,
+        host: host.unwrap_or_else(|| strings::text("net.unknown_host").to_string()),
+        status,
+    })
+}
+
+fn kind_of(cause: &(dyn StdError + 'static)) -> Option<(NetworkErrorKind, Option<StatusCode>)> {
+    if cause.is::<PinMismatch>() {
+        return Some((NetworkErrorKind::CertificatePin, None));
+    }
+    if let Some(auth) = cause.downcast_ref::<AuthError>() {
+        return Some(match auth {
+            AuthError::Missing { status, .. } => (NetworkErrorKind::AuthMissing, Some(*status)),
+            AuthError::Rejected { status, .. } => (NetworkErrorKind::AuthRejected, Some(*status)),
+        });
+    }
+    if let Some(error) = cause.downcast_ref::<StatusError>() {
+        return Some((status_kind(error.status)?, Some(error.status)));
+    }
+    if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+        if error.is_timeout() {
+            return Some((NetworkErrorKind::Timeout, None));
+        }
+        if let Some(status) = error.status() {
+            return Some((status_kind(status)?, Some(status)));
+        }
+    }
+    if let Some(error) = cause.downcast_ref::<io::Error>() {
+        #[cfg(feature = "tls-rustls")]
+        if error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<rustls::Error>())
+        {
+            return Some((NetworkErrorKind::Tls, None));
+        }
+        // 11001 is WSAHOST_NOT_FOUND.
+        if error.raw_os_error() == Some(11001) {
+            return Some((NetworkErrorKind::Dns, None));
+        }
+        match error.kind() {
+            io::ErrorKind::ConnectionRefused => return Some((NetworkErrorKind::ConnectionRefused, None)),
+            io::ErrorKind::TimedOut => return Some((NetworkErrorKind::Timeout, None)),
+            _ => {}
+        }
+    }
+
+    // hyper and reqwest only describe these in their messages.
+    let text = cause.to_string().to_lowercase();
+    if text.contains("proxy authentication required") {
+        Some((NetworkErrorKind::ProxyAuth, Some(StatusCode::PROXY_AUTHENTICATION_REQUIRED)))
+    } else if text.contains("dns error") || text.contains("failed to lookup address") {
+        Some((NetworkErrorKind::Dns, None))
+    } else if ["invalid peer certificate", "tls handshake", "certificate chain", "not trusted"]
+        .iter()
+        .any(|needle| text.contains(needle))
+    {
+        Some((NetworkErrorKind::Tls, None))
+    } else {
+        None
+    }
+}
+
+fn status_kind(status: StatusCode) -> Option<NetworkErrorKind> {
+    if status == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+        Some(NetworkErrorKind::ProxyAuth)
+    } else if status.is_client_error() {
+        Some(NetworkErrorKind::ClientError)
+    } else if status.is_server_error() {
+        Some(NetworkErrorKind::ServerError)
+    } else {
+        None
+    }
+}
+
+fn url_of(cause: &(dyn StdError + 'static)) -> Option<String> {
+    let url = if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+        error.url().map(Url::to_string)
+    } else if let Some(error) = cause.downcast_ref::<StatusError>() {
+        Some(error.url.clone())
+    } else if let Some(error) = cause.downcast_ref::<AuthError>() {
+        match error {
+            AuthError::Missing { url, .. } | AuthError::Rejected { url, .. } => Some(url.clone()),
+        }
+    } else if let Some(error) = cause.downcast_ref::<PinMismatch>() {
+        return Some(error.host.clone());
+    } else {
+        None
+    }?;
+
+    Url::parse(&url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(code: u16) -> anyhow::Error {
+        anyhow::Error::new(StatusError {
+            what: "Download",
+            url: "https://cdn.example.com/paradise.zip".to_string(),
+            status: StatusCode::from_u16(code).unwrap(),
+        })
+        .context("Failed to download the release")
+    }
+
+    fn kind(err: &anyhow::Error) -> Option<NetworkErrorKind> {
+        classify(err).map(|problem| problem.kind)
+    }
+
+    #[test]
+    fn http_answers_are_classified_by_status() {
+        let problem = classify(&status(404)).unwrap();
+        assert_eq!(problem.kind, NetworkErrorKind::ClientError);
+        assert_eq!(problem.host, "cdn.example.com");
+        assert_eq!(problem.status, Some(StatusCode::NOT_FOUND));
+        assert_eq!(problem.kind.exit_code(), 15);
+
+        assert_eq!(kind(&status(503)), Some(NetworkErrorKind::ServerError));
+        assert_eq!(kind(&status(407)), Some(NetworkErrorKind::ProxyAuth));
+        assert_eq!(kind(&status(200)), None);
+
+        let missing = anyhow::Error::new(AuthError::Missing {
+            url: "https://cdn.example.com/paradise.zip".to_string(),
+            status: StatusCode::UNAUTHORIZED,
+        });
+        assert_eq!(kind(&missing), Some(NetworkErrorKind::AuthMissing));
+    }
+
+    #[test]
+    fn connection_failures_are_classified_by_cause() {
+        let dns = anyhow::Error::new(io::Error::from_raw_os_error(11001));
+        assert_eq!(kind(&dns), Some(NetworkErrorKind::Dns));
+        let dns = anyhow::anyhow!("error trying to connect: dns error: failed to lookup address information");
+        assert_eq!(kind(&dns), Some(NetworkErrorKind::Dns));
+        assert_eq!(NetworkErrorKind::Dns.exit_code(), 10);
+
+        let refused = anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert_eq!(kind(&refused), Some(NetworkErrorKind::ConnectionRefused));
+        let timeout = anyhow::Error::new(io::Error::from(io::ErrorKind::TimedOut)).context("Failed to read response body");
+        assert_eq!(kind(&timeout), Some(NetworkErrorKind::Timeout));
+
+        let proxy = anyhow::anyhow!("Proxy Authentication Required");
+        let problem = classify(&proxy).unwrap();
+        assert_eq!(problem.kind, NetworkErrorKind::ProxyAuth);
+        assert_eq!(problem.status, Some(StatusCode::PROXY_AUTHENTICATION_REQUIRED));
+        assert_eq!(problem.kind.exit_code(), 14);
+
+        assert_eq!(kind(&anyhow::anyhow!("Failed to extract the release")), None);
+    }
+
+    #[test]
+    fn tls_failures_are_told_apart_from_pin_mismatches() {
+        let tls = anyhow::anyhow!("invalid peer certificate: UnknownIssuer");
+        assert_eq!(kind(&tls), Some(NetworkErrorKind::Tls));
+        #[cfg(feature = "tls-rustls")]
+        {
+            let tls = io::Error::new(io::ErrorKind::InvalidData, rustls::Error::InvalidCertificate(rustls::CertificateError::Expired));
+            assert_eq!(kind(&anyhow::Error::new(tls)), Some(NetworkErrorKind::Tls));
+        }
+
+        let pin = anyhow::Error::new(PinMismatch {
+            host: "updates.example.com".to_string(),
+            seen: "00".repeat(32),
+        });
+        let problem = classify(&pin).unwrap();
+        assert_eq!(problem.kind, NetworkErrorKind::CertificatePin);
+        assert_eq!(problem.host, "updates.example.com");
+        assert_eq!(problem.kind.exit_code(), NetworkErrorKind::Tls.exit_code());
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use windows::core::HSTRING;
+use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+/// Bytes available to the current user on the volume holding `path`.
+///
+/// `path` does not need to exist yet; the nearest existing ancestor is queried.
+pub fn free_space(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .ok_or_else(|| anyhow::anyhow!("No existing directory found for {}", path.display()))?;
+
+    let directory = HSTRING::from(
+        existing
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid directory path"))?,
+    );
+    let mut available = 0u64;
+
+    unsafe {
+        GetDiskFreeSpaceExW(&directory, Some(&mut available), None, None)
+            .with_context(|| format!("Failed to query free space for {}", existing.display()))?;
+    }
+
+    log::debug!("Free space on volume of {:?}: {} bytes", existing, available);
+    Ok(available)
+}
+
+/// Parses a byte count such as `500K`, `2M` or `1.5G` (binary units; a trailing `B` or `iB` is allowed).
+pub fn parse_bytes(input: &str) -> Result<u64> {
+    let trimmed = input.trim();
+    let upper = trimmed.to_ascii_uppercase();
+    let number = upper.trim_end_matches("IB").trim_end_matches('B');
+
+    let (digits, multiplier) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1u64 << 10),
+        Some('M') => (&number[..number.len() - 1], 1 << 20),
+        Some('G') => (&number[..number.len() - 1], 1 << 30),
+        _ => (number, 1),
+    };
+
+    let value: f64 = digits
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite() && *value >= 0.0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid byte count {:?}, expected e.g. 500K or 2M", trimmed))?;
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -1,28 +1,636 @@
 use anyhow::{Context, Result};
-use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+#[cfg(feature = "async")]
+use futures_util::future::join_all;
+use reqwest::header::{HeaderMap, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use reqwest::blocking::RequestBuilder;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+#[cfg(not(feature = "async"))]
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn download_file(url: &str, output_path: &PathBuf) -> Result<()> {
-    log::info!("Downloading from {} to {:?}", url, output_path);
+use crate::cancel;
+use crate::disk::format_bytes;
+use crate::http::{HttpClient, StatusError};
+use crate::manifest::local_path;
+use crate::progress::{DownloadProgress, ProgressGroup};
+use crate::verify::sha256_matches;
 
-    let response = reqwest::blocking::get(url)
-        .context("Failed to download file")?;
+mod pipeline;
+pub use pipeline::{async_download_file, block_on};
 
-    if !response.status().is_success() {
-        anyhow::bail!("Download failed with status: {}", response.status());
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Segments smaller than this are not worth an extra connection.
+const MIN_SEGMENT_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct DownloadOptions {
+    pub show_progress: bool,
+    /// Parallel connections for servers that accept range requests; 1 downloads sequentially.
+    pub connections: usize,
+    /// Average bytes per second across all connections; `None` is unlimited.
+    pub limit_rate: Option<u64>,
+    /// Size the manifest declares; wins over `Content-Length` when the two disagree.
+    pub expected_size: Option<u64>,
+    /// Send the auth token, for releases the manifest marks as requiring it.
+    pub authorize: bool,
+    /// Multi-bar display the progress bar joins; set by [`download_all`].
+    pub group: Option<ProgressGroup>,
+}
+
+impl DownloadOptions {
+    fn progress(&self, total: Option<u64>) -> DownloadProgress {
+        DownloadProgress::new(total, self.show_progress, self.group.as_ref())
+    }
+}
+
+/// A file for [`download_all`]: the URLs serving it, tried in order, the SHA256 it must
+/// have and where it goes.
+pub struct DownloadJob<'a> {
+    /// Labels the progress bar and the log lines.
+    pub name: String,
+    pub urls: Vec<&'a str>,
+    pub sha256: String,
+    pub destination: PathBuf,
+    pub options: DownloadOptions,
+    /// A required job that fails keeps the jobs not started yet from starting.
+    pub required: bool,
+}
+
+/// A finished download whose SHA256 is not the expected one. The file is left in place.
+#[derive(Debug, thiserror::Error)]
+#[error("SHA256 of {name} does not match: expected {expected}, got {computed}")]
+pub struct HashMismatch {
+    pub name: String,
+    pub expected: String,
+    pub computed: String,
+}
+
+/// Runs `jobs` through `fetch`, up to `concurrency` at a time, and checks each finished
+/// download against its SHA256. With the `async` feature the downloads are tasks on the
+/// shared runtime, otherwise each runs on a worker thread of its own.
+///
+/// Concurrent progress bars share one multi-bar display. Once a required job has failed,
+/// jobs that have not started are left alone and come back as `None`, the same as after
+/// Ctrl+C; running ones are allowed to finish. Results are in the order of `jobs`.
+pub fn download_all<'j, 'u, F, Fut>(
+    jobs: &'j [DownloadJob<'u>],
+    concurrency: usize,
+    fetch: F,
+) -> Result<Vec<Option<Result<Downloaded>>>>
+where
+    F: Fn(&'j DownloadJob<'u>, DownloadOptions) -> Fut + Sync,
+    Fut: Future<Output = Result<Downloaded>>,
+{
+    let queue = Queue::new(jobs, concurrency);
+    let worker = || async {
+        while let Some((index, job)) = queue.next() {
+            queue.finish(index, fetch(job, queue.options[index].clone()).await);
+        }
+    };
+
+    #[cfg(feature = "async")]
+    block_on(join_all((0..queue.workers).map(|_| worker())))?;
+    #[cfg(not(feature = "async"))]
+    thread::scope(|scope| {
+        let threads: Vec<_> = (0..queue.workers).map(|_| scope.spawn(|| block_on(worker()))).collect();
+        threads.into_iter().try_for_each(|thread| {
+            thread
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Download worker panicked")))
+        })
+    })?;
+
+    Ok(queue.into_results())
+}
+
+/// The jobs of one [`download_all`] and what has become of them.
+struct Queue<'j, 'u> {
+    jobs: &'j [DownloadJob<'u>],
+    workers: usize,
+    options: Vec<DownloadOptions>,
+    next: AtomicUsize,
+    failed: AtomicBool,
+    results: Vec<Mutex<Option<Result<Downloaded>>>>,
+}
+
+impl<'j, 'u> Queue<'j, 'u> {
+    fn new(jobs: &'j [DownloadJob<'u>], concurrency: usize) -> Self {
+        let workers = concurrency.clamp(1, jobs.len().max(1));
+        let group = (workers > 1).then(ProgressGroup::new);
+        let options = jobs
+            .iter()
+            .map(|job| DownloadOptions {
+                group: group.as_ref().map(|group| group.for_download(&job.name)),
+                ..job.options.clone()
+            })
+            .collect();
+        if workers > 1 {
+            log::info!("Downloading {} files, {} at a time", jobs.len(), workers);
+        }
+
+        Self {
+            jobs,
+            workers,
+            options,
+            next: AtomicUsize::new(0),
+            failed: AtomicBool::new(false),
+            results: jobs.iter().map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// The next job to start, unless a required one failed or the run was cancelled.
+    fn next(&self) -> Option<(usize, &'j DownloadJob<'u>)> {
+        if self.failed.load(Ordering::SeqCst) || cancel::is_cancelled() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        self.jobs.get(index).map(|job| (index, job))
+    }
+
+    /// Checks a finished download against its SHA256 and records the result.
+    fn finish(&self, index: usize, result: Result<Downloaded>) {
+        let job = &self.jobs[index];
+        let result = result.and_then(|downloaded| {
+            if sha256_matches(&job.destination, &downloaded.sha256, &job.sha256) {
+                Ok(downloaded)
+            } else {
+                Err(HashMismatch {
+                    name: job.name.clone(),
+                    expected: job.sha256.clone(),
+                    computed: downloaded.sha256,
+                }
+                .into())
+            }
+        });
+        if job.required && result.is_err() {
+            log::warn!("{} failed, not starting the remaining downloads", job.name);
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        *self.results[index].lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+    }
+
+    fn into_results(self) -> Vec<Option<Result<Downloaded>>> {
+        self.results
+            .into_iter()
+            .map(|result| result.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+}
+
+/// Holds the average transfer rate under a cap by telling callers how long to pause.
+struct RateLimiter {
+    rate: u64,
+    started: Instant,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    fn new(rate: Option<u64>) -> Option<Mutex<Self>> {
+        rate.filter(|rate| *rate > 0).map(|rate| {
+            log::info!("Limiting download rate to {}/s", format_bytes(rate));
+            Mutex::new(Self {
+                rate,
+                started: Instant::now(),
+                bytes: 0,
+            })
+        })
+    }
+
+    /// Records `bytes` and returns how long to sleep to stay under the rate.
+    fn record(&mut self, bytes: usize) -> Duration {
+        self.bytes += bytes as u64;
+        let due = Duration::from_secs_f64(self.bytes as f64 / self.rate as f64);
+        due.saturating_sub(self.started.elapsed())
     }
+}
+
+/// A finished download.
+#[derive(Debug)]
+pub struct Downloaded {
+    pub bytes: u64,
+    /// Hex SHA256 of the file, computed while it was written.
+    pub sha256: String,
+    pub elapsed: Duration,
+    /// Highest rate over any one second, in bytes per second.
+    pub peak_rate: u64,
+    /// Resumed attempts and retried segments.
+    pub retries: u32,
+}
 
-    let mut file = fs::File::create(output_path)
-        .context("Failed to create output file")?;
+/// How a single download attempt ended when it did not fail outright.
+enum Attempt {
+    Complete(Downloaded),
+    /// The transfer stopped early; the `.part` file can be resumed.
+    Interrupted(anyhow::Error),
+}
 
-    let bytes = response.bytes()
-        .context("Failed to read response bytes")?;
+/// Streams `url` into `output_path`.
+///
+/// Data goes to `<output_path>.part` first and is only renamed to `output_path` once it is
+/// complete and synced to disk. A usable `.part` file is resumed with a range request, and
+/// interrupted transfers are resumed the same way up to the configured retries. With more
+/// than one connection and a server that accepts ranges, the file is fetched in segments
+/// over that many connections at once instead.
+///
+/// Runs [`async_download_file`] on the shared runtime.
+pub fn download_file(
+    http: &HttpClient,
+    url: &str,
+    output_path: &PathBuf,
+    options: &DownloadOptions,
+) -> Result<Downloaded> {
+    block_on(async_download_file(http, url, output_path, options))?
+}
 
-    file.write_all(&bytes)
-        .context("Failed to write downloaded data")?;
+/// Syncs a complete `.part` file to disk and renames it to `output_path`.
+fn finish(part_path: &Path, output_path: &Path, downloaded: &Downloaded) -> Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .open(part_path)
+        .and_then(|file| file.sync_all())
+        .context("Failed to flush download to disk")?;
+    fs::rename(part_path, output_path).context("Failed to move finished download into place")?;
 
-    log::info!("Download completed: {} bytes", bytes.len());
+    log::info!("Download completed: {} bytes, SHA256 {}", downloaded.bytes, downloaded.sha256);
     Ok(())
 }
 
+/// Copies a release zip from a local or UNC path, hashing it on the way like a download.
+fn copy_local(source: &Path, part_path: &Path, options: &DownloadOptions) -> Result<Downloaded> {
+    log::info!("Release is a local file, copying {:?}", source);
+
+    let mut input = match fs::File::open(source) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            anyhow::bail!("Release zip not found: {}", source.display())
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to open {}", source.display()))
+        }
+    };
+    let length = input.metadata().map(|metadata| metadata.len()).ok();
+
+    let started = Instant::now();
+    let mut output =
+        BufWriter::new(fs::File::create(part_path).context("Failed to create output file")?);
+    let mut progress = options.progress(expected_size(options.expected_size, length));
+    let mut hasher = Sha256::new();
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut written = 0;
+    loop {
+        cancel::check()?;
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", source.display()))
+            }
+        };
+        output
+            .write_all(&buffer[..read])
+            .context("Failed to write downloaded data")?;
+        hasher.update(&buffer[..read]);
+        written += read as u64;
+        progress.advance(read as u64);
+    }
+    output.flush().context("Failed to write downloaded data")?;
+    progress.finish();
+
+    Ok(Downloaded {
+        bytes: written,
+        sha256: hex::encode(hasher.finalize()),
+        elapsed: started.elapsed(),
+        peak_rate: progress.peak_rate(),
+        retries: 0,
+    })
+}
+
+/// Feeds the contents of `path` into `hasher`.
+fn hash_file(path: &Path, hasher: &mut Sha256) -> Result<()> {
+    let mut file = fs::File::open(path).context("Failed to read download for hashing")?;
+    io::copy(&mut file, hasher).context("Failed to read download for hashing")?;
+    Ok(())
+}
+
+fn authorized(http: &HttpClient, request: RequestBuilder, authorize: bool) -> RequestBuilder {
+    if authorize {
+        http.authorize(request)
+    } else {
+        request
+    }
+}
+
+/// `<path>.part`, where an unfinished download of `path` is kept.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// Deletes a `.part` file left by an aborted run when it cannot be a prefix of the release.
+fn discard_unusable_part(part_path: &Path, expected_size: Option<u64>) -> Result<()> {
+    let Ok(metadata) = fs::metadata(part_path) else {
+        return Ok(());
+    };
+
+    let existing = metadata.len();
+    let unusable = match expected_size {
+        _ if existing == 0 => true,
+        Some(expected) => existing >= expected,
+        None => false,
+    };
+
+    if unusable {
+        log::info!("Discarding partial download of {} bytes from an earlier run", existing);
+        fs::remove_file(part_path).context("Failed to discard partial download")?;
+    } else {
+        log::info!("Found partial download of {} bytes from an earlier run", existing);
+    }
+    Ok(())
+}
+
+/// The size to check the download against: the manifest's when given, else the server's.
+fn expected_size(manifest: Option<u64>, advertised: Option<u64>) -> Option<u64> {
+    match (manifest, advertised) {
+        (Some(manifest), Some(advertised)) if manifest != advertised => {
+            log::warn!(
+                "Server reports {} bytes but the manifest says {}, expecting the manifest size",
+                advertised,
+                manifest
+            );
+            Some(manifest)
+        }
+        (Some(manifest), _) => Some(manifest),
+        (None, advertised) => advertised,
+    }
+}
+
+fn content_range_starts_at(headers: &HeaderMap, offset: u64) -> bool {
+    headers
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(&format!("bytes {}-", offset)))
+}
+
+/// Removes downloads in `dir` other than `keep` (and its `.part`), e.g. left over from another release.
+pub fn discard_stale_downloads(dir: &Path, keep: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    let keep_part = part_path(keep);
+    for entry in fs::read_dir(dir).context("Failed to list downloads")? {
+        let path = entry?.path();
+        if path.is_file() && path != keep && path != keep_part {
+            log::info!("Discarding stale download {:?}", path);
+            fs::remove_file(&path).context("Failed to remove stale download")?;
+        }
+    }
+    Ok(())
+}
+
+/// What a request for the release zip learned before downloading it.
+#[derive(Debug, Clone)]
+pub struct Preflight {
+    pub status: StatusCode,
+    pub content_length: Option<u64>,
+    pub accepts_ranges: bool,
+}
+
+impl Preflight {
+    /// The server says the file does not exist (404) or was removed (410).
+    pub fn is_missing(&self) -> bool {
+        matches!(self.status, StatusCode::NOT_FOUND | StatusCode::GONE)
+    }
+}
+
+/// Asks for the headers of `url` with a HEAD request, or a GET of its first byte when
+/// the server rejects HEAD.
+pub fn preflight(http: &HttpClient, url: &str, authorize: bool) -> Result<Preflight> {
+    if let Some(path) = local_path(url) {
+        let length = fs::metadata(&path).ok().map(|metadata| metadata.len());
+        return Ok(Preflight {
+            status: if length.is_some() { StatusCode::OK } else { StatusCode::NOT_FOUND },
+            content_length: length,
+            accepts_ranges: false,
+        });
+    }
+
+    log::info!("Checking release URL {}", url);
+
+    let response = http
+        .send(authorized(http, http.head(url)?, authorize))
+        .context("Failed to check the release URL")?;
+    http.check_auth(&response, authorize)?;
+
+    let status = response.status();
+    if status.is_success() || matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        let preflight = Preflight {
+            status,
+            content_length: header(CONTENT_LENGTH).and_then(|value| value.parse().ok()),
+            accepts_ranges: header(ACCEPT_RANGES)
+                .is_some_and(|value| value.eq_ignore_ascii_case("bytes")),
+        };
+        log::info!(
+            "HEAD {}: {}, length {:?}, ranges {}",
+            url,
+            preflight.status,
+            preflight.content_length,
+            preflight.accepts_ranges
+        );
+        return Ok(preflight);
+    }
+
+    log::info!("HEAD request answered {}, retrying as a ranged GET", status);
+    let request = http.get(url)?.header(RANGE, "bytes=0-0");
+    let response = http
+        .send(authorized(http, request, authorize))
+        .context("Failed to check the release URL")?;
+    http.check_auth(&response, authorize)?;
+
+    let status = response.status();
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    // "bytes 0-0/12345" carries the full size; a server ignoring the range sends it all.
+    let (content_length, accepts_ranges) = if status == StatusCode::PARTIAL_CONTENT {
+        let total = header(CONTENT_RANGE)
+            .and_then(|value| value.rsplit_once('/'))
+            .and_then(|(_, total)| total.parse().ok());
+        (total, true)
+    } else {
+        (header(CONTENT_LENGTH).and_then(|value| value.parse().ok()), false)
+    };
+
+    if !status.is_success() && !matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Err(StatusError::new("Release URL check", &response).into());
+    }
+
+    log::info!(
+        "GET {} (first byte): {}, length {:?}, ranges {}",
+        url,
+        status,
+        content_length,
+        accepts_ranges
+    );
+    Ok(Preflight {
+        status,
+        content_length,
+        accepts_ranges,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{header, ok, read_head, serve};
+    use crate::http::{user_agent, NetworkOptions};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn options() -> DownloadOptions {
+        DownloadOptions {
+            show_progress: false,
+            connections: 1,
+            limit_rate: None,
+            expected_size: None,
+            authorize: false,
+            group: None,
+        }
+    }
+
+    #[test]
+    fn downloads_name_the_bootstrapper() {
+        let server = serve(|_| ok("release zip"));
+        let http = HttpClient::new(NetworkOptions {
+            no_proxy: true,
+            ..NetworkOptions::default()
+        })
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("paradise.zip");
+
+        let downloaded = download_file(&http, &format!("{}/paradise.zip", server.url), &output, &options()).unwrap();
+        assert_eq!(downloaded.bytes, 11);
+        assert_eq!(fs::read_to_string(&output).unwrap(), "release zip");
+
+        let requests = server.requests();
+        assert!(!requests.is_empty());
+        for request in &requests {
+            assert_eq!(header(request, "user-agent"), Some(user_agent().as_str()), "{}", request);
+        }
+    }
+
+    fn client(read_timeout: Duration) -> HttpClient {
+        HttpClient::new(NetworkOptions {
+            read_timeout,
+            retries: 0,
+            no_proxy: true,
+            ..NetworkOptions::default()
+        })
+        .unwrap()
+    }
+
+    /// A server announcing `length` bytes and sending `sent` of them, one every `gap`,
+    /// before it goes quiet.
+    fn trickle(length: usize, sent: usize, gap: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/paradise.zip", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_head(&mut stream);
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", length).unwrap();
+            for _ in 0..sent {
+                stream.write_all(b"P").unwrap();
+                thread::sleep(gap);
+            }
+            thread::sleep(Duration::from_secs(10));
+        });
+        url
+    }
+
+    #[test]
+    fn stalled_downloads_time_out() {
+        let url = trickle(1000, 1, Duration::ZERO);
+        let dir = tempfile::tempdir().unwrap();
+
+        let started = Instant::now();
+        let e = download_file(&client(Duration::from_millis(300)), &url, &dir.path().join("paradise.zip"), &options())
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let message = format!("{:#}", e);
+        assert!(
+            message.starts_with(&format!("Failed to read response body: Request to {} timed out after ", url)),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn slow_downloads_outlast_the_read_timeout() {
+        let url = trickle(12, 12, Duration::from_millis(100));
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("paradise.zip");
+
+        let downloaded = download_file(&client(Duration::from_millis(500)), &url, &output, &options()).unwrap();
+        assert_eq!(downloaded.bytes, 12);
+        assert!(downloaded.elapsed > Duration::from_millis(1000), "{:?}", downloaded.elapsed);
+        assert_eq!(fs::read_to_string(&output).unwrap(), "P".repeat(12));
+    }
+
+    fn job(name: &str, sha256: &str, required: bool) -> DownloadJob<'static> {
+        DownloadJob {
+            name: name.to_string(),
+            urls: vec!["https://example.com/file"],
+            sha256: sha256.to_string(),
+            destination: PathBuf::from(name),
+            options: options(),
+            required,
+        }
+    }
+
+    #[test]
+    fn failed_required_downloads_stop_the_ones_not_started() {
+        let jobs = [
+            job("icons.zip", "1111", false),
+            job("paradise.zip", "2222", true),
+            job("textures.zip", "3333", true),
+        ];
+        let fetch = |job: &DownloadJob| match job.name.as_str() {
+            "icons.zip" => anyhow::bail!("icons are gone"),
+            _ => Ok(Downloaded {
+                bytes: 1,
+                sha256: "ffff".to_string(),
+                elapsed: Duration::ZERO,
+                peak_rate: 0,
+                retries: 0,
+            }),
+        };
+        let results = download_all(&jobs, 1, |job, _| async move { fetch(job) }).unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap().as_ref().unwrap_err().to_string(), "icons are gone");
+        let e = results[1].as_ref().unwrap().as_ref().unwrap_err();
+        assert_eq!(e.to_string(), "SHA256 of paradise.zip does not match: expected 2222, got ffff");
+        assert!(e.is::<HashMismatch>());
+        assert!(results[2].is_none());
+    }
+}
@@ -0,0 +1,510 @@
+use anyhow::{Context, Result};
+use futures_util::future::join_all;
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::{RequestBuilder, StatusCode};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio::runtime::Runtime;
+
+use super::{
+    content_range_starts_at, copy_local, discard_unusable_part, expected_size, finish, hash_file,
+    part_path, Attempt, DownloadOptions, Downloaded, RateLimiter, MIN_SEGMENT_SIZE,
+};
+use crate::cancel;
+use crate::error::InstallError;
+use crate::http::{self, HttpClient, StatusError};
+use crate::manifest::local_path;
+use crate::progress::DownloadProgress;
+
+/// Runs `future` on the runtime shared by every download of this run.
+///
+/// One runtime for the whole process, because the async client's pooled connections
+/// belong to the runtime they were opened on.
+pub fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+    let runtime = match RUNTIME.get() {
+        Some(runtime) => runtime,
+        None => {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .context("Failed to start the download runtime")?;
+            RUNTIME.get_or_init(|| runtime)
+        }
+    };
+    Ok(runtime.block_on(future))
+}
+
+/// [`super::download_file`] for callers already on the runtime. Segments are fetched
+/// concurrently as futures of this one, without a thread per connection.
+pub async fn async_download_file(
+    http: &HttpClient,
+    url: &str,
+    output_path: &PathBuf,
+    options: &DownloadOptions,
+) -> Result<Downloaded> {
+    log::info!("Downloading from {} to {:?}", url, output_path);
+
+    let part_path = part_path(output_path);
+    discard_unusable_part(&part_path, options.expected_size)?;
+    let limiter = RateLimiter::new(options.limit_rate);
+    let downloaded = match local_path(url) {
+        Some(source) => {
+            let (part_path, options) = (part_path.clone(), options.clone());
+            tokio::task::spawn_blocking(move || copy_local(&source, &part_path, &options))
+                .await
+                .context("Local copy worker panicked")??
+        }
+        None => match segmented_length(http, url, &part_path, options).await {
+            Some(length) => {
+                download_segmented(http, url, &part_path, length, options, limiter.as_ref()).await?
+            }
+            None => download_sequential(http, url, &part_path, options, limiter.as_ref()).await?,
+        },
+    };
+
+    finish(&part_path, output_path, &downloaded)?;
+    Ok(downloaded)
+}
+
+async fn throttle(limiter: Option<&Mutex<RateLimiter>>, bytes: usize) {
+    let Some(limiter) = limiter else {
+        return;
+    };
+    let pause = limiter
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .record(bytes);
+    if !pause.is_zero() {
+        tokio::time::sleep(pause).await;
+    }
+}
+
+/// The error for a read of a body from `url` that got nothing within the read timeout.
+fn timed_out(url: &str, started: Instant) -> anyhow::Error {
+    http::describe_io(std::io::ErrorKind::TimedOut.into(), url, started)
+}
+
+fn authorized(http: &HttpClient, request: RequestBuilder, authorize: bool) -> RequestBuilder {
+    if authorize {
+        http.authorize_async(request)
+    } else {
+        request
+    }
+}
+
+async fn download_sequential(
+    http: &HttpClient,
+    url: &str,
+    part_path: &Path,
+    options: &DownloadOptions,
+    limiter: Option<&Mutex<RateLimiter>>,
+) -> Result<Downloaded> {
+    let started = Instant::now();
+    let retries = http.retries();
+    let mut attempt = 0;
+
+    loop {
+        match download_part(http, url, part_path, options, limiter).await? {
+            Attempt::Complete(downloaded) => {
+                return Ok(Downloaded {
+                    elapsed: started.elapsed(),
+                    retries: attempt,
+                    ..downloaded
+                })
+            }
+            Attempt::Interrupted(e) if attempt < retries => {
+                attempt += 1;
+                log::warn!("{:#}, resuming (retry {} of {})", e, attempt, retries);
+                tokio::time::sleep(Duration::from_secs(u64::from(attempt))).await;
+            }
+            Attempt::Interrupted(e) => return Err(e),
+        }
+    }
+}
+
+async fn download_part(
+    http: &HttpClient,
+    url: &str,
+    part_path: &Path,
+    options: &DownloadOptions,
+    limiter: Option<&Mutex<RateLimiter>>,
+) -> Result<Attempt> {
+    let existing = fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = http.get_async(url)?;
+    if existing > 0 {
+        log::info!("Found partial download of {} bytes, requesting the rest", existing);
+        request = request.header(RANGE, format!("bytes={}-", existing));
+    }
+
+    let mut response = http
+        .send_async(authorized(http, request, options.authorize))
+        .await
+        .context("Failed to download file")?;
+
+    http.log_headers(&response);
+    http.check_auth(&response, options.authorize)?;
+
+    let resumed = existing > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    if resumed && !content_range_starts_at(response.headers(), existing) {
+        fs::remove_file(part_path).context("Failed to discard partial download")?;
+        return Ok(Attempt::Interrupted(anyhow::anyhow!(
+            "Server resumed at the wrong offset"
+        )));
+    }
+    if existing > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        fs::remove_file(part_path).context("Failed to discard partial download")?;
+        return Ok(Attempt::Interrupted(anyhow::anyhow!(
+            "Server refused to resume the partial download"
+        )));
+    }
+    if !response.status().is_success() {
+        return Err(StatusError::new("Download", &response).into());
+    }
+
+    let offset = if resumed {
+        existing
+    } else {
+        if existing > 0 {
+            log::info!("Server ignored the range request, downloading from the start");
+        }
+        0
+    };
+
+    let advertised = response.content_length().map(|length| offset + length);
+    let expected = expected_size(options.expected_size, advertised);
+    let file = if resumed {
+        tokio::fs::OpenOptions::new().append(true).open(part_path).await
+    } else {
+        tokio::fs::File::create(part_path).await
+    };
+    let mut file = BufWriter::new(file.context("Failed to create output file")?);
+
+    let mut hasher = if resumed {
+        let path = part_path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let mut hasher = Sha256::new();
+            hash_file(&path, &mut hasher).map(|()| hasher)
+        })
+        .await
+        .context("Hashing worker panicked")??
+    } else {
+        Sha256::new()
+    };
+    let mut hashing = Duration::ZERO;
+
+    let mut progress = options.progress(expected);
+    progress.resume_at(offset);
+    let transfer_started = Instant::now();
+
+    let mut written = offset;
+    let interrupted = loop {
+        cancel::check()?;
+        let started = Instant::now();
+        let chunk = match tokio::time::timeout(http.read_timeout(), response.chunk()).await {
+            Ok(Ok(Some(chunk))) => chunk,
+            Ok(Ok(None)) => break None,
+            Ok(Err(e)) => break Some(http.describe(e, started).context("Failed to read response body")),
+            Err(_) => break Some(timed_out(url, started).context("Failed to read response body")),
+        };
+        file.write_all(&chunk)
+            .await
+            .context("Failed to write downloaded data")?;
+        let hash_started = Instant::now();
+        hasher.update(&chunk);
+        hashing += hash_started.elapsed();
+        written += chunk.len() as u64;
+        progress.advance(chunk.len() as u64);
+        throttle(limiter, chunk.len()).await;
+    };
+    file.flush().await.context("Failed to write downloaded data")?;
+
+    if let Some(e) = interrupted {
+        return Ok(Attempt::Interrupted(e));
+    }
+
+    if let Some(expected) = expected {
+        if written < expected {
+            return Ok(Attempt::Interrupted(
+                InstallError::Truncated {
+                    received: written,
+                    expected,
+                }
+                .into(),
+            ));
+        }
+        if written > expected {
+            anyhow::bail!(
+                "Download is larger than expected ({} of {} bytes)",
+                written,
+                expected
+            );
+        }
+    }
+
+    progress.finish();
+    log::debug!("Spent {:?} hashing the download stream", hashing);
+    Ok(Attempt::Complete(Downloaded {
+        bytes: written,
+        sha256: hex::encode(hasher.finalize()),
+        elapsed: transfer_started.elapsed(),
+        peak_rate: progress.peak_rate(),
+        retries: 0,
+    }))
+}
+
+/// The release size, when it should be downloaded in segments.
+async fn segmented_length(
+    http: &HttpClient,
+    url: &str,
+    part_path: &Path,
+    options: &DownloadOptions,
+) -> Option<u64> {
+    if options.connections < 2 {
+        return None;
+    }
+    if part_path.exists() {
+        log::info!("Resuming the partial download over a single connection");
+        return None;
+    }
+
+    let request = match http.head_async(url) {
+        Ok(request) => authorized(http, request, options.authorize),
+        Err(e) => {
+            log::info!("HEAD request failed, using a single connection: {:#}", e);
+            return None;
+        }
+    };
+    let response = match http.send_async(request).await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            log::info!("HEAD request answered {}, using a single connection", response.status());
+            return None;
+        }
+        Err(e) => {
+            log::info!("HEAD request failed, using a single connection: {:#}", e);
+            return None;
+        }
+    };
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let accepts_ranges = header(ACCEPT_RANGES).is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+    let length = header(CONTENT_LENGTH).and_then(|value| value.parse::<u64>().ok());
+
+    if let (Some(expected), Some(length)) = (options.expected_size, length) {
+        if expected != length {
+            log::warn!(
+                "Server reports {} bytes but the manifest says {}, using a single connection",
+                length,
+                expected
+            );
+            return None;
+        }
+    }
+
+    match length {
+        Some(length) if accepts_ranges && length >= 2 * MIN_SEGMENT_SIZE => Some(length),
+        _ => {
+            log::info!("Server does not offer ranged downloads of this file, using a single connection");
+            None
+        }
+    }
+}
+
+async fn download_segmented(
+    http: &HttpClient,
+    url: &str,
+    part_path: &Path,
+    length: u64,
+    options: &DownloadOptions,
+    limiter: Option<&Mutex<RateLimiter>>,
+) -> Result<Downloaded> {
+    let segments = (options.connections as u64).min(length / MIN_SEGMENT_SIZE);
+    let segment_size = length.div_ceil(segments);
+    log::info!("Downloading {} bytes over {} connections", length, segments);
+    let started = Instant::now();
+
+    fs::File::create(part_path)
+        .and_then(|file| file.set_len(length))
+        .context("Failed to create output file")?;
+
+    let progress = Mutex::new(options.progress(Some(length)));
+    let failed = AtomicBool::new(false);
+    let retried = AtomicU32::new(0);
+    let segment = Segment {
+        http,
+        url,
+        part_path,
+        authorize: options.authorize,
+        progress: &progress,
+        failed: &failed,
+        retried: &retried,
+        limiter,
+    };
+
+    let workers = (0..segments).map(|index| {
+        let start = index * segment_size;
+        let end = (start + segment_size).min(length);
+        let segment = &segment;
+        async move {
+            let result = download_segment(segment, start, end).await;
+            if result.is_err() {
+                segment.failed.store(true, Ordering::SeqCst);
+            }
+            result
+        }
+    });
+    let result = join_all(workers).await.into_iter().collect::<Result<Vec<_>>>();
+
+    if let Err(e) = result {
+        // The file has holes, so it cannot be resumed.
+        let _ = fs::remove_file(part_path);
+        return Err(e);
+    }
+
+    let progress = progress.into_inner().unwrap_or_else(PoisonError::into_inner);
+    progress.finish();
+    let elapsed = started.elapsed();
+
+    // Segments arrive out of order, so the hash needs one pass over the finished file.
+    let started = Instant::now();
+    let path = part_path.to_path_buf();
+    let hasher = tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        hash_file(&path, &mut hasher).map(|()| hasher)
+    })
+    .await
+    .context("Hashing worker panicked")??;
+    log::debug!("Hashed segmented download in {:?}", started.elapsed());
+
+    Ok(Downloaded {
+        bytes: length,
+        sha256: hex::encode(hasher.finalize()),
+        elapsed,
+        peak_rate: progress.peak_rate(),
+        retries: retried.into_inner(),
+    })
+}
+
+/// What every segment of one download shares.
+struct Segment<'a> {
+    http: &'a HttpClient,
+    url: &'a str,
+    part_path: &'a Path,
+    authorize: bool,
+    progress: &'a Mutex<DownloadProgress>,
+    failed: &'a AtomicBool,
+    retried: &'a AtomicU32,
+    limiter: Option<&'a Mutex<RateLimiter>>,
+}
+
+/// Downloads bytes `start..end` into the same range of the `.part` file, retrying on failure.
+async fn download_segment(segment: &Segment<'_>, start: u64, end: u64) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(segment.part_path)
+        .await
+        .context("Failed to open output file")?;
+
+    let retries = segment.http.retries();
+    let mut position = start;
+    let mut attempt = 0;
+
+    loop {
+        match fetch_range(segment, &mut file, &mut position, end).await {
+            Ok(()) => return Ok(()),
+            Err(e)
+                if attempt < retries
+                    && !cancel::is_cancelled()
+                    && !segment.failed.load(Ordering::SeqCst) =>
+            {
+                attempt += 1;
+                segment.retried.fetch_add(1, Ordering::SeqCst);
+                log::warn!(
+                    "Segment {}-{} failed at byte {}: {:#}, retry {} of {}",
+                    start,
+                    end,
+                    position,
+                    e,
+                    attempt,
+                    retries
+                );
+                tokio::time::sleep(Duration::from_secs(u64::from(attempt))).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn fetch_range(
+    segment: &Segment<'_>,
+    file: &mut tokio::fs::File,
+    position: &mut u64,
+    end: u64,
+) -> Result<()> {
+    let Segment { http, url, authorize, progress, failed, limiter, .. } = *segment;
+    let request = http
+        .get_async(url)?
+        .header(RANGE, format!("bytes={}-{}", position, end - 1));
+    let mut response = http
+        .send_async(authorized(http, request, authorize))
+        .await
+        .context("Failed to download file")?;
+    http.check_auth(&response, authorize)?;
+
+    if response.status() != StatusCode::PARTIAL_CONTENT
+        || !content_range_starts_at(response.headers(), *position)
+    {
+        anyhow::bail!(
+            "Server did not return the requested range (status {})",
+            response.status()
+        );
+    }
+
+    file.seek(SeekFrom::Start(*position))
+        .await
+        .context("Failed to write downloaded data")?;
+
+    while *position < end {
+        cancel::check()?;
+        if failed.load(Ordering::SeqCst) {
+            anyhow::bail!("Another download segment failed");
+        }
+
+        let started = Instant::now();
+        let chunk = match tokio::time::timeout(http.read_timeout(), response.chunk()).await {
+            Ok(Ok(Some(chunk))) => chunk,
+            Ok(Ok(None)) => anyhow::bail!("Segment ended early at byte {} of {}", position, end),
+            Ok(Err(e)) => return Err(http.describe(e, started)).context("Failed to read response body"),
+            Err(_) => return Err(timed_out(url, started)).context("Failed to read response body"),
+        };
+        let read = chunk.len().min((end - *position) as usize);
+
+        file.write_all(&chunk[..read])
+            .await
+            .context("Failed to write downloaded data")?;
+        *position += read as u64;
+        progress
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .advance(read as u64);
+        throttle(limiter, read).await;
+    }
+    file.flush().await.context("Failed to write downloaded data")?;
+
+    Ok(())
+}
@@ -0,0 +1,371 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, LPARAM, WPARAM};
+use windows::Win32::System::Registry::{
+    RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+    REG_EXPAND_SZ, REG_SZ, RRF_NOEXPAND, RRF_RT_REG_EXPAND_SZ, RRF_RT_REG_SZ,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    SendMessageTimeoutW, HWND_BROADCAST, SMTO_ABORTIFHUNG, WM_SETTINGCHANGE,
+};
+
+use crate::manifest::{Environment, EnvironmentScope};
+use crate::paths::expand_env_vars;
+use crate::receipt::{InstalledEnvironment, SetVariable};
+use crate::registry;
+
+/// The user's environment, under `HKEY_CURRENT_USER`.
+const USER_KEY: &str = "Environment";
+/// The environment of every user, under `HKEY_LOCAL_MACHINE`.
+const MACHINE_KEY: &str = r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment";
+
+/// Applies `environment` for the install in `install_dir` and returns what was changed.
+/// `previous` is what an earlier install of the release changed: entries still wanted are
+/// kept without adding them twice, the rest is undone. Failures are logged and leave that
+/// one change out, since the install itself has succeeded.
+pub fn register(
+    environment: &Environment,
+    install_dir: &Path,
+    previous: Option<&InstalledEnvironment>,
+) -> InstalledEnvironment {
+    let machine = match environment.scope {
+        EnvironmentScope::Machine if registry::is_elevated() => true,
+        EnvironmentScope::Machine => {
+            log::warn!("The manifest asks for machine-wide environment changes, but the bootstrapper is not elevated; changing the user's environment");
+            false
+        }
+        EnvironmentScope::User => false,
+    };
+    let dirs: Vec<String> = environment
+        .add_to_path
+        .iter()
+        .map(|dir| match dir.as_str() {
+            "." => install_dir.display().to_string(),
+            dir => install_dir.join(dir.replace('/', "\\")).display().to_string(),
+        })
+        .collect();
+    let install_dir_text = install_dir.display().to_string();
+    let variables: Vec<(&String, String)> = environment
+        .variables
+        .iter()
+        .filter_map(|(name, value)| {
+            // Other variables stay as they are, for Windows to expand when it is read.
+            let expanded = expand_env_vars(value, |var| match var.eq_ignore_ascii_case("INSTALL_DIR") {
+                true => Some(install_dir_text.clone()),
+                false => Some(format!("%{}%", var)),
+            });
+            match expanded {
+                Ok(value) => Some((name, value)),
+                Err(e) => {
+                    log::warn!("Not setting environment variable {}: {:#}", name, e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    // What a previous install set in another scope, or no longer wants, is undone first.
+    let mut installed = InstalledEnvironment { machine, ..Default::default() };
+    if let Some(previous) = previous {
+        let mut stale = previous.clone();
+        if previous.machine == machine {
+            stale.path.retain(|dir| !dirs.iter().any(|wanted| same_dir(wanted, dir)));
+            stale.variables.retain(|set| !variables.iter().any(|(name, _)| **name == set.name));
+            installed.path = previous.path.iter().filter(|dir| !stale.path.contains(dir)).cloned().collect();
+            installed.variables = previous
+                .variables
+                .iter()
+                .filter(|set| variables.iter().any(|(name, _)| **name == set.name))
+                .cloned()
+                .collect();
+        }
+        unregister(&stale);
+    }
+
+    let scope = Scope::new(machine);
+    if !dirs.is_empty() {
+        let result = scope.read("Path").and_then(|path| {
+            let (updated, added) = add_path_entries(&path.unwrap_or_default(), &dirs);
+            if added.is_empty() {
+                log::info!("PATH already has {}", dirs.join(";"));
+                return Ok(());
+            }
+            scope.write("Path", &updated)?;
+            log::info!("Added {} to PATH", added.join(";"));
+            for dir in added {
+                if !installed.path.iter().any(|tracked| same_dir(tracked, &dir)) {
+                    installed.path.push(dir);
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to add to PATH: {:#}", e);
+        }
+    }
+
+    for (name, value) in variables {
+        let current = match scope.read(name) {
+            Ok(current) => current,
+            Err(e) => {
+                log::warn!("Failed to read environment variable {}: {:#}", name, e);
+                continue;
+            }
+        };
+        // A value from the previous install is not the one to restore.
+        let tracked = installed.variables.iter().position(|set| &set.name == name);
+        let previous = match tracked {
+            Some(i) => installed.variables.remove(i).previous,
+            None => current.clone(),
+        };
+        if current.as_deref() != Some(value.as_str()) {
+            if let Err(e) = scope.write(name, &value) {
+                log::warn!("Failed to set environment variable {}: {:#}", name, e);
+                continue;
+            }
+            log::info!("Set environment variable {}={}", name, value);
+        }
+        installed.variables.push(SetVariable { name: name.clone(), value, previous });
+    }
+
+    broadcast_change();
+    installed
+}
+
+/// Takes out what an install added: its PATH entries and variables, restoring their
+/// values from before. Variables changed since are left alone.
+pub fn unregister(installed: &InstalledEnvironment) {
+    if installed.path.is_empty() && installed.variables.is_empty() {
+        return;
+    }
+    let scope = Scope::new(installed.machine);
+
+    if !installed.path.is_empty() {
+        let result = scope.read("Path").and_then(|path| {
+            let path = path.unwrap_or_default();
+            let updated = remove_path_entries(&path, &installed.path);
+            if updated != path {
+                scope.write("Path", &updated)?;
+                log::info!("Removed {} from PATH", installed.path.join(";"));
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to remove from PATH: {:#}", e);
+        }
+    }
+
+    for set in &installed.variables {
+        let result = scope.read(&set.name).and_then(|current| {
+            if current.as_deref() != Some(set.value.as_str()) {
+                log::info!("Environment variable {} was changed since the install, leaving it", set.name);
+                return Ok(());
+            }
+            match &set.previous {
+                Some(previous) => scope.write(&set.name, previous),
+                None => scope.delete(&set.name),
+            }
+        });
+        match result {
+            Ok(()) => log::info!("Reverted environment variable {}", set.name),
+            Err(e) => log::warn!("Failed to revert environment variable {}: {:#}", set.name, e),
+        }
+    }
+
+    broadcast_change();
+}
+
+/// Undoes [`register`] when the install fails after it: takes out what `installed` set
+/// beyond `previous`, the earlier install's changes, and puts back those of them that
+/// `register` undid.
+pub fn revert(installed: Option<&InstalledEnvironment>, previous: Option<&InstalledEnvironment>) {
+    let none = InstalledEnvironment::default();
+    let (installed, previous) = (installed.unwrap_or(&none), previous.unwrap_or(&none));
+    let same_scope = installed.machine == previous.machine;
+
+    let mut added = installed.clone();
+    let mut dropped = previous.clone();
+    if same_scope {
+        added.path.retain(|dir| !previous.path.iter().any(|old| same_dir(old, dir)));
+        dropped.path.retain(|dir| !installed.path.iter().any(|new| same_dir(new, dir)));
+        dropped.variables.retain(|old| !installed.variables.iter().any(|set| set.name == old.name));
+        // Variables both set go back to the earlier install's value.
+        for set in &mut added.variables {
+            if let Some(old) = previous.variables.iter().find(|old| old.name == set.name) {
+                set.previous = Some(old.value.clone());
+            }
+        }
+    }
+    unregister(&added);
+    reapply(&dropped);
+}
+
+/// Puts an earlier install's PATH entries and variables back after [`unregister`].
+fn reapply(installed: &InstalledEnvironment) {
+    if installed.path.is_empty() && installed.variables.is_empty() {
+        return;
+    }
+    let scope = Scope::new(installed.machine);
+
+    if !installed.path.is_empty() {
+        let result = scope.read("Path").and_then(|path| {
+            let (updated, added) = add_path_entries(&path.unwrap_or_default(), &installed.path);
+            if !added.is_empty() {
+                scope.write("Path", &updated)?;
+                log::info!("Added {} back to PATH", added.join(";"));
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to add to PATH: {:#}", e);
+        }
+    }
+
+    for set in &installed.variables {
+        match scope.write(&set.name, &set.value) {
+            Ok(()) => log::info!("Set environment variable {} back to {}", set.name, set.value),
+            Err(e) => log::warn!("Failed to set environment variable {}: {:#}", set.name, e),
+        }
+    }
+
+    broadcast_change();
+}
+
+/// `path` with those of `dirs` it does not have yet appended, and those dirs.
+fn add_path_entries(path: &str, dirs: &[String]) -> (String, Vec<String>) {
+    let mut entries: Vec<&str> = path.split(';').filter(|entry| !entry.trim().is_empty()).collect();
+    let mut added = Vec::new();
+    for dir in dirs {
+        if !entries.iter().any(|entry| same_dir(entry, dir)) {
+            entries.push(dir);
+            added.push(dir.clone());
+        }
+    }
+    (entries.join(";"), added)
+}
+
+/// `path` without `dirs`, keeping every other entry as it was.
+fn remove_path_entries(path: &str, dirs: &[String]) -> String {
+    path.split(';')
+        .filter(|entry| !dirs.iter().any(|dir| same_dir(entry, dir)))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Whether two PATH entries name the same directory, as Windows compares paths.
+fn same_dir(a: &str, b: &str) -> bool {
+    let normalize = |dir: &str| dir.trim().trim_end_matches(['\\', '/']).replace('/', "\\").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+/// Tells running programs, Explorer above all, that the environment changed, so shells
+/// started from then on see it.
+fn broadcast_change() {
+    let area = HSTRING::from("Environment");
+    unsafe {
+        SendMessageTimeoutW(
+            HWND_BROADCAST,
+            WM_SETTINGCHANGE,
+            WPARAM(0),
+            LPARAM(area.as_ptr() as isize),
+            SMTO_ABORTIFHUNG,
+            5000,
+            None,
+        );
+    }
+}
+
+/// The registry key holding the user's or the machine's environment.
+struct Scope {
+    root: HKEY,
+    key: HSTRING,
+}
+
+impl Scope {
+    fn new(machine: bool) -> Self {
+        match machine {
+            true => Self { root: HKEY_LOCAL_MACHINE, key: HSTRING::from(MACHINE_KEY) },
+            false => Self { root: HKEY_CURRENT_USER, key: HSTRING::from(USER_KEY) },
+        }
+    }
+
+    /// The value of variable `name`, unexpanded, if it is set.
+    fn read(&self, name: &str) -> Result<Option<String>> {
+        let name = HSTRING::from(name);
+        let flags = RRF_RT_REG_SZ | RRF_RT_REG_EXPAND_SZ | RRF_NOEXPAND;
+        let mut size = 0u32;
+        let result = unsafe { RegGetValueW(self.root, &self.key, &name, flags, None, None, Some(&mut size)) };
+        match result {
+            Err(e) if e.code() == ERROR_FILE_NOT_FOUND.to_hresult() => return Ok(None),
+            result => result.with_context(|| format!("Failed to read {}", name))?,
+        }
+
+        let mut buffer = vec![0u16; (size as usize).div_ceil(2)];
+        unsafe {
+            RegGetValueW(
+                self.root,
+                &self.key,
+                &name,
+                flags,
+                None,
+                Some(buffer.as_mut_ptr().cast()),
+                Some(&mut size),
+            )
+        }
+        .with_context(|| format!("Failed to read {}", name))?;
+        let length = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+        Ok(Some(String::from_utf16_lossy(&buffer[..length])))
+    }
+
+    /// Sets variable `name`, expandable when it refers to other variables.
+    fn write(&self, name: &str, value: &str) -> Result<()> {
+        let kind = if value.contains('%') { REG_EXPAND_SZ } else { REG_SZ };
+        let data: Vec<u16> = value.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            RegSetKeyValueW(
+                self.root,
+                &self.key,
+                &HSTRING::from(name),
+                kind.0,
+                Some(data.as_ptr().cast()),
+                (data.len() * 2) as u32,
+            )
+        }
+        .with_context(|| format!("Failed to set {}", name))
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        match unsafe { RegDeleteKeyValueW(self.root, &self.key, &HSTRING::from(name)) } {
+            Err(e) if e.code() == ERROR_FILE_NOT_FOUND.to_hresult() => Ok(()),
+            result => result.with_context(|| format!("Failed to remove {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_entries_are_added_once() {
+        let dirs = vec![r"C:\Apps\paradise\bin".to_string()];
+        let (path, added) = add_path_entries(r"C:\Windows;%USERPROFILE%\bin;", &dirs);
+        assert_eq!(path, r"C:\Windows;%USERPROFILE%\bin;C:\Apps\paradise\bin");
+        assert_eq!(added, dirs);
+
+        let (again, added) = add_path_entries(r"C:\Windows;c:\apps\Paradise\BIN\", &dirs);
+        assert_eq!(again, r"C:\Windows;c:\apps\Paradise\BIN\");
+        assert!(added.is_empty());
+    }
+
+    #[test]
+    fn only_the_added_entries_are_removed() {
+        let dirs = vec![r"C:\Apps\paradise\bin".to_string()];
+        assert_eq!(
+            remove_path_entries(r"C:\Windows;C:\Apps\paradise\bin\;C:\Apps\paradise", &dirs),
+            r"C:\Windows;C:\Apps\paradise"
+        );
+        assert_eq!(remove_path_entries(r"C:\Windows", &dirs), r"C:\Windows");
+    }
+}
@@ -0,0 +1,283 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::diagnose;
+use crate::prereq::{PrerequisitesNotMet, RemediationFailed};
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_CANCELLED: i32 = 8;
+pub const EXIT_ALREADY_RUNNING: i32 = 9;
+
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  1  unclassified failure
+  2  manifest fetch or parse failed
+  3  download failed
+  4  verification failed (hash mismatch, missing files)
+  5  extraction failed
+  6  filesystem or permission error
+  7  prerequisites not met
+  8  cancelled by the user
+  9  another bootstrapper is already running
+ 10  server name could not be resolved (DNS)
+ 11  TLS or certificate problem
+ 12  connection refused
+ 13  network timeout
+ 14  proxy authentication required
+ 15  the server answered with an HTTP error
+ 16  a post-install command failed (the previous install was restored)
+ 17  installing a missing prerequisite failed
+ 18  license key missing or rejected, or the license server is unreachable
+ 19  the EULA was declined, or not accepted with --accept-eula
+ 20  programs using the install directory are still running
+ 21  update --check-only: the installed version is no longer supported and must be updated";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Manifest,
+    Download,
+    Verification,
+    Extraction,
+    Filesystem,
+    Prerequisites,
+    PostInstall,
+    /// Installing a missing prerequisite, as opposed to checking for it.
+    PrerequisiteInstall,
+    License,
+    Eula,
+    /// Programs from the install directory did not close.
+    Processes,
+    /// `update --check-only` found an installed version the manifest no longer supports.
+    UpdateRequired,
+    Cancelled,
+}
+
+impl Stage {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Stage::Manifest => 2,
+            Stage::Download => 3,
+            Stage::Verification => 4,
+            Stage::Extraction => 5,
+            Stage::Filesystem => 6,
+            Stage::Prerequisites => 7,
+            Stage::PostInstall => 16,
+            Stage::PrerequisiteInstall => 17,
+            Stage::License => 18,
+            Stage::Eula => 19,
+            Stage::Processes => 20,
+            Stage::UpdateRequired => 21,
+            Stage::Cancelled => EXIT_CANCELLED,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Manifest => "manifest",
+            Stage::Download => "download",
+            Stage::Verification => "verification",
+            Stage::Extraction => "extraction",
+            Stage::Filesystem => "filesystem",
+            Stage::Prerequisites => "prerequisites",
+            Stage::PostInstall => "post_install",
+            Stage::PrerequisiteInstall => "prerequisite_install",
+            Stage::License => "license",
+            Stage::Eula => "eula",
+            Stage::Processes => "processes",
+            Stage::UpdateRequired => "update_required",
+            Stage::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InstallError {
+    #[error("Installation cancelled by user")]
+    Cancelled,
+    #[error("Download truncated or altered (got {received} bytes, expected {expected}), please retry")]
+    Truncated { received: u64, expected: u64 },
+    #[error("ZIP file integrity check failed")]
+    IntegrityCheckFailed,
+    #[error(
+        "ZIP file integrity check failed again after a fresh download. A proxy or antivirus \
+         may be altering the download; the details are in the log in {}",
+        .logs.display()
+    )]
+    IntegrityCheckFailedAgain { logs: PathBuf },
+    #[error("Signature verification failed: {0}")]
+    SignatureFailed(String),
+    #[error("Required file not found in archive: {0}")]
+    MissingFile(String),
+    #[error("Extracted files do not match their manifest hash: {}", .0.join(", "))]
+    CorruptedFiles(Vec<String>),
+    #[error("Verification failed: {0} file(s) missing or modified")]
+    InstallationModified(usize),
+    #[error("No installation found in {}", .0.display())]
+    NotInstalled(PathBuf),
+    #[error(
+        "Not enough disk space: {}",
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    InsufficientSpace(Vec<SpaceShortfall>),
+    #[error("Post-install command {command} failed: {reason}")]
+    PostInstallFailed { command: String, reason: String },
+    #[error("This release needs a license key: pass --license-key or set PARADISE_LICENSE_KEY")]
+    LicenseKeyRequired,
+    #[error("The license key was rejected: {0}")]
+    LicenseRejected(String),
+    #[error("License server unreachable ({url}): {reason}")]
+    LicenseServerUnreachable { url: String, reason: String },
+    #[error("The license check with {url} failed: {reason}")]
+    LicenseCheckFailed { url: String, reason: String },
+    #[error("The license agreement was declined")]
+    EulaDeclined,
+    #[error("This release has a license agreement; pass --accept-eula to accept it")]
+    EulaNotAccepted,
+    #[error(
+        "These programs are still running and keep files of the install open: {}. Close them \
+         and try again, or pass --kill-running",
+        .0.join(", ")
+    )]
+    ProcessesRunning(Vec<String>),
+    #[error("Installed version {installed} is no longer supported, update to {minimum} or newer")]
+    UpdateRequired { installed: String, minimum: String },
+}
+
+/// A volume without room for what the install writes to it.
+#[derive(Debug, Clone)]
+pub struct SpaceShortfall {
+    /// A directory on the volume, as the user knows it.
+    pub path: PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+impl fmt::Display for SpaceShortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs {} bytes, {} bytes available",
+            self.path.display(),
+            self.required,
+            self.available
+        )
+    }
+}
+
+impl InstallError {
+    pub fn stage(&self) -> Stage {
+        match self {
+            InstallError::Cancelled => Stage::Cancelled,
+            InstallError::Truncated { .. } => Stage::Download,
+            InstallError::IntegrityCheckFailed
+            | InstallError::IntegrityCheckFailedAgain { .. }
+            | InstallError::SignatureFailed(_)
+            | InstallError::MissingFile(_)
+            | InstallError::CorruptedFiles(_)
+            | InstallError::InstallationModified(_) => Stage::Verification,
+            InstallError::NotInstalled(_) | InstallError::InsufficientSpace(_) => {
+                Stage::Filesystem
+            }
+            InstallError::PostInstallFailed { .. } => Stage::PostInstall,
+            InstallError::LicenseKeyRequired
+            | InstallError::LicenseRejected(_)
+            | InstallError::LicenseServerUnreachable { .. }
+            | InstallError::LicenseCheckFailed { .. } => Stage::License,
+            InstallError::EulaDeclined | InstallError::EulaNotAccepted => Stage::Eula,
+            InstallError::ProcessesRunning(_) => Stage::Processes,
+            InstallError::UpdateRequired { .. } => Stage::UpdateRequired,
+        }
+    }
+}
+
+/// Tags an error with the pipeline stage it came from without changing its message.
+#[derive(Debug)]
+pub struct StagedError {
+    pub stage: Stage,
+    inner: Box<dyn StdError + Send + Sync + 'static>,
+}
+
+impl fmt::Display for StagedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl StdError for StagedError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.inner.source()
+    }
+}
+
+pub trait StageExt<T> {
+    fn stage(self, stage: Stage) -> anyhow::Result<T>;
+}
+
+impl<T> StageExt<T> for anyhow::Result<T> {
+    fn stage(self, stage: Stage) -> anyhow::Result<T> {
+        self.map_err(|e| {
+            if stage_of(&e).is_some() {
+                e
+            } else {
+                anyhow::Error::new(StagedError {
+                    stage,
+                    inner: e.into(),
+                })
+            }
+        })
+    }
+}
+
+pub fn stage_of(err: &anyhow::Error) -> Option<Stage> {
+    for cause in err.chain() {
+        if let Some(staged) = cause.downcast_ref::<StagedError>() {
+            return Some(staged.stage);
+        }
+        if let Some(install_error) = cause.downcast_ref::<InstallError>() {
+            return Some(install_error.stage());
+        }
+        if cause.is::<PrerequisitesNotMet>() {
+            return Some(Stage::Prerequisites);
+        }
+        if cause.is::<RemediationFailed>() {
+            return Some(Stage::PrerequisiteInstall);
+        }
+    }
+
+    err.chain()
+        .any(|cause| cause.is::<std::io::Error>())
+        .then_some(Stage::Filesystem)
+}
+
+/// The exit code for `err`: the kind of network failure when it was one, else its stage.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    let stage = stage_of(err);
+    if stage == Some(Stage::Cancelled) {
+        return EXIT_CANCELLED;
+    }
+    if let Some(problem) = diagnose::classify(err) {
+        return problem.kind.exit_code();
+    }
+    stage.map_or(EXIT_FAILURE, Stage::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn unsupported_installs_exit_with_update_required() {
+        let err: anyhow::Result<()> = Err(InstallError::UpdateRequired {
+            installed: "1.2.0".to_string(),
+            minimum: "1.4.0".to_string(),
+        }
+        .into());
+        let err = err.context("Update check failed").stage(Stage::Manifest).unwrap_err();
+        assert_eq!(stage_of(&err), Some(Stage::UpdateRequired));
+        assert_eq!(Stage::UpdateRequired.label(), "update_required");
+        assert_eq!(exit_code(&err), 21);
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+
+use crate::http::{HttpClient, StatusError};
+use crate::manifest::{local_path, Manifest};
+use crate::prompt;
+use crate::strings;
+
+/// Lines shown before asking to go on.
+const PAGE_LINES: usize = 20;
+
+/// The manifest's EULA: `eula_text`, or what `eula_url` points at.
+pub fn fetch(http: &HttpClient, manifest: &Manifest) -> Result<Option<String>> {
+    if let Some(text) = &manifest.eula_text {
+        return Ok(Some(text.clone()));
+    }
+    let Some(url) = &manifest.eula_url else {
+        return Ok(None);
+    };
+
+    log::info!("Fetching the EULA from {}", url);
+    if let Some(path) = local_path(url) {
+        return fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read the EULA {}", path.display()))
+            .map(Some);
+    }
+    let response = http.send(http.get(url)?).context("Failed to fetch the EULA")?;
+    if !response.status().is_success() {
+        return Err(StatusError::new("EULA fetch", &response).into());
+    }
+    response.text().context("Failed to read the EULA").map(Some)
+}
+
+/// Hash the receipt records, so a changed EULA is shown again. Line endings do not count.
+pub fn sha256(text: &str) -> String {
+    let normalized = text.replace("\r\n", "\n");
+    hex::encode(Sha256::digest(normalized.trim().as_bytes()))
+}
+
+/// Shows `text` a page at a time and asks the user to type the accept word.
+pub fn ask(text: &str) -> Result<bool> {
+    println!("\n{}\n", strings::text("eula.title"));
+    let lines: Vec<&str> = text.lines().collect();
+    for (i, page) in lines.chunks(PAGE_LINES).enumerate() {
+        if i > 0 && prompt::ask(strings::text("eula.more"))?.eq_ignore_ascii_case("q") {
+            break;
+        }
+        for line in page {
+            println!("{}", line);
+        }
+    }
+    println!();
+
+    let answer = prompt::ask(strings::text("eula.confirm"))?;
+    Ok(strings::text("eula.accept")
+        .split('|')
+        .any(|word| answer.eq_ignore_ascii_case(word)))
+}
@@ -0,0 +1,305 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{self, Manifest, ManifestFormat};
+use crate::prereq::PrerequisiteSpec;
+use crate::verify;
+
+/// What `generate-manifest` describes and where it writes it.
+#[derive(Debug, Clone)]
+pub struct ManifestRelease {
+    pub zip: PathBuf,
+    pub output: PathBuf,
+    pub version: String,
+    pub url: String,
+    pub mirrors: Vec<String>,
+    /// The channel the release goes to; the top-level fields serve the default one.
+    pub channel: Option<String>,
+    /// `prerequisites` entries as JSON objects, replacing the release's.
+    pub prerequisites: Vec<String>,
+    /// Update the manifest already at `output` instead of starting a new one.
+    pub merge: bool,
+    /// Give files under an architecture's directory, e.g. `bin/arm64/`, that `arch`.
+    pub tag_arch: bool,
+}
+
+/// Writes the manifest for `release` and returns it, validated.
+pub fn generate(release: &ManifestRelease) -> Result<Manifest> {
+    let format = ManifestFormat::from_extension(&release.output.to_string_lossy()).unwrap_or(ManifestFormat::Json);
+    let mut root = if release.merge && release.output.exists() {
+        let text = fs::read_to_string(&release.output)
+            .with_context(|| format!("Failed to read the manifest {}", release.output.display()))?;
+        let existing: Value = manifest::deserialize(&text, Some(format))?;
+        match existing {
+            Value::Object(existing) => existing,
+            _ => anyhow::bail!("{} is not a manifest object", release.output.display()),
+        }
+    } else {
+        Map::new()
+    };
+
+    let schema_version = root.get("schema_version").and_then(Value::as_u64).unwrap_or(1);
+    root.insert(
+        "schema_version".to_string(),
+        json!(schema_version.max(u64::from(manifest::SCHEMA_VERSION))),
+    );
+    let target = match release.channel.as_deref() {
+        None | Some(manifest::DEFAULT_CHANNEL) => &mut root,
+        Some(channel) => object(object(&mut root, "channels")?, channel)?,
+    };
+    describe(release, target)?;
+
+    let text = match format {
+        ManifestFormat::Json => serde_json::to_string_pretty(&root).context("Failed to serialize the manifest")?,
+        ManifestFormat::Toml => toml::to_string_pretty(&root).context("Failed to serialize the manifest")?,
+    };
+    let generated = Manifest::parse(&text, Some(format), false, None).context("The generated manifest is invalid")?;
+    if let Some(channel) = &release.channel {
+        generated
+            .for_channel(channel)
+            .context("The generated manifest is invalid")?;
+    }
+
+    fs::write(&release.output, text)
+        .with_context(|| format!("Failed to write the manifest {}", release.output.display()))?;
+    log::info!("Wrote the manifest for {} to {}", release.version, release.output.display());
+    Ok(generated)
+}
+
+/// Sets the fields of `release` in `target`, the top level or a channel, leaving the
+/// fields it does not generate as they are.
+fn describe(release: &ManifestRelease, target: &mut Map<String, Value>) -> Result<()> {
+    let files = zip_files(&release.zip, release.tag_arch)?;
+    let installed_size: u64 = files.iter().filter_map(|file| file["size"].as_u64()).sum();
+    let zip_size = fs::metadata(&release.zip)
+        .with_context(|| format!("Failed to read {}", release.zip.display()))?
+        .len();
+
+    target.insert("version".to_string(), json!(release.version));
+    target.insert("release_zip_url".to_string(), json!(release.url));
+    // Mirrors of an older zip would serve the wrong file.
+    target.insert("mirrors".to_string(), json!(release.mirrors));
+    target.insert("sha256".to_string(), json!(verify::compute_sha256(&release.zip)?));
+    target.insert("size_bytes".to_string(), json!(zip_size));
+    // Both names set would be a duplicate field once merged.
+    target.remove("release_zip_size");
+    target.insert("installed_size_bytes".to_string(), json!(installed_size));
+    target.insert("files".to_string(), Value::Array(files));
+    // These describe the previous zip, so merged they would check or patch the new one
+    // against it.
+    for field in ["release_zip_sig_url", "archives", "targets", "patches"] {
+        target.remove(field);
+    }
+
+    if !release.prerequisites.is_empty() {
+        let prerequisites = release
+            .prerequisites
+            .iter()
+            .map(|text| {
+                let value: Value = serde_json::from_str(text)
+                    .with_context(|| format!("Invalid --prereq {}", text))?;
+                // Checked here so a typo names the option rather than the manifest.
+                serde_json::from_value::<PrerequisiteSpec>(value.clone())
+                    .with_context(|| format!("Invalid --prereq {}", text))?;
+                Ok(value)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        target.insert("prerequisites".to_string(), Value::Array(prerequisites));
+    }
+    Ok(())
+}
+
+/// A `files` entry, with its hash and size, for every file in the zip at `path`; with
+/// `tag_arch`, also the `arch` its directory names.
+fn zip_files(path: &Path, tag_arch: bool) -> Result<Vec<Value>> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).context("Failed to read ZIP archive")?;
+
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).context("Failed to read file from ZIP")?;
+        if entry.is_dir() {
+            continue;
+        }
+        // Named as the installer extracts them.
+        let name = entry.mangled_name().to_string_lossy().replace('\\', "/");
+        let size = entry.size();
+        let sha256 = verify::sha256_of(entry).with_context(|| format!("Failed to hash {} in the zip", name))?;
+        let mut file = json!({ "name": name, "sha256": sha256, "size": size });
+        if let Some(arch) = tag_arch.then(|| arch_of(&name)).flatten() {
+            file["arch"] = json!(arch);
+        }
+        files.push(file);
+    }
+    if files.is_empty() {
+        anyhow::bail!("{} has no files", path.display());
+    }
+    Ok(files)
+}
+
+/// The architecture the directories of the zip entry `name` are named after, if any.
+fn arch_of(name: &str) -> Option<&'static str> {
+    let (dirs, _) = name.rsplit_once('/')?;
+    dirs.split('/').find_map(|dir| match dir.to_ascii_lowercase().as_str() {
+        "x86_64" | "x64" | "amd64" => Some("x86_64"),
+        "aarch64" | "arm64" => Some("aarch64"),
+        _ => None,
+    })
+}
+
+/// The object under `key` in `map`, created when missing.
+fn object<'m>(map: &'m mut Map<String, Value>, key: &str) -> Result<&'m mut Map<String, Value>> {
+    map.entry(key.to_string())
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .with_context(|| format!("The manifest's {} is not an object", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_zip(path: &Path) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("data/", options).unwrap();
+        zip.start_file("paradise.exe", options).unwrap();
+        zip.write_all(b"test").unwrap();
+        zip.start_file("data/levels.pak", options).unwrap();
+        zip.write_all(b"levels").unwrap();
+        zip.finish().unwrap();
+    }
+
+    fn release(dir: &Path, output: &str) -> ManifestRelease {
+        ManifestRelease {
+            zip: dir.join("paradise.zip"),
+            output: dir.join(output),
+            version: "1.3.0".to_string(),
+            url: "https://example.com/paradise-1.3.0.zip".to_string(),
+            mirrors: Vec::new(),
+            channel: None,
+            prerequisites: vec![r#"{"type": "webview2", "required": true, "url": "https://go.microsoft.com/fwlink/p/?LinkId=2124703", "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"}"#.to_string()],
+            merge: false,
+            tag_arch: false,
+        }
+    }
+
+    #[test]
+    fn generated_manifests_describe_the_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zip(&dir.path().join("paradise.zip"));
+
+        for output in ["installer.json", "installer.toml"] {
+            let manifest = generate(&release(dir.path(), output)).unwrap();
+            assert_eq!(manifest.version, "1.3.0");
+            assert_eq!(manifest.sha256, verify::compute_sha256(&dir.path().join("paradise.zip")).unwrap());
+            let files: Vec<_> = manifest.files.iter().map(|file| (file.name.as_str(), file.size)).collect();
+            assert_eq!(files, [("paradise.exe", Some(4)), ("data/levels.pak", Some(6))]);
+            assert_eq!(
+                manifest.files[0].sha256.as_deref(),
+                Some("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")
+            );
+            assert_eq!(manifest.installed_size_bytes, Some(10));
+            assert_eq!(manifest.size_bytes, Some(fs::metadata(dir.path().join("paradise.zip")).unwrap().len()));
+            assert_eq!(manifest.prerequisites.0.len(), 1);
+        }
+    }
+
+    #[test]
+    fn architecture_directories_tag_their_files() {
+        assert_eq!(arch_of("bin/x64/native.dll"), Some("x86_64"));
+        assert_eq!(arch_of("ARM64/native.dll"), Some("aarch64"));
+        assert_eq!(arch_of("bin/native-arm64.dll"), None);
+        assert_eq!(arch_of("arm64"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("paradise.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        for name in ["paradise.exe", "bin/x64/native.dll", "bin/arm64/native.dll"] {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(name.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut tagged = release(dir.path(), "installer.json");
+        tagged.tag_arch = true;
+        let manifest = generate(&tagged).unwrap();
+        let arches: Vec<&str> = manifest.files.iter().map(|file| file.arch.as_str()).collect();
+        assert_eq!(arches, ["any", "x86_64", "aarch64"]);
+    }
+
+    #[test]
+    fn merging_keeps_the_fields_it_does_not_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zip(&dir.path().join("paradise.zip"));
+        let output = dir.path().join("installer.json");
+        fs::write(
+            &output,
+            json!({
+                "version": "1.2.0",
+                "release_zip_url": "https://example.com/paradise-1.2.0.zip",
+                "sha256": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+                "files": [{ "name": "paradise.exe" }],
+                "eula_text": "Be nice.",
+                "x-build": 42
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let mut beta = release(dir.path(), "installer.json");
+        beta.channel = Some("beta".to_string());
+        beta.merge = true;
+        let manifest = generate(&beta).unwrap();
+        assert_eq!(manifest.version, "1.2.0");
+        assert_eq!(manifest.eula_text.as_deref(), Some("Be nice."));
+        assert_eq!(manifest.for_channel("beta").unwrap().version, "1.3.0");
+
+        let written: Value = serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(written["x-build"], 42);
+        assert_eq!(written["schema_version"], manifest::SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn merging_drops_what_belongs_to_the_previous_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zip(&dir.path().join("paradise.zip"));
+        let output = dir.path().join("installer.json");
+        let sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        fs::write(
+            &output,
+            json!({
+                "version": "1.2.0",
+                "release_zip_url": "https://example.com/paradise-1.2.0.zip",
+                "release_zip_sig_url": "https://example.com/paradise-1.2.0.zip.minisig",
+                "sha256": sha256,
+                "files": [{ "name": "paradise.exe" }],
+                "archives": [{ "url": "https://cdn.example.com/assets-1.2.0.zip", "sha256": sha256 }],
+                "targets": {
+                    "aarch64": { "release_zip_url": "https://example.com/paradise-1.2.0-arm64.zip", "sha256": sha256 }
+                },
+                "patches": [{
+                    "from_version": "1.1.0",
+                    "patch_url": "https://example.com/paradise-1.1.0-1.2.0.patch",
+                    "sha256": sha256,
+                    "algorithm": "bsdiff"
+                }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let mut update = release(dir.path(), "installer.json");
+        update.merge = true;
+        let manifest = generate(&update).unwrap();
+        assert_eq!(manifest.version, "1.3.0");
+        assert_eq!(manifest.release_zip_url, "https://example.com/paradise-1.3.0.zip");
+        assert_eq!(manifest.release_zip_sig_url, None);
+        assert!(manifest.archives.is_empty());
+        assert!(manifest.targets.is_empty());
+        assert!(manifest.patches.is_empty());
+    }
+}
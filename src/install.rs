@@ -1,96 +1,1841 @@
 use anyhow::{Context, Result};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use crate::arch::Arch;
+use crate::backup;
+use crate::cache;
+use crate::cancel;
 use crate::cli::InstallMode;
-use crate::download::download_file;
-use crate::manifest::Manifest;
-use crate::shortcut::create_shortcut;
-use crate::verify::verify_sha256;
+use crate::diagnose::{self, NetworkErrorKind};
+use crate::disk::{format_bytes, free_space};
+use crate::download::async_download_file;
+use crate::download::{
+    discard_stale_downloads, download_all, download_file, preflight, DownloadJob, DownloadOptions,
+    Downloaded, HashMismatch, Preflight,
+};
+use crate::environment;
+use crate::error::{InstallError, SpaceShortfall, Stage, StageExt};
+use crate::eula;
+use crate::http::{AuthError, HttpClient, StatusError};
+use crate::launch::launch;
+use crate::license::{self, LicenseKey};
+use crate::manifest::{
+    local_path, validate_manifest_url, FileEntry, Manifest, Patch, Product, ShortcutLocation, Uninstall,
+    DEFAULT_CHANNEL,
+};
+use crate::patch;
+use crate::paths;
+use crate::post_install;
+use crate::prereq::Remediator;
+use crate::preserve;
+use crate::processes;
+use crate::prompt;
+use crate::receipt::{
+    AcceptedEula, InstalledComponent, InstalledEnvironment, InstallReceipt, ReceiptFile,
+    WrittenRegistryValue, RECEIPT_FILE,
+};
+use crate::registry;
+use crate::release_notes;
+use crate::report::{Event, OutputFormat, Reporter};
+use crate::shortcut::{create_shortcut, ShortcutOptions};
+use crate::signature;
+use crate::strings;
+use crate::trust;
+use crate::uninstall::{remove_files, remove_installation, remove_paths};
+use crate::verify::{compute_sha256, sha256_matches, verify_sha256};
+use crate::version::Version;
 use atomic::AtomicInstaller;
 
+
+/// How long programs holding the install open get to exit.
+const PROCESS_EXIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Name prefix of the working directories created next to the install directory.
+const STAGING_PREFIX: &str = ".paradise-staging-";
+
+/// Local release files used instead of the network for air-gapped installs.
+#[derive(Debug, Clone)]
+pub struct OfflineRelease {
+    pub zip: PathBuf,
+    pub manifest: PathBuf,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    pub dry_run: bool,
+    pub no_desktop_shortcut: bool,
+    pub no_install_dir_shortcut: bool,
+    /// Leave PATH and the environment variables alone.
+    pub no_env: bool,
+    /// End programs running from the install directory without asking (--kill-running).
+    pub kill_running: bool,
+    pub interactive: bool,
+    pub output: OutputFormat,
+    pub channel: Option<String>,
+    pub force: bool,
+    pub offline: Option<OfflineRelease>,
+    pub pin_version: Option<String>,
+    pub launch: bool,
+    pub skip_prereq_check: bool,
+    pub keep_backup: bool,
+    pub backup_retention: usize,
+    pub connections: usize,
+    /// Files downloaded at the same time.
+    pub parallel_downloads: usize,
+    pub limit_rate: Option<u64>,
+    pub no_cache: bool,
+    pub cache_max_size: u64,
+    /// Installed size as a multiple of the zip size when the manifest gives no sizes.
+    pub expansion_factor: f64,
+    pub arch: Arch,
+    /// Optional components to install (--components), instead of the installed or default ones.
+    pub components: Option<Vec<String>>,
+    /// Key for manifests with a `license_check_url`.
+    pub license_key: Option<LicenseKey>,
+    /// Accept the manifest's EULA without asking (--accept-eula).
+    pub accept_eula: bool,
+    /// Use the last fetched manifest without asking when the host is unreachable.
+    pub offline_ok: bool,
+    /// Oldest last fetched manifest an update may use.
+    pub max_manifest_age: Duration,
+    /// Also print transfer statistics to the console.
+    pub verbose: bool,
+}
+
 pub struct Installer {
     manifest_url: String,
     mode: InstallMode,
     build_dir: Option<PathBuf>,
-    dry_run: bool,
-    no_shortcut: bool,
+    options: InstallOptions,
+    reporter: Reporter,
+    http: HttpClient,
+    prefetched: Option<Manifest>,
 }
 
-impl Installer {
-    pub fn new(
-        manifest_url: String,
-        mode: InstallMode,
-        build_dir: Option<PathBuf>,
-        dry_run: bool,
-        no_shortcut: bool,
-    ) -> Result<Self> {
-        if matches!(mode, InstallMode::Specific) && build_dir.is_none() {
-            anyhow::bail!("Build directory is required for specific mode");
+impl Installer {
+    pub fn new(
+        manifest_url: String,
+        mode: InstallMode,
+        build_dir: Option<PathBuf>,
+        options: InstallOptions,
+        http: HttpClient,
+    ) -> Result<Self> {
+        validate_manifest_url(&manifest_url)?;
+
+        if matches!(mode, InstallMode::Specific) && build_dir.is_none() {
+            anyhow::bail!("Build directory is required for specific mode");
+        }
+
+        Ok(Self {
+            manifest_url,
+            mode,
+            build_dir,
+            reporter: Reporter::new(options.output),
+            options,
+            http,
+            prefetched: None,
+        })
+    }
+
+    /// Uses a manifest that was already fetched (e.g. to size the install path prompt).
+    pub fn with_manifest(mut self, manifest: Manifest) -> Self {
+        self.prefetched = Some(manifest);
+        self
+    }
+
+    pub fn run(&self) -> Result<()> {
+        let (channel, manifest) = self.fetch_manifest(None)?;
+        self.install(&manifest, &channel, None)
+    }
+
+    /// Installs the manifest's release unless it is already installed. With `check_only`,
+    /// only reports whether there is an update, failing when it is mandatory.
+    pub fn update(&self, check_only: bool) -> Result<()> {
+        let (channel, manifest) = self.fetch_manifest(Some(self.options.max_manifest_age))?;
+        let install_dir = self.get_install_directory()?;
+
+        let receipt = InstallReceipt::load(&install_dir)?;
+        let up_to_date = receipt
+            .as_ref()
+            .is_some_and(|r| r.version == manifest.version);
+        let unsupported = receipt.as_ref().and_then(|r| manifest.unsupported(&r.version));
+
+        self.reporter.event(Event::UpdateCheck {
+            installed: receipt.as_ref().map(|r| r.version.to_string()),
+            available: manifest.version.to_string(),
+            up_to_date,
+            update_required: unsupported.is_some(),
+        });
+
+        // A mandatory update goes ahead whatever the pin, rollback or prompt would say.
+        if let (Some(receipt), Some(min)) = (&receipt, unsupported) {
+            log::warn!(
+                "Installed version {} is older than the minimum supported version {}, the update is mandatory",
+                receipt.version,
+                min
+            );
+            self.reporter.say(&strings::format(
+                "update.required",
+                &[&receipt.version, &manifest.version],
+            ));
+            if let Some(message) = &manifest.min_supported_message {
+                self.reporter.say(message);
+            }
+            if check_only {
+                return Err(InstallError::UpdateRequired {
+                    installed: receipt.version.to_string(),
+                    minimum: min.to_string(),
+                }
+                .into());
+            }
+            return self.install(&manifest, &channel, None);
+        }
+
+        if check_only {
+            let receipt = receipt.ok_or_else(|| InstallError::NotInstalled(install_dir.clone()))?;
+            if up_to_date {
+                self.reporter
+                    .say(&strings::format("update.up_to_date", &[&receipt.version]));
+            } else {
+                self.reporter.say(&strings::format(
+                    "update.available",
+                    &[&receipt.version, &manifest.version],
+                ));
+            }
+            return Ok(());
+        }
+
+        match receipt {
+            Some(receipt) if !up_to_date && !self.options.force && receipt.rolled_back_from.is_some() => {
+                let from = receipt.rolled_back_from.as_ref().map(ToString::to_string).unwrap_or_default();
+                log::warn!(
+                    "Installed version {} was rolled back from {}, updating to {} moves forward again",
+                    receipt.version,
+                    from,
+                    manifest.version
+                );
+                self.reporter.say(&strings::format(
+                    "update.rolled_back",
+                    &[&receipt.version, &from, &manifest.version],
+                ));
+                let forward = self.options.interactive
+                    && prompt::confirm(strings::text("update.confirm_forward"), false)?;
+                if !forward {
+                    log::info!("Staying on rolled back version {}", receipt.version);
+                    self.reporter
+                        .say(&strings::format("update.staying", &[&receipt.version]));
+                    return Ok(());
+                }
+            }
+            Some(receipt) if receipt.pinned && !up_to_date && self.options.pin_version.is_none() => {
+                log::warn!(
+                    "Installed version {} is pinned, updating to {} moves off the pin",
+                    receipt.version,
+                    manifest.version
+                );
+                self.reporter.say(&strings::format(
+                    "update.pinned",
+                    &[&receipt.version, &manifest.version],
+                ));
+                if self.options.interactive
+                    && !prompt::confirm(strings::text("update.confirm_unpin"), false)?
+                {
+                    return Err(InstallError::Cancelled.into());
+                }
+            }
+            Some(receipt) if up_to_date && self.options.force => {
+                log::info!("Forcing reinstall of {}", receipt.version);
+                self.reporter
+                    .say(&strings::format("update.forcing", &[&receipt.version]));
+            }
+            Some(receipt) if up_to_date => {
+                log::info!("Installed version {} is up to date", receipt.version);
+                self.reporter
+                    .say(&strings::format("update.up_to_date", &[&receipt.version]));
+                return Ok(());
+            }
+            Some(receipt) if manifest.version < receipt.version => {
+                log::warn!("Installed version {} is newer than {}, downgrading", receipt.version, manifest.version);
+                self.reporter.say(&strings::format(
+                    "update.downgrading",
+                    &[&receipt.version, &manifest.version],
+                ));
+            }
+            Some(receipt) => {
+                log::info!("Updating from {} to {}", receipt.version, manifest.version);
+                self.reporter.say(&strings::format(
+                    "update.updating",
+                    &[&receipt.version, &manifest.version],
+                ));
+            }
+            None => {
+                log::info!("No existing installation found, installing {}", manifest.version);
+                self.reporter
+                    .say(&strings::format("update.fresh", &[&manifest.version]));
+            }
+        }
+
+        self.install(&manifest, &channel, None)
+    }
+
+    /// Installs a release from the manifest's `history` over the installed one, through
+    /// the same checks as any install. The receipt remembers the version it replaced, so
+    /// `update` asks before going forward again.
+    pub fn rollback(&self, to_version: Option<&str>) -> Result<()> {
+        let whole = self.whole_manifest(None)?;
+        let install_dir = self.get_install_directory()?;
+        let receipt = InstallReceipt::load(&install_dir)?
+            .ok_or_else(|| InstallError::NotInstalled(install_dir.clone()))?;
+
+        let version = match to_version {
+            Some(version) => version.to_string(),
+            None if self.options.interactive => {
+                let versions = whole.history_versions();
+                if versions.is_empty() {
+                    anyhow::bail!("The manifest has no earlier versions to roll back to");
+                }
+                self.reporter
+                    .say(&strings::format("rollback.versions", &[&versions.join(", ")]));
+                prompt::ask(strings::text("rollback.ask_version"))?
+            }
+            None => anyhow::bail!("rollback needs --to-version in unattended mode"),
+        };
+
+        let manifest = whole
+            .for_history_version(&version)
+            .and_then(|manifest| manifest.for_arch(self.options.arch.as_str()))
+            .and_then(|manifest| manifest.expand_urls(&self.channel(), self.options.arch.as_str()))
+            .stage(Stage::Manifest)?;
+        if manifest.version >= receipt.version && !self.options.force {
+            anyhow::bail!(
+                "{} is not older than the installed {}; pass --force to install it anyway",
+                manifest.version,
+                receipt.version
+            );
+        }
+
+        log::info!("Rolling back from {} to {}", receipt.version, manifest.version);
+        self.reporter.say(&strings::format(
+            "rollback.rolling_back",
+            &[&receipt.version, &manifest.version],
+        ));
+        self.install(&manifest, &receipt.channel, Some(&receipt.version))
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let (_, manifest) = self.fetch_manifest(None)?;
+        let install_dir = self.get_install_directory()?;
+        log::info!("Verifying installation in {:?}", install_dir);
+
+        let receipt = InstallReceipt::load(&install_dir)?
+            .ok_or_else(|| InstallError::NotInstalled(install_dir.clone()))?;
+
+        self.reporter.say(&strings::format(
+            "versions",
+            &[&receipt.version, &manifest.version],
+        ));
+
+        // Components the manifest no longer has cannot be checked.
+        let installed: Vec<String> = receipt
+            .component_names()
+            .into_iter()
+            .filter(|name| manifest.component_names().contains(&name.as_str()))
+            .collect();
+        let manifest = manifest.for_components(&installed).stage(Stage::Manifest)?;
+
+        let mut problems = 0;
+        for file_entry in &manifest.files {
+            let name = file_entry.installed_name();
+            let file_path = install_dir.join(name);
+            let recorded = receipt.files.iter().find(|f| f.name == name);
+
+            let status = if preserve::is_preserved(name, &receipt.preserve) {
+                // The user's copy, which is expected to differ from the release.
+                "preserved"
+            } else if !file_path.exists() && !file_entry.required && recorded.is_none() {
+                // The release did not have it.
+                "absent"
+            } else if !file_path.exists() {
+                "missing"
+            } else {
+                match recorded {
+                    Some(recorded) if !verify_sha256(&file_path, &recorded.sha256)? => "modified",
+                    Some(_) => "ok",
+                    None => "present",
+                }
+            };
+
+            if matches!(status, "missing" | "modified") {
+                problems += 1;
+            }
+
+            self.reporter.say(&format!("  {:<9} {}", status, name));
+            self.reporter.event(Event::FileChecked {
+                name: name.to_string(),
+                status: status.to_string(),
+            });
+        }
+
+        if problems > 0 {
+            return Err(InstallError::InstallationModified(problems).into());
+        }
+
+        log::info!("Installation verified successfully");
+        Ok(())
+    }
+
+    /// Removes the installation as its receipt describes it. The manifest is not fetched:
+    /// a newer one may list paths and keys this install never created.
+    pub fn uninstall(&self) -> Result<()> {
+        let install_dir = self.get_install_directory()?;
+        log::info!("Uninstalling from {:?}", install_dir);
+
+        if !install_dir.exists() {
+            return Err(InstallError::NotInstalled(install_dir).into());
+        }
+
+        let receipt = InstallReceipt::load(&install_dir)?;
+        if let Some(components) = &self.options.components {
+            return self.uninstall_components(&install_dir, receipt, components);
+        }
+
+        let (product, shortcuts, environment, registry_values, preserve, spec) = match receipt {
+            Some(receipt) => {
+                // The receipt is in the install directory, where anyone can edit it.
+                let spec = receipt.uninstall.checked(&receipt.product);
+                (receipt.product, receipt.shortcuts, receipt.environment, receipt.registry, receipt.preserve, spec)
+            }
+            None => {
+                let product = Product::default();
+                let shortcuts = self.shortcut_path(&install_dir, &product)?.into_iter().collect();
+                (product, shortcuts, None, Vec::new(), Vec::new(), Uninstall::default())
+            }
+        };
+        let preserved = preserve::find(&install_dir, &preserve)?;
+
+        if self.options.dry_run {
+            self.reporter.say(strings::text("uninstall.dry_run"));
+            self.reporter.say(&strings::format(
+                "uninstall.would_remove",
+                &[&install_dir.display()],
+            ));
+            for shortcut in &shortcuts {
+                self.reporter.say(&strings::format(
+                    "uninstall.would_remove",
+                    &[&shortcut.display()],
+                ));
+            }
+            for dir in environment.iter().flat_map(|environment| &environment.path) {
+                self.reporter
+                    .say(&strings::format("uninstall.would_remove_path", &[dir]));
+            }
+            for value in &registry_values {
+                self.reporter.say(&strings::format(
+                    "uninstall.would_remove",
+                    &[&registry::describe(value)],
+                ));
+            }
+            for path in &spec.remove_paths {
+                self.reporter
+                    .say(&strings::format("uninstall.would_remove", &[path]));
+            }
+            for key in &spec.registry_keys {
+                self.reporter.say(&strings::format(
+                    "uninstall.would_remove",
+                    &[&registry::describe_key(key)],
+                ));
+            }
+            for path in &preserved {
+                self.reporter
+                    .say(&strings::format("uninstall.would_keep", &[path]));
+            }
+            return Ok(());
+        }
+
+        if self.options.interactive
+            && !prompt::confirm(
+                &strings::format("uninstall.confirm", &[&product.display_name, &install_dir.display()]),
+                false,
+            )?
+        {
+            anyhow::bail!("Uninstall cancelled by user");
+        }
+
+        // Unattended uninstalls keep the user's data; only a person can decide to drop it,
+        // and only when the manifest lets them.
+        let keep = !preserved.is_empty()
+            && (!self.options.interactive
+                || !spec.offer_user_data_removal
+                || prompt::confirm(
+                    &strings::format("uninstall.confirm_keep", &[&preserved.join(", ")]),
+                    true,
+                )?);
+        let keep = if keep { preserve.as_slice() } else { &[] };
+
+        if let Some(environment) = &environment {
+            environment::unregister(environment);
+        }
+        registry::remove_all(&registry_values);
+        for path in remove_installation(&install_dir, &shortcuts, keep)? {
+            self.reporter
+                .say(&strings::format("uninstall.removed", &[&path.display()]));
+            self.reporter.event(Event::Removed { path });
+        }
+        for path in remove_paths(&spec.remove_paths) {
+            self.reporter
+                .say(&strings::format("uninstall.removed", &[&path.display()]));
+            self.reporter.event(Event::Removed { path });
+        }
+        for key in &spec.registry_keys {
+            match registry::remove_key(key) {
+                Ok(true) => self
+                    .reporter
+                    .say(&strings::format("uninstall.removed", &[&registry::describe_key(key)])),
+                Ok(false) => {}
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+
+        log::info!("Uninstall completed successfully");
+        Ok(())
+    }
+
+    /// Removes the files of the given optional components and drops them from the receipt,
+    /// leaving the rest of the installation in place.
+    fn uninstall_components(
+        &self,
+        install_dir: &Path,
+        receipt: Option<InstallReceipt>,
+        names: &[String],
+    ) -> Result<()> {
+        let mut receipt = receipt.ok_or_else(|| InstallError::NotInstalled(install_dir.to_path_buf()))?;
+        let installed = receipt.components.take().unwrap_or_default();
+
+        let mut removing = Vec::new();
+        for name in names {
+            match installed.iter().find(|component| &component.name == name) {
+                Some(component) if component.required => {
+                    anyhow::bail!("Component {} is required and cannot be removed", name)
+                }
+                Some(component) => removing.push(component),
+                None => anyhow::bail!(
+                    "Component {} is not installed, installed components: {}",
+                    name,
+                    receipt.component_names().join(", ")
+                ),
+            }
+        }
+        let files: Vec<String> = removing
+            .iter()
+            .flat_map(|component| component.files.iter().cloned())
+            .collect();
+
+        if self.options.dry_run {
+            self.reporter.say(strings::text("uninstall.dry_run"));
+            for file in &files {
+                self.reporter.say(&strings::format(
+                    "uninstall.would_remove",
+                    &[&install_dir.join(file).display()],
+                ));
+            }
+            return Ok(());
+        }
+
+        if self.options.interactive
+            && !prompt::confirm(
+                &strings::format("uninstall.confirm_components", &[&names.join(", ")]),
+                false,
+            )?
+        {
+            anyhow::bail!("Uninstall cancelled by user");
+        }
+
+        for path in remove_files(install_dir, &files)? {
+            self.reporter
+                .say(&strings::format("uninstall.removed", &[&path.display()]));
+            self.reporter.event(Event::Removed { path });
+        }
+
+        receipt.files.retain(|file| !files.contains(&file.name));
+        receipt.components = Some(
+            installed
+                .into_iter()
+                .filter(|component| !names.contains(&component.name))
+                .collect(),
+        );
+        receipt.save(install_dir)?;
+
+        log::info!("Removed components {}", names.join(", "));
+        Ok(())
+    }
+
+    pub fn clean_cache(&self) -> Result<()> {
+        if self.options.dry_run {
+            let (files, bytes) = cache::usage().stage(Stage::Filesystem)?;
+            self.reporter.say(&strings::format(
+                "cache.would_clean",
+                &[&files, &format_bytes(bytes)],
+            ));
+            return Ok(());
+        }
+
+        let (files, bytes) = cache::clean().stage(Stage::Filesystem)?;
+        log::info!("Cleaned download cache: {} files, {} bytes", files, bytes);
+        self.reporter.say(&strings::format(
+            "cache.cleaned",
+            &[&files, &format_bytes(bytes)],
+        ));
+        Ok(())
+    }
+
+    /// Fetches the manifest and narrows it to the selected release channel. `max_age`
+    /// limits how old a manifest kept from an earlier fetch may be.
+    fn fetch_manifest(&self, max_age: Option<Duration>) -> Result<(String, Manifest)> {
+        let manifest = self.whole_manifest(max_age)?;
+        let channel = self.channel();
+        let manifest = match &self.options.pin_version {
+            Some(version) => {
+                log::info!("Pinned to version {}", version);
+                manifest.for_version(version).stage(Stage::Manifest)?
+            }
+            None => manifest.for_channel(&channel).stage(Stage::Manifest)?,
+        };
+        let manifest = manifest
+            .for_arch(self.options.arch.as_str())
+            .and_then(|manifest| manifest.expand_urls(&channel, self.options.arch.as_str()))
+            .stage(Stage::Manifest)?;
+        log::info!(
+            "Release channel: {} ({}, {})",
+            channel,
+            manifest.version,
+            self.options.arch
+        );
+
+        self.reporter.event(Event::ManifestFetched {
+            version: manifest.version.to_string(),
+            channel: channel.clone(),
+            url: self.manifest_source(),
+        });
+
+        Ok((channel, manifest))
+    }
+
+    /// The manifest with all its channels and history, read or fetched.
+    fn whole_manifest(&self, max_age: Option<Duration>) -> Result<Manifest> {
+        match (&self.prefetched, &self.options.offline) {
+            (Some(manifest), _) => Ok(manifest.clone()),
+            (None, Some(offline)) => Manifest::from_file(&offline.manifest).stage(Stage::Manifest),
+            (None, None) => self.fetch_or_last_manifest(max_age).stage(Stage::Manifest),
+        }
+    }
+
+    /// Fetches the manifest; when its host is unreachable, offers the last fetched one
+    /// instead, if it is no older than `max_age`. Everything downloaded with it is still
+    /// checked against its hashes and signature.
+    fn fetch_or_last_manifest(&self, max_age: Option<Duration>) -> Result<Manifest> {
+        let error = match Manifest::from_url(&self.http, &self.manifest_url, !self.options.force) {
+            Ok(manifest) => return Ok(manifest),
+            Err(e) => e,
+        };
+        let Some(problem) = diagnose::classify(&error).filter(|problem| {
+            matches!(
+                problem.kind,
+                NetworkErrorKind::Dns
+                    | NetworkErrorKind::ConnectionRefused
+                    | NetworkErrorKind::Timeout
+                    | NetworkErrorKind::ServerError
+            )
+        }) else {
+            return Err(error);
+        };
+        let stale = match Manifest::last_fetched(&self.manifest_url, self.http.allow_insecure()) {
+            Ok(Some(stale)) => stale,
+            Ok(None) => return Err(error),
+            Err(e) => {
+                log::warn!("The last fetched manifest is unusable: {:#}", e);
+                return Err(error);
+            }
+        };
+
+        let age = stale.age();
+        log::warn!(
+            "Manifest host unreachable ({}); the manifest fetched {} is {} old",
+            problem.kind.label(),
+            stale.fetched_at,
+            format_age(age)
+        );
+        if let Some(max_age) = max_age.filter(|max_age| age > *max_age) {
+            return Err(error.context(format!(
+                "The last fetched manifest is {} old, more than the {} allowed (--max-manifest-age)",
+                format_age(age),
+                format_age(max_age)
+            )));
+        }
+
+        let use_it = if self.options.offline_ok {
+            true
+        } else if self.options.interactive {
+            self.reporter.say(&strings::format(
+                "manifest.stale",
+                &[&problem.message(), &format_age(age), &stale.fetched_at.format("%Y-%m-%d %H:%M")],
+            ));
+            prompt::confirm(strings::text("manifest.use_stale"), true)?
+        } else {
+            false
+        };
+        if !use_it {
+            return Err(error);
+        }
+
+        self.reporter.say(&strings::format("manifest.stale_used", &[&format_age(age)]));
+        self.reporter.event(Event::StaleManifest {
+            fetched_at: stale.fetched_at.to_rfc3339(),
+            age_secs: age.as_secs(),
+        });
+        Ok(stale.manifest)
+    }
+
+    fn manifest_source(&self) -> String {
+        match &self.options.offline {
+            Some(offline) => offline.manifest.display().to_string(),
+            None => self.manifest_url.clone(),
+        }
+    }
+
+    /// The channel given on the command line, else the one the existing install follows.
+    fn channel(&self) -> String {
+        if let Some(channel) = &self.options.channel {
+            return channel.clone();
+        }
+
+        self.get_install_directory()
+            .ok()
+            .and_then(|dir| InstallReceipt::load(&dir).ok().flatten())
+            .map(|receipt| receipt.channel)
+            .unwrap_or_else(|| DEFAULT_CHANNEL.to_string())
+    }
+
+    /// Installs `manifest`; `rolled_back_from` is the newer version a rollback replaces.
+    fn install(&self, manifest: &Manifest, channel: &str, rolled_back_from: Option<&Version>) -> Result<()> {
+        log::info!("Starting installation process");
+
+        if let Some(min) = manifest.unsupported(&manifest.version) {
+            let reason = manifest
+                .min_supported_message
+                .as_ref()
+                .map_or_else(String::new, |message| format!(": {}", message));
+            return Err(anyhow::anyhow!(
+                "{} is no longer supported, the oldest supported version is {}{}",
+                manifest.version,
+                min,
+                reason
+            ))
+            .stage(Stage::Manifest);
+        }
+
+        let preflight = self.check_release_url(manifest)?;
+        let sized;
+        let manifest = match preflight.as_ref().and_then(|preflight| preflight.content_length) {
+            Some(length) if manifest.size_bytes.is_none() => {
+                log::info!("Using the server's size for the release: {}", format_bytes(length));
+                sized = Manifest {
+                    size_bytes: Some(length),
+                    ..manifest.clone()
+                };
+                &sized
+            }
+            _ => manifest,
+        };
+
+        let install_dir = self.get_install_directory()?;
+        log::info!("Install directory: {:?}", install_dir);
+        self.reporter.event(Event::InstallDirectory {
+            path: install_dir.clone(),
+        });
+
+        let eula = self.accept_eula(manifest, &install_dir)?;
+        self.check_license(manifest)?;
+        self.check_prerequisites(manifest)?;
+        registry::check_access(&manifest.registry).stage(Stage::Filesystem)?;
+
+        let selected = self.select_components(manifest, &install_dir)?;
+        let narrowed = manifest.for_components(&selected).stage(Stage::Manifest)?;
+        let manifest = &narrowed;
+
+        self.show_release_notes(manifest, &install_dir);
+
+        if self.options.dry_run {
+            return self.print_plan(manifest, channel, &install_dir, preflight.as_ref());
+        }
+
+        if !self.confirm_replace(&install_dir, manifest)? {
+            return Err(InstallError::Cancelled.into());
+        }
+
+        let _armed = cancel::arm();
+
+        let temp_dir = self.staging_dir(&install_dir).stage(Stage::Filesystem)?;
+        self.check_disk_space(&install_dir, temp_dir.path(), manifest)?;
+
+        let asset_dir = temp_dir.path().join("assets");
+        let archive_dir = temp_dir.path().join("archives");
+        let component_dir = temp_dir.path().join("components");
+        let extract_dir = temp_dir.path().join("extracted");
+        let patched = match self.usable_patch(manifest, &install_dir) {
+            Some(patch) => {
+                match self.apply_delta(patch, manifest, &install_dir, temp_dir.path(), &extract_dir) {
+                    Ok(()) => true,
+                    Err(e) if cancel::is_cancelled() => return Err(e),
+                    Err(e) => {
+                        log::warn!("Delta update failed, falling back to the full download: {:#}", e);
+                        self.reporter.say(strings::text("patch.fallback"));
+                        if let Err(e) = fs::remove_dir_all(&extract_dir) {
+                            log::warn!("Failed to clear {:?}: {}", extract_dir, e);
+                        }
+                        false
+                    }
+                }
+            }
+            None => false,
+        };
+
+        let zip_path = match &self.options.offline {
+            _ if patched => {
+                self.download_verified(manifest, None, None, &asset_dir, &component_dir)?;
+                None
+            }
+            Some(offline) => {
+                log::info!("Offline install, using local release zip {:?}", offline.zip);
+                if let Some(archive) = manifest.archives.first() {
+                    return Err(anyhow::anyhow!(
+                        "Archive {} has to be downloaded, which an offline install does not do",
+                        archive.url
+                    ))
+                    .stage(Stage::Download);
+                }
+                self.skip_assets_offline(manifest)?;
+                if !offline.zip.is_file() {
+                    return Err(anyhow::anyhow!(
+                        "Release zip {} does not exist",
+                        offline.zip.display()
+                    ))
+                    .stage(Stage::Download);
+                }
+                cancel::check()?;
+                if !self.verify_release(&offline.zip, manifest, None)? {
+                    return Err(InstallError::IntegrityCheckFailed.into());
+                }
+                Some(offline.zip.clone())
+            }
+            None => {
+                let (zip_path, fresh) = match self.cached_release(manifest) {
+                    Some(cached) => {
+                        if !self.verify_release(&cached, manifest, Some(&manifest.sha256))? {
+                            return Err(InstallError::IntegrityCheckFailed.into());
+                        }
+                        (cached, false)
+                    }
+                    None => (self.download_path(manifest).stage(Stage::Filesystem)?, true),
+                };
+                self.download_verified(
+                    manifest,
+                    fresh.then_some(zip_path.as_path()),
+                    Some(&archive_dir),
+                    &asset_dir,
+                    &component_dir,
+                )?;
+                Some(zip_path)
+            }
+        };
+
+        if let Some(zip_path) = &zip_path {
+            self.verify_release_signature(zip_path, manifest)?;
+
+            let release_dir = match &manifest.extract_to {
+                Some(dir) => extract_dir.join(dir),
+                None => extract_dir.clone(),
+            };
+            fs::create_dir_all(&release_dir)
+                .context("Failed to create extraction directory")
+                .stage(Stage::Filesystem)?;
+            cancel::check()?;
+            let skipped: Vec<&str> = manifest
+                .unselected_files()
+                .chain(manifest.other_arch_files.iter().map(String::as_str))
+                .filter_map(|name| manifest.zip_entry_name(name))
+                .collect();
+            self.extract_zip(zip_path, &release_dir, &skipped)
+                .stage(Stage::Extraction)?;
+            self.extract_archives(&archive_dir, &extract_dir, manifest)
+                .stage(Stage::Extraction)?;
+        }
+        self.extract_components(&component_dir, &extract_dir, manifest)
+            .stage(Stage::Extraction)?;
+        if zip_path.is_some() {
+            move_to_targets(&extract_dir, manifest).stage(Stage::Filesystem)?;
+        }
+        self.place_assets(&asset_dir, &extract_dir, manifest)
+            .stage(Stage::Filesystem)?;
+
+        // A delta install checked its files while it could still fall back.
+        if zip_path.is_some() {
+            self.verify_extracted_files(&extract_dir, manifest)?;
+        }
+        cancel::check()?;
+
+        self.close_running_programs(&install_dir, manifest)?;
+        let previous = InstallReceipt::load(&install_dir).ok().flatten();
+        let atomic_installer = AtomicInstaller::new(&install_dir)?;
+        atomic_installer
+            .install(&extract_dir, &manifest.preserve)
+            .stage(Stage::Filesystem)?;
+        if let Err(e) = self.run_post_install(&install_dir, manifest) {
+            atomic_installer.rollback();
+            return Err(e);
+        }
+        let previous_registry = previous.as_ref().map(|receipt| receipt.registry.as_slice()).unwrap_or_default();
+        let registry = match self.write_registry(&install_dir, manifest, previous_registry) {
+            Ok(registry) => registry,
+            Err(e) => {
+                atomic_installer.rollback();
+                return Err(e);
+            }
+        };
+        let previous_shortcuts = previous.as_ref().map(|receipt| receipt.shortcuts.clone()).unwrap_or_default();
+        let previous_environment = previous.and_then(|receipt| receipt.environment);
+
+        // The registry values are only recorded in the receipt; without it, uninstall
+        // could never remove them.
+        let shortcuts = match self.create_shortcuts(&install_dir, manifest, &previous_shortcuts) {
+            Ok(shortcuts) => shortcuts,
+            Err(e) => {
+                registry::remove_all(&registry.undo);
+                atomic_installer.rollback();
+                return Err(e);
+            }
+        };
+        let environment = self.register_environment(&install_dir, manifest, previous_environment.clone());
+        let changes = SystemChanges {
+            shortcuts: shortcuts.clone(),
+            environment: environment.clone(),
+            registry: registry.values.clone(),
+        };
+        if let Err(e) = self
+            .write_receipt(&install_dir, manifest, channel, changes, eula, rolled_back_from)
+            .stage(Stage::Filesystem)
+        {
+            if !self.options.no_env {
+                environment::revert(environment.as_ref(), previous_environment.as_ref());
+            }
+            remove_shortcuts(&new_paths(&shortcuts, &previous_shortcuts));
+            registry::remove_all(&registry.undo);
+            atomic_installer.rollback();
+            return Err(e);
+        }
+        // Only now that the receipt no longer lists them; before, a failed install would
+        // have left the earlier one without them.
+        registry::remove_all(&registry.stale);
+        remove_shortcuts(&new_paths(&previous_shortcuts, &shortcuts));
+        self.clean_up_backups(&install_dir, atomic_installer.backup_dir());
+        if let Some(zip_path) = &zip_path {
+            self.keep_download(zip_path, manifest);
+        }
+
+        log::info!("Installation completed successfully");
+
+        self.offer_launch(&install_dir, &manifest.product)
+    }
+
+    /// Downloads the release to `zip_path` and its other archives to `archive_dir`, when
+    /// given, together with the manifest's assets and the zips of the selected components,
+    /// and checks their hashes. A wrong hash is nearly always a corrupted transfer, so
+    /// mismatching files are downloaded once more from scratch before giving up.
+    fn download_verified(
+        &self,
+        manifest: &Manifest,
+        zip_path: Option<&Path>,
+        archive_dir: Option<&Path>,
+        asset_dir: &Path,
+        component_dir: &Path,
+    ) -> Result<()> {
+        let mut jobs = self.download_jobs(manifest, zip_path, archive_dir, asset_dir, component_dir)?;
+
+        for attempt in 1..=2 {
+            if jobs.is_empty() {
+                return Ok(());
+            }
+
+            let results = download_all(&jobs, self.options.parallel_downloads, |job, options| {
+                self.download_artifact(job, options)
+            })
+            .stage(Stage::Download)?;
+            cancel::check()?;
+
+            let mut pending = Vec::new();
+            let mut mismatched = false;
+            for (job, result) in jobs.into_iter().zip(results) {
+                let is_release = zip_path == Some(job.destination.as_path());
+                let error = match result {
+                    Some(Ok(downloaded)) => {
+                        if is_release {
+                            self.verify_release(&job.destination, manifest, Some(&downloaded.sha256))?;
+                        } else {
+                            self.reporter.event(Event::Verification {
+                                target: format!("asset:{}", job.name),
+                                passed: true,
+                            });
+                        }
+                        continue;
+                    }
+                    // Not started after another download failed.
+                    None => {
+                        pending.push(job);
+                        continue;
+                    }
+                    Some(Err(e)) => e,
+                };
+
+                let Some(mismatch) = error.downcast_ref::<HashMismatch>() else {
+                    if job.required {
+                        return Err(error).stage(Stage::Download);
+                    }
+                    log::warn!("Skipping optional asset {}: {:#}", job.name, error);
+                    continue;
+                };
+                if is_release {
+                    self.verify_release(&job.destination, manifest, Some(&mismatch.computed))?;
+                    self.discard_cached(manifest);
+                } else {
+                    self.reporter.event(Event::Verification {
+                        target: format!("asset:{}", job.name),
+                        passed: false,
+                    });
+                }
+                self.discard_download(&job.destination);
+                mismatched = true;
+                pending.push(job);
+            }
+
+            if attempt == 1 && mismatched {
+                log::warn!("Hash mismatch, downloading the affected files again from scratch");
+                self.reporter.say(strings::text("download.hash_retry"));
+            }
+            jobs = pending;
+        }
+
+        jobs.retain(|job| {
+            if !job.required {
+                log::warn!("Skipping optional asset {}, its hash does not match", job.name);
+            }
+            job.required
+        });
+        if jobs.is_empty() {
+            return Ok(());
+        }
+        let logs = paths::logs_dir().unwrap_or_default();
+        Err(InstallError::IntegrityCheckFailedAgain { logs }.into())
+    }
+
+    /// The optional components to install: those given with `--components`, else the ones
+    /// already installed, else the user's picks in interactive mode, else the defaults.
+    /// Required components are installed regardless.
+    fn select_components(&self, manifest: &Manifest, install_dir: &Path) -> Result<Vec<String>> {
+        if let Some(names) = &self.options.components {
+            return Ok(names.clone());
+        }
+        if manifest.components.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let installed = InstallReceipt::load(install_dir)
+            .ok()
+            .flatten()
+            .filter(|receipt| receipt.components.is_some());
+        if let Some(receipt) = installed {
+            let names = manifest.component_names();
+            let (kept, dropped): (Vec<String>, Vec<String>) = receipt
+                .component_names()
+                .into_iter()
+                .partition(|name| names.contains(&name.as_str()));
+            for name in dropped {
+                log::warn!("Installed component {} is no longer in the manifest", name);
+            }
+            log::info!("Keeping the installed components: {}", kept.join(", "));
+            return Ok(kept);
+        }
+
+        let mut selected = Vec::new();
+        for component in manifest.components.iter().filter(|component| !component.required) {
+            let wanted = if self.options.interactive && !self.options.dry_run {
+                prompt::confirm(
+                    &strings::format(
+                        "components.confirm",
+                        &[&component.name, &component.description],
+                    ),
+                    component.default,
+                )?
+            } else {
+                component.default
+            };
+            if wanted {
+                selected.push(component.name.clone());
+            }
+        }
+        log::info!("Selected components: {}", selected.join(", "));
+        Ok(selected)
+    }
+
+    /// The patch from the installed version to `manifest`, if a delta update can be used:
+    /// online and not forced, the receipt's version has a patch, every file has a hash to
+    /// check the result against, no release signature would go unchecked, and the same
+    /// components stay installed, none of them with a zip of its own.
+    fn usable_patch<'m>(&self, manifest: &'m Manifest, install_dir: &Path) -> Option<&'m Patch> {
+        if manifest.patches.is_empty() || self.options.offline.is_some() || self.options.force {
+            return None;
+        }
+        let receipt = InstallReceipt::load(install_dir).ok().flatten()?;
+        let installed = receipt.version.clone();
+        let patch = manifest
+            .patches
+            .iter()
+            .find(|patch| patch.from_version == installed)?;
+
+        let mut selected: Vec<&str> = manifest
+            .components
+            .iter()
+            .filter(|component| component.selected)
+            .map(|component| component.name.as_str())
+            .collect();
+        let mut had = receipt.component_names();
+        selected.sort_unstable();
+        had.sort_unstable();
+        if selected != had
+            || manifest
+                .components
+                .iter()
+                .any(|component| component.selected && component.release_zip_url.is_some())
+        {
+            log::info!("Not using the patch from {}: the components change, or come as zips", installed);
+            return None;
+        }
+
+        if manifest.files.is_empty() || manifest.files.iter().any(|file| file.sha256.is_none()) {
+            log::info!("Not using the patch from {}: the manifest has no hash for every file", installed);
+            return None;
+        }
+        if (manifest.release_zip_sig_url.is_some() || !trust::ALLOW_UNSIGNED)
+            && matches!(signature::signing_key(), Ok(Some(_)))
+        {
+            log::info!("Not using the patch from {}: only the full release is signed", installed);
+            return None;
+        }
+        Some(patch)
+    }
+
+    /// Builds the new version in `extract_dir` from a copy of the installed files and
+    /// `patch`, then checks every file against the manifest's hashes.
+    fn apply_delta(
+        &self,
+        patch: &Patch,
+        manifest: &Manifest,
+        install_dir: &Path,
+        staging_dir: &Path,
+        extract_dir: &Path,
+    ) -> Result<()> {
+        log::info!("Updating from {} to {} with a patch", patch.from_version, manifest.version);
+        self.reporter.say(&strings::format(
+            "patch.using",
+            &[&patch.from_version, &manifest.version],
+        ));
+
+        let patch_path = staging_dir.join("patch.zip");
+        let job = DownloadJob {
+            name: format!("patch from {}", patch.from_version),
+            urls: vec![patch.patch_url.as_str()],
+            sha256: patch.sha256.clone(),
+            destination: patch_path.clone(),
+            options: DownloadOptions {
+                show_progress: self.options.interactive,
+                connections: self.options.connections,
+                limit_rate: self.options.limit_rate,
+                expected_size: patch.size_bytes,
+                authorize: manifest.auth.is_some(),
+                group: None,
+            },
+            required: true,
+        };
+        let result = download_all(std::slice::from_ref(&job), 1, |job, options| {
+            self.download_artifact(job, options)
+        })?;
+        match result.into_iter().next().flatten() {
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e),
+            None => anyhow::bail!("The patch download did not start"),
+        }
+        cancel::check()?;
+
+        atomic::copy_dir_all(install_dir, extract_dir).context("Failed to copy the installed files")?;
+        let receipt = extract_dir.join(RECEIPT_FILE);
+        if receipt.exists() {
+            fs::remove_file(&receipt).context("Failed to remove the copied receipt")?;
+        }
+        patch::apply_archive(&patch_path, extract_dir, patch.algorithm)?;
+
+        self.verify_extracted_files(extract_dir, manifest)
+    }
+
+    /// A working directory for extraction on the install directory's volume, so the final
+    /// swap is a rename rather than a copy. Falls back to `%TEMP%` when nothing next to the
+    /// install directory is writable. The directory is deleted when the returned handle
+    /// drops; ones left behind by a killed run are removed here.
+    fn staging_dir(&self, install_dir: &Path) -> Result<tempfile::TempDir> {
+        // The install directory's parent may not exist yet; stage in its nearest ancestor.
+        let parent = install_dir
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.is_dir());
+
+        if let Some(parent) = parent {
+            remove_stale_staging(parent);
+            match tempfile::Builder::new()
+                .prefix(STAGING_PREFIX)
+                .tempdir_in(parent)
+            {
+                Ok(dir) => {
+                    log::info!("Staging the install in {:?}", dir.path());
+                    return Ok(dir);
+                }
+                Err(e) => log::warn!(
+                    "Cannot create a staging directory in {:?}, using the temp directory: {}",
+                    parent,
+                    e
+                ),
+            }
+        }
+
+        let dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        log::info!("Staging the install in {:?}", dir.path());
+        Ok(dir)
+    }
+
+    /// Checks the release zip's detached signature: from `release_zip_sig_url`, or for
+    /// offline installs a `.minisig` or `.sig` file next to the zip.
+    fn verify_release_signature(&self, zip_path: &Path, manifest: &Manifest) -> Result<()> {
+        let signature = match &self.options.offline {
+            Some(offline) => {
+                let local = [".minisig", ".sig"]
+                    .iter()
+                    .map(|extension| {
+                        let mut path = offline.zip.clone().into_os_string();
+                        path.push(extension);
+                        PathBuf::from(path)
+                    })
+                    .find(|path| path.is_file());
+                match (local, &manifest.release_zip_sig_url) {
+                    (Some(path), _) => Some(path.display().to_string()),
+                    (None, Some(_)) => {
+                        return Err(InstallError::SignatureFailed(format!(
+                            "the manifest lists a signature, but there is no {}.minisig or .sig",
+                            offline.zip.display()
+                        ))
+                        .into())
+                    }
+                    (None, None) => None,
+                }
+            }
+            None => manifest.release_zip_sig_url.clone(),
+        };
+        self.verify_signature("release", "the release", zip_path, signature.as_deref(), manifest)
+    }
+
+    /// Checks the detached signature at `signature` over `path`, which holds `what`,
+    /// against the built-in signing key, when there is one. Builds that do not allow
+    /// unsigned releases refuse a file without one, so a tampered manifest cannot get
+    /// anything installed by leaving its signature out. `target` names the check in
+    /// the report.
+    fn verify_signature(
+        &self,
+        target: &str,
+        what: &str,
+        path: &Path,
+        signature: Option<&str>,
+        manifest: &Manifest,
+    ) -> Result<()> {
+        let Some(signature) = signature else {
+            if !trust::ALLOW_UNSIGNED {
+                return Err(InstallError::SignatureFailed(format!(
+                    "{} is not signed, and this bootstrapper only installs signed releases",
+                    what
+                ))
+                .into());
+            }
+            return Ok(());
+        };
+
+        let Some(key) = signature::signing_key().stage(Stage::Verification)? else {
+            log::warn!("Not checking the signature of {}: this build has no signing key", what);
+            return Ok(());
+        };
+        cancel::check()?;
+
+        log::info!("Checking the signature of {} from {}", what, signature);
+        let text = self
+            .fetch_signature(signature, manifest)
+            .stage(Stage::Download)?;
+        signature::verify_file(&key, path, &text)
+            .map_err(|e| InstallError::SignatureFailed(format!("{}: {:#}", what, e)))?;
+
+        self.reporter.event(Event::Verification {
+            target: format!("{}_signature", target),
+            passed: true,
+        });
+        Ok(())
+    }
+
+    fn fetch_signature(&self, location: &str, manifest: &Manifest) -> Result<String> {
+        if let Some(path) = local_path(location) {
+            return fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read signature {}", path.display()));
+        }
+
+        let mut request = self.http.get(location)?;
+        if manifest.auth.is_some() {
+            request = self.http.authorize(request);
+        }
+        let response = self.http.send(request)?;
+        self.http.check_auth(&response, manifest.auth.is_some())?;
+        if !response.status().is_success() {
+            return Err(StatusError::new("Signature download", &response).into());
+        }
+        response.text().context("Failed to read the signature")
+    }
+
+    /// Assets can only be downloaded, so an offline install leaves out the optional ones
+    /// and refuses to go ahead without a required one.
+    fn skip_assets_offline(&self, manifest: &Manifest) -> Result<()> {
+        if let Some(asset) = manifest.assets.iter().find(|asset| asset.required) {
+            return Err(anyhow::anyhow!(
+                "Asset {} has to be downloaded, which an offline install does not do",
+                asset.name
+            ))
+            .stage(Stage::Download);
+        }
+        if !manifest.assets.is_empty() {
+            log::warn!(
+                "Offline install, leaving out {} optional assets",
+                manifest.assets.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs the manifest's post-install commands in order. A failure that is not ignored
+    /// stops the rest.
+    fn run_post_install(&self, install_dir: &Path, manifest: &Manifest) -> Result<()> {
+        for command in &manifest.post_install {
+            cancel::check()?;
+            self.reporter.say(&strings::format(
+                "post_install.running",
+                &[&post_install::describe(command)],
+            ));
+            match post_install::run(install_dir, command) {
+                Ok(()) => {}
+                Err(e) if command.ignore_failure => {
+                    log::warn!("Post-install command {} failed, ignoring it: {:#}", command.command, e);
+                }
+                Err(e) => {
+                    log::error!("Post-install command {} failed: {:#}", command.command, e);
+                    return Err(InstallError::PostInstallFailed {
+                        command: command.command.clone(),
+                        reason: format!("{:#}", e),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts the release's other archives, downloaded to `archive_dir`, each into its
+    /// `extract_to` directory.
+    fn extract_archives(&self, archive_dir: &Path, extract_dir: &Path, manifest: &Manifest) -> Result<()> {
+        for (i, archive) in manifest.archives.iter().enumerate() {
+            log::info!("Extracting archive {}", archive.url);
+            let target = match &archive.extract_to {
+                Some(dir) => extract_dir.join(dir),
+                None => extract_dir.to_path_buf(),
+            };
+            fs::create_dir_all(&target).context("Failed to create extraction directory")?;
+            let zip_path = archive_zip_path(archive_dir, i);
+            let what = format!("archive {}", archive.url);
+            self.verify_signature("archive", &what, &zip_path, archive.sig_url.as_deref(), manifest)?;
+            self.extract_zip(&zip_path, &target, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Extracts the downloaded zips of the selected components over the release files.
+    fn extract_components(&self, component_dir: &Path, extract_dir: &Path, manifest: &Manifest) -> Result<()> {
+        for component in &manifest.components {
+            if component.selected && component.release_zip_url.is_some() {
+                log::info!("Extracting component {}", component.name);
+                let zip_path = component_zip_path(component_dir, &component.name);
+                let what = format!("component {}", component.name);
+                let signature = component.release_zip_sig_url.as_deref();
+                self.verify_signature("component", &what, &zip_path, signature, manifest)?;
+                let skipped: Vec<&str> = manifest.other_arch_files.iter().map(String::as_str).collect();
+                self.extract_zip(&zip_path, extract_dir, &skipped)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the downloaded assets from `asset_dir` to their place among the extracted
+    /// files. Optional assets that failed to download are not there and are skipped.
+    fn place_assets(&self, asset_dir: &Path, extract_dir: &Path, manifest: &Manifest) -> Result<()> {
+        for asset in &manifest.assets {
+            let source = asset_dir.join(&asset.name);
+            if !source.is_file() {
+                continue;
+            }
+            let what = format!("asset {}", asset.name);
+            self.verify_signature("asset", &what, &source, asset.sig_url.as_deref(), manifest)?;
+            let target = extract_dir.join(&asset.name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).context("Failed to create asset directory")?;
+            }
+            fs::rename(&source, &target)
+                .with_context(|| format!("Failed to move asset {} into place", asset.name))?;
+            log::info!("Placed asset {}", asset.name);
+        }
+        Ok(())
+    }
+
+    /// Checks the size and SHA256 of the release zip at `zip_path`; `computed` is the
+    /// hash taken while downloading, if there is one.
+    fn verify_release(
+        &self,
+        zip_path: &Path,
+        manifest: &Manifest,
+        computed: Option<&str>,
+    ) -> Result<bool> {
+        self.check_download_size(zip_path, manifest)?;
+        let passed = match computed {
+            Some(computed) => sha256_matches(zip_path, computed, &manifest.sha256),
+            None => verify_sha256(zip_path, &manifest.sha256)
+                .context("ZIP file SHA256 verification failed")
+                .stage(Stage::Verification)?,
+        };
+        self.reporter.event(Event::Verification {
+            target: "release_zip".to_string(),
+            passed,
+        });
+        Ok(passed)
+    }
+
+    /// Removes a cached zip filed under the manifest's hash, so a retry cannot reuse it.
+    fn discard_cached(&self, manifest: &Manifest) {
+        if let Ok(Some(path)) = cache::entry_path(&manifest.sha256) {
+            if path.is_file() {
+                log::info!("Removing cached release {:?}", path);
+                if let Err(e) = fs::remove_file(&path) {
+                    log::warn!("Failed to remove cached release {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    /// What an install downloads: the release zip to `zip_path`, unless it came from the
+    /// cache, the other archives of the release to `archive_dir`, unless a patch is used,
+    /// every manifest asset below `asset_dir`, and the zips of the selected components to
+    /// `component_dir`.
+    fn download_jobs<'m>(
+        &self,
+        manifest: &'m Manifest,
+        zip_path: Option<&Path>,
+        archive_dir: Option<&Path>,
+        asset_dir: &Path,
+        component_dir: &Path,
+    ) -> Result<Vec<DownloadJob<'m>>> {
+        let authorize = manifest.auth.is_some();
+        if authorize && !self.http.has_auth_token() {
+            log::warn!("The manifest says the release needs an auth token, but none was given");
+        }
+        let options = |expected_size| DownloadOptions {
+            show_progress: self.options.interactive,
+            connections: self.options.connections,
+            limit_rate: self.options.limit_rate,
+            expected_size,
+            authorize,
+            group: None,
+        };
+
+        let mut jobs = Vec::new();
+        if let Some(zip_path) = zip_path {
+            jobs.push(DownloadJob {
+                name: format!("release {}", manifest.version),
+                urls: manifest.download_urls().collect(),
+                sha256: manifest.sha256.clone(),
+                destination: zip_path.to_path_buf(),
+                options: options(manifest.size_bytes),
+                required: true,
+            });
+        }
+        if let Some(archive_dir) = archive_dir.filter(|_| !manifest.archives.is_empty()) {
+            fs::create_dir_all(archive_dir)
+                .context("Failed to create archive directory")
+                .stage(Stage::Filesystem)?;
+            for (i, archive) in manifest.archives.iter().enumerate() {
+                jobs.push(DownloadJob {
+                    name: format!("archive {}", archive.url),
+                    urls: archive.download_urls().collect(),
+                    sha256: archive.sha256.clone(),
+                    destination: archive_zip_path(archive_dir, i),
+                    options: options(archive.size_bytes),
+                    required: true,
+                });
+            }
+        }
+        for asset in &manifest.assets {
+            let destination = asset_dir.join(&asset.name);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)
+                    .context("Failed to create asset directory")
+                    .stage(Stage::Filesystem)?;
+            }
+            jobs.push(DownloadJob {
+                name: asset.name.clone(),
+                urls: asset.download_urls().collect(),
+                sha256: asset.sha256.clone(),
+                destination,
+                options: options(asset.size_bytes),
+                required: asset.required,
+            });
+        }
+        for component in manifest.components.iter().filter(|component| component.selected) {
+            let (Some(url), Some(sha256)) = (&component.release_zip_url, &component.sha256) else {
+                continue;
+            };
+            fs::create_dir_all(component_dir)
+                .context("Failed to create component directory")
+                .stage(Stage::Filesystem)?;
+            jobs.push(DownloadJob {
+                name: format!("component {}", component.name),
+                urls: vec![url.as_str()],
+                sha256: sha256.clone(),
+                destination: component_zip_path(component_dir, &component.name),
+                options: options(component.size_bytes),
+                required: true,
+            });
+        }
+        Ok(jobs)
+    }
+
+    /// Downloads `job` from the first of its URLs that works.
+    async fn download_artifact(&self, job: &DownloadJob<'_>, options: DownloadOptions) -> Result<Downloaded> {
+        let mut last_error = None;
+
+        for url in &job.urls {
+            self.reporter.event(Event::DownloadStarted {
+                url: url.to_string(),
+            });
+            match async_download_file(&self.http, url, &job.destination, &options).await {
+                Ok(downloaded) => {
+                    self.report_download(url, &downloaded);
+                    return Ok(downloaded);
+                }
+                Err(e) if cancel::is_cancelled() => return Err(e),
+                Err(e) => {
+                    log::warn!("Download from {} failed: {:#}", url, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No download URL for {}", job.name)))
+    }
+
+    fn report_download(&self, url: &str, downloaded: &Downloaded) {
+        let seconds = downloaded.elapsed.as_secs_f64();
+        let average = per_second(downloaded.bytes as f64, seconds) as u64;
+        log::info!(
+            "Downloaded {} from {} in {:.0}s, avg {}/s, peak {}/s, {} retries",
+            format_bytes(downloaded.bytes),
+            url,
+            seconds,
+            format_bytes(average),
+            format_bytes(downloaded.peak_rate),
+            downloaded.retries
+        );
+
+        if self.options.verbose {
+            self.reporter.say(&strings::format(
+                "download.summary",
+                &[
+                    &format_bytes(downloaded.bytes),
+                    &format!("{:.0}", seconds),
+                    &format_bytes(average),
+                    &format_bytes(downloaded.peak_rate),
+                    &downloaded.retries,
+                ],
+            ));
+        }
+        self.reporter.event(Event::DownloadFinished {
+            url: url.to_string(),
+            bytes: downloaded.bytes,
+            elapsed_secs: seconds,
+            avg_bytes_per_sec: average,
+            peak_bytes_per_sec: downloaded.peak_rate,
+            retries: downloaded.retries,
+        });
+    }
+
+    /// Catches a zip of the wrong size before hashing, so it is reported as truncated or
+    /// altered rather than as a hash mismatch.
+    fn check_download_size(&self, zip_path: &Path, manifest: &Manifest) -> Result<()> {
+        let Some(expected) = manifest.size_bytes else {
+            return Ok(());
+        };
+
+        let received = fs::metadata(zip_path)
+            .context("Failed to read the downloaded release")
+            .stage(Stage::Filesystem)?
+            .len();
+        if received == expected {
+            return Ok(());
+        }
+
+        self.discard_download(zip_path);
+        if received < expected {
+            return Err(InstallError::Truncated { received, expected }.into());
+        }
+        Err(anyhow::anyhow!(
+            "Download truncated or altered (got {} bytes, expected {})",
+            received,
+            expected
+        ))
+        .stage(Stage::Verification)
+    }
+
+    /// Where the release zip of `manifest` is downloaded to, named after its hash so a
+    /// partial download is only ever resumed for the same release.
+    fn download_path(&self, manifest: &Manifest) -> Result<PathBuf> {
+        let dir = paths::downloads_dir()?;
+        fs::create_dir_all(&dir).context("Failed to create downloads directory")?;
+
+        let zip_path = dir.join(format!("{}.zip", manifest.sha256));
+        discard_stale_downloads(&dir, &zip_path)?;
+        Ok(zip_path)
+    }
+
+    /// A verified zip of this release from the download cache, unless --no-cache.
+    fn cached_release(&self, manifest: &Manifest) -> Option<PathBuf> {
+        if self.options.no_cache {
+            log::info!("Download cache disabled with --no-cache");
+            return None;
+        }
+
+        let cached = cache::lookup(&manifest.sha256)?;
+        log::info!("Installing from cached download {:?}", cached);
+        self.reporter
+            .say(&strings::format("cache.hit", &[&manifest.version]));
+        Some(cached)
+    }
+
+    /// After a successful install, moves a fresh download into the cache (or deletes it
+    /// with --no-cache). Failures are only logged.
+    fn keep_download(&self, zip_path: &Path, manifest: &Manifest) {
+        if self.options.offline.is_some() {
+            return;
+        }
+        if self.options.no_cache {
+            self.discard_download(zip_path);
+            return;
+        }
+        match cache::entry_path(&manifest.sha256) {
+            // Installed straight from the cache.
+            Ok(Some(entry)) if entry == zip_path => return,
+            Ok(Some(_)) => {}
+            _ => {
+                self.discard_download(zip_path);
+                return;
+            }
+        }
+
+        if let Err(e) = cache::store(zip_path, &manifest.sha256, self.options.cache_max_size) {
+            log::warn!("Failed to cache the release download: {:#}", e);
+            self.discard_download(zip_path);
         }
+    }
 
-        Ok(Self {
-            manifest_url,
-            mode,
-            build_dir,
-            dry_run,
-            no_shortcut,
-        })
+    /// Deletes a downloaded release zip; offline zips belong to the user and are left alone.
+    fn discard_download(&self, zip_path: &Path) {
+        if self.options.offline.is_some() {
+            return;
+        }
+        if let Err(e) = fs::remove_file(zip_path) {
+            log::warn!("Failed to remove downloaded release {:?}: {}", zip_path, e);
+        }
     }
 
-    pub fn run(&self) -> Result<()> {
-        log::info!("Starting installation process");
+    /// Drops this run's backup without --keep-backup and prunes backups beyond the retention count.
+    ///
+    /// The install has already succeeded, so failures here are only logged.
+    fn clean_up_backups(&self, install_dir: &Path, backup: Option<&Path>) {
+        if let Some(backup) = backup.filter(|_| !self.options.keep_backup) {
+            match fs::remove_dir_all(backup) {
+                Ok(()) => log::info!("Removed backup {:?}", backup),
+                Err(e) => log::warn!("Failed to remove backup {:?}: {}", backup, e),
+            }
+        }
 
-        let manifest = Manifest::from_url(&self.manifest_url)?;
-        manifest.check_prerequisites()?;
+        if let Err(e) = backup::prune_backups(install_dir, self.options.backup_retention) {
+            log::warn!("Failed to prune old backups: {:#}", e);
+        }
+    }
 
-        let install_dir = self.get_install_directory()?;
-        log::info!("Install directory: {:?}", install_dir);
+    /// Starts the installed exe with --launch, or after asking when interactive.
+    fn offer_launch(&self, install_dir: &Path, product: &Product) -> Result<()> {
+        let wanted = self.options.launch
+            || (self.options.interactive
+                && prompt::confirm(&strings::format("launch.confirm", &[&product.display_name]), false)?);
+        if !wanted {
+            return Ok(());
+        }
 
-        if self.dry_run {
-            log::info!("DRY RUN: Would download from {}", manifest.release_zip_url);
-            log::info!("DRY RUN: Would install to {:?}", install_dir);
+        let exe_path = install_dir.join(&product.executable);
+        if !exe_path.exists() {
+            log::warn!("{} not found, not launching", product.executable);
+            self.reporter
+                .say(&strings::format("launch.missing", &[&product.executable]));
             return Ok(());
         }
 
-        let temp_dir = tempfile::tempdir()
-            .context("Failed to create temporary directory")?;
+        if let Err(e) = launch(&exe_path, install_dir) {
+            log::warn!("{:#}", e);
+            self.reporter
+                .say(&strings::format("launch.failed", &[&format!("{:#}", e)]));
+        }
+        Ok(())
+    }
+
+    /// Shows the manifest's EULA and has the user accept it, unless the installed release
+    /// recorded accepting the same text. Nothing is written before it is accepted.
+    fn accept_eula(&self, manifest: &Manifest, install_dir: &Path) -> Result<Option<AcceptedEula>> {
+        let Some(text) = eula::fetch(&self.http, manifest).stage(Stage::Manifest)? else {
+            return Ok(None);
+        };
+        let sha256 = eula::sha256(&text);
+
+        let previous = InstallReceipt::load(install_dir)
+            .ok()
+            .flatten()
+            .and_then(|receipt| receipt.eula)
+            .filter(|accepted| accepted.sha256 == sha256);
+        if let Some(previous) = previous {
+            log::info!("EULA {} was accepted on {}", sha256, previous.accepted_at);
+            return Ok(Some(previous));
+        }
+
+        if self.options.accept_eula {
+            log::info!("EULA {} accepted with --accept-eula", sha256);
+        } else if self.options.dry_run {
+            self.reporter.say(strings::text("eula.would_ask"));
+            return Ok(None);
+        } else if !self.options.interactive {
+            return Err(InstallError::EulaNotAccepted.into());
+        } else if eula::ask(&text)? {
+            log::info!("EULA {} accepted", sha256);
+        } else {
+            log::info!("EULA {} declined", sha256);
+            return Err(InstallError::EulaDeclined.into());
+        }
 
-        let zip_path = temp_dir.path().join("release.zip");
-        download_file(&manifest.release_zip_url, &zip_path)?;
+        Ok(Some(AcceptedEula {
+            sha256,
+            accepted_at: chrono::Local::now().to_rfc3339(),
+        }))
+    }
 
-        verify_sha256(&zip_path, &manifest.sha256)
-            .context("ZIP file SHA256 verification failed")?
-            .then_some(())
-            .ok_or_else(|| anyhow::anyhow!("ZIP file integrity check failed"))?;
+    /// Shows the release notes before an update to another version; unattended runs only
+    /// log them. Notes that cannot be fetched are logged and skipped.
+    fn show_release_notes(&self, manifest: &Manifest, install_dir: &Path) {
+        let installed = InstallReceipt::load(install_dir).ok().flatten().map(|receipt| receipt.version);
+        if installed.is_none() || installed.as_ref() == Some(&manifest.version) {
+            return;
+        }
+        let text = match release_notes::fetch(&self.http, manifest) {
+            Ok(Some(text)) => text,
+            Ok(None) => return,
+            Err(e) => {
+                log::warn!("Could not get the release notes, continuing without them: {:#}", e);
+                return;
+            }
+        };
 
-        let extract_dir = temp_dir.path().join("extracted");
-        fs::create_dir_all(&extract_dir)?;
-        self.extract_zip(&zip_path, &extract_dir)?;
+        let lines = release_notes::render(&text);
+        if self.options.interactive {
+            self.reporter
+                .say(&format!("\n{}", strings::format("notes.title", &[&manifest.version])));
+            for line in &lines {
+                self.reporter.say(line);
+            }
+            self.reporter.say("");
+        } else {
+            log::info!("Release notes for {}:", manifest.version);
+            for line in &lines {
+                log::info!("  {}", line);
+            }
+        }
+    }
 
-        self.verify_extracted_files(&extract_dir, &manifest)?;
+    fn check_license(&self, manifest: &Manifest) -> Result<()> {
+        let Some(url) = &manifest.license_check_url else {
+            return Ok(());
+        };
+        if self.options.dry_run {
+            self.reporter.say(&strings::format("license.would_check", &[url]));
+            return Ok(());
+        }
+        license::ensure_licensed(
+            &self.http,
+            url,
+            manifest.version.as_str(),
+            self.options.license_key.as_ref(),
+            self.options.interactive,
+        )
+    }
 
-        let atomic_installer = AtomicInstaller::new(&install_dir)?;
-        atomic_installer.install(&extract_dir)?;
+    fn check_prerequisites(&self, manifest: &Manifest) -> Result<()> {
+        if !self.options.skip_prereq_check {
+            return manifest
+                .check_prerequisites(self.options.arch.as_str(), Some(self))
+                .stage(Stage::Prerequisites);
+        }
 
-        if !self.no_shortcut {
-            self.create_shortcuts(&install_dir)?;
+        log::warn!("!!! Prerequisite checks bypassed with --skip-prereq-check !!!");
+        let failure = manifest
+            .check_prerequisites(self.options.arch.as_str(), None)
+            .err()
+            .map(|e| format!("{:#}", e));
+        match &failure {
+            Some(failure) => {
+                log::warn!("Ignoring failed prerequisite check: {}", failure);
+                self.reporter
+                    .say(&strings::format("prereq.skipped_failure", &[failure]));
+            }
+            None => self.reporter.say(strings::text("prereq.skipped")),
         }
+        self.reporter.event(Event::PrerequisitesSkipped { failure });
 
-        log::info!("Installation completed successfully");
         Ok(())
     }
 
     fn get_install_directory(&self) -> Result<PathBuf> {
         match &self.mode {
-            InstallMode::Standard => {
-                let base = directories::BaseDirs::new()
-                    .ok_or_else(|| anyhow::anyhow!("Failed to get base directories"))?
-                    .data_local_dir()
-                    .join("paradise")
-                    .join("appfolder");
-                Ok(base)
-            }
+            InstallMode::Standard => paths::standard_install_dir(),
             InstallMode::Specific => {
                 let dir = self
                     .build_dir
@@ -101,7 +1846,297 @@ impl Installer {
         }
     }
 
-    fn extract_zip(&self, zip_path: &Path, extract_dir: &Path) -> Result<()> {
+    fn print_plan(
+        &self,
+        manifest: &Manifest,
+        channel: &str,
+        install_dir: &Path,
+        preflight: Option<&Preflight>,
+    ) -> Result<()> {
+        let (download_url, size) = match &self.options.offline {
+            Some(offline) => (
+                offline.zip.display().to_string(),
+                fs::metadata(&offline.zip).ok().map(|metadata| metadata.len()),
+            ),
+            None => (manifest.release_zip_url.clone(), manifest.size_bytes),
+        };
+        let patch = self.usable_patch(manifest, install_dir);
+        log::info!("DRY RUN: Would download from {}", download_url);
+        log::info!("DRY RUN: Would install to {:?}", install_dir);
+
+        let shortcuts: Vec<PathBuf> = self
+            .planned_shortcuts(install_dir, manifest)?
+            .into_iter()
+            .map(|shortcut| shortcut.path)
+            .collect();
+
+        let reporter = &self.reporter;
+        reporter.say("dry run, nothing will be downloaded or written");
+        reporter.say(&format!("version: {} ({})", manifest.version, channel));
+        reporter.say(&format!("architecture: {}", self.options.arch));
+        reporter.say(&format!("download url: {}", download_url));
+        match size {
+            Some(bytes) => reporter.say(&format!(
+                "download size: {} bytes ({:.1} MiB)",
+                bytes,
+                bytes as f64 / (1024.0 * 1024.0)
+            )),
+            None => reporter.say("download size: unknown"),
+        }
+        if !manifest.archives.is_empty() {
+            reporter.say("further archives:");
+            for archive in &manifest.archives {
+                let into = archive.extract_to.as_deref().unwrap_or(".");
+                reporter.say(&format!("  {} into {}", archive.url, into));
+            }
+        }
+        match patch {
+            Some(patch) => {
+                let size = patch
+                    .size_bytes
+                    .map(|bytes| format!("{} bytes ({:.1} MiB)", bytes, bytes as f64 / (1024.0 * 1024.0)))
+                    .unwrap_or_else(|| "unknown size".to_string());
+                reporter.say(&format!(
+                    "update: delta from {}, patch {} from {}, full download if it fails",
+                    patch.from_version, size, patch.patch_url
+                ));
+            }
+            None => reporter.say("update: full download"),
+        }
+        if let Some(preflight) = preflight {
+            reporter.say(&format!(
+                "release url check: {}, range requests {}",
+                preflight.status,
+                if preflight.accepts_ranges { "supported" } else { "not supported" }
+            ));
+        }
+        reporter.say(&format!("install directory: {}", install_dir.display()));
+        match manifest.close_processes.is_empty() {
+            true => reporter.say("programs that must be closed first: those running from the install directory"),
+            false => reporter.say(&format!(
+                "programs that must be closed first: those running from the install directory and {}",
+                manifest.close_processes.join(", ")
+            )),
+        }
+        reporter.say("files:");
+        for file_entry in &manifest.files {
+            match &file_entry.target {
+                Some(target) => reporter.say(&format!("  {} -> {}", file_entry.name, target)),
+                None => reporter.say(&format!("  {}", file_entry.name)),
+            }
+        }
+        if !manifest.components.is_empty() {
+            reporter.say("components:");
+            for component in &manifest.components {
+                let kind = match (component.required, component.selected) {
+                    (true, _) => "required",
+                    (false, true) => "selected",
+                    (false, false) => "not selected",
+                };
+                reporter.say(&format!("  {} ({}) {}", component.name, kind, component.description));
+            }
+        }
+        if !manifest.assets.is_empty() {
+            reporter.say("assets:");
+            for asset in &manifest.assets {
+                let kind = if asset.required { "required" } else { "optional" };
+                reporter.say(&format!("  {} ({}) from {}", asset.name, kind, asset.url));
+            }
+        }
+        if !manifest.post_install.is_empty() {
+            reporter.say("post-install commands (not run):");
+            for command in &manifest.post_install {
+                reporter.say(&format!("  {}", post_install::describe(command)));
+            }
+        }
+        for path in &shortcuts {
+            reporter.say(&format!("shortcut: {}", path.display()));
+        }
+        if shortcuts.is_empty() {
+            reporter.say("shortcut: none");
+        }
+
+        reporter.event(Event::Plan {
+            version: manifest.version.to_string(),
+            channel: channel.to_string(),
+            download_url,
+            download_size: size,
+            delta_from: patch.map(|patch| patch.from_version.to_string()),
+            patch_size: patch.and_then(|patch| patch.size_bytes),
+            url_status: preflight.map(|preflight| preflight.status.as_u16()),
+            accepts_ranges: preflight.map(|preflight| preflight.accepts_ranges),
+            install_dir: install_dir.to_path_buf(),
+            files: manifest.files.iter().map(|f| f.installed_name().to_string()).collect(),
+            components: manifest
+                .components
+                .iter()
+                .filter(|component| component.selected)
+                .map(|component| component.name.clone())
+                .collect(),
+            shortcut: shortcuts.first().cloned(),
+            shortcuts,
+        });
+
+        Ok(())
+    }
+
+    /// Checks the release URL before anything is written, so a manifest pointing at a
+    /// missing zip fails straight away. Skipped for offline and cached installs.
+    fn check_release_url(&self, manifest: &Manifest) -> Result<Option<Preflight>> {
+        if self.options.offline.is_some() {
+            return Ok(None);
+        }
+        if self.is_cached(manifest) {
+            log::info!("Release is in the download cache, not checking its URL");
+            return Ok(None);
+        }
+
+        let url = &manifest.release_zip_url;
+        let result = match preflight(&self.http, url, manifest.auth.is_some()) {
+            Ok(result) => result,
+            Err(e) if e.downcast_ref::<AuthError>().is_some() => return Err(e).stage(Stage::Download),
+            Err(e) => {
+                log::warn!("Could not check the release URL, continuing: {:#}", e);
+                return Ok(None);
+            }
+        };
+
+        if result.is_missing() {
+            if manifest.mirrors.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Release zip {} does not exist (server answered {}), check release_zip_url in the manifest {}",
+                    url,
+                    result.status,
+                    self.manifest_source()
+                ))
+                .stage(Stage::Download);
+            }
+            log::warn!("Release zip {} answered {}, relying on the mirrors", url, result.status);
+            return Ok(None);
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Whether the release zip is in the download cache and will not be downloaded.
+    fn is_cached(&self, manifest: &Manifest) -> bool {
+        !self.options.no_cache
+            && cache::entry_path(&manifest.sha256)
+                .ok()
+                .flatten()
+                .is_some_and(|path| path.is_file())
+    }
+
+    /// Checks every volume the install writes to before anything is downloaded: the
+    /// download directory takes the zip, the staging directory the extracted files and
+    /// assets, and the install directory a copy of them when it is on another volume.
+    fn check_disk_space(
+        &self,
+        install_dir: &Path,
+        staging_dir: &Path,
+        manifest: &Manifest,
+    ) -> Result<()> {
+        let Some(installed) = manifest.installed_size(self.options.expansion_factor) else {
+            log::info!("Manifest does not declare a size, skipping disk space check");
+            return Ok(());
+        };
+
+        // (volume, a directory on it, bytes needed there)
+        let mut needs: Vec<(String, PathBuf, u64)> = Vec::new();
+        let mut need = |path: &Path, bytes: u64| {
+            let volume = volume_key(path);
+            match needs.iter_mut().find(|(key, _, _)| *key == volume) {
+                Some((_, _, total)) => *total += bytes,
+                None => needs.push((volume, path.to_path_buf(), bytes)),
+            }
+        };
+
+        let downloads = self.options.offline.is_none() && !self.is_cached(manifest);
+        if let (true, Some(zip)) = (downloads, manifest.size_bytes) {
+            need(&paths::downloads_dir().stage(Stage::Filesystem)?, zip);
+        }
+        let archives: u64 = manifest.archives.iter().filter_map(|archive| archive.size_bytes).sum();
+        if archives > 0 {
+            need(staging_dir, archives);
+        }
+        need(staging_dir, installed);
+        if volume_key(install_dir) != volume_key(staging_dir) {
+            need(install_dir, installed);
+        }
+
+        let mut shortfalls = Vec::new();
+        for (_, path, required) in needs {
+            let available = free_space(&path).stage(Stage::Filesystem)?;
+            log::info!(
+                "Disk space for {:?}: {} required, {} available",
+                path,
+                format_bytes(required),
+                format_bytes(available)
+            );
+            if available < required {
+                shortfalls.push(SpaceShortfall {
+                    path,
+                    required,
+                    available,
+                });
+            }
+        }
+
+        if !shortfalls.is_empty() {
+            return Err(InstallError::InsufficientSpace(shortfalls).into());
+        }
+        Ok(())
+    }
+
+    fn confirm_replace(&self, install_dir: &Path, manifest: &Manifest) -> Result<bool> {
+        let has_existing = fs::read_dir(install_dir)
+            .map(|mut entries| entries.next().is_some())
+            .unwrap_or(false);
+
+        if !has_existing {
+            return Ok(true);
+        }
+
+        let installed_version = match InstallReceipt::load(install_dir) {
+            Ok(receipt) => receipt.map(|r| r.version),
+            Err(e) => {
+                log::warn!("Ignoring unreadable install receipt: {:#}", e);
+                None
+            }
+        };
+
+        if let Some(installed) = &installed_version {
+            log::info!("Installed version: {}, available: {}", installed, manifest.version);
+            self.reporter
+                .say(&strings::format("versions", &[installed, &manifest.version]));
+        }
+
+        if !self.options.interactive {
+            log::info!(
+                "Existing installation at {:?} will be replaced (a backup is kept)",
+                install_dir
+            );
+            return Ok(true);
+        }
+
+        match installed_version {
+            Some(installed) if installed == manifest.version && self.options.force => Ok(true),
+            Some(installed) if installed == manifest.version => {
+                prompt::confirm(strings::text("replace.reinstall"), false)
+            }
+            Some(_) => prompt::confirm(
+                &strings::format("replace.update", &[&manifest.version]),
+                true,
+            ),
+            None => prompt::confirm(
+                &strings::format("replace.existing", &[&install_dir.display()]),
+                true,
+            ),
+        }
+    }
+
+    /// Extracts `zip_path` into `extract_dir`, leaving out the files named in `skipped`.
+    fn extract_zip(&self, zip_path: &Path, extract_dir: &Path, skipped: &[&str]) -> Result<()> {
         log::info!("Extracting ZIP to {:?}", extract_dir);
 
         let file = fs::File::open(zip_path)
@@ -110,11 +2145,26 @@ impl Installer {
         let mut archive = zip::ZipArchive::new(file)
             .context("Failed to read ZIP archive")?;
 
+        let started = Instant::now();
+        let (mut files, mut bytes) = (0, 0);
         for i in 0..archive.len() {
+            cancel::check()?;
             let mut file = archive.by_index(i)
                 .context("Failed to read file from ZIP")?;
 
-            let outpath = extract_dir.join(file.mangled_name());
+            let name = file.mangled_name();
+            let entry = name.to_string_lossy().replace('\\', "/");
+            if skipped.contains(&entry.as_str()) {
+                log::debug!("Skipping entry {:?} of an unselected component or another architecture", file.name());
+                continue;
+            }
+            let outpath = extract_dir.join(name);
+            log::debug!(
+                "Extracting entry {}: {:?} ({} bytes)",
+                i,
+                file.name(),
+                file.size()
+            );
 
             if file.is_dir() {
                 fs::create_dir_all(&outpath)?;
@@ -124,57 +2174,538 @@ impl Installer {
                 }
                 let mut outfile = fs::File::create(&outpath)
                     .context("Failed to create extracted file")?;
-                std::io::copy(&mut file, &mut outfile)
+                bytes += std::io::copy(&mut file, &mut outfile)
                     .context("Failed to write extracted file")?;
+                files += 1;
             }
         }
 
-        log::info!("ZIP extraction completed");
+        let seconds = started.elapsed().as_secs_f64();
+        let files_per_sec = per_second(files as f64, seconds);
+        let bytes_per_sec = per_second(bytes as f64, seconds) as u64;
+        log::info!(
+            "ZIP extraction completed: {} files ({}) in {:.1}s, {:.0} files/s, {}/s",
+            files,
+            format_bytes(bytes),
+            seconds,
+            files_per_sec,
+            format_bytes(bytes_per_sec)
+        );
+        if self.options.verbose {
+            self.reporter.say(&strings::format(
+                "extract.summary",
+                &[
+                    &files,
+                    &format_bytes(bytes),
+                    &format!("{:.1}", seconds),
+                    &format!("{:.0}", files_per_sec),
+                    &format_bytes(bytes_per_sec),
+                ],
+            ));
+        }
+        self.reporter.event(Event::Extracted {
+            files,
+            bytes,
+            elapsed_secs: seconds,
+            files_per_sec,
+            bytes_per_sec,
+        });
         Ok(())
     }
 
     fn verify_extracted_files(&self, extract_dir: &Path, manifest: &Manifest) -> Result<()> {
         log::info!("Checking extracted files");
 
+        let mut present = Vec::new();
         for file_entry in &manifest.files {
-            let file_path = extract_dir.join(&file_entry.name);
-            
-            if !file_path.exists() {
-                anyhow::bail!("Required file not found in archive: {}", file_entry.name);
+            let file_path = extract_dir.join(file_entry.installed_name());
+
+            if file_path.exists() {
+                present.push(file_entry);
+            } else if file_entry.required {
+                return Err(InstallError::MissingFile(file_entry.installed_name().to_string()).into());
+            } else {
+                log::info!("Optional file {} is not in the release", file_entry.installed_name());
             }
         }
-
         log::info!("All required files found");
-        Ok(())
-    }
 
-    fn create_shortcuts(&self, install_dir: &Path) -> Result<()> {
-        let exe_path = install_dir.join("paradise.exe");
-        
-        if !exe_path.exists() {
-            log::warn!("paradise.exe not found, skipping shortcut creation");
-            return Ok(());
+        let mut corrupted = Vec::new();
+        for file_entry in present {
+            let Some(expected) = &file_entry.sha256 else {
+                continue;
+            };
+            cancel::check()?;
+            let name = file_entry.installed_name();
+            let passed = verify_sha256(&extract_dir.join(name), expected)
+                .with_context(|| format!("Failed to hash extracted file {}", name))
+                .stage(Stage::Verification)?;
+            self.reporter.event(Event::Verification {
+                target: format!("file:{}", name),
+                passed,
+            });
+            if !passed {
+                corrupted.push(name.to_string());
+            }
+        }
+        if !corrupted.is_empty() {
+            return Err(InstallError::CorruptedFiles(corrupted).into());
         }
 
+        Ok(())
+    }
+
+    fn shortcut_path(&self, install_dir: &Path, product: &Product) -> Result<Option<PathBuf>> {
+        let name = format!("{}.lnk", product.display_name);
         match &self.mode {
             InstallMode::Standard => {
+                if self.options.no_desktop_shortcut {
+                    return Ok(None);
+                }
+
                 let desktop = directories::UserDirs::new()
                     .and_then(|d| d.desktop_dir().map(|p| p.to_path_buf()))
                     .ok_or_else(|| anyhow::anyhow!("Failed to get desktop directory"))?;
 
-                let shortcut_path = desktop.join("paradise.lnk");
-                create_shortcut(&exe_path, &shortcut_path)?;
-                log::info!("Desktop shortcut created: {:?}", shortcut_path);
+                Ok(Some(desktop.join(name)))
             }
             InstallMode::Specific => {
-                let shortcut_path = install_dir.join("paradise.lnk");
-                create_shortcut(&exe_path, &shortcut_path)?;
-                log::info!("Shortcut created in build directory: {:?}", shortcut_path);
+                if self.options.no_install_dir_shortcut {
+                    return Ok(None);
+                }
+
+                Ok(Some(install_dir.join(name)))
+            }
+        }
+    }
+
+    /// The shortcuts an install creates: those the manifest lists, or one to the product's
+    /// executable for the install mode when it lists none, less the locations turned off.
+    fn planned_shortcuts(&self, install_dir: &Path, manifest: &Manifest) -> Result<Vec<PlannedShortcut>> {
+        let Some(entries) = &manifest.shortcuts else {
+            let product = &manifest.product;
+            return Ok(self
+                .shortcut_path(install_dir, product)?
+                .into_iter()
+                .map(|path| PlannedShortcut {
+                    path,
+                    target: install_dir.join(product.executable.replace('/', "\\")),
+                    arguments: None,
+                    icon: product.icon_path.as_ref().map(|icon| (install_dir.join(icon.replace('/', "\\")), 0)),
+                    description: Some(product.display_name.clone()),
+                })
+                .collect());
+        };
+
+        let mut planned = Vec::new();
+        for entry in entries {
+            let dir = match entry.location {
+                ShortcutLocation::Desktop if self.options.no_desktop_shortcut => continue,
+                ShortcutLocation::InstallDir if self.options.no_install_dir_shortcut => continue,
+                // Only --no-shortcut turns off both, and with them the Start Menu.
+                ShortcutLocation::StartMenu
+                    if self.options.no_desktop_shortcut && self.options.no_install_dir_shortcut =>
+                {
+                    continue
+                }
+                ShortcutLocation::Desktop => directories::UserDirs::new()
+                    .and_then(|d| d.desktop_dir().map(|p| p.to_path_buf()))
+                    .ok_or_else(|| anyhow::anyhow!("Failed to get desktop directory"))?,
+                ShortcutLocation::StartMenu => directories::BaseDirs::new()
+                    .map(|d| d.config_dir().join(r"Microsoft\Windows\Start Menu\Programs"))
+                    .ok_or_else(|| anyhow::anyhow!("Failed to get Start Menu directory"))?,
+                ShortcutLocation::InstallDir => install_dir.to_path_buf(),
+            };
+            planned.push(PlannedShortcut {
+                path: dir.join(format!("{}.lnk", entry.name)),
+                target: install_dir.join(entry.target.replace('/', "\\")),
+                arguments: (!entry.args.is_empty()).then(|| post_install::join_args(&entry.args)),
+                icon: entry
+                    .icon
+                    .as_ref()
+                    .map(|icon| (install_dir.join(icon.replace('/', "\\")), entry.icon_index)),
+                description: entry.description.clone(),
+            });
+        }
+        Ok(planned)
+    }
+
+    /// Creates the planned shortcuts. When one fails, those created before it are deleted
+    /// again, except the ones an earlier install had in the same place, `previous`.
+    fn create_shortcuts(&self, install_dir: &Path, manifest: &Manifest, previous: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let planned = self.planned_shortcuts(install_dir, manifest)?;
+        if planned.is_empty() {
+            log::info!("Shortcut creation disabled, skipping");
+            return Ok(Vec::new());
+        }
+
+        let mut created = Vec::new();
+        for shortcut in planned {
+            if !shortcut.target.exists() {
+                log::warn!("{} not found, skipping shortcut {:?}", shortcut.target.display(), shortcut.path);
+                continue;
+            }
+            if let Some(dir) = shortcut.path.parent() {
+                if let Err(e) = fs::create_dir_all(dir) {
+                    remove_shortcuts(&new_paths(&created, previous));
+                    return Err(e).with_context(|| format!("Failed to create {}", dir.display()));
+                }
+            }
+
+            let options = ShortcutOptions {
+                arguments: shortcut.arguments.as_deref(),
+                icon: shortcut.icon.as_ref().map(|(icon, index)| (icon.as_path(), *index)),
+                description: shortcut.description.as_deref(),
+            };
+            if let Err(e) = create_shortcut(&shortcut.target, &shortcut.path, &options) {
+                remove_shortcuts(&new_paths(&created, previous));
+                return Err(e);
+            }
+            log::info!("Shortcut created: {:?}", shortcut.path);
+            self.reporter.event(Event::ShortcutCreated {
+                path: shortcut.path.clone(),
+            });
+            created.push(shortcut.path);
+        }
+
+        Ok(created)
+    }
+
+    /// Makes sure no program holds files of the install open before it is replaced: ends
+    /// them with --kill-running, when the user agrees, or unattended when the manifest
+    /// allows it, and otherwise waits for them to close. Fails if they keep running.
+    fn close_running_programs(&self, install_dir: &Path, manifest: &Manifest) -> Result<()> {
+        let names = &manifest.close_processes;
+        let running = processes::find(install_dir, names).stage(Stage::Filesystem)?;
+        if running.is_empty() {
+            return Ok(());
+        }
+
+        let described: Vec<String> = running.iter().map(|process| process.describe()).collect();
+        log::warn!("Programs keep files of the install open: {}", described.join(", "));
+        self.reporter
+            .say(&strings::format("processes.running", &[&described.join(", ")]));
+        let end = processes::should_end(
+            self.options.kill_running,
+            self.options.interactive,
+            manifest.kill_running_unattended,
+            || prompt::confirm(strings::text("processes.confirm_close"), false),
+        )?;
+        if end {
+            for process in &running {
+                match processes::terminate(process) {
+                    Ok(()) => {
+                        log::info!("Ended {}", process.describe());
+                        self.reporter
+                            .say(&strings::format("processes.ended", &[&process.describe()]));
+                    }
+                    Err(e) => log::warn!("{:#}", e),
+                }
             }
+        } else {
+            self.reporter.say(strings::text("processes.waiting"));
         }
 
+        let still_running = processes::wait_for_exit(install_dir, names, PROCESS_EXIT_TIMEOUT)
+            .stage(Stage::Filesystem)?;
+        if !still_running.is_empty() {
+            let described = still_running.iter().map(|process| process.describe()).collect();
+            return Err(InstallError::ProcessesRunning(described).into());
+        }
         Ok(())
     }
+
+    /// Writes the manifest's registry values, and finds those an earlier install wrote
+    /// that this release no longer has.
+    fn write_registry(
+        &self,
+        install_dir: &Path,
+        manifest: &Manifest,
+        previous: &[WrittenRegistryValue],
+    ) -> Result<RegistryChanges> {
+        let undo = registry::write_all(&manifest.registry, install_dir, &manifest.product).stage(Stage::Filesystem)?;
+        let mut values = undo.clone();
+        for value in &mut values {
+            // A key an earlier install created is still the installer's to remove, and
+            // what it overwrote is still what uninstall puts back; the data found now is
+            // that install's own.
+            if let Some(old) = previous.iter().find(|old| same_registry_value(old, value)) {
+                value.created_key |= old.created_key;
+                value.previous = old.previous.clone();
+            }
+        }
+        let stale = previous
+            .iter()
+            .filter(|old| !values.iter().any(|value| same_registry_value(old, value)))
+            .cloned()
+            .collect();
+        Ok(RegistryChanges { values, undo, stale })
+    }
+
+    /// Adds the manifest's PATH entries and variables, undoing what an earlier install
+    /// added that this release no longer wants. With `--no-env` nothing changes and what
+    /// an earlier install added stays recorded, so uninstall still takes it out.
+    fn register_environment(
+        &self,
+        install_dir: &Path,
+        manifest: &Manifest,
+        previous: Option<InstalledEnvironment>,
+    ) -> Option<InstalledEnvironment> {
+        if self.options.no_env {
+            if manifest.environment.is_some() {
+                log::info!("Leaving the environment alone (--no-env)");
+            }
+            return previous;
+        }
+        let Some(wanted) = &manifest.environment else {
+            if let Some(previous) = &previous {
+                environment::unregister(previous);
+            }
+            return None;
+        };
+
+        let installed = environment::register(wanted, install_dir, previous.as_ref());
+        for dir in &installed.path {
+            self.reporter.say(&strings::format("env.path_added", &[dir]));
+        }
+        for set in &installed.variables {
+            self.reporter.say(&strings::format("env.variable_set", &[&set.name]));
+        }
+        Some(installed)
+    }
+
+    fn write_receipt(
+        &self,
+        install_dir: &Path,
+        manifest: &Manifest,
+        channel: &str,
+        changes: SystemChanges,
+        eula: Option<AcceptedEula>,
+        rolled_back_from: Option<&Version>,
+    ) -> Result<()> {
+        // Optional files the release did not have are left out.
+        let installed = |file: &&FileEntry| file.required || install_dir.join(file.installed_name()).exists();
+        let files = manifest
+            .files
+            .iter()
+            .filter(installed)
+            .map(|file_entry| {
+                Ok(ReceiptFile {
+                    name: file_entry.installed_name().to_string(),
+                    sha256: compute_sha256(&install_dir.join(file_entry.installed_name()))?,
+                    optional: !file_entry.required,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let receipt = InstallReceipt {
+            version: manifest.version.clone(),
+            product: manifest.product.clone(),
+            channel: channel.to_string(),
+            pinned: self.options.pin_version.is_some(),
+            rolled_back_from: rolled_back_from.cloned(),
+            prereq_check_skipped: self.options.skip_prereq_check,
+            installed_at: chrono::Local::now().to_rfc3339(),
+            files,
+            shortcuts: changes.shortcuts,
+            components: (!manifest.components.is_empty()).then(|| {
+                manifest
+                    .components
+                    .iter()
+                    .filter(|component| component.selected)
+                    .map(|component| InstalledComponent {
+                        name: component.name.clone(),
+                        required: component.required,
+                        files: component
+                            .files
+                            .iter()
+                            .filter(installed)
+                            .map(|file| file.installed_name().to_string())
+                            .collect(),
+                    })
+                    .collect()
+            }),
+            eula,
+            environment: changes.environment,
+            registry: changes.registry,
+            preserve: manifest.preserve.clone(),
+            uninstall: manifest.uninstall.clone(),
+        };
+
+        receipt.save(install_dir)
+    }
+}
+
+/// Installs missing prerequisites, such as the WebView2 runtime, that checks know how to.
+impl Remediator for Installer {
+    fn dry_run(&self) -> bool {
+        self.options.dry_run
+    }
+
+    fn installing(&self, name: &str) {
+        if self.options.dry_run {
+            self.reporter.say(&format!("{} missing, would install it", name));
+        } else {
+            self.reporter.say(&strings::format("prereq.installing", &[&name]));
+        }
+    }
+
+    fn download(&self, url: &str, destination: &Path) -> Result<()> {
+        let options = DownloadOptions {
+            show_progress: self.options.interactive,
+            connections: 1,
+            limit_rate: self.options.limit_rate,
+            expected_size: None,
+            authorize: false,
+            group: None,
+        };
+        download_file(&self.http, url, &destination.to_path_buf(), &options)?;
+        cancel::check()
+    }
+}
+
+/// A duration as days and hours, or minutes below an hour.
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    match (minutes / (24 * 60), minutes / 60 % 24) {
+        (0, 0) => format!("{}m", minutes),
+        (0, hours) => format!("{}h", hours),
+        (days, hours) => format!("{}d {}h", days, hours),
+    }
+}
+
+/// What an install changed outside the install directory, recorded in the receipt so
+/// uninstall can undo it.
+struct SystemChanges {
+    shortcuts: Vec<PathBuf>,
+    environment: Option<InstalledEnvironment>,
+    registry: Vec<WrittenRegistryValue>,
+}
+
+/// The registry values an install wrote.
+struct RegistryChanges {
+    /// What the receipt records, for uninstall.
+    values: Vec<WrittenRegistryValue>,
+    /// Undoes the writes, putting back what was there before, should the install fail.
+    undo: Vec<WrittenRegistryValue>,
+    /// What an earlier install wrote that this release no longer has, removed once the
+    /// receipt is written.
+    stale: Vec<WrittenRegistryValue>,
+}
+
+/// A shortcut an install would create.
+struct PlannedShortcut {
+    path: PathBuf,
+    target: PathBuf,
+    arguments: Option<String>,
+    icon: Option<(PathBuf, i32)>,
+    description: Option<String>,
+}
+
+/// Deletes shortcuts an install created; failures are logged.
+fn remove_shortcuts(shortcuts: &[PathBuf]) {
+    for shortcut in shortcuts {
+        match fs::remove_file(shortcut) {
+            Ok(()) => log::info!("Removed shortcut {:?}", shortcut),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove shortcut {:?}: {}", shortcut, e),
+        }
+    }
+}
+
+/// Those of `paths` that `others` does not have, comparing them as Windows does.
+fn new_paths(paths: &[PathBuf], others: &[PathBuf]) -> Vec<PathBuf> {
+    let same = |a: &PathBuf, b: &PathBuf| a.to_string_lossy().eq_ignore_ascii_case(&b.to_string_lossy());
+    paths.iter().filter(|path| !others.iter().any(|other| same(path, other))).cloned().collect()
+}
+
+/// Whether two registry values are the same one; Windows compares names case-insensitively.
+fn same_registry_value(a: &WrittenRegistryValue, b: &WrittenRegistryValue) -> bool {
+    a.root == b.root && a.path.eq_ignore_ascii_case(&b.path) && a.name.eq_ignore_ascii_case(&b.name)
+}
+
+/// Where the `index`th of the release's other archives is downloaded to.
+fn archive_zip_path(archive_dir: &Path, index: usize) -> PathBuf {
+    archive_dir.join(format!("{}.zip", index))
+}
+
+/// Where the zip of component `name` is downloaded to.
+fn component_zip_path(component_dir: &Path, name: &str) -> PathBuf {
+    component_dir.join(format!("{}.zip", name))
+}
+
+/// Moves the extracted files that have a `target` there. They are all moved aside first,
+/// so one file's target may be where another one was extracted. Directories left empty
+/// by the moves are removed.
+fn move_to_targets(extract_dir: &Path, manifest: &Manifest) -> Result<()> {
+    let moved: Vec<(&FileEntry, &String)> = manifest
+        .files
+        .iter()
+        .filter_map(|file| Some((file, file.target.as_ref()?)))
+        // A missing required file is reported when the files are checked.
+        .filter(|(file, _)| file.required || extract_dir.join(&file.name).exists())
+        .collect();
+    if moved.is_empty() {
+        return Ok(());
+    }
+
+    let aside = extract_dir.join(".paradise-targets");
+    fs::create_dir_all(&aside).context("Failed to create a directory for moving files")?;
+    for (i, (file, _)) in moved.iter().enumerate() {
+        let source = extract_dir.join(&file.name);
+        fs::rename(&source, aside.join(i.to_string()))
+            .with_context(|| format!("Failed to move {} to its target", file.name))?;
+        let mut dir = source.parent();
+        while let Some(parent) = dir.filter(|dir| *dir != extract_dir) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+    for (i, (file, target)) in moved.iter().enumerate() {
+        let destination = extract_dir.join(target);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).context("Failed to create a target directory")?;
+        }
+        fs::rename(aside.join(i.to_string()), &destination)
+            .with_context(|| format!("Failed to move {} to {}", file.name, target))?;
+        log::debug!("Moved {} to {}", file.name, target);
+    }
+    fs::remove_dir(&aside).context("Failed to remove the directory for moving files")
+}
+
+/// Identifies the volume holding `path`, for telling whether two paths share one.
+fn volume_key(path: &Path) -> String {
+    paths::volume_root(path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_lowercase()
+}
+
+/// Deletes staging directories in `parent` left behind by a run that was killed. Only one
+/// bootstrapper runs at a time, so none of them can be in use.
+fn remove_stale_staging(parent: &Path) {
+    let Ok(entries) = fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_staging = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(STAGING_PREFIX));
+        if is_staging && entry.path().is_dir() {
+            log::info!("Removing stale staging directory {:?}", entry.path());
+            if let Err(e) = fs::remove_dir_all(entry.path()) {
+                log::warn!("Failed to remove stale staging directory {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+}
+
+/// `amount` per second over `seconds`, treating an instant transfer as taking a millisecond.
+fn per_second(amount: f64, seconds: f64) -> f64 {
+    amount / seconds.max(0.001)
 }
 
 mod atomic {
@@ -182,6 +2713,9 @@ mod atomic {
     use std::fs;
     use std::path::{Path, PathBuf};
 
+    use crate::backup::new_backup_path;
+    use crate::cancel;
+
     pub struct AtomicInstaller {
         target_dir: PathBuf,
         backup_dir: Option<PathBuf>,
@@ -191,8 +2725,7 @@ mod atomic {
         pub fn new(target_dir: &Path) -> Result<Self> {
             let target_dir = target_dir.to_path_buf();
             let backup_dir = if target_dir.exists() {
-                let backup = target_dir.with_extension("backup");
-                Some(backup)
+                Some(new_backup_path(&target_dir))
             } else {
                 None
             };
@@ -203,39 +2736,87 @@ mod atomic {
             })
         }
 
-        pub fn install(&self, source_dir: &Path) -> Result<()> {
+        /// Swaps `source_dir` in, then carries the previous installation's files matching
+        /// `preserve` over into it.
+        pub fn install(&self, source_dir: &Path, preserve: &[String]) -> Result<()> {
             log::info!("Performing atomic installation to {:?}", self.target_dir);
 
             if let Some(ref backup) = self.backup_dir {
                 log::info!("Backing up existing installation to {:?}", backup);
-                if backup.exists() {
-                    fs::remove_dir_all(backup)
-                        .context("Failed to remove old backup")?;
-                }
                 fs::rename(&self.target_dir, backup)
                     .context("Failed to create backup")?;
             }
 
+            if let Err(e) = self.swap_in(source_dir) {
+                self.rollback();
+                return Err(e);
+            }
+
+            if let Some(ref backup) = self.backup_dir {
+                match crate::preserve::restore(backup, &self.target_dir, preserve) {
+                    Ok(kept) if !kept.is_empty() => {
+                        log::info!("Kept {} preserved file(s) from the previous installation", kept.len())
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        self.rollback();
+                        return Err(e.context("Failed to keep the preserved files"));
+                    }
+                }
+            }
+
+            log::info!("Atomic installation completed successfully");
+            Ok(())
+        }
+
+        /// Where the previous installation was moved, if there was one.
+        pub fn backup_dir(&self) -> Option<&Path> {
+            self.backup_dir.as_deref()
+        }
+
+        fn swap_in(&self, source_dir: &Path) -> Result<()> {
             if let Some(parent) = self.target_dir.parent() {
                 fs::create_dir_all(parent)
                     .context("Failed to create parent directory")?;
             }
 
             fs::rename(source_dir, &self.target_dir)
-                .or_else(|_| {
+                .or_else(|e| {
+                    log::warn!(
+                        "Could not move {:?} into place ({}), copying it instead",
+                        source_dir,
+                        e
+                    );
                     fs::create_dir_all(&self.target_dir)?;
                     copy_dir_all(source_dir, &self.target_dir)
                 })
-                .context("Failed to move/copy installation directory")?;
+                .context("Failed to move/copy installation directory")
+        }
 
-            log::info!("Atomic installation completed successfully");
-            Ok(())
+        /// Puts the previous installation back after a failed or cancelled swap, or after
+        /// a later step failed. Without one, the new installation is removed.
+        pub fn rollback(&self) {
+            if self.target_dir.exists() {
+                if let Err(e) = fs::remove_dir_all(&self.target_dir) {
+                    log::error!("Failed to remove partial installation: {}", e);
+                    return;
+                }
+            }
+            let Some(ref backup) = self.backup_dir else {
+                return;
+            };
+
+            log::warn!("Restoring previous installation from {:?}", backup);
+            if let Err(e) = fs::rename(backup, &self.target_dir) {
+                log::error!("Failed to restore backup {:?}: {}", backup, e);
+            }
         }
     }
 
-    fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
+    pub fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
         fs::create_dir_all(dst)?;
         for entry in fs::read_dir(src)? {
+            cancel::check()?;
             let entry = entry?;
             let ty = entry.file_type()?;
             let src_path = entry.path();
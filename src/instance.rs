@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use windows::core::w;
+use windows::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE};
+use windows::Win32::System::Threading::CreateMutexW;
+
+/// Holds the named mutex that keeps a second bootstrapper from running concurrently.
+///
+/// The handle is closed on drop (including during a panic unwind) and by Windows when
+/// the process exits, so the lock can never outlive this process.
+pub struct InstanceGuard(HANDLE);
+
+/// Returns `None` when another bootstrapper already holds the lock.
+pub fn acquire() -> Result<Option<InstanceGuard>> {
+    unsafe {
+        let handle = CreateMutexW(None, false, w!("Local\\paradise-bootstrapper"))
+            .context("Failed to create single-instance mutex")?;
+
+        let already_running = GetLastError()
+            .err()
+            .is_some_and(|e| e.code() == ERROR_ALREADY_EXISTS.to_hresult());
+
+        if already_running {
+            let _ = CloseHandle(handle);
+            return Ok(None);
+        }
+
+        Ok(Some(InstanceGuard(handle)))
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Starts `exe_path` detached from the console, with the install directory as its working directory.
+pub fn launch(exe_path: &Path, working_dir: &Path) -> Result<()> {
+    log::info!("Launching {:?} in {:?}", exe_path, working_dir);
+
+    let mut command = Command::new(exe_path);
+    command.current_dir(working_dir);
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS};
+        command.creation_flags(DETACHED_PROCESS.0 | CREATE_NEW_PROCESS_GROUP.0);
+    }
+
+    command
+        .spawn()
+        .with_context(|| format!("Failed to launch {}", exe_path.display()))?;
+    Ok(())
+}
@@ -1,4 +1,15 @@
+pub mod disk;
+pub mod generate;
+pub mod http;
 pub mod manifest;
+pub mod paths;
+pub mod pinning;
+pub mod preserve;
+pub mod prereq;
+pub mod proxy;
+pub mod receipt;
+pub mod trust;
 pub mod verify;
+pub mod version;
 
 
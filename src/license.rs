@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::diagnose::{self, NetworkErrorKind};
+use crate::error::InstallError;
+use crate::http::{HttpClient, StatusError};
+use crate::paths;
+use crate::prompt;
+use crate::strings;
+
+/// Holds the license server's approval, in [`paths::data_dir`].
+const APPROVAL_FILE: &str = "license.json";
+
+/// A license key, kept out of `Debug` output and logs.
+#[derive(Clone)]
+pub struct LicenseKey(String);
+
+impl LicenseKey {
+    pub fn new(key: String) -> Self {
+        LicenseKey(key)
+    }
+}
+
+impl fmt::Debug for LicenseKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LicenseKey(***)")
+    }
+}
+
+/// What is POSTed to `license_check_url`: the key, or the token of an earlier approval.
+#[derive(Serialize)]
+struct CheckRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<&'a str>,
+    version: &'a str,
+}
+
+#[derive(Deserialize)]
+struct CheckResponse {
+    valid: bool,
+    /// The signed approval to send instead of the key next time.
+    #[serde(default)]
+    token: Option<String>,
+    /// Why the key was rejected, shown to the user.
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Answer {
+    Approved { token: Option<String> },
+    Rejected(String),
+}
+
+/// The server's approval of a key, saved so updates neither prompt nor need the key.
+#[derive(Debug, Serialize, Deserialize)]
+struct Approval {
+    /// The `license_check_url` that approved it; another server's approval does not count.
+    url: String,
+    token: String,
+}
+
+impl Approval {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(APPROVAL_FILE))
+    }
+
+    fn load(url: &str) -> Option<Self> {
+        let text = fs::read_to_string(Self::path().ok()?).ok()?;
+        match serde_json::from_str::<Approval>(&text) {
+            Ok(approval) if approval.url == url => Some(approval),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Ignoring unreadable license approval: {}", e);
+                None
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create the data directory")?;
+        }
+        let text = serde_json::to_string_pretty(self).context("Failed to serialize the license approval")?;
+        fs::write(&path, text).context("Failed to save the license approval")
+    }
+
+    fn remove() {
+        let Ok(path) = Self::path() else { return };
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove the license approval {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Makes sure the server at `url` accepts this install of `version`.
+///
+/// A saved approval is tried first unless `key` is given; the server must still accept it,
+/// since nothing here can tell a real one from a copied or hand-written file. Otherwise the
+/// key is `key`, or asked for when `interactive`.
+pub fn ensure_licensed(
+    http: &HttpClient,
+    url: &str,
+    version: &str,
+    key: Option<&LicenseKey>,
+    interactive: bool,
+) -> Result<()> {
+    if key.is_none() {
+        if let Some(approval) = Approval::load(url) {
+            log::info!("Checking the saved license approval with {}", url);
+            let request = CheckRequest {
+                license_key: None,
+                token: Some(&approval.token),
+                version,
+            };
+            match check(http, url, &request) {
+                Ok(Answer::Approved { token }) => {
+                    log::info!("License approval still valid");
+                    save_approval(url, token);
+                    return Ok(());
+                }
+                Ok(Answer::Rejected(reason)) => {
+                    log::warn!("The saved license approval was rejected: {}", reason);
+                    Approval::remove();
+                }
+                Err(e) => return Err(check_failed(url, e)),
+            }
+        }
+    }
+
+    let prompted;
+    let key = match key {
+        Some(LicenseKey(key)) => key.as_str(),
+        None if interactive => {
+            prompted = prompt::ask(strings::text("license.prompt"))?;
+            prompted.as_str()
+        }
+        None => return Err(InstallError::LicenseKeyRequired.into()),
+    };
+    if key.is_empty() {
+        return Err(InstallError::LicenseKeyRequired.into());
+    }
+
+    log::info!("Checking the license key with {}", url);
+    let request = CheckRequest {
+        license_key: Some(key),
+        token: None,
+        version,
+    };
+    match check(http, url, &request) {
+        Ok(Answer::Approved { token }) => {
+            log::info!("License key accepted");
+            save_approval(url, token);
+            Ok(())
+        }
+        Ok(Answer::Rejected(reason)) => Err(InstallError::LicenseRejected(reason).into()),
+        Err(e) => Err(check_failed(url, e)),
+    }
+}
+
+/// The error for a license check that got no usable answer. Only a server that could not
+/// be reached in time is "unreachable"; one that failed or answered nonsense is not.
+fn check_failed(url: &str, e: anyhow::Error) -> anyhow::Error {
+    log::error!("License check failed: {:#}", e);
+    let reason = diagnose::classify(&e).map_or_else(|| format!("{:#}", e), |problem| problem.message());
+    let url = url.to_string();
+    if is_unreachable(&e) {
+        InstallError::LicenseServerUnreachable { url, reason }.into()
+    } else {
+        InstallError::LicenseCheckFailed { url, reason }.into()
+    }
+}
+
+/// Whether `e` is a failure to connect to the server or a timeout.
+fn is_unreachable(e: &anyhow::Error) -> bool {
+    let connect = e.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|error| error.is_connect() || error.is_timeout())
+    });
+    connect
+        || diagnose::classify(e).is_some_and(|problem| {
+            matches!(
+                problem.kind,
+                NetworkErrorKind::Dns | NetworkErrorKind::ConnectionRefused | NetworkErrorKind::Timeout
+            )
+        })
+}
+
+fn save_approval(url: &str, token: Option<String>) {
+    let Some(token) = token else {
+        log::debug!("The license server sent no approval token");
+        return;
+    };
+    let approval = Approval {
+        url: url.to_string(),
+        token,
+    };
+    if let Err(e) = approval.save() {
+        log::warn!("{:#}", e);
+    }
+}
+
+/// POSTs `request` to the license server. Errors mean it could not be asked; a 4xx
+/// answer or `"valid": false` is a rejection.
+fn check(http: &HttpClient, url: &str, request: &CheckRequest) -> Result<Answer> {
+    let body = serde_json::to_vec(request).context("Failed to serialize the license check")?;
+    let response = http.send(
+        http.post(url)?
+            .header(CONTENT_TYPE, "application/json")
+            .body(body),
+    )?;
+    http.log_headers(&response);
+
+    let status = response.status();
+    if status.is_server_error() || !(status.is_success() || status.is_client_error()) {
+        return Err(StatusError::new("license check", &response).into());
+    }
+    let text = response.text().context("Failed to read the license server's answer")?;
+    parse_answer(status, &text)
+}
+
+/// Reads the server's `status` and body `text`: a 4xx is a rejection, with the body's
+/// `message` when it has one, otherwise the JSON says whether the key is valid.
+fn parse_answer(status: StatusCode, text: &str) -> Result<Answer> {
+    let answer = serde_json::from_str::<CheckResponse>(text);
+
+    if status.is_client_error() {
+        let message = answer.ok().and_then(|answer| answer.message);
+        return Ok(Answer::Rejected(message.unwrap_or_else(|| status.to_string())));
+    }
+    let answer = answer.context("The license server's answer is not valid JSON")?;
+    if answer.valid {
+        Ok(Answer::Approved { token: answer.token })
+    } else {
+        Ok(Answer::Rejected(
+            answer.message.unwrap_or_else(|| "the key is not valid".to_string()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_answers_approve_with_their_token() {
+        let answer = parse_answer(StatusCode::OK, r#"{"valid": true, "token": "abc"}"#).unwrap();
+        assert_eq!(answer, Answer::Approved { token: Some("abc".to_string()) });
+        let answer = parse_answer(StatusCode::OK, r#"{"valid": true}"#).unwrap();
+        assert_eq!(answer, Answer::Approved { token: None });
+    }
+
+    #[test]
+    fn invalid_keys_and_client_errors_are_rejections() {
+        let answer = parse_answer(StatusCode::OK, r#"{"valid": false, "message": "expired"}"#).unwrap();
+        assert_eq!(answer, Answer::Rejected("expired".to_string()));
+        let answer = parse_answer(StatusCode::OK, r#"{"valid": false}"#).unwrap();
+        assert_eq!(answer, Answer::Rejected("the key is not valid".to_string()));
+
+        let answer = parse_answer(StatusCode::FORBIDDEN, r#"{"valid": false, "message": "revoked"}"#).unwrap();
+        assert_eq!(answer, Answer::Rejected("revoked".to_string()));
+        let answer = parse_answer(StatusCode::NOT_FOUND, "<html>Not Found</html>").unwrap();
+        assert_eq!(answer, Answer::Rejected("404 Not Found".to_string()));
+    }
+
+    #[test]
+    fn malformed_answers_are_errors() {
+        let e = parse_answer(StatusCode::OK, "<html>maintenance</html>").unwrap_err();
+        assert_eq!(e.to_string(), "The license server's answer is not valid JSON");
+        assert!(parse_answer(StatusCode::OK, r#"{"token": "abc"}"#).is_err());
+    }
+
+    #[test]
+    fn only_connection_failures_make_the_server_unreachable() {
+        let refused = anyhow::Error::new(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(is_unreachable(&refused));
+        let timeout = anyhow::Error::new(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(is_unreachable(&timeout));
+
+        let e = check_failed("https://license.example.com", anyhow::anyhow!("not valid JSON"));
+        assert!(matches!(e.downcast_ref(), Some(InstallError::LicenseCheckFailed { .. })));
+        let e = check_failed("https://license.example.com", refused);
+        assert!(matches!(e.downcast_ref(), Some(InstallError::LicenseServerUnreachable { .. })));
+    }
+}
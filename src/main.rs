@@ -1,29 +1,66 @@
 // syringee made this thx
 
+mod arch;
+mod backup;
+mod cache;
+mod cancel;
 mod cli;
+mod config;
+mod diagnose;
+mod disk;
 mod download;
+mod error;
+mod environment;
+mod eula;
+mod generate;
+mod http;
 mod install;
+mod instance;
+mod launch;
+mod license;
 mod manifest;
+mod overrides;
+mod patch;
+mod paths;
+mod pinning;
+mod post_install;
+mod preserve;
+mod prereq;
+mod processes;
+mod progress;
+mod prompt;
+mod proxy;
+mod receipt;
+mod registry;
+mod release_notes;
+mod report;
 mod shortcut;
+mod signature;
+mod strings;
+mod trust;
+mod uninstall;
 mod verify;
+mod version;
 
 use anyhow::Result;
-use log::{error, info};
+use clap::Parser;
+use log::{error, info, warn};
 use simplelog::{ConfigBuilder, LevelFilter, WriteLogger};
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
-use cli::InstallMode;
-use install::Installer;
+use cli::{Cli, Command, InstallMode};
+use config::{Config, ShortcutPreferences};
+use diagnose::NetworkProblem;
+use http::HttpClient;
+use install::{InstallOptions, Installer};
+use license::LicenseKey;
+use manifest::Manifest;
+use report::{Event, Reporter};
 
-const MANIFEST_URL: &str = "https://raw.githubusercontent.com/syringeefy/Xenith/refs/heads/main/installer.json";
-
-fn setup_logging() -> Result<()> {
-    let log_dir = directories::BaseDirs::new()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get base directories"))?
-        .data_local_dir()
-        .join("paradise")
-        .join("logs");
+fn setup_logging(level: LevelFilter) -> Result<PathBuf> {
+    let log_dir = paths::logs_dir()?;
 
     fs::create_dir_all(&log_dir)?;
 
@@ -38,90 +75,454 @@ fn setup_logging() -> Result<()> {
         .set_location_level(LevelFilter::Debug)
         .build();
 
-    WriteLogger::init(LevelFilter::Info, config, fs::File::create(log_file)?)?;
+    let mut file = fs::File::create(&log_file)?;
+    writeln!(file, "log level: {}", level)?;
 
-    Ok(())
+    WriteLogger::init(level, config, file)?;
+
+    Ok(log_file)
 }
 
-fn show_menu() -> Result<InstallMode> {
-    println!("paradise bootstrapper");
-    println!("1) standard install (appdata)");
-    println!("2) custom path install");
-    print!("choice: ");
+fn show_menu(manifest_url: &mut String, preferred: &InstallMode) -> Result<(Command, InstallMode)> {
+    for key in [
+        "menu.title",
+        "menu.standard",
+        "menu.custom",
+        "menu.verify",
+        "menu.update",
+        "menu.uninstall",
+        "menu.rollback",
+        "menu.manifest_url",
+    ] {
+        println!("{}", strings::text(key));
+    }
+    print!("{}", strings::text("menu.choice"));
     io::stdout().flush()?;
 
     let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    if io::stdin().read_line(&mut input)? == 0 {
+        anyhow::bail!("No menu choice entered: input is closed");
+    }
     let choice = input.trim();
 
     match choice {
-        "1" => Ok(InstallMode::Standard),
-        "2" => Ok(InstallMode::Specific),
+        "1" => Ok((Command::Install, InstallMode::Standard)),
+        "2" => Ok((Command::Install, InstallMode::Specific)),
+        "3" => Ok((Command::Verify, preferred.clone())),
+        "4" => Ok((Command::Update { check_only: false }, preferred.clone())),
+        "5" => Ok((Command::Uninstall, preferred.clone())),
+        "6" => Ok((Command::Rollback { to_version: None }, preferred.clone())),
+        "7" => {
+            match get_manifest_url() {
+                Ok(url) => {
+                    info!("Manifest URL: {}", url);
+                    *manifest_url = url;
+                }
+                Err(e) if !io::stdin().is_terminal() => return Err(e),
+                Err(e) => println!("{}", e),
+            }
+            show_menu(manifest_url, preferred)
+        }
         _ => {
-            println!("invalid choice");
-            show_menu()
+            println!("{}", strings::text("menu.invalid"));
+            show_menu(manifest_url, preferred)
         }
     }
 }
 
-fn get_build_directory() -> Result<std::path::PathBuf> {
-    print!("install path: ");
+fn get_manifest_url() -> Result<String> {
+    print!("{}", strings::text("prompt.manifest_url"));
     io::stdout().flush()?;
 
     let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
-    let path = input.trim();
+    if io::stdin().read_line(&mut input)? == 0 {
+        anyhow::bail!("No manifest url entered: input is closed");
+    }
+    let url = input.trim().to_string();
+
+    manifest::validate_manifest_url(&url)?;
+    Ok(url)
+}
 
-    if path.is_empty() {
-        anyhow::bail!("Directory path cannot be empty");
+fn get_build_directory(default: Option<&Path>, required: Option<u64>) -> Result<PathBuf> {
+    if let Some(required) = required {
+        println!(
+            "{}",
+            strings::format("path.required", &[&disk::format_bytes(required)])
+        );
+    }
+
+    loop {
+        match default {
+            Some(default) => print!(
+                "{}",
+                strings::format("path.prompt_default", &[&default.display()])
+            ),
+            None => print!("{}", strings::text("path.prompt")),
+        }
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            anyhow::bail!("No install path entered");
+        }
+
+        match resolve_build_directory(&input, default, required) {
+            Ok(Some(path)) => return Ok(path),
+            Ok(None) => continue,
+            Err(e) => println!("{}", strings::format("path.invalid", &[&e])),
+        }
+    }
+}
+
+/// Normalizes a typed install path. `Ok(None)` means the user declined a confirmation.
+fn resolve_build_directory(
+    input: &str,
+    default: Option<&Path>,
+    required: Option<u64>,
+) -> Result<Option<PathBuf>> {
+    let cleaned = paths::strip_quotes(input);
+
+    if cleaned.is_empty() {
+        return match default {
+            Some(default) => Ok(Some(default.to_path_buf())),
+            None => anyhow::bail!("Directory path cannot be empty"),
+        };
+    }
+
+    let mut path = PathBuf::from(paths::expand_env_vars(cleaned, |name| std::env::var(name).ok())?);
+
+    if path.is_relative() {
+        let absolute = std::env::current_dir()?.join(&path);
+        let question =
+            strings::format("path.relative", &[&path.display(), &absolute.display()]);
+        if !prompt::confirm(&question, false)? {
+            return Ok(None);
+        }
+        path = absolute;
+    }
+
+    match paths::volume_root(&path) {
+        Some(root) if root.exists() => {}
+        Some(root) => anyhow::bail!("Drive {} does not exist", root.display()),
+        None => anyhow::bail!("Cannot determine the drive of {}", path.display()),
+    }
+
+    if path.is_file() {
+        anyhow::bail!("{} is a file, not a directory", path.display());
+    }
+
+    let available = disk::free_space(&path)?;
+    println!(
+        "{}",
+        strings::format("path.free", &[&disk::format_bytes(available)])
+    );
+    if let Some(required) = required {
+        if available < required {
+            anyhow::bail!(
+                "not enough space: {} required, {} available",
+                disk::format_bytes(required),
+                disk::format_bytes(available)
+            );
+        }
+    }
+
+    let has_contents = fs::read_dir(&path)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    let has_receipt = path.join(receipt::RECEIPT_FILE).exists();
+
+    if has_contents && !has_receipt {
+        println!("{}", strings::format("path.not_empty", &[&path.display()]));
+        if !prompt::confirm(strings::text("path.continue"), false)? {
+            return Ok(None);
+        }
     }
 
-    let path_buf = std::path::PathBuf::from(path);
-    Ok(path_buf)
+    info!("Custom install path resolved to {:?}", path);
+    Ok(Some(path))
+}
+
+fn pause() -> Result<()> {
+    print!("{}", strings::text("exit.pause"));
+    io::stdout().flush()?;
+    let _ = io::stdin().read_line(&mut String::new());
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    setup_logging()?;
+    let mut cli = Cli::parse();
+
+    if let Some(Command::Completions { shell }) = cli.command {
+        Cli::print_completions(shell);
+        return Ok(());
+    }
+    if let Some(Command::GenerateManifest(args)) = &cli.command {
+        let release = generate::ManifestRelease {
+            zip: args.release_zip.clone(),
+            output: args.output.clone(),
+            version: args.version.clone(),
+            url: args.url.clone(),
+            mirrors: args.mirrors.clone(),
+            channel: cli.channel.clone(),
+            prerequisites: args.prerequisites.clone(),
+            merge: args.merge,
+            tag_arch: args.tag_arch,
+        };
+        let manifest = generate::generate(&release)?;
+        println!(
+            "wrote {} ({} files, sha256 {})",
+            args.output.display(),
+            manifest.files.len(),
+            manifest.sha256
+        );
+        return Ok(());
+    }
+
+    let lang = strings::init(cli.lang);
+
+    let Some(instance) = instance::acquire()? else {
+        println!("{}", strings::text("instance.running"));
+        std::process::exit(error::EXIT_ALREADY_RUNNING);
+    };
+
+    let (config, config_error) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
+    };
+    let sources = overrides::apply(&mut cli, &config)?;
+
+    let mut manifest_url = cli
+        .manifest_url
+        .clone()
+        .unwrap_or_else(|| trust::MANIFEST_URL.to_string());
+    manifest::validate_manifest_url(&manifest_url)?;
+
+    let log_file = setup_logging(cli.log_level())?;
+
+    info!("Bootstrapper {} starting", env!("CARGO_PKG_VERSION"));
+    info!("Console language: {:?}", lang);
+    cancel::install_handler()?;
+    let http = HttpClient::new(cli.network_options())?;
+    match cli.offline_release() {
+        Some(offline) => info!(
+            "Offline install: zip {:?}, manifest {:?}",
+            offline.zip, offline.manifest
+        ),
+        None => info!("Manifest URL: {}", manifest_url),
+    }
+    sources.log();
+    if !cli.silent && !cli.has_console() {
+        info!("No console attached: skipping the menu, prompts and pause");
+    }
+    if let Some(e) = config_error {
+        warn!("Ignoring config file, using defaults: {:#}", e);
+        eprintln!("warning: ignoring config file, using defaults ({:#})", e);
+    }
+    for warning in &sources.warnings {
+        warn!("{}", warning);
+        eprintln!("warning: {}", warning);
+    }
 
-    info!("paradise Bootstrapper starting");
-    info!("Manifest URL: {}", MANIFEST_URL);
+    let (command, mode) = match &cli.command {
+        Some(command) => (command.clone(), cli.install_mode()),
+        None if cli.unattended() => {
+            info!("Unattended mode: using standard install");
+            (Command::Install, cli.install_mode())
+        }
+        None => show_menu(&mut manifest_url, &cli.install_mode())?,
+    };
+    info!("Command: {}", command.label());
+    // Cleaning the cache never touches an install directory, so there is no path to ask for.
+    let mode = match command {
+        Command::CleanCache => InstallMode::Standard,
+        _ => mode,
+    };
 
-    let mode = show_menu()?;
-    let build_dir = if matches!(mode, InstallMode::Specific) {
-        Some(get_build_directory()?)
-    } else {
-        None
+    let arch = match cli.arch {
+        Some(arch) => {
+            info!("Architecture: {} (--arch)", arch);
+            arch
+        }
+        None => {
+            let arch = arch::detect();
+            info!("Architecture: {} (detected)", arch);
+            arch
+        }
+    };
+    let expansion_factor = cli
+        .expansion_factor
+        .unwrap_or(manifest::DEFAULT_EXPANSION_FACTOR);
+    let mut prefetched = None;
+    let build_dir = match (&mode, &cli.dir) {
+        (InstallMode::Standard, _) => None,
+        (InstallMode::Specific, Some(dir)) => Some(dir.clone()),
+        (InstallMode::Specific, None) if cli.unattended() => match &config.install_dir {
+            Some(dir) => Some(dir.clone()),
+            None => anyhow::bail!("--dir is required for a specific install in unattended mode"),
+        },
+        (InstallMode::Specific, None) => {
+            if matches!(command, Command::Install | Command::Update { .. } | Command::Rollback { .. }) {
+                let fetched = match cli.offline_release() {
+                    Some(offline) => Manifest::from_file(&offline.manifest),
+                    None => Manifest::from_url(&http, &manifest_url, !cli.force),
+                };
+                match fetched {
+                    Ok(manifest) => prefetched = Some(manifest),
+                    Err(e) => warn!("Could not fetch manifest before path prompt: {:#}", e),
+                }
+            }
+            let channel = cli.channel.as_deref().unwrap_or(manifest::DEFAULT_CHANNEL);
+            let required = prefetched
+                .as_ref()
+                .and_then(|manifest| match &cli.pin_version {
+                    Some(version) => manifest.for_version(version).ok(),
+                    None => manifest.for_channel(channel).ok(),
+                })
+                .and_then(|manifest| manifest.for_arch(arch.as_str()).ok())
+                .and_then(|manifest| manifest.installed_size(expansion_factor));
+            Some(get_build_directory(config.install_dir.as_deref(), required)?)
+        }
     };
 
     if let Some(ref dir) = build_dir {
         info!("Build directory: {:?}", dir);
     }
 
-    let installer = Installer::new(
-        MANIFEST_URL.to_string(),
-        mode,
-        build_dir,
-        false,
-        false,
-    )?;
+    let options = InstallOptions {
+        dry_run: cli.dry_run,
+        no_desktop_shortcut: cli.no_shortcut || cli.no_desktop_shortcut,
+        no_install_dir_shortcut: cli.no_shortcut || cli.no_install_dir_shortcut,
+        no_env: cli.no_env,
+        kill_running: cli.kill_running,
+        interactive: !cli.unattended(),
+        output: cli.output,
+        channel: cli.channel.clone(),
+        force: cli.force,
+        offline: cli.offline_release(),
+        pin_version: cli.pin_version.clone(),
+        launch: cli.launch,
+        skip_prereq_check: cli.skip_prereq_check,
+        keep_backup: cli.keep_backup,
+        backup_retention: cli.backup_retention,
+        connections: cli.connections,
+        parallel_downloads: cli.parallel_downloads,
+        limit_rate: cli.limit_rate,
+        no_cache: cli.no_cache,
+        cache_max_size: cli.cache_max_size.unwrap_or(cache::DEFAULT_MAX_SIZE),
+        expansion_factor,
+        arch,
+        components: cli.components.clone(),
+        license_key: cli.license_key.clone().map(LicenseKey::new),
+        accept_eula: cli.accept_eula,
+        offline_ok: cli.offline_ok,
+        max_manifest_age: std::time::Duration::from_secs(cli.max_manifest_age.saturating_mul(60 * 60)),
+        verbose: cli.verbose > 0,
+    };
+    let reporter = Reporter::new(cli.output);
+    let pause_at_exit = !cli.no_pause && !cli.unattended();
+
+    if cli.save_config {
+        let saved = Config {
+            install_mode: Some(mode.clone()),
+            install_dir: build_dir.clone().or(config.install_dir),
+            manifest_url: (manifest_url != trust::MANIFEST_URL).then(|| manifest_url.clone()),
+            log_level: cli.log_level,
+            limit_rate: config.limit_rate,
+            cache_max_size: config.cache_max_size,
+            expansion_factor: config.expansion_factor,
+            shortcuts: ShortcutPreferences {
+                desktop: Some(!options.no_desktop_shortcut),
+                install_dir: Some(!options.no_install_dir_shortcut),
+            },
+        };
+        if let Err(e) = saved.save() {
+            warn!("Failed to save config: {:#}", e);
+            eprintln!("warning: failed to save config ({:#})", e);
+        }
+    }
+
+    let mut installer = Installer::new(manifest_url, mode, build_dir, options, http)?;
+    if let Some(manifest) = prefetched {
+        installer = installer.with_manifest(manifest);
+    }
+
+    let result = match &command {
+        Command::Install => installer.run(),
+        Command::Verify => installer.verify(),
+        Command::Update { check_only } => installer.update(*check_only),
+        Command::Uninstall => installer.uninstall(),
+        Command::Rollback { to_version } => installer.rollback(to_version.as_deref()),
+        Command::CleanCache => installer.clean_cache(),
+        Command::Completions { .. } => unreachable!("completions are printed before setup"),
+        Command::GenerateManifest(_) => unreachable!("manifests are generated before setup"),
+    };
 
-    match installer.run() {
+    match result {
         Ok(_) => {
-            info!("Installation completed successfully");
-            println!("\ninstall complete");
-            print!("press enter to exit...");
-            io::stdout().flush()?;
-            let _ = io::stdin().read_line(&mut String::new());
+            if cli.dry_run {
+                info!("Dry run completed successfully");
+            } else {
+                info!("{} completed successfully", command.label());
+            }
+            reporter.event(Event::Finished {
+                command: command.label().to_string(),
+                dry_run: cli.dry_run,
+                status: "success".to_string(),
+            });
+            if !cli.unattended() {
+                if cli.dry_run {
+                    println!("\n{}", strings::text("result.dry_run"));
+                } else {
+                    println!(
+                        "\n{}",
+                        strings::format("result.complete", &[&strings::text(command.name_key())])
+                    );
+                }
+            }
+            if pause_at_exit {
+                pause()?;
+            }
             Ok(())
         }
         Err(e) => {
-            error!("Installation failed: {}", e);
-            println!("\ninstall failed: {}", e);
-            println!("check logs in %LOCALAPPDATA%\\paradise\\logs");
-            print!("press enter to exit...");
-            io::stdout().flush()?;
-            let _ = io::stdin().read_line(&mut String::new());
-            std::process::exit(1);
+            error!("{} failed: {:#}", command.label(), e);
+            let problem = diagnose::classify(&e);
+            if let Some(problem) = &problem {
+                info!("Network error classified as {}", problem.kind.label());
+            }
+            reporter.event(Event::Failed {
+                command: command.label().to_string(),
+                stage: error::stage_of(&e).map(|stage| stage.label().to_string()),
+                category: problem.as_ref().map(|problem| problem.kind.label().to_string()),
+                message: format!("{:#}", e),
+            });
+            let name = strings::text(command.name_key());
+            let suggestion = problem
+                .as_ref()
+                .map(|problem| strings::format("result.suggestion", &[&problem.suggestion()]));
+            if pause_at_exit {
+                let message = problem
+                    .as_ref()
+                    .map_or_else(|| e.to_string(), NetworkProblem::message);
+                println!("\n{}", strings::format("result.failed", &[&name, &message]));
+                if let Some(suggestion) = &suggestion {
+                    println!("{}", suggestion);
+                }
+                println!("{}", strings::text("result.check_logs"));
+                pause()?;
+            } else {
+                let message = problem
+                    .as_ref()
+                    .map_or_else(|| format!("{:#}", e), NetworkProblem::message);
+                eprintln!("{}", strings::format("result.failed", &[&name, &message]));
+                if let Some(suggestion) = &suggestion {
+                    eprintln!("{}", suggestion);
+                }
+                eprintln!("{}", strings::format("result.log_file", &[&log_file.display()]));
+            }
+            drop(instance);
+            std::process::exit(error::exit_code(&e));
         }
     }
 }
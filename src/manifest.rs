@@ -1,145 +1,2969 @@
 use anyhow::{Context, Result};
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::time::Instant;
+use windows::Win32::System::Registry::{HKEY, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+use crate::http::{HttpClient, StatusError};
+use crate::paths;
+use crate::prereq::{self, Prerequisites, Remediator};
+use crate::version::Version;
+
+/// Channel served by the top-level manifest fields.
+pub const DEFAULT_CHANNEL: &str = "stable";
+
+/// Architecture the top-level release fields are built for, unless `targets` lists it.
+pub const DEFAULT_ARCH: &str = "x86_64";
+
+/// Newest manifest schema this bootstrapper understands. Version 2 added `assets`,
+/// `installed_size_bytes` and per-file `sha256` and `size`; version 3 added `preserve`,
+/// `min_supported_version` and `close_processes`, which an older bootstrapper would ignore
+/// at the cost of users' data or of a broken install.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// How much larger than its zip an install is assumed to be when the manifest gives no
+/// installed size.
+pub const DEFAULT_EXPANSION_FACTOR: f64 = 2.5;
+
+/// Placeholders the release zip, mirror and patch URLs may have, as `${version}`; `$$` is
+/// a literal `$`.
+pub const URL_PLACEHOLDERS: [&str; 3] = ["version", "arch", "channel"];
+
+const CACHE_FILE: &str = "manifest.json";
+/// The last manifest validated after a network fetch, for when the network is down.
+const LAST_MANIFEST_FILE: &str = "last_manifest.json";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Manifest {
-    pub version: String,
+    /// Manifests without one predate the field and are version 1.
+    #[serde(default = "first_schema_version")]
+    pub schema_version: u32,
+    /// Oldest bootstrapper that handles this manifest correctly, as a semver version.
+    #[serde(default)]
+    pub min_bootstrapper_version: Option<Version>,
+    /// Where users get a newer bootstrapper when theirs is too old.
+    #[serde(default)]
+    pub bootstrapper_download_url: Option<String>,
+    /// Installed versions older than this must update: `update` offers no way to stay on
+    /// them, and no release older than it is installed.
+    #[serde(default)]
+    pub min_supported_version: Option<Version>,
+    /// Shown to users of an unsupported version, e.g. why they must update.
+    #[serde(default)]
+    pub min_supported_message: Option<String>,
+    pub version: Version,
+    /// Names the installed product; a rebranded release only needs its own.
+    #[serde(default)]
+    pub product: Product,
+    /// Empty when the release is given as `archives` only; the first archive then takes
+    /// its place once the manifest is parsed. This URL, the mirrors, the archives' and the
+    /// patches' may have [`URL_PLACEHOLDERS`], filled in by [`Manifest::expand_urls`].
     #[serde(rename = "release_zip_url")]
+    #[serde(default)]
     pub release_zip_url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    #[serde(default)]
     pub sha256: String,
+    /// Directory in the install directory the release zip is extracted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract_to: Option<String>,
+    /// Further zips of the release, e.g. assets on another CDN, extracted with it.
+    #[serde(default)]
+    pub archives: Vec<Archive>,
+    /// Detached minisign signature over the release zip.
+    #[serde(default)]
+    pub release_zip_sig_url: Option<String>,
+    /// Size of the release zip, checked before its hash; `release_zip_size` is accepted too.
+    #[serde(default, alias = "release_zip_size")]
+    pub size_bytes: Option<u64>,
+    /// Disk space the extracted release takes up.
+    #[serde(default)]
+    pub installed_size_bytes: Option<u64>,
     pub files: Vec<FileEntry>,
     #[serde(default)]
     pub prerequisites: Prerequisites,
+    /// Set when the release zip URLs need the auth token.
+    #[serde(default)]
+    pub auth: Option<AuthScheme>,
     #[serde(rename = "license_check_url")]
     #[serde(default)]
     pub license_check_url: Option<String>,
+    /// Terms the user has to accept before anything is downloaded, fetched from here...
+    #[serde(default)]
+    pub eula_url: Option<String>,
+    /// ...or given inline.
+    #[serde(default)]
+    pub eula_text: Option<String>,
+    /// Markdown notes shown before updating to this version, fetched from here...
+    #[serde(default)]
+    pub release_notes_url: Option<String>,
+    /// ...or given inline.
+    #[serde(default)]
+    pub release_notes: Option<String>,
+    #[serde(default)]
+    pub channels: BTreeMap<String, Release>,
+    #[serde(default)]
+    pub history: Vec<Release>,
+    /// Builds for other CPU architectures, keyed by architecture.
+    #[serde(default)]
+    pub targets: BTreeMap<String, Target>,
+    /// Patches from older versions to this release.
+    #[serde(default)]
+    pub patches: Vec<Patch>,
+    /// Optional parts of the release the user picks at install time.
+    #[serde(default)]
+    pub components: Vec<Component>,
+    /// Extra files downloaded alongside the release, whatever the channel or version.
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+    /// User data in the install directory, e.g. settings and saves, carried over from the
+    /// installation an update replaces.
+    #[serde(default)]
+    pub preserve: Vec<String>,
+    /// Programs that must not be running while the install directory is replaced, relative
+    /// to it; anything else started from the install directory counts as well.
+    #[serde(default)]
+    pub close_processes: Vec<String>,
+    /// Whether unattended installs end those programs rather than wait for them.
+    #[serde(default)]
+    pub kill_running_unattended: bool,
+    /// Programs from the release run once its files are in place.
+    #[serde(default)]
+    pub post_install: Vec<PostInstallCommand>,
+    /// PATH entries and environment variables set once the install succeeded.
+    #[serde(default)]
+    pub environment: Option<Environment>,
+    /// Registry values written once the files are in place.
+    #[serde(default)]
+    pub registry: Vec<RegistryEntry>,
+    /// Shortcuts created after the install; without the list, one named after the product
+    /// on the desktop or in the install directory.
+    #[serde(default)]
+    pub shortcuts: Option<Vec<ShortcutEntry>>,
+    /// What uninstall removes beyond what the install wrote. Kept in the receipt, so
+    /// uninstall never needs the manifest.
+    #[serde(default)]
+    pub uninstall: Uninstall,
+    /// Whether download URLs may use plain http (--allow-insecure).
+    #[serde(skip)]
+    pub allow_insecure: bool,
+    /// Whether URLs may be local paths: only in a manifest read from disk.
+    #[serde(skip)]
+    pub allow_local: bool,
+    /// Files, components' included, built for other architectures; set by
+    /// [`Manifest::for_arch`] so extraction leaves them out.
+    #[serde(skip)]
+    pub other_arch_files: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct FileEntry {
-    pub name: String,
+fn first_schema_version() -> u32 {
+    1
+}
+
+/// Only the schema and bootstrapper versions of a manifest, read before the rest so a
+/// newer schema or bootstrapper is asked for even when the other fields no longer parse.
+#[derive(Deserialize)]
+struct SchemaProbe {
+    #[serde(default = "first_schema_version")]
+    schema_version: u32,
+    #[serde(default)]
+    min_bootstrapper_version: Option<Version>,
+    #[serde(default)]
+    bootstrapper_download_url: Option<String>,
+}
+
+/// The formats a manifest can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    Json,
+    Toml,
+}
+
+impl ManifestFormat {
+    /// The format a `Content-Type` names, if it names one.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/json" | "text/json" => Some(Self::Json),
+            "application/toml" | "application/x-toml" | "text/toml" | "text/x-toml" => Some(Self::Toml),
+            other if other.ends_with("+json") => Some(Self::Json),
+            other if other.ends_with("+toml") => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// The format the extension of `path` (a file path or URL path) names, if any.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("json") {
+            Some(Self::Json)
+        } else if extension.eq_ignore_ascii_case("toml") {
+            Some(Self::Toml)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Json => "JSON",
+            Self::Toml => "TOML",
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(self, text: &str) -> Result<T> {
+        // Both parsers' messages say where in the text the problem is.
+        let result = match self {
+            Self::Json => {
+                let mut deserializer = serde_json::Deserializer::from_str(text);
+                serde_path_to_error::deserialize(&mut deserializer)
+                    .map_err(|e| describe_json_error(text, e.inner(), Some(e.path())))
+                    .and_then(|value| {
+                        deserializer.end().map_err(|e| describe_json_error(text, &e, None))?;
+                        Ok(value)
+                    })
+            }
+            Self::Toml => toml::from_str(text).map_err(anyhow::Error::from),
+        };
+        result.with_context(|| format!("Failed to parse manifest as {}", self.name()))
+    }
+}
+
+/// Reads `text` as `format`, or when the format is unknown as JSON and then as TOML.
+pub(crate) fn deserialize<T: DeserializeOwned>(text: &str, format: Option<ManifestFormat>) -> Result<T> {
+    if let Some(title) = html_page(text) {
+        anyhow::bail!(
+            "Got an HTML page{} instead of the manifest; the manifest URL may be wrong, or a proxy \
+             or login page answered in its place",
+            title.map_or_else(String::new, |title| format!(" ({:?})", title))
+        );
+    }
+    if let Some(format) = format {
+        return format.deserialize(text);
+    }
+    let json_error = match ManifestFormat::Json.deserialize(text) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+    ManifestFormat::Toml.deserialize(text).map_err(|toml_error| {
+        anyhow::anyhow!("Manifest is neither JSON nor TOML. {:#}. {:#}", json_error, toml_error)
+    })
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct Prerequisites {
-    #[serde(rename = "windows_version_min")]
+/// Whether `text` is an HTML page, e.g. an error page served in place of the manifest,
+/// and its title if it has one.
+fn html_page(text: &str) -> Option<Option<String>> {
+    let page = text.trim_start_matches('\u{feff}').trim_start();
+    // ASCII lowercasing keeps the byte offsets of `page`.
+    let lower = page.to_ascii_lowercase();
+    if !lower.starts_with("<!doctype html") && !lower.starts_with("<html") {
+        return None;
+    }
+    let title = lower.find("<title>").and_then(|open| {
+        let begin = open + "<title>".len();
+        let end = begin + lower[begin..].find("</title>")?;
+        let title = page[begin..end].split_whitespace().collect::<Vec<_>>().join(" ");
+        Some(title).filter(|title| !title.is_empty())
+    });
+    Some(title)
+}
+
+/// serde_json's message, which gives the line and column, followed by the JSON `path` of
+/// the value it was reading and the offending line with a caret under the column.
+fn describe_json_error(text: &str, error: &serde_json::Error, path: Option<&serde_path_to_error::Path>) -> anyhow::Error {
+    if error.line() == 0 {
+        return anyhow::anyhow!("{}", error);
+    }
+    let line_start: usize = text.split_inclusive('\n').take(error.line() - 1).map(str::len).sum();
+    let line = text[line_start..].lines().next().unwrap_or_default();
+
+    let mut message = error.to_string();
+    // The top level is ".".
+    if let Some(path) = path.map(ToString::to_string).filter(|path| path != ".") {
+        message.push_str(&format!(", in {}", path));
+    }
+    message.push('\n');
+    message.push_str(&snippet(line, error.line(), error.column()));
+    anyhow::anyhow!(message)
+}
+
+/// `line`, number `number`, with a caret under `column`; long lines, like those of a
+/// minified manifest, are cut to the part around it.
+fn snippet(line: &str, number: usize, column: usize) -> String {
+    const WIDTH: usize = 60;
+    let chars: Vec<char> = line.chars().collect();
+    let column = line
+        .char_indices()
+        .take_while(|(i, _)| *i < column)
+        .count()
+        .max(1);
+    let start = column.saturating_sub(WIDTH / 2).min(chars.len().saturating_sub(WIDTH));
+    let end = (start + WIDTH).min(chars.len());
+    let before = if start > 0 { "..." } else { "" };
+    let after = if end < chars.len() { "..." } else { "" };
+    let shown: String = chars[start..end].iter().collect();
+    let gutter = number.to_string();
+    format!(
+        "{} | {}{}{}\n{} | {}^",
+        gutter,
+        before,
+        shown,
+        after,
+        " ".repeat(gutter.len()),
+        " ".repeat(before.len() + column - 1 - start)
+    )
+}
+
+/// How release zip requests authenticate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <token>` with the token from --auth-token.
+    Bearer,
+}
+
+/// A release listed under `channels` or `history`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub version: Version,
+    #[serde(default)]
+    pub release_zip_url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
     #[serde(default)]
-    pub windows_version_min: Option<String>,
-    #[serde(rename = "vc_redist")]
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract_to: Option<String>,
+    #[serde(default)]
+    pub archives: Vec<Archive>,
+    #[serde(default)]
+    pub release_zip_sig_url: Option<String>,
+    #[serde(default, alias = "release_zip_size")]
+    pub size_bytes: Option<u64>,
+    #[serde(default)]
+    pub installed_size_bytes: Option<u64>,
+    pub files: Vec<FileEntry>,
+    #[serde(default)]
+    pub release_notes_url: Option<String>,
+    #[serde(default)]
+    pub release_notes: Option<String>,
+    /// Replaces the top-level prerequisites for this release.
+    #[serde(default)]
+    pub prerequisites: Option<Prerequisites>,
     #[serde(default)]
-    pub vc_redist: Option<VcRedist>,
+    pub targets: BTreeMap<String, Target>,
+    #[serde(default)]
+    pub patches: Vec<Patch>,
+    /// Replaces the top-level components for this release.
+    #[serde(default)]
+    pub components: Option<Vec<Component>>,
 }
 
+/// A part of the release the user can leave out, e.g. HD textures or extra locales.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VcRedist {
+pub struct Component {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Files of the component. They are in the release zip unless the component has a
+    /// zip of its own.
+    pub files: Vec<FileEntry>,
+    #[serde(default)]
+    pub release_zip_url: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+    /// Detached minisign signature over the component's zip.
+    #[serde(default)]
+    pub release_zip_sig_url: Option<String>,
+    /// Selected unless the user says otherwise.
+    #[serde(default)]
+    pub default: bool,
+    /// Always installed.
+    #[serde(default)]
     pub required: bool,
-    pub url: String,
+    /// Set by [`Manifest::for_components`].
+    #[serde(skip)]
+    pub selected: bool,
 }
 
-impl Manifest {
-    pub fn from_url(url: &str) -> Result<Self> {
-        log::info!("Fetching manifest from: {}", url);
-        let response = reqwest::blocking::get(url)
-            .context("Failed to fetch manifest from URL")?;
+/// A patch turning an installed `from_version` into this release, listed under `patches`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Patch {
+    pub from_version: Version,
+    pub patch_url: String,
+    pub sha256: String,
+    pub algorithm: PatchAlgorithm,
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+}
 
-        if !response.status().is_success() {
-            anyhow::bail!("Manifest fetch failed with status: {}", response.status());
+/// How the files in a patch archive encode their changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchAlgorithm {
+    Bsdiff,
+    /// zstd `--patch-from`, with the installed file as reference.
+    Zstd,
+}
+
+impl PatchAlgorithm {
+    /// Suffix of the per-file patches in the archive.
+    pub fn extension(self) -> &'static str {
+        match self {
+            PatchAlgorithm::Bsdiff => ".bsdiff",
+            PatchAlgorithm::Zstd => ".zst",
         }
+    }
+}
 
-        let text = response.text().context("Failed to read manifest response")?;
-        let manifest: Manifest = serde_json::from_str(&text)
-            .context("Failed to parse manifest JSON")?;
+/// A build of a release for one CPU architecture, listed under `targets`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Target {
+    #[serde(default)]
+    pub release_zip_url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    #[serde(default)]
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract_to: Option<String>,
+    #[serde(default)]
+    pub archives: Vec<Archive>,
+    #[serde(default)]
+    pub release_zip_sig_url: Option<String>,
+    #[serde(default, alias = "release_zip_size")]
+    pub size_bytes: Option<u64>,
+    #[serde(default)]
+    pub installed_size_bytes: Option<u64>,
+    pub files: Vec<FileEntry>,
+    #[serde(default)]
+    pub patches: Vec<Patch>,
+}
 
-        manifest.validate()?;
-        log::info!("Manifest validated successfully: version {}", manifest.version);
+/// A zip of the release besides the release zip, listed under `archives`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    pub sha256: String,
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+    /// Directory in the install directory the zip is extracted to, its root when absent.
+    #[serde(default)]
+    pub extract_to: Option<String>,
+    /// Detached minisign signature over the zip, like `release_zip_sig_url`.
+    #[serde(default)]
+    pub sig_url: Option<String>,
+}
 
-        Ok(manifest)
+impl Archive {
+    /// The archive URL followed by its mirrors, in the order they should be tried.
+    pub fn download_urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str()).chain(self.mirrors.iter().map(String::as_str))
     }
+}
 
-    pub fn validate(&self) -> Result<()> {
-        if self.version.is_empty() {
-            anyhow::bail!("Manifest version is empty");
+/// A file downloaded with the release and placed in the install directory, e.g. a
+/// content pack.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Asset {
+    /// Path in the install directory, e.g. `packs/hd.pak`.
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    pub sha256: String,
+    #[serde(default)]
+    pub size_bytes: Option<u64>,
+    /// Optional assets that fail to download are skipped with a warning.
+    #[serde(default = "asset_required")]
+    pub required: bool,
+    /// Detached minisign signature over the file.
+    #[serde(default)]
+    pub sig_url: Option<String>,
+}
+
+fn asset_required() -> bool {
+    true
+}
+
+impl Asset {
+    /// The asset URL followed by its mirrors, in the order they should be tried.
+    pub fn download_urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str()).chain(self.mirrors.iter().map(String::as_str))
+    }
+}
+
+/// A program run from the install directory after the files are in place, e.g. to
+/// register a COM dll.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostInstallCommand {
+    /// Path of the program relative to the install directory.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Run through a UAC prompt. Its output cannot be captured.
+    #[serde(default)]
+    pub elevated: bool,
+    /// Log a failure and go on instead of rolling the install back.
+    #[serde(default)]
+    pub ignore_failure: bool,
+}
+
+/// How the installed product presents itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Product {
+    pub display_name: String,
+    pub publisher: String,
+    /// The main program, relative to the install directory; launched after the install.
+    pub executable: String,
+    /// Icon file relative to the install directory; the executable's own icon when absent.
+    pub icon_path: Option<String>,
+    pub help_url: Option<String>,
+}
+
+impl Default for Product {
+    fn default() -> Self {
+        Self {
+            display_name: "paradise".to_string(),
+            publisher: "paradise".to_string(),
+            executable: "paradise.exe".to_string(),
+            icon_path: None,
+            help_url: None,
         }
+    }
+}
 
-        if self.release_zip_url.is_empty() {
-            anyhow::bail!("Manifest release_zip_url is empty");
+/// A shortcut to a program of the release.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShortcutEntry {
+    /// Program relative to the install directory.
+    pub target: String,
+    /// File name of the shortcut, without `.lnk`.
+    pub name: String,
+    pub location: ShortcutLocation,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Icon file relative to the install directory; the target's own icon when absent.
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub icon_index: i32,
+    /// Shown as the shortcut's tooltip.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutLocation {
+    Desktop,
+    StartMenu,
+    InstallDir,
+}
+
+/// A registry value an install writes and uninstall removes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub root: RegistryRoot,
+    /// Key under `root`, e.g. `Software\paradise`.
+    pub path: String,
+    /// Empty for the key's default value.
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: RegistryType,
+    /// Text for `string` values, in which `${install_dir}` is the install directory, or
+    /// a number for `dword` ones.
+    pub value: RegistryData,
+}
+
+/// A registry hive, written `HKCU` or `HKLM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistryRoot {
+    #[serde(rename = "HKCU")]
+    CurrentUser,
+    /// Needs an elevated bootstrapper.
+    #[serde(rename = "HKLM")]
+    LocalMachine,
+}
+
+impl RegistryRoot {
+    /// The predefined handle of the hive.
+    pub fn hkey(self) -> HKEY {
+        match self {
+            RegistryRoot::CurrentUser => HKEY_CURRENT_USER,
+            RegistryRoot::LocalMachine => HKEY_LOCAL_MACHINE,
         }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryType {
+    String,
+    Dword,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RegistryData {
+    Number(u32),
+    Text(String),
+}
+
+/// Where uninstall may look beyond the install directory.
+pub const UNINSTALL_ROOTS: [&str; 4] = ["%LOCALAPPDATA%", "%APPDATA%", "%PROGRAMDATA%", "%TEMP%"];
+
+/// What uninstall removes besides the install directory, the shortcuts and the values
+/// and variables the install set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Uninstall {
+    /// Files and directories the product creates outside the install directory, e.g.
+    /// `%LOCALAPPDATA%\paradise\cache`, removed whole. Each starts with one of
+    /// [`UNINSTALL_ROOTS`] and names the product.
+    pub remove_paths: Vec<String>,
+    /// Registry keys the product creates itself, removed with everything below them. Each
+    /// names the product.
+    pub registry_keys: Vec<RegistryKey>,
+    /// Whether an interactive uninstall asks about removing the `preserve`d user data;
+    /// otherwise it is always kept.
+    pub offer_user_data_removal: bool,
+}
+
+impl Default for Uninstall {
+    fn default() -> Self {
+        Self { remove_paths: Vec::new(), registry_keys: Vec::new(), offer_user_data_removal: true }
+    }
+}
 
-        if self.sha256.is_empty() {
-            anyhow::bail!("Manifest sha256 is empty");
+impl Uninstall {
+    /// Fails on the first path or key that is not `product`'s own.
+    pub fn validate(&self, product: &Product) -> Result<()> {
+        if let Some(problem) = self.problems(product).next() {
+            anyhow::bail!(problem);
         }
+        Ok(())
+    }
 
-        if self.files.is_empty() {
-            anyhow::bail!("Manifest files list is empty");
+    /// The section without the paths and keys that are not the product's own, each logged.
+    /// For the receipt's copy, which anyone can edit.
+    pub fn checked(&self, product: &Product) -> Uninstall {
+        for problem in self.problems(product) {
+            log::warn!("Not removing {}", problem);
         }
+        Uninstall {
+            remove_paths: self
+                .remove_paths
+                .iter()
+                .filter(|path| is_uninstall_path(path) && names_product(path, product))
+                .cloned()
+                .collect(),
+            registry_keys: self
+                .registry_keys
+                .iter()
+                .filter(|key| is_product_key(&key.path) && names_product(&key.path, product))
+                .cloned()
+                .collect(),
+            offer_user_data_removal: self.offer_user_data_removal,
+        }
+    }
 
-        for file in &self.files {
-            if file.name.is_empty() {
-                anyhow::bail!("File entry has empty name");
+    fn problems<'a>(&'a self, product: &'a Product) -> impl Iterator<Item = String> + 'a {
+        let paths = self.remove_paths.iter().enumerate().filter_map(move |(i, path)| {
+            if !is_uninstall_path(path) {
+                Some(format!("uninstall.remove_paths[{}] {:?} must be a path inside one of {}", i, path, UNINSTALL_ROOTS.join(", ")))
+            } else if !names_product(path, product) {
+                Some(format!("uninstall.remove_paths[{}] {:?} does not name the product {:?}", i, path, product.display_name))
+            } else {
+                None
             }
-        }
+        });
+        let keys = self.registry_keys.iter().enumerate().filter_map(move |(i, key)| {
+            if !is_product_key(&key.path) {
+                Some(format!("uninstall.registry_keys[{}].path {:?} is not a product's registry key", i, key.path))
+            } else if !names_product(&key.path, product) {
+                Some(format!(
+                    "uninstall.registry_keys[{}].path {:?} does not name the product {:?}",
+                    i, key.path, product.display_name
+                ))
+            } else {
+                None
+            }
+        });
+        paths.chain(keys)
+    }
+}
 
-        Ok(())
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryKey {
+    pub root: RegistryRoot,
+    /// Key under `root`, e.g. `Software\paradise`.
+    pub path: String,
+}
+
+/// Changes to the environment an install makes, undone by uninstall.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Environment {
+    /// Directories relative to the install directory appended to PATH, `.` for the
+    /// install directory itself.
+    #[serde(default)]
+    pub add_to_path: Vec<String>,
+    /// Variables to set; `%INSTALL_DIR%` in a value is the install directory.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    /// `machine` changes the environment of every user, which needs an elevated
+    /// bootstrapper; otherwise the user's own environment is changed.
+    #[serde(default)]
+    pub scope: EnvironmentScope,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvironmentScope {
+    #[default]
+    User,
+    Machine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Where the file is once extracted, e.g. `bin/win64/paradise.exe`.
+    pub name: String,
+    /// Where the file is moved to in the install directory when that is not `name`,
+    /// e.g. `paradise.exe`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Checked against the extracted file when present.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Size of the extracted file in bytes.
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// The architecture the file is for, e.g. one variant of a native DLL.
+    #[serde(default, skip_serializing_if = "FileArch::is_any")]
+    pub arch: FileArch,
+    /// Optional files, e.g. a readme or debug symbols, are checked when the release has
+    /// them but may be missing.
+    #[serde(default = "file_required")]
+    pub required: bool,
+}
+
+fn file_required() -> bool {
+    true
+}
+
+impl FileEntry {
+    /// The file's path in the install directory.
+    pub fn installed_name(&self) -> &str {
+        self.target.as_deref().unwrap_or(&self.name)
     }
+}
 
+/// The machines a `files` entry is installed on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileArch {
+    #[default]
+    #[serde(rename = "any")]
+    Any,
+    #[serde(rename = "x86_64")]
+    X86_64,
+    #[serde(rename = "aarch64")]
+    Aarch64,
+}
 
-    pub fn check_prerequisites(&self) -> Result<()> {
-        if let Some(min_version) = &self.prerequisites.windows_version_min {
-            log::info!("Checking Windows version requirement: {}", min_version);
-            let current_version = get_windows_version()?;
-            log::info!("Current Windows version: {}", current_version);
+impl FileArch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileArch::Any => "any",
+            FileArch::X86_64 => "x86_64",
+            FileArch::Aarch64 => "aarch64",
         }
+    }
 
-        if let Some(vc_redist) = &self.prerequisites.vc_redist {
-            if vc_redist.required {
-                log::info!("VC++ Redistributable may be required: {}", vc_redist.url);
-            }
-        }
+    fn is_any(&self) -> bool {
+        *self == FileArch::Any
+    }
 
-        Ok(())
+    /// Whether the file is installed on `arch` machines.
+    pub fn matches(self, arch: &str) -> bool {
+        self == FileArch::Any || self.as_str() == arch
+    }
+
+    /// Whether some machine gets both files.
+    fn overlaps(self, other: FileArch) -> bool {
+        self == FileArch::Any || other == FileArch::Any || self == other
     }
 }
 
-fn get_windows_version() -> Result<String> {
-    use windows::Win32::System::Registry::*;
-    use windows::core::PCSTR;
+/// The last manifest fetched from the network, with the validators to revalidate it.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedManifest {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    #[serde(default)]
+    format: Option<ManifestFormat>,
+    body: String,
+}
 
-    unsafe {
-        let mut hkey = HKEY::default();
-        let result = RegOpenKeyExA(
-            HKEY_LOCAL_MACHINE,
-            PCSTR(b"SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\0".as_ptr() as *const u8),
-            0,
-            KEY_READ,
-            &mut hkey,
-        );
+impl CachedManifest {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::cache_dir()?.join(CACHE_FILE))
+    }
 
-        if result.is_err() {
-            return Ok("Unknown".to_string());
+    /// The cached manifest for `url`; a corrupted cache file is deleted.
+    fn load(url: &str) -> Option<Self> {
+        let path = Self::path().ok()?;
+        let text = fs::read_to_string(&path).ok()?;
+
+        let cached: CachedManifest = match serde_json::from_str(&text) {
+            Ok(cached) => cached,
+            Err(e) => {
+                log::warn!("Discarding corrupted manifest cache {:?}: {}", path, e);
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+        };
+
+        if cached.url != url {
+            log::info!("Cached manifest is for {}, not using it", cached.url);
+            return None;
         }
+        Some(cached)
+    }
 
-        let mut version_size = 256u32;
-        let mut version_buffer = vec![0u8; version_size as usize];
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create cache directory")?;
+        }
+        let text = serde_json::to_string(self).context("Failed to serialize manifest cache")?;
+        fs::write(&path, text).context("Failed to write manifest cache")
+    }
+}
 
-        let result = RegQueryValueExA(
-            hkey,
-            PCSTR(b"CurrentVersion\0".as_ptr() as *const u8),
-            None,
-            None,
-            Some(version_buffer.as_mut_ptr()),
-            Some(&mut version_size),
-        );
+/// The last manifest fetched from the network and validated, with when it was fetched.
+#[derive(Debug, Serialize, Deserialize)]
+struct LastManifest {
+    url: String,
+    fetched_at: String,
+    #[serde(default)]
+    format: Option<ManifestFormat>,
+    body: String,
+}
 
-        let _ = RegCloseKey(hkey);
+impl LastManifest {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::cache_dir()?.join(LAST_MANIFEST_FILE))
+    }
 
-        if result.is_ok() {
-            version_buffer.truncate(version_size as usize - 1);
-            if let Ok(version) = std::str::from_utf8(&version_buffer) {
-                return Ok(version.to_string());
+    fn save(url: &str, body: &str, format: Option<ManifestFormat>) {
+        let saved = Self {
+            url: url.to_string(),
+            fetched_at: chrono::Local::now().to_rfc3339(),
+            format,
+            body: body.to_string(),
+        };
+        let result = Self::path().and_then(|path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).context("Failed to create cache directory")?;
             }
+            let text = serde_json::to_string(&saved).context("Failed to serialize the manifest")?;
+            fs::write(&path, text).context("Failed to write the last manifest")
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to keep the manifest for offline use: {:#}", e);
         }
+    }
+}
+
+/// A manifest from an earlier fetch, used while the manifest host is unreachable.
+#[derive(Debug)]
+pub struct StaleManifest {
+    pub manifest: Manifest,
+    pub fetched_at: chrono::DateTime<chrono::FixedOffset>,
+}
 
-        Ok("Unknown".to_string())
+impl StaleManifest {
+    pub fn age(&self) -> std::time::Duration {
+        chrono::Utc::now()
+            .signed_duration_since(self.fetched_at)
+            .to_std()
+            .unwrap_or_default()
     }
 }
 
+impl Manifest {
+    /// The manifest last fetched from `url`, validated again, if there is one.
+    pub fn last_fetched(url: &str, allow_insecure: bool) -> Result<Option<StaleManifest>> {
+        let path = LastManifest::path()?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read the last manifest"),
+        };
+        let last: LastManifest = serde_json::from_str(&text).context("Failed to parse the last manifest")?;
+        if last.url != url {
+            log::info!("The last manifest is from {}, not {}", last.url, url);
+            return Ok(None);
+        }
+        let fetched_at = chrono::DateTime::parse_from_rfc3339(&last.fetched_at)
+            .context("The last manifest has an invalid fetch time")?;
+        let manifest = Self::parse(&last.body, last.format, allow_insecure, None)?;
+        Ok(Some(StaleManifest { manifest, fetched_at }))
+    }
+
+    /// Fetches the manifest, revalidating the cached copy with `If-None-Match` unless
+    /// `use_cache` is false. The manifest is JSON or TOML, as its `Content-Type` or
+    /// extension says.
+    pub fn from_url(http: &HttpClient, url: &str, use_cache: bool) -> Result<Self> {
+        if let Some(path) = local_path(url) {
+            log::info!("Reading manifest from local file: {:?}", path);
+            let text = read_manifest_file(&path)?;
+            let format = ManifestFormat::from_extension(&path.to_string_lossy());
+            return Self::parse(&text, format, http.allow_insecure(), path.parent())
+                .with_context(|| format!("Invalid manifest file {}", path.display()));
+        }
+
+        log::info!("Fetching manifest from: {}", url);
+
+        let cached = if use_cache {
+            CachedManifest::load(url)
+        } else {
+            log::info!("Ignoring the manifest cache");
+            None
+        };
+
+        let request = |authorize: bool| -> Result<_> {
+            let mut request = http.get_manifest(url)?;
+            if let Some(cached) = &cached {
+                if let Some(etag) = &cached.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &cached.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            if authorize {
+                request = http.authorize(request);
+            }
+            http.send(request).context("Failed to fetch manifest from URL")
+        };
+
+        // The token only goes to a manifest host that asks for it.
+        let mut authorized = false;
+        let mut response = request(false)?;
+        if http.check_auth(&response, false).is_err() && http.has_auth_token() {
+            log::info!(
+                "Manifest request answered {}, retrying with the auth token",
+                response.status()
+            );
+            authorized = true;
+            response = request(true)?;
+        }
+        http.check_auth(&response, authorized)?;
+        let started = Instant::now();
+
+        if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status(), &cached) {
+            log::info!("Manifest not modified, using the cached copy");
+            let manifest = Self::parse(&cached.body, cached.format, http.allow_insecure(), None)?;
+            LastManifest::save(url, &cached.body, cached.format);
+            return Ok(manifest);
+        }
+
+        if !response.status().is_success() {
+            return Err(StatusError::new("Manifest fetch", &response).into());
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let format = header(CONTENT_TYPE)
+            .as_deref()
+            .and_then(ManifestFormat::from_content_type)
+            .or_else(|| {
+                let path = reqwest::Url::parse(url).ok()?.path().to_string();
+                ManifestFormat::from_extension(&path)
+            });
+
+        let text = response
+            .text()
+            .map_err(|e| http.describe(e, started))
+            .context("Failed to read manifest response")?;
+        let manifest = Self::parse(&text, format, http.allow_insecure(), None)?;
+        log::info!("Manifest fetched from the network");
+        LastManifest::save(url, &text, format);
+
+        if etag.is_some() || last_modified.is_some() {
+            let cached = CachedManifest {
+                url: url.to_string(),
+                etag,
+                last_modified,
+                format,
+                body: text,
+            };
+            if let Err(e) = cached.save() {
+                log::warn!("Failed to cache manifest: {:#}", e);
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        log::info!("Reading manifest from: {:?}", path);
+        let text = read_manifest_file(path)?;
+        let format = ManifestFormat::from_extension(&path.to_string_lossy());
+        // Offline installs never download from the manifest's URLs.
+        Self::parse(&text, format, true, path.parent())
+            .with_context(|| format!("Invalid manifest file {}", path.display()))
+    }
+
+    /// Parses and validates a manifest in `format`, or whichever of JSON and TOML it is
+    /// when unknown; relative local paths in it resolve against `base`, the directory of
+    /// a manifest read from disk.
+    pub(crate) fn parse(
+        text: &str,
+        format: Option<ManifestFormat>,
+        allow_insecure: bool,
+        base: Option<&Path>,
+    ) -> Result<Self> {
+        if let Ok(probe) = deserialize::<SchemaProbe>(text, format) {
+            check_schema_version(probe.schema_version)?;
+            if let Some(min) = &probe.min_bootstrapper_version {
+                check_bootstrapper_version(
+                    min,
+                    env!("CARGO_PKG_VERSION"),
+                    probe.bootstrapper_download_url.as_deref(),
+                )?;
+            }
+        }
+        let mut manifest: Manifest = deserialize(text, format)?;
+        manifest.allow_insecure = allow_insecure;
+        manifest.allow_local = base.is_some();
+        manifest.normalize_hashes();
+        manifest.promote_first_archive();
+        if let Some(base) = base {
+            manifest.resolve_paths(base);
+        }
+
+        manifest.validate()?;
+        log::info!(
+            "Manifest validated successfully: version {}, schema {}",
+            manifest.version,
+            manifest.schema_version
+        );
+
+        Ok(manifest)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        check_schema_version(self.schema_version)?;
+
+        if self.version.is_empty() {
+            anyhow::bail!("Manifest version is empty");
+        }
+
+        self.version
+            .check()
+            .with_context(|| format!("Manifest version {:?} is not a semver version", self.version.as_str()))?;
+
+        if let Some(min) = &self.min_bootstrapper_version {
+            min.check().with_context(|| {
+                format!("Manifest min_bootstrapper_version {:?} is not a semver version", min.as_str())
+            })?;
+        }
+
+        if let Some(min) = &self.min_supported_version {
+            min.check().with_context(|| {
+                format!("Manifest min_supported_version {:?} is not a semver version", min.as_str())
+            })?;
+        }
+
+        if self.release_zip_url.is_empty() {
+            anyhow::bail!("Manifest has neither a release_zip_url nor archives");
+        }
+
+        if self.size_bytes == Some(0) {
+            anyhow::bail!("Manifest release zip size is 0 bytes");
+        }
+
+        self.check_urls()?;
+
+        if self.eula_url.is_some() && self.eula_text.is_some() {
+            anyhow::bail!("Manifest has both eula_url and eula_text");
+        }
+
+        if self.release_notes_url.is_some() && self.release_notes.is_some() {
+            anyhow::bail!("Manifest has both release_notes_url and release_notes");
+        }
+
+        check_sha256("sha256", &self.sha256)?;
+
+        for extract_to in self.extract_dirs() {
+            if !is_relative_inside(extract_to) {
+                anyhow::bail!(
+                    "extract_to {:?} must be a relative path inside the install directory",
+                    extract_to
+                );
+            }
+        }
+        for (i, archive) in self.archives.iter().enumerate() {
+            check_sha256(&format!("archives[{}].sha256", i), &archive.sha256)?;
+        }
+
+        if self.files.is_empty() {
+            anyhow::bail!("Manifest files list is empty");
+        }
+
+        for (i, file) in self.files.iter().enumerate() {
+            if file.name.is_empty() {
+                anyhow::bail!("files[{}].name is empty", i);
+            }
+            if let Some(sha256) = &file.sha256 {
+                check_sha256(&format!("files[{}].sha256", i), sha256)?;
+            }
+        }
+        self.check_file_targets()?;
+        let executable = self.product.executable.replace('\\', "/");
+        let files = self
+            .files
+            .iter()
+            .chain(self.components.iter().flat_map(|component| &component.files));
+        if let Some(file) = files
+            .filter(|file| !file.required)
+            .find(|file| file.installed_name().replace('\\', "/").eq_ignore_ascii_case(&executable))
+        {
+            anyhow::bail!("File {} is the product's executable and cannot be optional", file.name);
+        }
+
+        for (i, asset) in self.assets.iter().enumerate() {
+            let name = Path::new(&asset.name);
+            if asset.name.is_empty()
+                || !name
+                    .components()
+                    .all(|component| matches!(component, path::Component::Normal(_)))
+            {
+                anyhow::bail!(
+                    "assets[{}].name {:?} must be a relative path inside the install directory",
+                    i,
+                    asset.name
+                );
+            }
+            check_sha256(&format!("assets[{}].sha256", i), &asset.sha256)?;
+        }
+
+        for (i, component) in self.components.iter().enumerate() {
+            if component.name.is_empty()
+                || !component
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            {
+                anyhow::bail!(
+                    "components[{}].name {:?} may only use letters, digits, '-', '_' and '.'",
+                    i,
+                    component.name
+                );
+            }
+            if self.components[..i].iter().any(|other| other.name == component.name) {
+                anyhow::bail!("Component {} is listed twice", component.name);
+            }
+            if component.files.is_empty() {
+                anyhow::bail!("Component {} has no files", component.name);
+            }
+            for (j, file) in component.files.iter().enumerate() {
+                if let Some(sha256) = &file.sha256 {
+                    check_sha256(&format!("components[{}].files[{}].sha256", i, j), sha256)?;
+                }
+                if file.name.is_empty() {
+                    anyhow::bail!("components[{}].files[{}].name is empty", i, j);
+                }
+                if !Path::new(&file.name)
+                    .components()
+                    .all(|part| matches!(part, path::Component::Normal(_)))
+                {
+                    anyhow::bail!(
+                        "components[{}].files[{}].name {:?} must be a relative path inside the install directory",
+                        i,
+                        j,
+                        file.name
+                    );
+                }
+                if self.files.iter().any(|other| other.name == file.name) {
+                    anyhow::bail!(
+                        "File {} is listed both in files and in component {}",
+                        file.name,
+                        component.name
+                    );
+                }
+            }
+            if component.release_zip_url.is_some() {
+                match &component.sha256 {
+                    Some(sha256) => check_sha256(&format!("components[{}].sha256", i), sha256)?,
+                    None => anyhow::bail!("Component {} has a zip but no sha256", component.name),
+                }
+            }
+        }
+
+        if let Some(environment) = &self.environment {
+            for (i, dir) in environment.add_to_path.iter().enumerate() {
+                if dir != "." && !is_relative_inside(dir) {
+                    anyhow::bail!(
+                        "environment.add_to_path[{}] {:?} must be a relative path inside the install directory",
+                        i,
+                        dir
+                    );
+                }
+            }
+            for name in environment.variables.keys() {
+                if name.is_empty() || name.contains(['=', '%']) || name.chars().any(char::is_whitespace) {
+                    anyhow::bail!("Environment variable name {:?} is invalid", name);
+                }
+                if name.eq_ignore_ascii_case("PATH") {
+                    anyhow::bail!("Environment variables cannot replace PATH, use add_to_path");
+                }
+            }
+        }
+
+        if !is_file_name(&self.product.display_name) {
+            anyhow::bail!("product.display_name {:?} is not a valid file name", self.product.display_name);
+        }
+        if self.product.publisher.trim().is_empty() {
+            anyhow::bail!("product.publisher is empty");
+        }
+        let product_paths = [("executable", Some(&self.product.executable)), ("icon_path", self.product.icon_path.as_ref())];
+        for (field, path) in product_paths {
+            if let Some(path) = path.filter(|path| !is_relative_inside(path)) {
+                anyhow::bail!("product.{} {:?} must be a relative path inside the install directory", field, path);
+            }
+        }
+
+        if let Some((i, pattern)) = self.preserve.iter().enumerate().find(|(_, pattern)| !is_relative_inside(pattern)) {
+            anyhow::bail!("preserve[{}] {:?} must be a relative path inside the install directory", i, pattern);
+        }
+
+        if let Some((i, name)) = self.close_processes.iter().enumerate().find(|(_, name)| !is_relative_inside(name)) {
+            anyhow::bail!(
+                "close_processes[{}] {:?} must be a relative path inside the install directory",
+                i,
+                name
+            );
+        }
+
+        for (i, shortcut) in self.shortcuts.iter().flatten().enumerate() {
+            if !is_relative_inside(&shortcut.target) {
+                anyhow::bail!(
+                    "shortcuts[{}].target {:?} must be a relative path inside the install directory",
+                    i,
+                    shortcut.target
+                );
+            }
+            if let Some(icon) = shortcut.icon.as_deref().filter(|icon| !is_relative_inside(icon)) {
+                anyhow::bail!(
+                    "shortcuts[{}].icon {:?} must be a relative path inside the install directory",
+                    i,
+                    icon
+                );
+            }
+            if !is_file_name(&shortcut.name) {
+                anyhow::bail!("shortcuts[{}].name {:?} is not a valid file name", i, shortcut.name);
+            }
+        }
+        let shortcuts = self.shortcuts.as_deref().unwrap_or_default();
+        for (i, shortcut) in shortcuts.iter().enumerate() {
+            if shortcuts[..i].iter().any(|other| {
+                other.location == shortcut.location && other.name.eq_ignore_ascii_case(&shortcut.name)
+            }) {
+                anyhow::bail!("Shortcut {:?} is listed twice for the same location", shortcut.name);
+            }
+        }
+
+        for (i, entry) in self.registry.iter().enumerate() {
+            let path = entry.path.trim_matches('\\');
+            if path.is_empty() || path.split('\\').any(|part| part.is_empty() || part == "..") {
+                anyhow::bail!("registry[{}].path {:?} is not a registry key", i, entry.path);
+            }
+            match (entry.kind, &entry.value) {
+                (RegistryType::String, RegistryData::Text(_)) | (RegistryType::Dword, RegistryData::Number(_)) => {}
+                (RegistryType::String, _) => anyhow::bail!("registry[{}] is a string value, but its value is a number", i),
+                (RegistryType::Dword, _) => anyhow::bail!("registry[{}] is a dword value, but its value is not a number", i),
+            }
+        }
+
+        self.uninstall.validate(&self.product)?;
+
+        for (i, post_install) in self.post_install.iter().enumerate() {
+            let command = Path::new(&post_install.command);
+            if post_install.command.is_empty()
+                || !command
+                    .components()
+                    .all(|component| matches!(component, path::Component::Normal(_)))
+            {
+                anyhow::bail!(
+                    "post_install[{}].command {:?} must be a relative path inside the install directory",
+                    i,
+                    post_install.command
+                );
+            }
+        }
+
+        for (i, patch) in self.patches.iter().enumerate() {
+            if patch.from_version.is_empty() {
+                anyhow::bail!("Patch to {} needs a from_version", self.version);
+            }
+            patch.from_version.check().with_context(|| {
+                format!("patches[{}].from_version {:?} is not a semver version", i, patch.from_version.as_str())
+            })?;
+            check_sha256(&format!("patches[{}].sha256", i), &patch.sha256)?;
+        }
+
+        // Every channel, not just the one being installed, so a broken one is caught
+        // the first time anyone fetches the manifest.
+        for (name, release) in &self.channels {
+            self.with_release(release)
+                .with_context(|| format!("Channel {:?} is invalid", name))?;
+        }
+        // Likewise every release a rollback could go back to, so a stale hash is caught
+        // when the manifest is published rather than when it is needed.
+        for release in &self.history {
+            self.with_release(release)
+                .with_context(|| format!("History release {} is invalid", release.version))?;
+        }
+        for (arch, target) in &self.targets {
+            self.with_target(target)
+                .with_context(|| format!("Target {:?} is invalid", arch))?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |url: &mut String| {
+            if let Some(path) = local_path(url).filter(|path| path.is_relative()) {
+                *url = base.join(path).display().to_string();
+            }
+        };
+
+        let resolve_archives = |archives: &mut Vec<Archive>| {
+            for archive in archives {
+                resolve(&mut archive.url);
+                archive.mirrors.iter_mut().for_each(resolve);
+                archive.sig_url.iter_mut().for_each(resolve);
+            }
+        };
+
+        resolve(&mut self.release_zip_url);
+        self.mirrors.iter_mut().for_each(resolve);
+        resolve_archives(&mut self.archives);
+        self.release_zip_sig_url.iter_mut().for_each(resolve);
+        self.eula_url.iter_mut().for_each(resolve);
+        self.release_notes_url.iter_mut().for_each(resolve);
+        self.patches.iter_mut().for_each(|patch| resolve(&mut patch.patch_url));
+        for release in self.channels.values_mut().chain(&mut self.history) {
+            resolve(&mut release.release_zip_url);
+            release.mirrors.iter_mut().for_each(resolve);
+            resolve_archives(&mut release.archives);
+            release.release_zip_sig_url.iter_mut().for_each(resolve);
+            release.release_notes_url.iter_mut().for_each(resolve);
+            release.patches.iter_mut().for_each(|patch| resolve(&mut patch.patch_url));
+            for target in release.targets.values_mut() {
+                resolve(&mut target.release_zip_url);
+                target.mirrors.iter_mut().for_each(resolve);
+                resolve_archives(&mut target.archives);
+                target.release_zip_sig_url.iter_mut().for_each(resolve);
+                target.patches.iter_mut().for_each(|patch| resolve(&mut patch.patch_url));
+            }
+        }
+        for target in self.targets.values_mut() {
+            resolve(&mut target.release_zip_url);
+            target.mirrors.iter_mut().for_each(resolve);
+            resolve_archives(&mut target.archives);
+            target.release_zip_sig_url.iter_mut().for_each(resolve);
+            target.patches.iter_mut().for_each(|patch| resolve(&mut patch.patch_url));
+        }
+        let components = self
+            .channels
+            .values_mut()
+            .chain(&mut self.history)
+            .flat_map(|release| release.components.iter_mut().flatten())
+            .chain(&mut self.components);
+        for component in components {
+            component.release_zip_url.iter_mut().for_each(resolve);
+            component.release_zip_sig_url.iter_mut().for_each(resolve);
+        }
+        for asset in &mut self.assets {
+            resolve(&mut asset.url);
+            asset.mirrors.iter_mut().for_each(resolve);
+            asset.sig_url.iter_mut().for_each(resolve);
+        }
+    }
+
+    /// Every URL in the manifest, with the field it is in, e.g. `mirrors[1]`. Those of
+    /// channels and targets are checked with the manifests they turn into.
+    fn urls(&self) -> Vec<(String, &str)> {
+        let mut urls = vec![("release_zip_url".to_string(), self.release_zip_url.as_str())];
+        let optional = [
+            ("release_zip_sig_url", &self.release_zip_sig_url),
+            ("eula_url", &self.eula_url),
+            ("release_notes_url", &self.release_notes_url),
+            ("license_check_url", &self.license_check_url),
+            ("bootstrapper_download_url", &self.bootstrapper_download_url),
+            ("product.help_url", &self.product.help_url),
+        ];
+        for (field, url) in optional {
+            if let Some(url) = url {
+                urls.push((field.to_string(), url));
+            }
+        }
+        for (i, component) in self.components.iter().enumerate() {
+            if let Some(url) = &component.release_zip_url {
+                urls.push((format!("components[{}].release_zip_url", i), url));
+            }
+            if let Some(url) = &component.release_zip_sig_url {
+                urls.push((format!("components[{}].release_zip_sig_url", i), url));
+            }
+        }
+
+        urls.extend(self.mirrors.iter().enumerate().map(|(i, url)| (format!("mirrors[{}]", i), url.as_str())));
+        for (i, archive) in self.archives.iter().enumerate() {
+            urls.push((format!("archives[{}].url", i), &archive.url));
+            urls.extend(
+                archive.mirrors.iter().enumerate().map(|(j, url)| (format!("archives[{}].mirrors[{}]", i, j), url.as_str())),
+            );
+            if let Some(url) = &archive.sig_url {
+                urls.push((format!("archives[{}].sig_url", i), url));
+            }
+        }
+        for (i, asset) in self.assets.iter().enumerate() {
+            urls.push((format!("assets[{}].url", i), &asset.url));
+            urls.extend(
+                asset.mirrors.iter().enumerate().map(|(j, url)| (format!("assets[{}].mirrors[{}]", i, j), url.as_str())),
+            );
+            if let Some(url) = &asset.sig_url {
+                urls.push((format!("assets[{}].sig_url", i), url));
+            }
+        }
+        for (i, patch) in self.patches.iter().enumerate() {
+            urls.push((format!("patches[{}].patch_url", i), &patch.patch_url));
+        }
+        urls.extend(
+            self.prerequisites
+                .urls()
+                .map(|(i, url)| (format!("prerequisites[{}].url", i), url)),
+        );
+        urls
+    }
+
+    /// Checks every URL of the manifest and reports all the bad ones at once. Downloads
+    /// must be https so a tampered file can't come with a matching tampered hash; plain
+    /// http needs --allow-insecure. Local paths are trusted like the manifest itself, so
+    /// only a manifest read from disk may have them.
+    ///
+    /// URLs with placeholders are checked as they would be for this version, the default
+    /// channel and x86_64, after unknown placeholders are reported.
+    fn check_urls(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        let mut insecure = false;
+        for (field, url) in self.urls() {
+            let expanded = match is_templated(&field) {
+                true => expand_placeholders(url, self.version.as_str(), DEFAULT_ARCH, DEFAULT_CHANNEL),
+                false => Ok(url.to_string()),
+            };
+            let problem = match expanded.map_err(|problem| format!("{} {:?} {}", field, url, problem)) {
+                Ok(url) => match self.check_url(&field, &url) {
+                    Ok(()) => continue,
+                    Err(problem) => problem,
+                },
+                Err(problem) => problem,
+            };
+            insecure |= problem.contains("got http:");
+            problems.push(problem);
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let hint = if insecure { " (pass --allow-insecure for test servers)" } else { "" };
+        anyhow::bail!("Manifest has invalid URLs: {}{}", problems.join("; "), hint)
+    }
+
+    /// Why `url`, the manifest's `field`, cannot be used, if it cannot.
+    fn check_url(&self, field: &str, url: &str) -> Result<(), String> {
+        if url.trim().is_empty() {
+            return Err(format!("{} is empty", field));
+        }
+
+        // The license check is a request to a server, never a file.
+        if field != "license_check_url" {
+            match local_path(url) {
+                Some(_) if !self.allow_local => {
+                    return Err(format!(
+                        "{} {} is a local path, which only a manifest read from disk may use",
+                        field, url
+                    ))
+                }
+                Some(path) if path.is_absolute() => return Ok(()),
+                Some(_) => {
+                    return Err(format!(
+                        "{} {} is a relative path, which only a manifest read from disk may use",
+                        field, url
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        let parsed = reqwest::Url::parse(url).map_err(|e| format!("{} {} is not a valid URL: {}", field, url, e))?;
+        match parsed.scheme() {
+            "https" if parsed.host_str().is_some_and(|host| !host.is_empty()) => Ok(()),
+            "https" => Err(format!("{} {} has no host", field, url)),
+            "http" if self.allow_insecure => {
+                log::warn!("Allowing plain http {} {}", field, url);
+                Ok(())
+            }
+            scheme => Err(format!("{} must use https, got {}: {}", field, scheme, url)),
+        }
+    }
+
+    /// Checks that every `target` is a path inside the install directory that no other
+    /// file ends up at too. Windows paths are compared without regard to case.
+    fn check_file_targets(&self) -> Result<()> {
+        let files: Vec<&FileEntry> = self
+            .files
+            .iter()
+            .chain(self.components.iter().flat_map(|component| &component.files))
+            .collect();
+        let key = |name: &str| name.replace('\\', "/").to_lowercase();
+
+        for (i, file) in files.iter().enumerate() {
+            let Some(target) = &file.target else {
+                continue;
+            };
+            if !is_relative_inside(target) {
+                anyhow::bail!(
+                    "File {} has target {:?}, which must be a relative path inside the install directory",
+                    file.name,
+                    target
+                );
+            }
+            if let Some(other) = files
+                .iter()
+                .enumerate()
+                .find(|(j, other)| {
+                    *j != i && other.arch.overlaps(file.arch) && key(other.installed_name()) == key(target)
+                })
+                .map(|(_, other)| other)
+            {
+                anyhow::bail!("Files {} and {} are both installed as {}", file.name, other.name, target);
+            }
+            if files[..i].iter().any(|other| other.target.is_some() && key(&other.name) == key(&file.name)) {
+                anyhow::bail!("File {} is moved to more than one target", file.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of every channel this manifest can serve, the default one first.
+    pub fn channel_names(&self) -> Vec<&str> {
+        let mut names = vec![DEFAULT_CHANNEL];
+        names.extend(
+            self.channels
+                .keys()
+                .map(String::as_str)
+                .filter(|name| *name != DEFAULT_CHANNEL),
+        );
+        names
+    }
+
+    /// Returns the manifest with the release fields of `channel` in place of the top-level ones.
+    pub fn for_channel(&self, channel: &str) -> Result<Manifest> {
+        let Some(release) = self.channels.get(channel) else {
+            if channel == DEFAULT_CHANNEL {
+                return Ok(self.clone());
+            }
+            anyhow::bail!(
+                "Unknown release channel {:?}, available channels: {}",
+                channel,
+                self.channel_names().join(", ")
+            );
+        };
+
+        self.with_release(release)
+            .with_context(|| format!("Channel {:?} is invalid", channel))
+    }
+
+    /// Every version this manifest can install, latest first.
+    pub fn available_versions(&self) -> Vec<&str> {
+        let mut versions = vec![self.version.as_str()];
+        for release in self.channels.values().chain(&self.history) {
+            if !versions.contains(&release.version.as_str()) {
+                versions.push(release.version.as_str());
+            }
+        }
+        versions
+    }
+
+    /// Returns the manifest narrowed to the release with exactly `version`.
+    pub fn for_version(&self, version: &str) -> Result<Manifest> {
+        if self.version == version {
+            return Ok(self.clone());
+        }
+
+        let release = self
+            .channels
+            .values()
+            .chain(&self.history)
+            .find(|release| release.version == version)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Version {} is not in the manifest, available versions: {}",
+                    version,
+                    self.available_versions().join(", ")
+                )
+            })?;
+
+        self.with_release(release)
+            .with_context(|| format!("Release {} is invalid", version))
+    }
+
+    /// Returns the manifest narrowed to the `history` release with `version`, to roll
+    /// back to.
+    pub fn for_history_version(&self, version: &str) -> Result<Manifest> {
+        let release = self
+            .history
+            .iter()
+            .find(|release| release.version == version)
+            .ok_or_else(|| match self.history.is_empty() {
+                true => anyhow::anyhow!("The manifest has no earlier versions to roll back to"),
+                false => anyhow::anyhow!(
+                    "Version {} is not in the manifest's history, earlier versions: {}",
+                    version,
+                    self.history_versions().join(", ")
+                ),
+            })?;
+
+        self.with_release(release)
+            .with_context(|| format!("Release {} is invalid", version))
+    }
+
+    /// The `min_supported_version` that `version` is older than, if it is.
+    pub fn unsupported(&self, version: &Version) -> Option<&Version> {
+        self.min_supported_version.as_ref().filter(|min| version < *min)
+    }
+
+    /// Versions listed in `history`, as written.
+    pub fn history_versions(&self) -> Vec<&str> {
+        self.history.iter().map(|release| release.version.as_str()).collect()
+    }
+
+    fn with_release(&self, release: &Release) -> Result<Manifest> {
+        let mut manifest = self.clone();
+        manifest.version = release.version.clone();
+        manifest.release_zip_url = release.release_zip_url.clone();
+        manifest.mirrors = release.mirrors.clone();
+        manifest.sha256 = release.sha256.clone();
+        manifest.extract_to = release.extract_to.clone();
+        manifest.archives = release.archives.clone();
+        manifest.release_zip_sig_url = release.release_zip_sig_url.clone();
+        manifest.size_bytes = release.size_bytes;
+        manifest.installed_size_bytes = release.installed_size_bytes;
+        manifest.files = release.files.clone();
+        manifest.release_notes_url = release.release_notes_url.clone();
+        manifest.release_notes = release.release_notes.clone();
+        if let Some(prerequisites) = &release.prerequisites {
+            manifest.prerequisites = prerequisites.clone();
+        }
+        manifest.targets = release.targets.clone();
+        manifest.patches = release.patches.clone();
+        if let Some(components) = &release.components {
+            manifest.components = components.clone();
+        }
+        manifest.channels.clear();
+        manifest.history.clear();
+        manifest.promote_first_archive();
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Returns the manifest with the `selected` components and the required ones marked
+    /// as selected and their files added to `files`.
+    pub fn for_components(&self, selected: &[String]) -> Result<Manifest> {
+        if let Some(unknown) = selected
+            .iter()
+            .find(|name| !self.components.iter().any(|component| &component.name == *name))
+        {
+            anyhow::bail!(
+                "Unknown component {:?}, available components: {}",
+                unknown,
+                self.component_names().join(", ")
+            );
+        }
+
+        let mut manifest = self.clone();
+        for component in &mut manifest.components {
+            component.selected = component.required || selected.contains(&component.name);
+            if component.selected {
+                manifest.files.extend(component.files.iter().cloned());
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn component_names(&self) -> Vec<&str> {
+        self.components.iter().map(|component| component.name.as_str()).collect()
+    }
+
+    /// Files in the release zip that belong to components that were not selected.
+    pub fn unselected_files(&self) -> impl Iterator<Item = &str> {
+        self.components
+            .iter()
+            .filter(|component| !component.selected && component.release_zip_url.is_none())
+            .flat_map(|component| component.files.iter().map(|file| file.name.as_str()))
+    }
+
+    /// Architectures this release has a build for.
+    pub fn architectures(&self) -> Vec<&str> {
+        let mut architectures: Vec<&str> = self.targets.keys().map(String::as_str).collect();
+        for arch in self.files.iter().map(|file| file.arch).filter(|arch| !arch.is_any()) {
+            if !architectures.contains(&arch.as_str()) {
+                architectures.push(arch.as_str());
+            }
+        }
+        if !architectures.contains(&DEFAULT_ARCH) {
+            architectures.insert(0, DEFAULT_ARCH);
+        }
+        architectures
+    }
+
+    /// Returns the manifest with the build for `arch` in place of the top-level release
+    /// fields. Those are the x86_64 build unless `targets` lists x86_64 itself, and also
+    /// the build for any architecture their `files` have variants for. `files` entries
+    /// for other architectures are left out.
+    pub fn for_arch(&self, arch: &str) -> Result<Manifest> {
+        let mut manifest = match self.targets.get(arch) {
+            Some(target) => self
+                .with_target(target)
+                .with_context(|| format!("Target {:?} is invalid", arch))?,
+            None if arch == DEFAULT_ARCH || self.files.iter().any(|file| file.arch.as_str() == arch) => {
+                let mut manifest = self.clone();
+                manifest.targets.clear();
+                manifest
+            }
+            None => anyhow::bail!(
+                "Release {} has no build for {} machines, only for: {}",
+                self.version,
+                arch,
+                self.architectures().join(", ")
+            ),
+        };
+        manifest.select_arch_files(arch)?;
+        Ok(manifest)
+    }
+
+    /// Drops the `files` entries, components' included, that are for another architecture
+    /// than `arch`, failing when a required file has variants but none for `arch`.
+    fn select_arch_files(&mut self, arch: &str) -> Result<()> {
+        let key = |file: &FileEntry| file.installed_name().replace('\\', "/").to_lowercase();
+        let lists = std::iter::once(&mut self.files)
+            .chain(self.components.iter_mut().map(|component| &mut component.files));
+        for files in lists {
+            for file in files.iter().filter(|file| !file.arch.matches(arch)) {
+                let variants = files.iter().filter(|variant| key(variant) == key(file));
+                if file.required && !variants.clone().any(|variant| variant.arch.matches(arch)) {
+                    let available: Vec<&str> = variants.map(|variant| variant.arch.as_str()).collect();
+                    anyhow::bail!(
+                        "File {} has no variant for {} machines, only for: {}",
+                        file.installed_name(),
+                        arch,
+                        available.join(", ")
+                    );
+                }
+                self.other_arch_files.push(file.name.clone());
+            }
+            files.retain(|file| file.arch.matches(arch));
+        }
+        Ok(())
+    }
+
+    fn with_target(&self, target: &Target) -> Result<Manifest> {
+        let mut manifest = self.clone();
+        manifest.release_zip_url = target.release_zip_url.clone();
+        manifest.mirrors = target.mirrors.clone();
+        manifest.sha256 = target.sha256.clone();
+        manifest.extract_to = target.extract_to.clone();
+        manifest.archives = target.archives.clone();
+        manifest.release_zip_sig_url = target.release_zip_sig_url.clone();
+        manifest.size_bytes = target.size_bytes;
+        manifest.installed_size_bytes = target.installed_size_bytes;
+        manifest.files = target.files.clone();
+        manifest.patches = target.patches.clone();
+        manifest.channels.clear();
+        manifest.history.clear();
+        manifest.targets.clear();
+        manifest.promote_first_archive();
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Lowercases every hash in the manifest, channels, history and targets included, so
+    /// they compare as they are with computed ones. Malformed hashes are left as published
+    /// for [`Manifest::validate`] to report.
+    fn normalize_hashes(&mut self) {
+        fn files(files: &mut [FileEntry]) {
+            for sha256 in files.iter_mut().filter_map(|file| file.sha256.as_mut()) {
+                normalize_sha256(sha256);
+            }
+        }
+        fn archives(archives: &mut [Archive]) {
+            for archive in archives {
+                normalize_sha256(&mut archive.sha256);
+            }
+        }
+        fn patches(patches: &mut [Patch]) {
+            for patch in patches {
+                normalize_sha256(&mut patch.sha256);
+            }
+        }
+        fn targets(targets: &mut BTreeMap<String, Target>) {
+            for target in targets.values_mut() {
+                normalize_sha256(&mut target.sha256);
+                archives(&mut target.archives);
+                files(&mut target.files);
+                patches(&mut target.patches);
+            }
+        }
+
+        normalize_sha256(&mut self.sha256);
+        archives(&mut self.archives);
+        files(&mut self.files);
+        patches(&mut self.patches);
+        targets(&mut self.targets);
+        for release in self.channels.values_mut().chain(&mut self.history) {
+            normalize_sha256(&mut release.sha256);
+            archives(&mut release.archives);
+            files(&mut release.files);
+            patches(&mut release.patches);
+            targets(&mut release.targets);
+        }
+        let components = self
+            .channels
+            .values_mut()
+            .chain(&mut self.history)
+            .flat_map(|release| release.components.iter_mut().flatten())
+            .chain(&mut self.components);
+        for component in components {
+            if let Some(sha256) = &mut component.sha256 {
+                normalize_sha256(sha256);
+            }
+            files(&mut component.files);
+        }
+        for asset in &mut self.assets {
+            normalize_sha256(&mut asset.sha256);
+        }
+    }
+
+    /// A release given only as `archives` has its first archive moved into the release zip
+    /// fields, so the rest of the installer only deals with extra archives.
+    fn promote_first_archive(&mut self) {
+        if !self.release_zip_url.is_empty() || self.archives.is_empty() {
+            return;
+        }
+        let first = self.archives.remove(0);
+        self.release_zip_url = first.url;
+        self.mirrors = first.mirrors;
+        self.sha256 = first.sha256;
+        self.size_bytes = first.size_bytes;
+        self.extract_to = first.extract_to;
+        if first.sig_url.is_some() {
+            self.release_zip_sig_url = first.sig_url;
+        }
+    }
+
+    /// Returns the manifest with the [`URL_PLACEHOLDERS`] in the release zip, mirror,
+    /// archive and patch URLs filled in, once [`Manifest::for_channel`] or
+    /// [`Manifest::for_version`] and [`Manifest::for_arch`] picked the build to install.
+    /// Everything downloaded, logged or shown by `--dry-run` uses these URLs.
+    pub fn expand_urls(&self, channel: &str, arch: &str) -> Result<Manifest> {
+        let mut manifest = self.clone();
+        let version = manifest.version.to_string();
+        let expand = |url: &mut String| -> Result<()> {
+            *url = expand_placeholders(url, &version, arch, channel)
+                .map_err(|problem| anyhow::anyhow!("URL {:?} {}", url, problem))?;
+            Ok(())
+        };
+        expand(&mut manifest.release_zip_url)?;
+        manifest.mirrors.iter_mut().try_for_each(expand)?;
+        for archive in &mut manifest.archives {
+            expand(&mut archive.url)?;
+            archive.mirrors.iter_mut().try_for_each(expand)?;
+            archive.sig_url.iter_mut().try_for_each(expand)?;
+        }
+        for patch in &mut manifest.patches {
+            expand(&mut patch.patch_url)?;
+        }
+        if let Some(problem) = manifest
+            .urls()
+            .into_iter()
+            .filter(|(field, _)| is_templated(field))
+            .find_map(|(field, url)| manifest.check_url(&field, url).err())
+        {
+            anyhow::bail!("Manifest has invalid URLs: {}", problem);
+        }
+        if manifest.release_zip_url != self.release_zip_url {
+            log::info!("Release zip URL for {} on {}: {}", channel, arch, manifest.release_zip_url);
+        }
+        Ok(manifest)
+    }
+
+    /// The release zip URL followed by its mirrors, in the order they should be tried.
+    pub fn download_urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.release_zip_url.as_str())
+            .chain(self.mirrors.iter().map(String::as_str))
+    }
+
+    /// Every `extract_to` directory of the release zip and the archives.
+    fn extract_dirs(&self) -> impl Iterator<Item = &str> {
+        self.extract_to
+            .iter()
+            .chain(self.archives.iter().filter_map(|archive| archive.extract_to.as_ref()))
+            .map(String::as_str)
+    }
+
+    /// Where the file `name` of the install directory is inside the release zip; `None`
+    /// when the zip is extracted to a directory `name` is not in.
+    pub fn zip_entry_name<'n>(&self, name: &'n str) -> Option<&'n str> {
+        match &self.extract_to {
+            Some(dir) => name
+                .strip_prefix(dir.trim_end_matches(['/', '\\']))
+                .and_then(|rest| rest.strip_prefix(['/', '\\'])),
+            None => Some(name),
+        }
+    }
+
+    /// Disk space the extracted release and its assets take up: `installed_size_bytes`,
+    /// else the file sizes when every file has one, else the zip size times
+    /// `expansion_factor`. `None` when the manifest gives no sizes at all.
+    pub fn installed_size(&self, expansion_factor: f64) -> Option<u64> {
+        let release = match self.installed_size_bytes {
+            Some(total) => total,
+            None if self.files.iter().all(|file| file.size.is_some()) => {
+                self.files.iter().filter_map(|file| file.size).sum()
+            }
+            None => {
+                let zips = self
+                    .components
+                    .iter()
+                    .filter(|component| component.selected)
+                    .filter_map(|component| component.size_bytes)
+                    .chain(self.archives.iter().filter_map(|archive| archive.size_bytes));
+                ((self.size_bytes? + zips.sum::<u64>()) as f64 * expansion_factor) as u64
+            }
+        };
+        let assets: u64 = self.assets.iter().filter_map(|asset| asset.size_bytes).sum();
+        Some(release + assets)
+    }
+
+    /// Checks the prerequisites on this machine, installing missing ones through
+    /// `remediator` where possible; `arch` picks the VC++ runtime to look for. Fails
+    /// listing every prerequisite that is not met.
+    pub fn check_prerequisites(&self, arch: &str, remediator: Option<&dyn Remediator>) -> Result<()> {
+        prereq::run_all(&self.prerequisites.checks(arch), remediator)
+    }
+}
+
+/// Refuses manifests written for a newer bootstrapper: fields it does not know would be
+/// ignored, and the install would silently differ from what the manifest describes.
+fn check_schema_version(schema_version: u32) -> Result<()> {
+    if schema_version == 0 {
+        anyhow::bail!("Manifest schema_version 0 is invalid");
+    }
+    if schema_version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "The manifest uses schema version {}, but this bootstrapper only understands up to \
+             version {}. Download the latest bootstrapper and run it again",
+            schema_version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// Refuses to go on when this bootstrapper, version `current`, is older than `min`: it
+/// would mishandle the manifest. Checked before the prerequisites, since getting a new
+/// bootstrapper is the first thing the user has to do.
+fn check_bootstrapper_version(min: &Version, current: &str, download_url: Option<&str>) -> Result<()> {
+    min.check()
+        .with_context(|| format!("Manifest min_bootstrapper_version {:?} is not a semver version", min.as_str()))?;
+    let current_version = Version::parse(current).context("Invalid bootstrapper version")?;
+    if current_version >= *min {
+        return Ok(());
+    }
+
+    match download_url {
+        Some(url) => anyhow::bail!(
+            "This release needs bootstrapper {} or newer, but this one is {}. Download the new \
+             bootstrapper from {} and run it again",
+            min,
+            current,
+            url
+        ),
+        None => anyhow::bail!(
+            "This release needs bootstrapper {} or newer, but this one is {}. Download the latest \
+             bootstrapper and run it again",
+            min,
+            current
+        ),
+    }
+}
+
+pub fn validate_manifest_url(url: &str) -> Result<()> {
+    if local_path(url).is_some() {
+        return Ok(());
+    }
+
+    let parsed = reqwest::Url::parse(url)
+        .with_context(|| format!("Invalid manifest URL: {}", url))?;
+
+    match parsed.scheme() {
+        "http" | "https" => Ok(()),
+        scheme => anyhow::bail!(
+            "Manifest URL must use http, https or file, got {}: {}",
+            scheme,
+            url
+        ),
+    }
+}
+
+/// The filesystem path `location` points at: a `file://` URL, or a bare local, UNC or
+/// relative path. `None` for anything else, e.g. http URLs.
+pub fn local_path(location: &str) -> Option<PathBuf> {
+    match reqwest::Url::parse(location) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+        // `C:\share\installer.json` parses as a URL with scheme `c`.
+        Ok(url) if url.scheme().len() == 1 => Some(PathBuf::from(location)),
+        Ok(_) => None,
+        Err(_) => Some(PathBuf::from(location)),
+    }
+}
+
+fn normalize_sha256(sha256: &mut str) {
+    if sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        sha256.make_ascii_lowercase();
+    }
+}
+
+/// Checks that `value`, the manifest's `field`, is a SHA-256 hash: exactly 64 hex digits.
+fn check_sha256(field: &str, value: &str) -> Result<()> {
+    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!(
+            "Manifest {} {:?} is not a SHA-256 hash, expected 64 hex digits",
+            field,
+            value
+        );
+    }
+    Ok(())
+}
+
+/// Whether `name` can name a file, e.g. a shortcut, on Windows.
+fn is_file_name(name: &str) -> bool {
+    !name.trim().is_empty() && !name.contains(['\\', '/', ':', '*', '?', '"', '<', '>', '|'])
+}
+
+/// Whether the manifest's `field`, as [`Manifest::urls`] names it, may have
+/// [`URL_PLACEHOLDERS`].
+fn is_templated(field: &str) -> bool {
+    field == "release_zip_url" || ["mirrors[", "archives[", "patches["].iter().any(|prefix| field.starts_with(prefix))
+}
+
+/// `template` with `${version}`, `${arch}` and `${channel}` filled in and `$$` turned into
+/// `$`. Any other `$` is kept as it is. Fails on an unknown placeholder.
+fn expand_placeholders(template: &str, version: &str, arch: &str, channel: &str) -> Result<String, String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let Some(end) = after.find('}') else {
+                return Err("has a ${ without its closing }".to_string());
+            };
+            let value = match &after[..end] {
+                "version" => version,
+                "arch" => arch,
+                "channel" => channel,
+                name => {
+                    let known: Vec<String> = URL_PLACEHOLDERS.iter().map(|name| format!("${{{}}}", name)).collect();
+                    return Err(format!("has an unknown placeholder ${{{}}}, known ones: {}", name, known.join(", ")));
+                }
+            };
+            output.push_str(value);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = after;
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Whether `path` is a non-empty relative path that stays inside the directory it is
+/// joined to.
+fn is_relative_inside(path: &str) -> bool {
+    !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, path::Component::Normal(_)))
+}
+
+/// Whether `path` names a key below a top-level one like `Software`, which uninstall
+/// may remove whole.
+fn is_product_key(path: &str) -> bool {
+    let path = path.trim_matches('\\');
+    path.contains('\\') && !path.split('\\').any(|part| part.is_empty() || part == "..")
+}
+
+/// Whether a part of `path` below its first is the product's name. Keys like
+/// `Software\Microsoft` and folders like `%LOCALAPPDATA%\Microsoft` are shared with Windows
+/// and other programs; only what is named after the product is its own to remove whole.
+fn names_product(path: &str, product: &Product) -> bool {
+    let name = product.display_name.to_lowercase();
+    path.split(['\\', '/']).skip(1).any(|part| part.to_lowercase() == name)
+}
+
+/// Whether `path` is one of [`UNINSTALL_ROOTS`] followed by a relative path inside it.
+fn is_uninstall_path(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    UNINSTALL_ROOTS.iter().any(|root| {
+        path.len() > root.len()
+            && path[..root.len()].eq_ignore_ascii_case(root)
+            && path[root.len()..].starts_with('/')
+            && is_relative_inside(&path[root.len() + 1..])
+    })
+}
+
+fn read_manifest_file(path: &Path) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("Manifest file not found: {}", path.display())
+        }
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to read manifest file {}", path.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const SHA256: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    /// The smallest manifest that validates; tests change what they are about.
+    fn fixture() -> Value {
+        json!({
+            "version": "1.2.0",
+            "release_zip_url": "https://example.com/paradise-1.2.0.zip",
+            "sha256": SHA256,
+            "files": [{ "name": "paradise.exe" }]
+        })
+    }
+
+    fn parse(value: &Value) -> Result<Manifest> {
+        Manifest::parse(&value.to_string(), Some(ManifestFormat::Json), false, None)
+    }
+
+    /// The whole error chain, as the installer prints it.
+    fn error(value: &Value) -> String {
+        format!("{:#}", parse(value).unwrap_err())
+    }
+
+    #[test]
+    fn manifests_without_a_schema_version_are_version_1() {
+        let mut manifest = fixture();
+        // Unknown fields are ignored within a supported schema version.
+        manifest["installer_theme"] = json!("dark");
+        manifest["files"][0]["attributes"] = json!(["hidden"]);
+        assert_eq!(parse(&manifest).unwrap().schema_version, 1);
+    }
+
+    #[test]
+    fn version_2_manifests_are_read_with_their_fields() {
+        let mut manifest = fixture();
+        manifest["schema_version"] = json!(2);
+        manifest["installed_size_bytes"] = json!(1_048_576);
+        manifest["files"] = json!([{ "name": "paradise.exe", "sha256": SHA256, "size": 4096 }]);
+        manifest["assets"] = json!([{ "name": "sdk/readme.txt", "url": "https://example.com/readme.txt", "sha256": SHA256 }]);
+        let manifest = parse(&manifest).unwrap();
+        assert_eq!(manifest.schema_version, 2);
+        assert_eq!(manifest.installed_size_bytes, Some(1_048_576));
+        assert_eq!(manifest.files[0].size, Some(4096));
+        assert_eq!(manifest.assets[0].name, "sdk/readme.txt");
+    }
+
+    #[test]
+    fn version_3_manifests_are_read_with_their_fields() {
+        let mut manifest = fixture();
+        manifest["schema_version"] = json!(3);
+        manifest["preserve"] = json!(["saves/"]);
+        manifest["min_supported_version"] = json!("1.0.0");
+        manifest["close_processes"] = json!(["paradise.exe"]);
+        let manifest = parse(&manifest).unwrap();
+        assert_eq!(manifest.schema_version, 3);
+        assert_eq!(manifest.preserve, ["saves/"]);
+        assert_eq!(manifest.min_supported_version.unwrap(), "1.0.0");
+        assert_eq!(manifest.close_processes, ["paradise.exe"]);
+    }
+
+    #[test]
+    fn future_schema_versions_ask_for_a_newer_bootstrapper() {
+        let mut manifest = fixture();
+        manifest["schema_version"] = json!(SCHEMA_VERSION + 1);
+        assert_eq!(
+            error(&manifest),
+            format!(
+                "The manifest uses schema version {}, but this bootstrapper only understands up to version {}. \
+                 Download the latest bootstrapper and run it again",
+                SCHEMA_VERSION + 1,
+                SCHEMA_VERSION
+            )
+        );
+
+        // Reported as such even when the newer schema changed fields this one knows.
+        manifest["files"] = json!({ "paradise.exe": { "sha256": SHA256 } });
+        assert!(error(&manifest).starts_with("The manifest uses schema version"));
+    }
+
+    #[test]
+    fn schema_version_0_is_invalid() {
+        let mut manifest = fixture();
+        manifest["schema_version"] = json!(0);
+        assert_eq!(error(&manifest), "Manifest schema_version 0 is invalid");
+    }
+
+    #[test]
+    fn download_urls_must_be_https() {
+        let mut manifest = fixture();
+        manifest["release_zip_url"] = json!("http://example.com/paradise-1.2.0.zip");
+        assert_eq!(
+            error(&manifest),
+            "Manifest has invalid URLs: release_zip_url must use https, got http: \
+             http://example.com/paradise-1.2.0.zip (pass --allow-insecure for test servers)"
+        );
+
+        manifest["release_zip_url"] = json!("https://example.com/paradise-1.2.0.zip");
+        manifest["mirrors"] = json!(["ftp://mirror.example.com/paradise-1.2.0.zip"]);
+        assert!(error(&manifest).starts_with("Manifest has invalid URLs: mirrors[0] must use https, got ftp: "));
+
+        manifest["release_zip_url"] = json!("http://example.com/paradise-1.2.0.zip");
+        manifest["mirrors"] = json!([]);
+        let manifest = Manifest::parse(&manifest.to_string(), None, true, None).unwrap();
+        assert!(manifest.allow_insecure);
+    }
+
+    #[test]
+    fn component_files_stay_inside_the_install_directory() {
+        let mut manifest = fixture();
+        manifest["components"] = json!([{ "name": "hd_textures", "files": [{ "name": "textures/../../evil.pak" }] }]);
+        assert_eq!(
+            error(&manifest),
+            "components[0].files[0].name \"textures/../../evil.pak\" must be a relative path inside \
+             the install directory"
+        );
+    }
+
+    #[test]
+    fn archives_alone_make_up_the_release() {
+        let mut manifest = fixture();
+        manifest.as_object_mut().unwrap().remove("release_zip_url");
+        manifest.as_object_mut().unwrap().remove("sha256");
+        manifest["archives"] = json!([
+            { "url": "https://example.com/base.zip", "sha256": SHA256, "sig_url": "https://example.com/base.zip.minisig" },
+            { "url": "https://cdn.example.com/assets.zip", "sha256": SHA256, "extract_to": "data" }
+        ]);
+        let manifest = parse(&manifest).unwrap();
+        assert_eq!(manifest.release_zip_url, "https://example.com/base.zip");
+        assert_eq!(manifest.release_zip_sig_url.as_deref(), Some("https://example.com/base.zip.minisig"));
+        assert_eq!(manifest.archives.len(), 1);
+        assert_eq!(manifest.archives[0].extract_to.as_deref(), Some("data"));
+
+        assert_eq!(
+            error(&json!({ "version": "1.2.0", "files": [{ "name": "paradise.exe" }] })),
+            "Manifest has neither a release_zip_url nor archives"
+        );
+    }
+
+    #[test]
+    fn archives_stay_inside_the_install_directory() {
+        let mut manifest = fixture();
+        manifest["archives"] = json!([{ "url": "https://example.com/assets.zip", "sha256": SHA256, "extract_to": "../data" }]);
+        assert_eq!(
+            error(&manifest),
+            "extract_to \"../data\" must be a relative path inside the install directory"
+        );
+
+        manifest["archives"][0]["extract_to"] = json!("data");
+        manifest["archives"][0]["sig_url"] = json!("http://example.com/assets.zip.minisig");
+        assert!(error(&manifest).contains("archives[0].sig_url must use https"));
+
+        manifest["archives"][0]["sig_url"] = json!("https://example.com/assets.zip.minisig");
+        manifest["extract_to"] = json!("bin");
+        let manifest = parse(&manifest).unwrap();
+        assert_eq!(manifest.zip_entry_name("bin/paradise.exe"), Some("paradise.exe"));
+        assert_eq!(manifest.zip_entry_name("data/pack.pak"), None);
+    }
+
+    #[test]
+    fn old_bootstrappers_are_sent_to_the_new_one() {
+        let mut manifest = fixture();
+        manifest["min_bootstrapper_version"] = json!("999.0.0");
+        manifest["bootstrapper_download_url"] = json!("https://example.com/bootstrapper.exe");
+        assert_eq!(
+            error(&manifest),
+            format!(
+                "This release needs bootstrapper 999.0.0 or newer, but this one is {}. Download the new \
+                 bootstrapper from https://example.com/bootstrapper.exe and run it again",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        // Even when the rest is written for that bootstrapper and no longer parses.
+        manifest["files"] = json!("all of them");
+        assert!(error(&manifest).starts_with("This release needs bootstrapper 999.0.0 or newer"));
+        manifest["files"] = fixture()["files"].clone();
+
+        manifest["min_bootstrapper_version"] = json!("0.0.1");
+        assert!(parse(&manifest).is_ok());
+
+        manifest["min_bootstrapper_version"] = json!("latest");
+        assert!(error(&manifest).starts_with("Manifest min_bootstrapper_version \"latest\" is not a semver version"));
+    }
+
+    #[test]
+    fn bootstrapper_versions_compare_as_semver() {
+        assert!(check_bootstrapper_version(&"0.9.0".into(), "0.10.0", None).is_ok());
+        assert!(check_bootstrapper_version(&"0.10.0".into(), "0.9.0", None).is_err());
+        assert!(check_bootstrapper_version(&"1.0.0".into(), "1.0.0-rc.1", None).is_err());
+        assert!(check_bootstrapper_version(&"1.0.0-rc.1".into(), "1.0.0", None).is_ok());
+    }
+
+    #[test]
+    fn versions_older_than_the_minimum_are_unsupported() {
+        let mut manifest = fixture();
+        assert!(parse(&manifest).unwrap().unsupported(&"0.1.0".into()).is_none());
+
+        manifest["min_supported_version"] = json!("1.2");
+        let manifest_with_min = parse(&manifest).unwrap();
+        assert_eq!(manifest_with_min.unsupported(&"1.1.9".into()).unwrap(), "1.2.0");
+        assert!(manifest_with_min.unsupported(&"1.2.0-rc.1".into()).is_some());
+        assert!(manifest_with_min.unsupported(&"1.2.0".into()).is_none());
+        assert!(manifest_with_min.unsupported(&"1.10.0".into()).is_none());
+
+        manifest["min_supported_version"] = json!("soon");
+        assert!(error(&manifest).starts_with("Manifest min_supported_version \"soon\" is not a semver version"));
+    }
+
+    #[test]
+    fn the_release_zip_size_has_two_names() {
+        let mut manifest = fixture();
+        manifest["release_zip_size"] = json!(4096);
+        assert_eq!(parse(&manifest).unwrap().size_bytes, Some(4096));
+
+        manifest["release_zip_size"] = json!(0);
+        assert_eq!(error(&manifest), "Manifest release zip size is 0 bytes");
+    }
+
+    #[test]
+    fn hashes_are_normalized_to_lowercase() {
+        let mut manifest = fixture();
+        manifest["sha256"] = json!(SHA256.to_uppercase());
+        manifest["files"][0]["sha256"] = json!(SHA256.to_uppercase());
+        let manifest = parse(&manifest).unwrap();
+        assert_eq!(manifest.sha256, SHA256);
+        assert_eq!(manifest.files[0].sha256.as_deref(), Some(SHA256));
+    }
+
+    #[test]
+    fn malformed_hashes_name_their_field() {
+        let mut manifest = fixture();
+        manifest["sha256"] = json!(format!("{}\n", SHA256.to_uppercase()));
+        assert_eq!(
+            error(&manifest),
+            format!(
+                "Manifest sha256 \"{}\\n\" is not a SHA-256 hash, expected 64 hex digits",
+                SHA256.to_uppercase()
+            )
+        );
+
+        manifest["sha256"] = json!(&SHA256[1..]);
+        assert!(error(&manifest).starts_with("Manifest sha256 \"f86d"));
+
+        manifest["sha256"] = json!(SHA256);
+        manifest["files"] = json!([{ "name": "paradise.exe" }, { "name": "data.pak", "sha256": "TBD" }]);
+        assert_eq!(
+            error(&manifest),
+            "Manifest files[1].sha256 \"TBD\" is not a SHA-256 hash, expected 64 hex digits"
+        );
+
+        manifest["files"] = json!([{ "name": "paradise.exe" }]);
+        manifest["patches"] = json!([{ "from_version": "1.1.0", "patch_url": "https://example.com/p.zip", "sha256": "", "algorithm": "bsdiff" }]);
+        assert_eq!(
+            error(&manifest),
+            "Manifest patches[0].sha256 \"\" is not a SHA-256 hash, expected 64 hex digits"
+        );
+
+        manifest["patches"] = json!([]);
+        manifest["channels"] = json!({ "beta": { "version": "1.3.0", "release_zip_url": "https://example.com/b.zip", "sha256": "TBD", "files": [{ "name": "paradise.exe" }] } });
+        assert_eq!(
+            error(&manifest),
+            "Channel \"beta\" is invalid: Manifest sha256 \"TBD\" is not a SHA-256 hash, expected 64 hex digits"
+        );
+    }
+
+    #[test]
+    fn every_invalid_url_is_reported_with_its_field() {
+        let mut manifest = fixture();
+        manifest["release_zip_url"] = json!("htps://typo.example.com/paradise.zip");
+        manifest["mirrors"] = json!(["https://mirror.example.com/paradise.zip", ""]);
+        manifest["eula_url"] = json!("javascript:alert(1)");
+        manifest["license_check_url"] = json!("https://");
+        manifest["prerequisites"] = json!([{ "type": "vc_redist", "required": true, "url": "C:\\redist\\vc_redist.x64.exe" }]);
+        assert_eq!(
+            error(&manifest),
+            "Manifest has invalid URLs: release_zip_url must use https, got htps: htps://typo.example.com/paradise.zip; \
+             eula_url must use https, got javascript: javascript:alert(1); \
+             license_check_url https:// is not a valid URL: empty host; \
+             mirrors[1] is empty; \
+             prerequisites[0].url C:\\redist\\vc_redist.x64.exe is a local path, which only a manifest read from disk may use"
+        );
+    }
+
+    /// [`fixture`] with a channel and a prerequisite, written as TOML.
+    const TOML_FIXTURE: &str = r#"
+        version = "1.2.0"
+        release_zip_url = "https://example.com/paradise-1.2.0.zip"
+        sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+
+        [[files]]
+        name = "paradise.exe"
+
+        [[prerequisites]]
+        type = "vc_redist"
+        required = true
+        url = "https://aka.ms/vs/17/release/vc_redist.x64.exe"
+
+        [channels.beta]
+        version = "1.3.0-beta.1"
+        release_zip_url = "https://example.com/paradise-1.3.0-beta.1.zip"
+        sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        files = [{ name = "paradise.exe" }]
+    "#;
+
+    #[test]
+    fn toml_manifests_read_like_json_ones() {
+        for format in [Some(ManifestFormat::Toml), None] {
+            let manifest = Manifest::parse(TOML_FIXTURE, format, false, None).unwrap();
+            assert_eq!(manifest.version, "1.2.0");
+            assert_eq!(manifest.sha256, SHA256);
+            assert_eq!(manifest.files[0].name, "paradise.exe");
+            assert_eq!(manifest.prerequisites.0.len(), 1);
+            assert_eq!(manifest.for_channel("beta").unwrap().version, "1.3.0-beta.1");
+        }
+
+        let json = fixture().to_string();
+        assert_eq!(Manifest::parse(&json, None, false, None).unwrap().version, "1.2.0");
+    }
+
+    #[test]
+    fn manifest_formats_come_from_content_types_and_extensions() {
+        let from_content_type = ManifestFormat::from_content_type;
+        assert_eq!(from_content_type("application/json; charset=utf-8"), Some(ManifestFormat::Json));
+        assert_eq!(from_content_type("application/vnd.paradise+json"), Some(ManifestFormat::Json));
+        assert_eq!(from_content_type("Application/TOML"), Some(ManifestFormat::Toml));
+        assert_eq!(from_content_type("text/plain; charset=utf-8"), None);
+
+        assert_eq!(ManifestFormat::from_extension("/share/installer.TOML"), Some(ManifestFormat::Toml));
+        assert_eq!(ManifestFormat::from_extension("/installer.json"), Some(ManifestFormat::Json));
+        assert_eq!(ManifestFormat::from_extension("/installer"), None);
+    }
+
+    #[test]
+    fn parse_errors_name_the_format() {
+        let error = |text: &str, format| format!("{:#}", Manifest::parse(text, format, false, None).unwrap_err());
+
+        let json = error("{\"version\": }", Some(ManifestFormat::Json));
+        assert!(json.starts_with("Failed to parse manifest as JSON: expected value at line 1"), "{}", json);
+
+        let toml = error("version = ", Some(ManifestFormat::Toml));
+        assert!(toml.starts_with("Failed to parse manifest as TOML: "), "{}", toml);
+        assert!(toml.contains("line 1"), "{}", toml);
+
+        let unknown = error("version: 1.2.0", None);
+        assert!(unknown.starts_with("Manifest is neither JSON nor TOML. Failed to parse manifest as JSON: "), "{}", unknown);
+        assert!(unknown.contains(". Failed to parse manifest as TOML: "), "{}", unknown);
+    }
+
+    #[test]
+    fn json_errors_point_at_the_value() {
+        let error = |text: &str| format!("{:#}", Manifest::parse(text, Some(ManifestFormat::Json), false, None).unwrap_err());
+
+        let mut manifest = fixture();
+        manifest["files"] = json!([{ "name": "paradise.exe" }, { "name": "data.pak", "size": "big" }]);
+        let text = serde_json::to_string_pretty(&manifest).unwrap();
+        let wrong_type = error(&text);
+        assert!(wrong_type.contains("expected u64 at line 8 column 19, in files[1].size\n"), "{}", wrong_type);
+        assert!(wrong_type.ends_with("8 |       \"size\": \"big\"\n  |                   ^"), "{}", wrong_type);
+
+        manifest["files"] = json!([{ "name": "paradise.exe" }, { "sha256": SHA256 }]);
+        let missing = error(&manifest.to_string());
+        assert!(missing.contains("missing field `name` at line 1 column "), "{}", missing);
+        assert!(missing.contains(", in files[1]\n1 | ..."), "{}", missing);
+
+        let syntax = error("{\n  \"version\": \"1.2.0\",\n  \"files\": [,]\n}");
+        assert_eq!(
+            syntax,
+            "Failed to parse manifest as JSON: expected value at line 3 column 13, in files[0]\n3 |   \"files\": [,]\n  |             ^"
+        );
+    }
+
+    #[test]
+    fn html_pages_are_not_taken_for_manifests() {
+        let page = "<!DOCTYPE html>\n<html><head><title>404 Not\n Found</title></head><body>nginx</body></html>";
+        for format in [Some(ManifestFormat::Json), None] {
+            assert_eq!(
+                format!("{:#}", Manifest::parse(page, format, false, None).unwrap_err()),
+                "Got an HTML page (\"404 Not Found\") instead of the manifest; the manifest URL may be wrong, \
+                 or a proxy or login page answered in its place"
+            );
+        }
+        assert!(html_page("<html><body>Sign in</body></html>").unwrap().is_none());
+        assert!(html_page("{\"eula_text\": \"<html>\"}").is_none());
+    }
+
+    #[test]
+    fn validation_errors_name_the_entry() {
+        let mut manifest = fixture();
+        manifest["files"] = json!([{ "name": "paradise.exe" }, { "name": "" }]);
+        assert_eq!(error(&manifest), "files[1].name is empty");
+
+        let mut manifest = fixture();
+        manifest["assets"] = json!([{ "name": "packs/../../hd.pak", "url": "https://example.com/hd.pak", "sha256": SHA256 }]);
+        assert_eq!(
+            error(&manifest),
+            "assets[0].name \"packs/../../hd.pak\" must be a relative path inside the install directory"
+        );
+    }
+
+    #[test]
+    fn file_targets_stay_inside_and_do_not_collide() {
+        let mut manifest = fixture();
+        manifest["files"] = json!([
+            { "name": "bin/win64/paradise.exe", "target": "paradise.exe" },
+            { "name": "bin/win64/paradise.dll", "target": "lib/paradise.dll" }
+        ]);
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.files[0].installed_name(), "paradise.exe");
+
+        manifest["files"][1]["target"] = json!("../paradise.dll");
+        assert_eq!(
+            error(&manifest),
+            "File bin/win64/paradise.dll has target \"../paradise.dll\", which must be a relative path inside the install directory"
+        );
+
+        manifest["files"][1] = json!({ "name": "bin/win64/paradise.dll", "target": "Paradise.EXE" });
+        assert_eq!(
+            error(&manifest),
+            "Files bin/win64/paradise.exe and bin/win64/paradise.dll are both installed as paradise.exe"
+        );
+
+        manifest["files"][1] = json!({ "name": "paradise.exe" });
+        assert!(error(&manifest).starts_with("Files bin/win64/paradise.exe and paradise.exe are both installed"));
+
+        manifest["files"][1] = json!({ "name": "bin/win64/paradise.exe", "target": "game.exe" });
+        assert_eq!(error(&manifest), "File bin/win64/paradise.exe is moved to more than one target");
+    }
+
+    #[test]
+    fn files_are_required_unless_they_say_otherwise() {
+        let mut manifest = fixture();
+        manifest["files"] = json!([{ "name": "paradise.exe" }, { "name": "paradise.pdb", "required": false }]);
+        let parsed = parse(&manifest).unwrap();
+        assert!(parsed.files[0].required);
+        assert!(!parsed.files[1].required);
+
+        manifest["files"][0]["required"] = json!(false);
+        assert_eq!(error(&manifest), "File paradise.exe is the product's executable and cannot be optional");
+
+        manifest["files"][0] = json!({ "name": "bin/Paradise.exe", "target": "paradise.exe", "required": false });
+        assert_eq!(error(&manifest), "File bin/Paradise.exe is the product's executable and cannot be optional");
+    }
+
+    #[test]
+    fn files_can_have_a_variant_per_architecture() {
+        let mut manifest = fixture();
+        manifest["files"] = json!([
+            { "name": "paradise.exe" },
+            { "name": "bin/x64/native.dll", "target": "native.dll", "arch": "x86_64" },
+            { "name": "bin/arm64/native.dll", "target": "native.dll", "arch": "aarch64" }
+        ]);
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.architectures(), ["x86_64", "aarch64"]);
+
+        let arm = parsed.for_arch("aarch64").unwrap();
+        let names: Vec<&str> = arm.files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["paradise.exe", "bin/arm64/native.dll"]);
+        assert_eq!(arm.other_arch_files, ["bin/x64/native.dll"]);
+        assert_eq!(parsed.for_arch("x86_64").unwrap().other_arch_files, ["bin/arm64/native.dll"]);
+
+        manifest["files"][2]["arch"] = json!("any");
+        assert_eq!(
+            error(&manifest),
+            "Files bin/x64/native.dll and bin/arm64/native.dll are both installed as native.dll"
+        );
+
+        manifest["files"][2] = json!({ "name": "bin/x64/helper.dll", "arch": "x86_64" });
+        manifest["targets"] = json!({ "aarch64": {
+            "release_zip_url": "https://example.com/paradise-arm64.zip",
+            "sha256": SHA256,
+            "files": [{ "name": "paradise.exe" }, { "name": "native.dll", "arch": "x86_64" }]
+        }});
+        assert_eq!(
+            format!("{:#}", parse(&manifest).unwrap().for_arch("aarch64").unwrap_err()),
+            "File native.dll has no variant for aarch64 machines, only for: x86_64"
+        );
+
+        manifest["files"][1]["arch"] = json!("arm32");
+        assert!(error(&manifest).contains("unknown variant `arm32`, expected one of `any`, `x86_64`, `aarch64`"));
+    }
+
+    #[test]
+    fn url_placeholders_are_filled_in_for_the_chosen_build() {
+        let mut manifest = fixture();
+        let template = "https://example.com/v${version}/paradise-${version}-${arch}.zip";
+        manifest["release_zip_url"] = json!(template);
+        manifest["mirrors"] = json!(["https://mirror.example.com/${channel}/paradise-${version}-${arch}.zip"]);
+        manifest["channels"] = json!({ "beta": {
+            "version": "1.3.0-beta.1",
+            "release_zip_url": template,
+            "sha256": SHA256,
+            "files": [{ "name": "paradise.exe" }],
+            "patches": [{
+                "from_version": "1.2.0",
+                "patch_url": "https://example.com/patch-1.2.0-${version}.bin",
+                "sha256": SHA256,
+                "algorithm": "bsdiff"
+            }],
+            "targets": { "aarch64": {
+                "release_zip_url": "https://example.com/${channel}/${arch}/paradise-${version}.zip",
+                "sha256": SHA256,
+                "files": [{ "name": "paradise.exe" }]
+            }}
+        }});
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.release_zip_url, template);
+
+        let stable = parsed.for_channel("stable").unwrap().for_arch("x86_64").unwrap();
+        let stable = stable.expand_urls("stable", "x86_64").unwrap();
+        assert_eq!(
+            stable.download_urls().collect::<Vec<_>>(),
+            [
+                "https://example.com/v1.2.0/paradise-1.2.0-x86_64.zip",
+                "https://mirror.example.com/stable/paradise-1.2.0-x86_64.zip"
+            ]
+        );
+
+        // The version is the channel's, and the URLs those of the build picked for the
+        // machine, before anything is filled in.
+        let beta = parsed.for_channel("beta").unwrap();
+        let x64 = beta.for_arch("x86_64").unwrap().expand_urls("beta", "x86_64").unwrap();
+        assert_eq!(x64.release_zip_url, "https://example.com/v1.3.0-beta.1/paradise-1.3.0-beta.1-x86_64.zip");
+        assert_eq!(x64.patches[0].patch_url, "https://example.com/patch-1.2.0-1.3.0-beta.1.bin");
+        let arm = beta.for_arch("aarch64").unwrap().expand_urls("beta", "aarch64").unwrap();
+        assert_eq!(arm.release_zip_url, "https://example.com/beta/aarch64/paradise-1.3.0-beta.1.zip");
+    }
+
+    #[test]
+    fn unknown_url_placeholders_fail_and_dollar_signs_can_be_escaped() {
+        assert_eq!(
+            expand_placeholders("https://example.com/$${version}/a$b-$$-${arch}$", "1.2.0", "aarch64", "beta").unwrap(),
+            "https://example.com/${version}/a$b-$-aarch64$"
+        );
+
+        let mut manifest = fixture();
+        manifest["release_zip_url"] = json!("https://example.com/$${version}/paradise-${version}.zip");
+        let expanded = parse(&manifest).unwrap().expand_urls("stable", "x86_64").unwrap();
+        assert_eq!(expanded.release_zip_url, "https://example.com/${version}/paradise-1.2.0.zip");
+
+        manifest["release_zip_url"] = json!("https://${channel}.example.com/paradise-${version}.zip");
+        parse(&manifest).unwrap();
+
+        manifest["mirrors"] = json!(["https://example.com/${product}/paradise-${version}.zip"]);
+        assert_eq!(
+            error(&manifest),
+            "Manifest has invalid URLs: mirrors[0] \"https://example.com/${product}/paradise-${version}.zip\" \
+             has an unknown placeholder ${product}, known ones: ${version}, ${arch}, ${channel}"
+        );
+        manifest["mirrors"] = json!(["https://example.com/paradise-${version.zip"]);
+        assert_eq!(
+            error(&manifest),
+            "Manifest has invalid URLs: mirrors[0] \"https://example.com/paradise-${version.zip\" \
+             has a ${ without its closing }"
+        );
+    }
+
+    #[test]
+    fn environment_changes_stay_inside_the_install_directory() {
+        let mut manifest = fixture();
+        manifest["environment"] = json!({ "add_to_path": [".", "bin"], "variables": { "PARADISE_HOME": "%INSTALL_DIR%" } });
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.environment.unwrap().scope, EnvironmentScope::User);
+
+        manifest["environment"]["add_to_path"] = json!(["../tools"]);
+        assert_eq!(
+            error(&manifest),
+            "environment.add_to_path[0] \"../tools\" must be a relative path inside the install directory"
+        );
+
+        manifest["environment"] = json!({ "variables": { "Path": "C:\\tools" } });
+        assert_eq!(error(&manifest), "Environment variables cannot replace PATH, use add_to_path");
+    }
+
+    #[test]
+    fn registry_values_match_their_type() {
+        let mut manifest = fixture();
+        manifest["registry"] = json!([
+            { "root": "HKCU", "path": "Software\\paradise", "name": "InstallLocation", "type": "string", "value": "${install_dir}" },
+            { "root": "HKCU", "path": "Software\\paradise", "name": "ProtocolVersion", "type": "dword", "value": 3 }
+        ]);
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.registry[1].value, RegistryData::Number(3));
+
+        manifest["registry"][1]["value"] = json!("3");
+        assert_eq!(error(&manifest), "registry[1] is a dword value, but its value is not a number");
+
+        manifest["registry"][1] = json!({ "root": "HKLM", "path": "Software\\..\\Classes", "type": "string", "value": "" });
+        assert_eq!(error(&manifest), "registry[1].path \"Software\\\\..\\\\Classes\" is not a registry key");
+    }
+
+    #[test]
+    fn uninstall_removes_only_paths_and_keys_of_the_product() {
+        let mut manifest = fixture();
+        assert_eq!(parse(&manifest).unwrap().uninstall, Uninstall::default());
+        assert!(Uninstall::default().offer_user_data_removal);
+
+        manifest["uninstall"] = json!({
+            "remove_paths": ["%LOCALAPPDATA%\\paradise\\cache", "%appdata%/paradise"],
+            "registry_keys": [{ "root": "HKCU", "path": "Software\\paradise" }],
+            "offer_user_data_removal": false
+        });
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.uninstall.remove_paths.len(), 2);
+        assert!(!parsed.uninstall.offer_user_data_removal);
+
+        manifest["uninstall"]["remove_paths"][1] = json!("%LOCALAPPDATA%\\..\\Microsoft");
+        assert_eq!(
+            error(&manifest),
+            "uninstall.remove_paths[1] \"%LOCALAPPDATA%\\\\..\\\\Microsoft\" must be a path inside one of \
+             %LOCALAPPDATA%, %APPDATA%, %PROGRAMDATA%, %TEMP%"
+        );
+        manifest["uninstall"]["remove_paths"][1] = json!("%USERPROFILE%\\paradise");
+        assert!(error(&manifest).starts_with("uninstall.remove_paths[1]"));
+        manifest["uninstall"]["remove_paths"][1] = json!("%APPDATA%");
+        assert!(error(&manifest).starts_with("uninstall.remove_paths[1]"));
+
+        manifest["uninstall"]["remove_paths"] = json!([]);
+        manifest["uninstall"]["registry_keys"][0]["path"] = json!("Software");
+        assert_eq!(error(&manifest), "uninstall.registry_keys[0].path \"Software\" is not a product's registry key");
+    }
+
+    #[test]
+    fn uninstall_refuses_what_does_not_name_the_product() {
+        let mut manifest = fixture();
+        manifest["uninstall"] = json!({
+            "remove_paths": ["%LOCALAPPDATA%\\Paradise"],
+            "registry_keys": [{ "root": "HKCU", "path": "Software\\Paradise Team\\PARADISE" }]
+        });
+        assert!(parse(&manifest).is_ok());
+
+        for path in ["%LOCALAPPDATA%\\Microsoft", "%TEMP%\\x", "%APPDATA%\\Microsoft\\Windows\\Start Menu"] {
+            manifest["uninstall"]["remove_paths"][0] = json!(path);
+            assert_eq!(
+                error(&manifest),
+                format!("uninstall.remove_paths[0] {:?} does not name the product \"paradise\"", path)
+            );
+        }
+        manifest["uninstall"]["remove_paths"] = json!([]);
+
+        for (root, path) in [("HKCU", "Software\\Microsoft"), ("HKLM", "SOFTWARE\\Classes"), ("HKLM", "SOFTWARE\\Classes\\CLSID")] {
+            manifest["uninstall"]["registry_keys"][0] = json!({ "root": root, "path": path });
+            assert_eq!(
+                error(&manifest),
+                format!("uninstall.registry_keys[0].path {:?} does not name the product \"paradise\"", path)
+            );
+        }
+
+        // The name is the product's, not always paradise.
+        manifest["product"] = json!({ "display_name": "Skyline", "publisher": "Skyline Games", "executable": "skyline.exe" });
+        manifest["uninstall"]["registry_keys"][0] = json!({ "root": "HKCU", "path": "Software\\paradise" });
+        assert!(error(&manifest).ends_with("does not name the product \"Skyline\""));
+        manifest["uninstall"]["registry_keys"][0] = json!({ "root": "HKCU", "path": "Software\\Skyline Games\\Skyline" });
+        assert!(parse(&manifest).is_ok());
+    }
+
+    #[test]
+    fn an_edited_receipt_cannot_widen_what_uninstall_removes() {
+        let uninstall: Uninstall = serde_json::from_value(json!({
+            "remove_paths": ["%LOCALAPPDATA%\\paradise\\cache", "C:\\Users", "%APPDATA%\\..\\..", "%LOCALAPPDATA%\\Microsoft"],
+            "registry_keys": [
+                { "root": "HKCU", "path": "Software" },
+                { "root": "HKCU", "path": "Software\\paradise" },
+                { "root": "HKCU", "path": "Software\\Microsoft\\Windows" }
+            ],
+            "offer_user_data_removal": false
+        }))
+        .unwrap();
+        let product = Product::default();
+        let checked = uninstall.checked(&product);
+        assert_eq!(checked.remove_paths, [r"%LOCALAPPDATA%\paradise\cache"]);
+        assert_eq!(checked.registry_keys.len(), 1);
+        assert_eq!(checked.registry_keys[0].path, r"Software\paradise");
+        assert!(!checked.offer_user_data_removal);
+        assert!(checked.validate(&product).is_ok());
+        assert_eq!(
+            format!("{:#}", uninstall.validate(&product).unwrap_err()),
+            "uninstall.remove_paths[1] \"C:\\\\Users\" must be a path inside one of %LOCALAPPDATA%, %APPDATA%, %PROGRAMDATA%, %TEMP%"
+        );
+    }
+
+    #[test]
+    fn shortcuts_point_inside_the_install_directory() {
+        let mut manifest = fixture();
+        manifest["shortcuts"] = json!([
+            { "target": "paradise.exe", "name": "Paradise", "location": "desktop" },
+            { "target": "tools/editor.exe", "name": "Paradise Editor", "location": "start_menu", "args": ["--project", "My Levels"], "icon": "editor.ico" }
+        ]);
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.shortcuts.unwrap()[1].location, ShortcutLocation::StartMenu);
+
+        manifest["shortcuts"][1]["target"] = json!("../editor.exe");
+        assert_eq!(
+            error(&manifest),
+            "shortcuts[1].target \"../editor.exe\" must be a relative path inside the install directory"
+        );
+
+        manifest["shortcuts"][1] = json!({ "target": "paradise.exe", "name": "paradise", "location": "desktop" });
+        assert_eq!(error(&manifest), "Shortcut \"paradise\" is listed twice for the same location");
+    }
+
+    #[test]
+    fn product_defaults_to_paradise() {
+        let mut manifest = fixture();
+        assert_eq!(parse(&manifest).unwrap().product, Product::default());
+
+        manifest["product"] = json!({ "display_name": "Eden", "publisher": "Eden Games", "executable": "bin/eden.exe" });
+        let product = parse(&manifest).unwrap().product;
+        assert_eq!((product.display_name.as_str(), product.icon_path), ("Eden", None));
+
+        manifest["product"]["executable"] = json!("../eden.exe");
+        assert_eq!(
+            error(&manifest),
+            "product.executable \"../eden.exe\" must be a relative path inside the install directory"
+        );
+
+        manifest["product"] = json!({ "display_name": "Eden: Director's Cut" });
+        assert_eq!(error(&manifest), "product.display_name \"Eden: Director's Cut\" is not a valid file name");
+    }
+
+    #[test]
+    fn versions_must_be_semver() {
+        let mut manifest = fixture();
+        for version in ["1.3.0-beta.2", "1.3.0+20240501", "1.3"] {
+            manifest["version"] = json!(version);
+            assert_eq!(parse(&manifest).unwrap().version.as_str(), version);
+        }
+
+        manifest["version"] = json!("1.3.0.1");
+        assert!(error(&manifest).starts_with("Manifest version \"1.3.0.1\" is not a semver version"));
+
+        manifest["version"] = json!("1.3.0");
+        manifest["patches"] = json!([{
+            "from_version": "latest",
+            "patch_url": "https://example.com/patch.zip",
+            "sha256": SHA256,
+            "algorithm": "bsdiff"
+        }]);
+        assert!(error(&manifest).starts_with("patches[0].from_version \"latest\" is not a semver version"));
+    }
+
+    #[test]
+    fn history_releases_are_validated_and_found_for_rollback() {
+        let mut manifest = fixture();
+        manifest["history"] = json!([{
+            "version": "1.1.0",
+            "release_zip_url": "https://example.com/paradise-1.1.0.zip",
+            "sha256": SHA256,
+            "files": [{ "name": "paradise.exe" }]
+        }]);
+        let parsed = parse(&manifest).unwrap();
+        assert_eq!(parsed.for_history_version("1.1").unwrap().release_zip_url, "https://example.com/paradise-1.1.0.zip");
+        assert_eq!(
+            format!("{:#}", parsed.for_history_version("1.0.0").unwrap_err()),
+            "Version 1.0.0 is not in the manifest's history, earlier versions: 1.1.0"
+        );
+
+        manifest["history"][0]["sha256"] = json!("stale");
+        assert!(error(&manifest).starts_with("History release 1.1.0 is invalid: Manifest sha256 \"stale\""));
+    }
+
+    #[test]
+    fn preserved_paths_are_inside_the_install_directory() {
+        let mut manifest = fixture();
+        manifest["preserve"] = json!(["settings.json", "saves/", "**/*.sav"]);
+        assert_eq!(parse(&manifest).unwrap().preserve.len(), 3);
+
+        manifest["preserve"] = json!(["../profiles"]);
+        assert_eq!(
+            error(&manifest),
+            "preserve[0] \"../profiles\" must be a relative path inside the install directory"
+        );
+    }
+
+    #[test]
+    fn processes_to_close_are_inside_the_install_directory() {
+        let mut manifest = fixture();
+        manifest["close_processes"] = json!(["paradise.exe", "tools/crash-reporter.exe"]);
+        assert!(!parse(&manifest).unwrap().kill_running_unattended);
+
+        manifest["close_processes"] = json!(["../launcher.exe"]);
+        assert_eq!(
+            error(&manifest),
+            "close_processes[0] \"../launcher.exe\" must be a relative path inside the install directory"
+        );
+    }
+}
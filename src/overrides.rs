@@ -0,0 +1,269 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::cli::{Cli, LogLevel};
+use crate::config::Config;
+use crate::disk;
+
+pub const MANIFEST_URL_VAR: &str = "PARADISE_MANIFEST_URL";
+pub const INSTALL_DIR_VAR: &str = "PARADISE_INSTALL_DIR";
+pub const NO_SHORTCUT_VAR: &str = "PARADISE_NO_SHORTCUT";
+pub const LOG_LEVEL_VAR: &str = "PARADISE_LOG_LEVEL";
+pub const SILENT_VAR: &str = "PARADISE_SILENT";
+pub const PROXY_VAR: &str = "PARADISE_PROXY";
+pub const AUTH_TOKEN_VAR: &str = "PARADISE_AUTH_TOKEN";
+pub const LICENSE_KEY_VAR: &str = "PARADISE_LICENSE_KEY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    CommandLine,
+    Environment(&'static str),
+    ConfigFile,
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment(var) => write!(f, "environment ({})", var),
+            Source::ConfigFile => write!(f, "config file"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sources {
+    pub manifest_url: Source,
+    pub install_mode: Source,
+    pub install_dir: Source,
+    pub no_shortcut: Source,
+    pub log_level: Source,
+    pub silent: Source,
+    pub proxy: Source,
+    pub auth_token: Source,
+    pub license_key: Source,
+    pub limit_rate: Source,
+    pub cache_max_size: Source,
+    pub expansion_factor: Source,
+    /// Config file values that were ignored, reported once logging is set up.
+    pub warnings: Vec<String>,
+}
+
+impl Sources {
+    pub fn log(&self) {
+        log::info!("Setting manifest_url from {}", self.manifest_url);
+        log::info!("Setting install_mode from {}", self.install_mode);
+        log::info!("Setting install_dir from {}", self.install_dir);
+        log::info!("Setting no_shortcut from {}", self.no_shortcut);
+        log::info!("Setting log_level from {}", self.log_level);
+        log::info!("Setting silent from {}", self.silent);
+        log::info!("Setting proxy from {}", self.proxy);
+        log::info!("Setting auth_token from {}", self.auth_token);
+        log::info!("Setting license_key from {}", self.license_key);
+        log::info!("Setting limit_rate from {}", self.limit_rate);
+        log::info!("Setting cache_max_size from {}", self.cache_max_size);
+        log::info!("Setting expansion_factor from {}", self.expansion_factor);
+    }
+}
+
+/// Fills settings that were not given on the command line from `PARADISE_*` variables,
+/// then from the config file.
+///
+/// A custom install path from the config file is not copied into `cli.dir`; it is only
+/// offered as the default answer when prompting for the path.
+///
+/// A bad value in the config file is ignored with a warning, as a config file that does
+/// not parse is, rather than stopping the run.
+pub fn apply(cli: &mut Cli, config: &Config) -> Result<Sources> {
+    let mut warnings = Vec::new();
+
+    let manifest_url = if cli.manifest_url.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = var(MANIFEST_URL_VAR) {
+        cli.manifest_url = Some(value);
+        Source::Environment(MANIFEST_URL_VAR)
+    } else if let Some(value) = &config.manifest_url {
+        cli.manifest_url = Some(value.clone());
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    let install_mode = if cli.mode.is_some() {
+        Source::CommandLine
+    } else if let Some(mode) = &config.install_mode {
+        cli.mode = Some(mode.clone());
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    let install_dir = if cli.dir.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = var(INSTALL_DIR_VAR) {
+        cli.dir = Some(PathBuf::from(value));
+        Source::Environment(INSTALL_DIR_VAR)
+    } else if config.install_dir.is_some() {
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    if !cli.no_desktop_shortcut && config.shortcuts.desktop == Some(false) {
+        cli.no_desktop_shortcut = true;
+    }
+    if !cli.no_install_dir_shortcut && config.shortcuts.install_dir == Some(false) {
+        cli.no_install_dir_shortcut = true;
+    }
+
+    let no_shortcut = if cli.no_shortcut {
+        Source::CommandLine
+    } else if let Some(value) = var(NO_SHORTCUT_VAR) {
+        cli.no_shortcut = parse_bool(NO_SHORTCUT_VAR, &value)?;
+        Source::Environment(NO_SHORTCUT_VAR)
+    } else {
+        Source::Default
+    };
+
+    let log_level = if cli.log_level.is_some() || cli.verbose > 0 {
+        Source::CommandLine
+    } else if let Some(value) = var(LOG_LEVEL_VAR) {
+        let level = LogLevel::from_str(&value, true).map_err(|_| {
+            anyhow::anyhow!(
+                "{} must be one of error, warn, info, debug, trace; got {:?}",
+                LOG_LEVEL_VAR,
+                value
+            )
+        })?;
+        cli.log_level = Some(level);
+        Source::Environment(LOG_LEVEL_VAR)
+    } else if let Some(level) = config.log_level {
+        cli.log_level = Some(level);
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    let silent = if cli.silent {
+        Source::CommandLine
+    } else if let Some(value) = var(SILENT_VAR) {
+        cli.silent = parse_bool(SILENT_VAR, &value)?;
+        Source::Environment(SILENT_VAR)
+    } else {
+        Source::Default
+    };
+
+    let proxy = if cli.proxy.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = var(PROXY_VAR) {
+        cli.proxy = Some(value);
+        Source::Environment(PROXY_VAR)
+    } else {
+        Source::Default
+    };
+
+    let auth_token = if cli.auth_token.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = var(AUTH_TOKEN_VAR) {
+        cli.auth_token = Some(value);
+        Source::Environment(AUTH_TOKEN_VAR)
+    } else {
+        Source::Default
+    };
+
+    let license_key = if cli.license_key.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = var(LICENSE_KEY_VAR) {
+        cli.license_key = Some(value);
+        Source::Environment(LICENSE_KEY_VAR)
+    } else {
+        Source::Default
+    };
+
+    let limit_rate = if cli.limit_rate.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = config_bytes("limit_rate", config.limit_rate.as_deref(), &mut warnings) {
+        cli.limit_rate = Some(value);
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    let cache_max_size = if cli.cache_max_size.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = config_bytes("cache_max_size", config.cache_max_size.as_deref(), &mut warnings) {
+        cli.cache_max_size = Some(value);
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    let expansion_factor = if cli.expansion_factor.is_some() {
+        Source::CommandLine
+    } else if let Some(value) = config.expansion_factor.filter(|value| {
+        let valid = value.is_finite() && *value >= 1.0;
+        if !valid {
+            warnings.push(format!(
+                "Ignoring expansion_factor {} in the config file: it must be a finite number ≥ 1",
+                value
+            ));
+        }
+        valid
+    }) {
+        cli.expansion_factor = Some(value);
+        Source::ConfigFile
+    } else {
+        Source::Default
+    };
+
+    Ok(Sources {
+        manifest_url,
+        install_mode,
+        install_dir,
+        no_shortcut,
+        log_level,
+        silent,
+        proxy,
+        auth_token,
+        license_key,
+        limit_rate,
+        cache_max_size,
+        expansion_factor,
+        warnings,
+    })
+}
+
+/// The size `field` of the config file gives, or `None` with a warning when it does not
+/// parse.
+fn config_bytes(field: &str, value: Option<&str>, warnings: &mut Vec<String>) -> Option<u64> {
+    match disk::parse_bytes(value?) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            warnings.push(format!("Ignoring {} {:?} in the config file: {:#}", field, value?, e));
+            None
+        }
+    }
+}
+
+fn var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => anyhow::bail!(
+            "{} must be a boolean (true/false, 1/0, yes/no), got {:?}",
+            name,
+            value
+        ),
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use crate::cancel;
+use crate::manifest::PatchAlgorithm;
+
+/// Entries replacing or adding a whole file.
+const NEW_PREFIX: &str = "new/";
+/// Entries patching an installed file.
+const PATCH_PREFIX: &str = "patch/";
+/// Paths deleted from the install, one per line.
+const REMOVED_FILE: &str = "removed.txt";
+
+/// zstd `--patch-from` patches may reference anything in the old file.
+const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+/// Applies a patch archive to `dir`, a copy of the installed files.
+///
+/// The archive is a zip with `new/<name>` for files taken as they are,
+/// `patch/<name>.bsdiff` or `patch/<name>.zst` for changes to the installed `<name>`
+/// (bsdiff, or zstd `--patch-from` with the installed file as reference), and an optional
+/// `removed.txt` listing files to delete. Files it does not mention stay unchanged.
+pub fn apply_archive(archive_path: &Path, dir: &Path, algorithm: PatchAlgorithm) -> Result<()> {
+    log::info!("Applying {:?} patch {:?} to {:?}", algorithm, archive_path, dir);
+
+    let file = fs::File::open(archive_path).context("Failed to open the patch")?;
+    let mut archive = zip::ZipArchive::new(file).context("Failed to read the patch archive")?;
+
+    let (mut patched, mut replaced, mut removed) = (0, 0, 0);
+    for i in 0..archive.len() {
+        cancel::check()?;
+        let mut entry = archive.by_index(i).context("Failed to read the patch archive")?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry
+            .enclosed_name()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow::anyhow!("Patch entry {:?} points outside the install", entry.name()))?;
+        let name = name.to_string_lossy().replace('\\', "/");
+
+        if name == REMOVED_FILE {
+            for line in BufReader::new(&mut entry).lines() {
+                let line = line.context("Failed to read the patch's removed.txt")?;
+                let line = line.trim();
+                if !line.is_empty() {
+                    remove(dir, line)?;
+                    removed += 1;
+                }
+            }
+        } else if let Some(target) = name.strip_prefix(NEW_PREFIX) {
+            let path = dir.join(target);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = fs::File::create(&path)
+                .with_context(|| format!("Failed to write {}", target))?;
+            io::copy(&mut entry, &mut output).with_context(|| format!("Failed to write {}", target))?;
+            replaced += 1;
+        } else if let Some(patch_name) = name.strip_prefix(PATCH_PREFIX) {
+            let target = patch_name
+                .strip_suffix(algorithm.extension())
+                .ok_or_else(|| anyhow::anyhow!("Patch entry {} is not a {:?} patch", name, algorithm))?;
+            let path = dir.join(target);
+            let old = fs::read(&path)
+                .with_context(|| format!("Failed to read installed file {} to patch", target))?;
+            let new = apply(algorithm, &old, &mut entry)
+                .with_context(|| format!("Failed to patch {}", target))?;
+            fs::write(&path, new).with_context(|| format!("Failed to write {}", target))?;
+            patched += 1;
+        } else {
+            anyhow::bail!("Unexpected patch entry {}", name);
+        }
+    }
+
+    log::info!(
+        "Patch applied: {} files patched, {} replaced or added, {} removed",
+        patched,
+        replaced,
+        removed
+    );
+    Ok(())
+}
+
+fn apply(algorithm: PatchAlgorithm, old: &[u8], patch: &mut impl Read) -> Result<Vec<u8>> {
+    let mut new = Vec::new();
+    match algorithm {
+        PatchAlgorithm::Bsdiff => bsdiff::patch(old, patch, &mut new)?,
+        PatchAlgorithm::Zstd => {
+            let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(BufReader::new(patch), old)?;
+            decoder.window_log_max(ZSTD_WINDOW_LOG_MAX)?;
+            decoder.read_to_end(&mut new)?;
+        }
+    }
+    Ok(new)
+}
+
+fn remove(dir: &Path, name: &str) -> Result<()> {
+    let relative = PathBuf::from(name);
+    if relative.is_absolute() || relative.components().any(|c| matches!(c, Component::ParentDir)) {
+        anyhow::bail!("Patch removes {:?}, which is outside the install", name);
+    }
+
+    let path = dir.join(relative);
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            log::debug!("Patch removes {}, which is not installed", name);
+            Ok(())
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const OLD_EXE: &[u8] = b"paradise 1.2.0 with the old renderer and the old sound engine";
+    const NEW_EXE: &[u8] = b"paradise 1.3.0 with the new renderer and the old sound engine";
+
+    fn write_patch(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        let options = zip::write::FileOptions::default();
+        for (name, data) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    /// An install of 1.2.0 and the directory its patches are written to.
+    fn installed() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let install = dir.path().join("install");
+        fs::create_dir_all(install.join("data")).unwrap();
+        fs::write(install.join("paradise.exe"), OLD_EXE).unwrap();
+        fs::write(install.join("data/levels.pak"), b"levels").unwrap();
+        fs::write(install.join("data/old.pak"), b"old").unwrap();
+        (dir, install)
+    }
+
+    #[test]
+    fn bsdiff_patches_update_add_and_remove_files() {
+        let (dir, install) = installed();
+        let mut diff = Vec::new();
+        bsdiff::diff(OLD_EXE, NEW_EXE, &mut diff).unwrap();
+        let patch = dir.path().join("patch.zip");
+        write_patch(
+            &patch,
+            &[
+                ("patch/paradise.exe.bsdiff", &diff),
+                ("new/data/new.pak", b"new"),
+                ("removed.txt", b"data/old.pak\n\ndata/gone.pak\n"),
+            ],
+        );
+
+        apply_archive(&patch, &install, PatchAlgorithm::Bsdiff).unwrap();
+        assert_eq!(fs::read(install.join("paradise.exe")).unwrap(), NEW_EXE);
+        assert_eq!(fs::read(install.join("data/new.pak")).unwrap(), b"new");
+        assert_eq!(fs::read(install.join("data/levels.pak")).unwrap(), b"levels");
+        assert!(!install.join("data/old.pak").exists());
+    }
+
+    #[test]
+    fn zstd_patches_use_the_installed_file_as_reference() {
+        let (dir, install) = installed();
+        let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 3, OLD_EXE).unwrap();
+        encoder.write_all(NEW_EXE).unwrap();
+        let diff = encoder.finish().unwrap();
+        let patch = dir.path().join("patch.zip");
+        write_patch(&patch, &[("patch/paradise.exe.zst", &diff)]);
+
+        apply_archive(&patch, &install, PatchAlgorithm::Zstd).unwrap();
+        assert_eq!(fs::read(install.join("paradise.exe")).unwrap(), NEW_EXE);
+    }
+
+    #[test]
+    fn bad_patches_are_refused() {
+        let (dir, install) = installed();
+        let patch = dir.path().join("patch.zip");
+        let error = |entries: &[(&str, &[u8])], algorithm| {
+            write_patch(&patch, entries);
+            format!("{:#}", apply_archive(&patch, &install, algorithm).unwrap_err())
+        };
+
+        let e = error(&[("patch/paradise.exe.bsdiff", b"not a bsdiff patch")], PatchAlgorithm::Bsdiff);
+        assert!(e.starts_with("Failed to patch paradise.exe: "), "{}", e);
+        assert_eq!(fs::read(install.join("paradise.exe")).unwrap(), OLD_EXE);
+
+        let e = error(&[("patch/paradise.exe.zst", b"")], PatchAlgorithm::Bsdiff);
+        assert_eq!(e, "Patch entry patch/paradise.exe.zst is not a Bsdiff patch");
+        let e = error(&[("patch/missing.exe.bsdiff", b"")], PatchAlgorithm::Bsdiff);
+        assert!(e.starts_with("Failed to read installed file missing.exe to patch"), "{}", e);
+        let e = error(&[("removed.txt", b"../outside.txt")], PatchAlgorithm::Bsdiff);
+        assert_eq!(e, "Patch removes \"../outside.txt\", which is outside the install");
+        let e = error(&[("readme.txt", b"")], PatchAlgorithm::Bsdiff);
+        assert_eq!(e, "Unexpected patch entry readme.txt");
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// `%LOCALAPPDATA%\paradise`, which holds logs, config and the standard install.
+pub fn data_dir() -> Result<PathBuf> {
+    let dir = directories::BaseDirs::new()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get base directories"))?
+        .data_local_dir()
+        .join("paradise");
+    Ok(dir)
+}
+
+pub fn logs_dir() -> Result<PathBuf> {
+    Ok(data_dir()?.join("logs"))
+}
+
+/// Where release downloads are kept until installed, so an interrupted one can be resumed.
+pub fn downloads_dir() -> Result<PathBuf> {
+    Ok(data_dir()?.join("downloads"))
+}
+
+/// Holds the last fetched manifest, so unchanged manifests are not downloaded again.
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(data_dir()?.join("cache"))
+}
+
+pub fn standard_install_dir() -> Result<PathBuf> {
+    Ok(data_dir()?.join("appfolder"))
+}
+
+/// Trims whitespace and one pair of surrounding quotes, as pasted from Explorer's "Copy as path".
+pub fn strip_quotes(input: &str) -> &str {
+    let trimmed = input.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = trimmed
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return inner.trim();
+        }
+    }
+    trimmed
+}
+
+/// Expands `%NAME%` references to what `lookup` gives for `NAME`, e.g.
+/// `|name| std::env::var(name).ok()`. `%%` is a literal percent sign; names `lookup` does
+/// not know are an error.
+pub fn expand_env_vars(input: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('%') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let Some(end) = after.find('%') else {
+            anyhow::bail!("Unterminated environment variable in {:?}", input);
+        };
+
+        let name = &after[..end];
+        if name.is_empty() {
+            output.push('%');
+        } else {
+            let value = lookup(name)
+                .ok_or_else(|| anyhow::anyhow!("Environment variable %{}% is not set", name))?;
+            output.push_str(&value);
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// The drive or root a path lives on, e.g. `D:\` for `D:\Games\paradise`.
+pub fn volume_root(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .last()
+}
@@ -0,0 +1,277 @@
+use anyhow::{Context, Result};
+use std::fmt;
+
+#[cfg(feature = "tls-rustls")]
+pub use verifier::pinned_tls_config;
+
+/// Comma-separated SPKI pins for the manifest host, set when the release binary is built:
+/// `PARADISE_MANIFEST_PINS=<hex>,<hex> cargo build --release`.
+const BUILT_IN_PINS: Option<&str> = option_env!("PARADISE_MANIFEST_PINS");
+
+/// The SHA256 of a certificate's DER SubjectPublicKeyInfo.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SpkiPin([u8; 32]);
+
+impl SpkiPin {
+    /// Parses 64 hex digits, optionally separated by colons.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let digits: String = text.trim().chars().filter(|c| *c != ':').collect();
+        let bytes = hex::decode(&digits).map_err(|_| format!("{:?} is not a hex SHA256", text))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| format!("{:?} is not 32 bytes (64 hex digits)", text))?;
+        Ok(SpkiPin(bytes))
+    }
+}
+
+impl fmt::Debug for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// A manifest host whose certificate matches none of the pins.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Certificate pin mismatch for {host}: the server's key ({seen}) matches none of the pinned \
+     keys. This is not an ordinary TLS error; the connection may be intercepted, e.g. by a \
+     TLS-inspecting proxy, or the server's certificate was replaced"
+)]
+pub struct PinMismatch {
+    pub host: String,
+    pub seen: String,
+}
+
+/// The pins the manifest host must match: the built-in ones (ignored in debug builds)
+/// plus those given with `--pin-cert`.
+pub fn manifest_pins(extra: &[SpkiPin]) -> Result<Vec<SpkiPin>> {
+    let mut pins = Vec::new();
+
+    match BUILT_IN_PINS.filter(|pins| !pins.trim().is_empty()) {
+        Some(_) if cfg!(debug_assertions) => {
+            log::info!("Debug build, ignoring the built-in certificate pins");
+        }
+        Some(built_in) => {
+            for pin in built_in.split(',') {
+                pins.push(
+                    SpkiPin::parse(pin)
+                        .map_err(anyhow::Error::msg)
+                        .context("Invalid built-in certificate pin")?,
+                );
+            }
+        }
+        None => {}
+    }
+    pins.extend_from_slice(extra);
+
+    Ok(pins)
+}
+
+/// The rustls verifier behind pinning; native-tls builds cannot pin.
+#[cfg(feature = "tls-rustls")]
+mod verifier {
+    use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+    use rustls::{
+        Certificate, CertificateError, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+    };
+    use sha2::{Digest, Sha256};
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::SystemTime;
+
+    use super::{PinMismatch, SpkiPin};
+
+    /// A TLS configuration that accepts the manifest host only when its chain verifies as
+    /// usual and its own certificate carries a pinned key. A mismatch is also recorded
+    /// in `mismatch`, so the caller can report it instead of a generic connection error.
+    ///
+    /// `extra_roots` are DER certificates trusted on top of the bundled roots.
+    pub fn pinned_tls_config(
+        pins: Vec<SpkiPin>,
+        extra_roots: &[Vec<u8>],
+        mismatch: Arc<Mutex<Option<PinMismatch>>>,
+    ) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+        for root in extra_roots {
+            if let Err(e) = roots.add(&Certificate(root.clone())) {
+                log::debug!("Skipping an unusable root certificate: {}", e);
+            }
+        }
+
+        let verifier = PinnedVerifier {
+            inner: WebPkiVerifier::new(roots, None),
+            pins,
+            mismatch,
+        };
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth()
+    }
+
+    struct PinnedVerifier {
+        inner: WebPkiVerifier,
+        pins: Vec<SpkiPin>,
+        mismatch: Arc<Mutex<Option<PinMismatch>>>,
+    }
+
+    impl ServerCertVerifier for PinnedVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            intermediates: &[Certificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            // Ordinary TLS failures keep their usual errors.
+            let verified = self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+
+            // Only the end-entity key counts: the intermediates are whatever the server
+            // sends, and webpki ignores those the chain does not need, so anyone can add
+            // one carrying a pinned key.
+            let seen = key_pin(end_entity);
+            if seen.is_some_and(|pin| self.pins.contains(&pin)) {
+                log::info!("Manifest host certificate matches a pinned key");
+                return Ok(verified);
+            }
+
+            let host = match server_name {
+                ServerName::DnsName(name) => name.as_ref().to_string(),
+                other => format!("{:?}", other),
+            };
+            let seen = seen.map_or_else(|| "unreadable".to_string(), |pin| format!("{:?}", pin));
+            let error = PinMismatch { host, seen };
+            log::error!("{}", error);
+            *self.mismatch.lock().unwrap_or_else(PoisonError::into_inner) = Some(error.clone());
+
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(Arc::new(error))))
+        }
+    }
+
+    /// The pin of the key `certificate` carries, if its DER can be read.
+    fn key_pin(certificate: &Certificate) -> Option<SpkiPin> {
+        subject_public_key_info(&certificate.0).map(|spki| SpkiPin(Sha256::digest(spki).into()))
+    }
+
+    /// The DER SubjectPublicKeyInfo of an X.509 certificate, tag and length included.
+    fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+        let (_, certificate, _) = der_element(certificate)?;
+        let (_, mut tbs, _) = der_element(certificate)?;
+
+        // version [0] is optional; then serial, signature, issuer, validity and subject.
+        let (tag, _, rest) = der_element(tbs)?;
+        if tag == 0xa0 {
+            tbs = rest;
+        }
+        for _ in 0..5 {
+            tbs = der_element(tbs)?.2;
+        }
+
+        let (tag, contents, rest) = der_element(tbs)?;
+        if tag != 0x30 {
+            return None;
+        }
+        let header = tbs.len() - rest.len() - contents.len();
+        Some(&tbs[..header + contents.len()])
+    }
+
+    /// Splits the DER element at the start of `input` into its tag, contents and what follows.
+    fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (&tag, input) = input.split_first()?;
+        let (&first, mut input) = input.split_first()?;
+
+        let length = if first < 0x80 {
+            usize::from(first)
+        } else {
+            let count = usize::from(first & 0x7f);
+            if count == 0 || count > 4 || input.len() < count {
+                return None;
+            }
+            let (bytes, rest) = input.split_at(count);
+            input = rest;
+            bytes.iter().fold(0, |length, byte| (length << 8) | usize::from(*byte))
+        };
+
+        if input.len() < length {
+            return None;
+        }
+        let (contents, rest) = input.split_at(length);
+        Some((tag, contents, rest))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rustls::client::ServerCertVerifier;
+
+        const LEAF: &[u8] = include_bytes!("../testdata/tls/127.0.0.1.der");
+        const CA: &[u8] = include_bytes!("../testdata/tls/ca.der");
+        /// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | sha256sum`
+        const LEAF_PIN: &str = "6e500c1674e546c5c05e83090fcd19c02e3642a7df7af6a210ce92d882a48028";
+        const CA_PIN: &str = "40c81e295683993e1f89653c3737b3bce25d9ed580f66c032aa651007c345ff5";
+
+        fn verify(pins: &[&str], intermediates: &[Certificate]) -> (bool, Option<PinMismatch>) {
+            let mismatch = Arc::new(Mutex::new(None));
+            let mut roots = RootCertStore::empty();
+            roots.add(&Certificate(CA.to_vec())).unwrap();
+            let verifier = PinnedVerifier {
+                inner: WebPkiVerifier::new(roots, None),
+                pins: pins.iter().map(|pin| SpkiPin::parse(pin).unwrap()).collect(),
+                mismatch: Arc::clone(&mismatch),
+            };
+            let verified = verifier
+                .verify_server_cert(
+                    &Certificate(LEAF.to_vec()),
+                    intermediates,
+                    &ServerName::try_from("127.0.0.1").unwrap(),
+                    &mut std::iter::empty(),
+                    &[],
+                    SystemTime::now(),
+                )
+                .is_ok();
+            let mismatch = mismatch.lock().unwrap().take();
+            (verified, mismatch)
+        }
+
+        #[test]
+        fn the_key_is_read_from_the_certificate() {
+            assert_eq!(key_pin(&Certificate(LEAF.to_vec())), Some(SpkiPin::parse(LEAF_PIN).unwrap()));
+            assert_eq!(key_pin(&Certificate(CA.to_vec())), Some(SpkiPin::parse(CA_PIN).unwrap()));
+            assert_eq!(subject_public_key_info(&LEAF[..LEAF.len() / 2]), None);
+            assert_eq!(subject_public_key_info(b"\x30\x03\x02\x01\x01"), None);
+        }
+
+        #[test]
+        fn the_server_key_must_be_pinned() {
+            let (verified, mismatch) = verify(&[CA_PIN, LEAF_PIN], &[]);
+            assert!(verified);
+            assert!(mismatch.is_none());
+
+            let (verified, mismatch) = verify(&[&"ab".repeat(32)], &[]);
+            assert!(!verified);
+            assert_eq!(mismatch.unwrap().seen, LEAF_PIN);
+        }
+
+        #[test]
+        fn pinned_keys_sent_alongside_the_chain_do_not_count() {
+            let (verified, mismatch) = verify(&[CA_PIN], &[Certificate(CA.to_vec())]);
+            assert!(!verified);
+            assert_eq!(mismatch.unwrap().seen, LEAF_PIN);
+        }
+    }
+}
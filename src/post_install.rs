@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use windows::core::{w, HSTRING, PCWSTR};
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::Threading::{GetExitCodeProcess, WaitForSingleObject, INFINITE};
+use windows::Win32::UI::Shell::{
+    ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SEE_MASK_NO_CONSOLE,
+    SHELLEXECUTEINFOW,
+};
+
+use crate::manifest::PostInstallCommand;
+
+/// Runs `command` from `install_dir` and waits for it. Fails when the program cannot be
+/// started or exits with a non-zero code; its output goes to the log.
+pub fn run(install_dir: &Path, command: &PostInstallCommand) -> Result<()> {
+    let program = resolve(install_dir, &command.command)?;
+    log::info!(
+        "Running post-install command {:?} {:?}{}",
+        program,
+        command.args,
+        if command.elevated { " (elevated)" } else { "" }
+    );
+
+    let code = if command.elevated {
+        run_elevated(&program, &command.args, install_dir)?
+    } else {
+        let output = Command::new(&program)
+            .args(&command.args)
+            .current_dir(install_dir)
+            .output()
+            .with_context(|| format!("Failed to start {}", program.display()))?;
+        log_output("stdout", &output.stdout);
+        log_output("stderr", &output.stderr);
+        output.status.code().unwrap_or(-1) as u32
+    };
+
+    log::info!("{} exited with code {}", command.command, code);
+    if code != 0 {
+        anyhow::bail!("exit code {}", code);
+    }
+    Ok(())
+}
+
+/// The program `command` names inside `install_dir`. The manifest only lists relative
+/// paths, and links inside the install directory may not lead out of it either.
+fn resolve(install_dir: &Path, command: &str) -> Result<PathBuf> {
+    let root = install_dir
+        .canonicalize()
+        .context("Failed to resolve the install directory")?;
+    let program = root
+        .join(command)
+        .canonicalize()
+        .with_context(|| format!("{} is not in the install directory", command))?;
+    if !program.starts_with(&root) || !program.is_file() {
+        anyhow::bail!("{} does not resolve to a file inside the install directory", command);
+    }
+    Ok(program)
+}
+
+fn log_output(stream: &str, bytes: &[u8]) {
+    for line in String::from_utf8_lossy(bytes).lines().filter(|line| !line.trim().is_empty()) {
+        log::info!("  [{}] {}", stream, line);
+    }
+}
+
+/// Starts `program` through the `runas` verb, which shows the UAC prompt, and returns its
+/// exit code.
+fn run_elevated(program: &Path, args: &[String], working_dir: &Path) -> Result<u32> {
+    log::info!("Output of elevated commands is not captured");
+
+    let file = HSTRING::from(program.to_string_lossy().as_ref());
+    let parameters = HSTRING::from(join_args(args));
+    let directory = HSTRING::from(working_dir.to_string_lossy().as_ref());
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: std::mem::size_of::<SHELLEXECUTEINFOW>() as u32,
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC | SEE_MASK_NO_CONSOLE,
+        lpVerb: w!("runas"),
+        lpFile: PCWSTR(file.as_ptr()),
+        lpParameters: PCWSTR(parameters.as_ptr()),
+        lpDirectory: PCWSTR(directory.as_ptr()),
+        // SW_HIDE
+        nShow: 0,
+        ..Default::default()
+    };
+
+    unsafe {
+        ShellExecuteExW(&mut info)
+            .with_context(|| format!("Failed to start {} elevated", program.display()))?;
+    }
+    if info.hProcess.is_invalid() {
+        anyhow::bail!("No process was started for {}", program.display());
+    }
+
+    let mut code = 0u32;
+    let result = unsafe {
+        WaitForSingleObject(info.hProcess, INFINITE);
+        GetExitCodeProcess(info.hProcess, &mut code)
+    };
+    unsafe {
+        let _ = CloseHandle(info.hProcess);
+    }
+    result.context("Failed to read the exit code")?;
+    Ok(code)
+}
+
+/// `args` as one command line, each quoted as needed.
+pub fn join_args(args: &[String]) -> String {
+    args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ")
+}
+
+/// Quotes an argument for the command line the way `CommandLineToArgvW` splits it.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// How a command is shown in a dry run.
+pub fn describe(command: &PostInstallCommand) -> String {
+    let mut text = std::iter::once(&command.command)
+        .chain(&command.args)
+        .map(|part| quote(part))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if command.elevated {
+        text.push_str(" (elevated)");
+    }
+    if command.ignore_failure {
+        text.push_str(" (failure ignored)");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn arguments_are_quoted_as_windows_splits_them() {
+        for (arg, quoted) in [
+            ("--silent", "--silent"),
+            (r"C:\Apps\paradise", r"C:\Apps\paradise"),
+            ("", r#""""#),
+            ("My Levels", r#""My Levels""#),
+            ("a\tb", "\"a\tb\""),
+            (r#"say "hi""#, r#""say \"hi\"""#),
+            // Backslashes are only special before a quote, including the closing one.
+            (r"C:\Program Files\", r#""C:\Program Files\\""#),
+            (r#"a\"b"#, r#""a\\\"b""#),
+            (r"a\\ b", r#""a\\ b""#),
+        ] {
+            assert_eq!(quote(arg), quoted, "{}", arg);
+        }
+        let args = ["--project".to_string(), "My Levels".to_string()];
+        assert_eq!(join_args(&args), r#"--project "My Levels""#);
+    }
+
+    #[test]
+    fn commands_must_stay_inside_the_install_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let install = dir.path().join("install");
+        fs::create_dir_all(install.join("tools")).unwrap();
+        fs::write(install.join("tools/setup.exe"), b"").unwrap();
+        fs::write(dir.path().join("outside.exe"), b"").unwrap();
+
+        let root = install.canonicalize().unwrap();
+        assert_eq!(resolve(&install, "tools/setup.exe").unwrap(), root.join("tools/setup.exe"));
+        assert_eq!(resolve(&install, "tools/../tools/setup.exe").unwrap(), root.join("tools/setup.exe"));
+
+        let e = resolve(&install, "../outside.exe").unwrap_err();
+        assert_eq!(e.to_string(), "../outside.exe does not resolve to a file inside the install directory");
+        let e = resolve(&install, "tools").unwrap_err();
+        assert_eq!(e.to_string(), "tools does not resolve to a file inside the install directory");
+        let e = resolve(&install, "missing.exe").unwrap_err();
+        assert_eq!(e.to_string(), "missing.exe is not in the install directory");
+    }
+
+    #[test]
+    fn links_may_not_lead_out_of_the_install_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let install = dir.path().join("install");
+        fs::create_dir_all(&install).unwrap();
+        let outside = dir.path().join("outside.exe");
+        fs::write(&outside, b"").unwrap();
+
+        #[cfg(unix)]
+        let linked = std::os::unix::fs::symlink(&outside, install.join("setup.exe"));
+        #[cfg(windows)]
+        let linked = std::os::windows::fs::symlink_file(&outside, install.join("setup.exe"));
+        if linked.is_err() {
+            // Windows only lets administrators and developer mode create links.
+            return;
+        }
+        let e = resolve(&install, "setup.exe").unwrap_err();
+        assert_eq!(e.to_string(), "setup.exe does not resolve to a file inside the install directory");
+    }
+}
@@ -0,0 +1,1244 @@
+use anyhow::{Context, Result};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use windows::core::PCSTR;
+use windows::Win32::System::Registry::{
+    RegGetValueA, HKEY, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, REG_ROUTINE_FLAGS,
+    RRF_RT_REG_DWORD, RRF_RT_REG_SZ, RRF_SUBKEY_WOW6432KEY, RRF_SUBKEY_WOW6464KEY,
+};
+
+use crate::disk::{format_bytes, free_space};
+use crate::manifest::RegistryRoot;
+use crate::paths;
+use crate::verify::compute_sha256;
+
+/// What a prerequisite check found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrereqResult {
+    Met,
+    /// Missing, but the release installs without it.
+    Warning(String),
+    Failed(String),
+}
+
+/// One thing the machine needs before a release is installed.
+pub trait PrerequisiteCheck {
+    fn name(&self) -> String;
+
+    fn check(&self) -> PrereqResult;
+
+    /// Whether [`remediate`](Self::remediate) can install what a failed check misses.
+    fn can_remediate(&self) -> bool {
+        false
+    }
+
+    /// Installs what a failed check misses.
+    fn remediate(&self, _remediator: &dyn Remediator) -> Result<()> {
+        anyhow::bail!("{} cannot be installed automatically", self.name())
+    }
+}
+
+/// What checks that can install a missing prerequisite use from the installer.
+pub trait Remediator {
+    /// Whether installs are only reported.
+    fn dry_run(&self) -> bool;
+
+    /// Called before `name` is installed, or would be in a dry run.
+    fn installing(&self, name: &str);
+
+    fn download(&self, url: &str, destination: &Path) -> Result<()>;
+}
+
+/// Every prerequisite that is not met.
+#[derive(Debug, thiserror::Error)]
+pub struct PrerequisitesNotMet(pub Vec<String>);
+
+impl fmt::Display for PrerequisitesNotMet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0[..] {
+            [failure] => f.write_str(failure),
+            failures => write!(f, "{} prerequisites are not met: {}", failures.len(), failures.join("; ")),
+        }
+    }
+}
+
+/// A missing prerequisite that could not be installed.
+#[derive(Debug, thiserror::Error)]
+#[error("Installing {name} failed: {reason}")]
+pub struct RemediationFailed {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Runs all `checks`, installing what is missing through `remediator` where a check can,
+/// and fails listing every prerequisite that is still not met. Without a remediator
+/// nothing is installed.
+pub fn run_all(checks: &[Box<dyn PrerequisiteCheck>], remediator: Option<&dyn Remediator>) -> Result<()> {
+    let mut failures = Vec::new();
+    for check in checks {
+        let name = check.name();
+        log::info!("Checking prerequisite: {}", name);
+
+        let result = match (check.check(), remediator) {
+            (PrereqResult::Failed(reason), Some(remediator)) if check.can_remediate() => {
+                log::info!("{}, installing {}", reason, name);
+                remediator.installing(&name);
+                if remediator.dry_run() {
+                    PrereqResult::Warning(format!("{}; it would be installed", reason))
+                } else {
+                    check.remediate(remediator).map_err(|e| RemediationFailed {
+                        name: name.clone(),
+                        reason: format!("{:#}", e),
+                    })?;
+                    match check.check() {
+                        PrereqResult::Met => PrereqResult::Met,
+                        PrereqResult::Warning(problem) => {
+                            PrereqResult::Warning(format!("installed, but the check still says: {}", problem))
+                        }
+                        PrereqResult::Failed(problem) => {
+                            PrereqResult::Failed(format!("{} (still missing after installing {})", problem, name))
+                        }
+                    }
+                }
+            }
+            (result, _) => result,
+        };
+
+        match result {
+            PrereqResult::Met => log::info!("Prerequisite met: {}", name),
+            PrereqResult::Warning(problem) => log::warn!("{}: {}", name, problem),
+            PrereqResult::Failed(problem) => {
+                log::error!("Prerequisite not met: {}", problem);
+                failures.push(problem);
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(PrerequisitesNotMet(failures).into())
+    }
+}
+
+/// The `prerequisites` of a manifest: a list of checks such as
+/// `{"type": "vc_redist", ...}`, or the older object with a field per kind of check.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct Prerequisites(pub Vec<PrerequisiteSpec>);
+
+/// One entry of the `prerequisites` list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PrerequisiteSpec {
+    WindowsVersion(WindowsVersionMin),
+    VcRedist(VcRedist),
+    Dotnet(DotnetRuntime),
+    Webview2(WebView2),
+    RegistryValue(RegistryValue),
+    File(FileExists),
+    DiskSpace(DiskSpace),
+    /// A type this bootstrapper does not know, only warned about, so manifests can add
+    /// checks without breaking older bootstrappers.
+    #[serde(skip)]
+    Unknown(String),
+}
+
+/// The `type`s of [`PrerequisiteSpec`] other than `Unknown`.
+const KNOWN_TYPES: &[&str] = &[
+    "windows_version",
+    "vc_redist",
+    "dotnet",
+    "webview2",
+    "registry_value",
+    "file",
+    "disk_space",
+];
+
+impl PrerequisiteSpec {
+    fn from_value(value: serde_json::Value) -> Result<Self, String> {
+        let kind = value
+            .get("type")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| "prerequisite has no \"type\"".to_string())?
+            .to_string();
+        if !KNOWN_TYPES.contains(&kind.as_str()) {
+            return Ok(PrerequisiteSpec::Unknown(kind));
+        }
+        serde_json::from_value(value).map_err(|e| format!("invalid {} prerequisite: {}", kind, e))
+    }
+}
+
+/// The object form of `prerequisites` manifests used before the list.
+#[derive(Deserialize)]
+struct LegacyPrerequisites {
+    windows_version_min: Option<String>,
+    vc_redist: Option<VcRedist>,
+    dotnet: Option<DotnetRuntime>,
+    webview2: Option<WebView2>,
+}
+
+impl From<LegacyPrerequisites> for Prerequisites {
+    fn from(legacy: LegacyPrerequisites) -> Self {
+        let specs = [
+            legacy
+                .windows_version_min
+                .map(|min_version| PrerequisiteSpec::WindowsVersion(WindowsVersionMin { min_version })),
+            legacy.vc_redist.map(PrerequisiteSpec::VcRedist),
+            legacy.dotnet.map(PrerequisiteSpec::Dotnet),
+            legacy.webview2.map(PrerequisiteSpec::Webview2),
+        ];
+        Prerequisites(specs.into_iter().flatten().collect())
+    }
+}
+
+impl<'de> Deserialize<'de> for Prerequisites {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Array(entries) => entries
+                .into_iter()
+                .map(PrerequisiteSpec::from_value)
+                .collect::<Result<Vec<_>, _>>()
+                .map(Prerequisites)
+                .map_err(D::Error::custom),
+            legacy => serde_json::from_value::<LegacyPrerequisites>(legacy)
+                .map(Prerequisites::from)
+                .map_err(D::Error::custom),
+        }
+    }
+}
+
+impl Prerequisites {
+    /// The checks to run; `arch` picks the VC++ runtime to look for.
+    pub fn checks(&self, arch: &str) -> Vec<Box<dyn PrerequisiteCheck>> {
+        self.0
+            .iter()
+            .map(|spec| -> Box<dyn PrerequisiteCheck> {
+                match spec {
+                    PrerequisiteSpec::WindowsVersion(spec) => Box::new(spec.clone()),
+                    PrerequisiteSpec::VcRedist(spec) => Box::new(VcRedistCheck {
+                        spec: spec.clone(),
+                        arch: arch.to_string(),
+                    }),
+                    PrerequisiteSpec::Dotnet(spec) => Box::new(spec.clone()),
+                    PrerequisiteSpec::Webview2(spec) => Box::new(spec.clone()),
+                    PrerequisiteSpec::RegistryValue(spec) => Box::new(spec.clone()),
+                    PrerequisiteSpec::File(spec) => Box::new(spec.clone()),
+                    PrerequisiteSpec::DiskSpace(spec) => Box::new(spec.clone()),
+                    PrerequisiteSpec::Unknown(kind) => Box::new(UnknownCheck(kind.clone())),
+                }
+            })
+            .collect()
+    }
+
+    /// The URL of every check that has one, with the check's index: what the installer
+    /// downloads a missing prerequisite from, or where the user can get it. Optional URLs
+    /// left empty are skipped.
+    pub fn urls(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.0.iter().enumerate().filter_map(|(i, spec)| {
+            let url = match spec {
+                PrerequisiteSpec::Webview2(webview2) => Some(webview2.url.as_str()),
+                PrerequisiteSpec::VcRedist(vc_redist) => Some(vc_redist.url.as_str()).filter(|url| !url.is_empty()),
+                PrerequisiteSpec::Dotnet(dotnet) => Some(dotnet.url.as_str()).filter(|url| !url.is_empty()),
+                PrerequisiteSpec::RegistryValue(registry) => registry.url.as_deref(),
+                PrerequisiteSpec::File(file) => file.url.as_deref(),
+                _ => None,
+            }?;
+            Some((i, url))
+        })
+    }
+}
+
+/// The failure for a missing prerequisite, or a warning when the release does without it.
+fn missing(required: bool, problem: String) -> PrereqResult {
+    if required {
+        PrereqResult::Failed(problem)
+    } else {
+        PrereqResult::Warning(problem)
+    }
+}
+
+/// `problem`, pointing at `url` when the manifest gives one.
+fn with_url(problem: String, url: Option<&str>) -> String {
+    match url {
+        Some(url) => format!("{}. Install it from {}", problem, url),
+        None => problem,
+    }
+}
+
+/// The oldest Windows the release runs on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowsVersionMin {
+    /// `10.0.19041`, or a plain build number like `19041`.
+    pub min_version: String,
+}
+
+impl PrerequisiteCheck for WindowsVersionMin {
+    fn name(&self) -> String {
+        format!("Windows {} or later", self.min_version)
+    }
+
+    fn check(&self) -> PrereqResult {
+        let current = match WindowsVersion::current() {
+            Ok(current) => current,
+            Err(e) => return PrereqResult::Failed(format!("{:#}", e)),
+        };
+        log::info!("Current Windows version: {}", current);
+        match meets_windows_minimum(&self.min_version, current) {
+            Ok(true) => PrereqResult::Met,
+            Ok(false) => PrereqResult::Failed(format!(
+                "This release needs Windows {} or later, but this machine runs Windows {}",
+                self.min_version, current
+            )),
+            Err(e) => PrereqResult::Failed(format!("{:#}", e)),
+        }
+    }
+}
+
+/// A Windows version as `RtlGetVersion` reports it, e.g. 10.0.22631 for Windows 11 23H2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WindowsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl WindowsVersion {
+    /// The running system's version. Unlike `GetVersionEx` and the `CurrentVersion`
+    /// registry value, which say 6.2 or 6.3 on Windows 10 and 11, `RtlGetVersion` is not
+    /// subject to compatibility shims.
+    pub fn current() -> Result<Self> {
+        use windows::Wdk::System::SystemServices::RtlGetVersion;
+        use windows::Win32::System::SystemInformation::OSVERSIONINFOW;
+
+        let mut info = OSVERSIONINFOW {
+            dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
+            ..Default::default()
+        };
+        unsafe { RtlGetVersion(&mut info) }
+            .ok()
+            .context("Failed to read the Windows version")?;
+
+        Ok(Self {
+            major: info.dwMajorVersion,
+            minor: info.dwMinorVersion,
+            build: info.dwBuildNumber,
+        })
+    }
+}
+
+impl fmt::Display for WindowsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+/// Whether `current` is at least `minimum`, given as `10.0.19041` (missing parts are 0)
+/// or as a plain build number like `19041`. Build numbers grow across Windows releases,
+/// so a plain build number is compared with the build alone.
+pub fn meets_windows_minimum(minimum: &str, current: WindowsVersion) -> Result<bool> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid windows_version_min {:?}, expected e.g. \"10.0.19041\" or \"19041\"",
+            minimum
+        )
+    };
+    let parts = minimum
+        .trim()
+        .split('.')
+        .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(match parts[..] {
+        [build] => current.build >= build,
+        [major, minor] => current >= WindowsVersion { major, minor, build: 0 },
+        [major, minor, build] => current >= WindowsVersion { major, minor, build },
+        _ => return Err(invalid()),
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VcRedist {
+    pub required: bool,
+    pub url: String,
+    /// Oldest acceptable runtime, e.g. `14.38`; any installed version does without it.
+    #[serde(default)]
+    pub min_version: Option<String>,
+}
+
+/// A [`VcRedist`] for the machine's architecture.
+struct VcRedistCheck {
+    spec: VcRedist,
+    arch: String,
+}
+
+impl PrerequisiteCheck for VcRedistCheck {
+    fn name(&self) -> String {
+        format!("Visual C++ runtime for {}", self.arch)
+    }
+
+    fn check(&self) -> PrereqResult {
+        let installed = VcRuntime::installed(&self.arch);
+        match &installed {
+            Some(runtime) => log::info!(
+                "VC++ runtime for {}: {}",
+                self.arch,
+                runtime.version.as_deref().unwrap_or("unknown version")
+            ),
+            None => log::info!("No VC++ runtime for {} found", self.arch),
+        }
+        check_vc_redist(&self.spec, &self.arch, installed.as_ref())
+    }
+}
+
+/// A Visual C++ 2015-2022 runtime, as its installer registers it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VcRuntime {
+    /// The `Version` value, e.g. `v14.38.33135.00`.
+    pub version: Option<String>,
+}
+
+impl VcRuntime {
+    /// The runtime installed for `arch`, looked up in the 64-bit and then the 32-bit
+    /// registry view, since the redistributable has written to either.
+    pub fn installed(arch: &str) -> Option<Self> {
+        let key = format!("{}\0", vc_runtime_key(arch));
+        [RRF_SUBKEY_WOW6464KEY, RRF_SUBKEY_WOW6432KEY]
+            .into_iter()
+            .find_map(|view| {
+                (registry_dword(HKEY_LOCAL_MACHINE, &key, "Installed\0", view)? == 1).then(|| VcRuntime {
+                    version: registry_string(HKEY_LOCAL_MACHINE, &key, "Version\0", view),
+                })
+            })
+    }
+}
+
+/// The key under HKLM the redistributable for `arch` registers itself in.
+fn vc_runtime_key(arch: &str) -> String {
+    let runtime = if arch == "aarch64" { "arm64" } else { "x64" };
+    format!("SOFTWARE\\Microsoft\\VisualStudio\\14.0\\VC\\Runtimes\\{}", runtime)
+}
+
+/// Checks `vc_redist` against `installed`, the runtime found for `arch`.
+pub fn check_vc_redist(vc_redist: &VcRedist, arch: &str, installed: Option<&VcRuntime>) -> PrereqResult {
+    let problem = match (installed, &vc_redist.min_version) {
+        (None, _) => format!("the Visual C++ runtime for {} is not installed", arch),
+        (Some(_), None) => return PrereqResult::Met,
+        (Some(runtime), Some(minimum)) => {
+            let Some(wanted) = parse_dotted(minimum) else {
+                return PrereqResult::Failed(format!(
+                    "Invalid vc_redist min_version {:?}, expected e.g. \"14.38\"",
+                    minimum
+                ));
+            };
+            match runtime.version.as_deref() {
+                Some(version) if parse_dotted(version).is_some_and(|found| found >= wanted) => {
+                    return PrereqResult::Met
+                }
+                Some(version) => format!(
+                    "the Visual C++ runtime for {} is version {}, older than the required {}",
+                    arch,
+                    version.trim_start_matches('v'),
+                    minimum
+                ),
+                None => format!(
+                    "the version of the Visual C++ runtime for {} is unknown, {} is required",
+                    arch, minimum
+                ),
+            }
+        }
+    };
+
+    if !vc_redist.required {
+        return PrereqResult::Warning(format!(
+            "{}; the release may not start without it ({})",
+            problem, vc_redist.url
+        ));
+    }
+    PrereqResult::Failed(format!(
+        "This release needs the Visual C++ runtime: {}. Install it from {}",
+        problem, vc_redist.url
+    ))
+}
+
+/// `v14.38.33135.00` or `14.38` as its numeric parts, without trailing zero parts so
+/// `14.38` and `14.38.0` compare equal.
+fn parse_dotted(text: &str) -> Option<Vec<u32>> {
+    let mut parts = text
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+/// The .NET runtime (`Microsoft.NETCore.App`) the release needs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DotnetRuntime {
+    pub required: bool,
+    /// E.g. `8.0` or `8.0.4`. Runtimes of a newer major version do not count, since .NET
+    /// apps do not roll forward to them by default.
+    pub min_version: String,
+    pub url: String,
+}
+
+impl PrerequisiteCheck for DotnetRuntime {
+    fn name(&self) -> String {
+        format!(".NET runtime {}", self.min_version)
+    }
+
+    fn check(&self) -> PrereqResult {
+        let runtimes = installed_dotnet_runtimes();
+        log::info!(".NET runtimes found: {}", runtimes.join(", "));
+        check_dotnet(self, &runtimes)
+    }
+}
+
+/// Versions of the installed `Microsoft.NETCore.App` runtimes: the folders under
+/// `%DOTNET_ROOT%` or `%ProgramFiles%\\dotnet`, else what `dotnet --list-runtimes` says.
+pub fn installed_dotnet_runtimes() -> Vec<String> {
+    let root = std::env::var_os("DOTNET_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("ProgramFiles").map(|dir| Path::new(&dir).join("dotnet")));
+    if let Some(runtimes) = root.and_then(|root| dotnet_runtimes_in(&root)) {
+        return runtimes;
+    }
+
+    log::debug!("No .NET runtime folder found, asking dotnet --list-runtimes");
+    match std::process::Command::new("dotnet").arg("--list-runtimes").output() {
+        Ok(output) => parse_list_runtimes(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            log::debug!("Could not run dotnet: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Runtime versions installed below the .NET root `root`, or `None` when it has no
+/// `shared\\Microsoft.NETCore.App` folder.
+pub fn dotnet_runtimes_in(root: &Path) -> Option<Vec<String>> {
+    let entries = fs::read_dir(root.join("shared").join("Microsoft.NETCore.App")).ok()?;
+    Some(
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .filter(|name| DotnetVersion::parse(name).is_some())
+            .collect(),
+    )
+}
+
+/// The `Microsoft.NETCore.App` versions in `dotnet --list-runtimes` output, whose lines
+/// look like `Microsoft.NETCore.App 8.0.4 [C:\\Program Files\\dotnet\\shared\\...]`.
+pub fn parse_list_runtimes(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Microsoft.NETCore.App "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A .NET version such as `8.0.4` or `9.0.0-preview.3.24172.9`. Missing parts are 0,
+/// and a prerelease sorts before its release.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DotnetVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Placed before `prerelease` so a release sorts after every prerelease.
+    is_release: bool,
+    pub prerelease: String,
+}
+
+impl DotnetVersion {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (numbers, prerelease) = match text.trim().split_once('-') {
+            Some((numbers, prerelease)) => (numbers, Some(prerelease)),
+            None => (text.trim(), None),
+        };
+        let mut parts = numbers.split('.').map(|part| part.parse::<u32>().ok());
+        let major = parts.next()??;
+        let minor = parts.next().unwrap_or(Some(0))?;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            major,
+            minor,
+            patch,
+            is_release: prerelease.is_none(),
+            prerelease: prerelease.unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Checks `dotnet` against the installed runtime versions `runtimes`. A runtime counts
+/// when it has the required major version and is at least `min_version`.
+pub fn check_dotnet(dotnet: &DotnetRuntime, runtimes: &[String]) -> PrereqResult {
+    let Some(minimum) = DotnetVersion::parse(&dotnet.min_version) else {
+        return PrereqResult::Failed(format!(
+            "Invalid dotnet min_version {:?}, expected e.g. \"8.0\"",
+            dotnet.min_version
+        ));
+    };
+    let suitable = runtimes
+        .iter()
+        .filter_map(|runtime| DotnetVersion::parse(runtime))
+        .any(|version| version.major == minimum.major && version >= minimum);
+    if suitable {
+        return PrereqResult::Met;
+    }
+
+    let problem = if runtimes.is_empty() {
+        format!("no .NET runtime is installed, {} is required", dotnet.min_version)
+    } else {
+        format!(
+            "the installed .NET runtimes ({}) do not include {} or a later {}.x",
+            runtimes.join(", "),
+            dotnet.min_version,
+            minimum.major
+        )
+    };
+    if !dotnet.required {
+        return PrereqResult::Warning(format!(
+            "{}; parts of the release may not work ({})",
+            problem, dotnet.url
+        ));
+    }
+    PrereqResult::Failed(format!(
+        "This release needs the .NET runtime: {}. Install it from {}",
+        problem, dotnet.url
+    ))
+}
+
+/// The Evergreen WebView2 runtime, installed with its bootstrapper when missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebView2 {
+    /// Installed from `url` when missing; otherwise only a warning is logged.
+    pub required: bool,
+    /// The Evergreen bootstrapper, e.g. `https://go.microsoft.com/fwlink/p/?LinkId=2124703`.
+    pub url: String,
+    /// SHA256 of the bootstrapper at `url`, checked before it is run. Required, since the
+    /// bootstrapper runs silently and often elevated.
+    pub sha256: String,
+}
+
+impl PrerequisiteCheck for WebView2 {
+    fn name(&self) -> String {
+        "the WebView2 runtime".to_string()
+    }
+
+    fn check(&self) -> PrereqResult {
+        match webview2_version() {
+            Some(version) => {
+                log::info!("WebView2 runtime {} is installed", version);
+                PrereqResult::Met
+            }
+            None if self.required => PrereqResult::Failed("The WebView2 runtime is not installed".to_string()),
+            None => PrereqResult::Warning(
+                "the WebView2 runtime is not installed; the in-app browser will not work".to_string(),
+            ),
+        }
+    }
+
+    fn can_remediate(&self) -> bool {
+        true
+    }
+
+    fn remediate(&self, remediator: &dyn Remediator) -> Result<()> {
+        let dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+        let installer = dir.path().join("MicrosoftEdgeWebview2Setup.exe");
+        remediator
+            .download(&self.url, &installer)
+            .with_context(|| format!("download from {} failed", self.url))?;
+        let expected = self.sha256.trim();
+        let computed = compute_sha256(&installer)?;
+        if !computed.eq_ignore_ascii_case(expected) {
+            anyhow::bail!(
+                "the bootstrapper from {} has SHA256 {}, expected {}",
+                self.url,
+                computed,
+                expected
+            );
+        }
+
+        let status = std::process::Command::new(&installer)
+            .args(["/silent", "/install"])
+            .status()
+            .context("could not start the bootstrapper")?;
+        if !status.success() {
+            let code = status
+                .code()
+                .map_or_else(|| "none".to_string(), |code| format!("{:#x}", code as u32));
+            anyhow::bail!("the bootstrapper exited with code {}", code);
+        }
+        Ok(())
+    }
+}
+
+/// Client key EdgeUpdate registers the WebView2 runtime under.
+const WEBVIEW2_CLIENT_KEY: &str =
+    "SOFTWARE\\Microsoft\\EdgeUpdate\\Clients\\{F3017226-FE2A-4295-8BDF-00C3A9A7E4C5}\0";
+
+/// The installed WebView2 runtime's version: the `pv` value of its EdgeUpdate client key,
+/// per machine (in the 32-bit registry view) or per user. Uninstalled runtimes can leave
+/// an empty or `0.0.0.0` version behind.
+pub fn webview2_version() -> Option<String> {
+    [
+        (HKEY_LOCAL_MACHINE, RRF_SUBKEY_WOW6432KEY),
+        (HKEY_CURRENT_USER, REG_ROUTINE_FLAGS(0)),
+    ]
+    .into_iter()
+    .filter_map(|(root, view)| registry_string(root, WEBVIEW2_CLIENT_KEY, "pv\0", view))
+    .find(|version| !version.is_empty() && version != "0.0.0.0")
+}
+
+/// A registry value that must exist, and optionally have a given value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryValue {
+    /// `HKLM\...` or `HKCU\...` (or `HKEY_LOCAL_MACHINE\...`, `HKEY_CURRENT_USER\...`).
+    pub key: String,
+    pub value: String,
+    /// Expected data of a string or DWORD value, compared as text.
+    #[serde(default)]
+    pub equals: Option<String>,
+    #[serde(default = "required_by_default")]
+    pub required: bool,
+    /// Where to get what sets the value, named in the failure.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+fn required_by_default() -> bool {
+    true
+}
+
+impl PrerequisiteCheck for RegistryValue {
+    fn name(&self) -> String {
+        format!("registry value {}\\{}", self.key, self.value)
+    }
+
+    fn check(&self) -> PrereqResult {
+        let Some((root, subkey)) = parse_registry_key(&self.key) else {
+            return PrereqResult::Failed(format!(
+                "Invalid registry key {:?}, expected it to start with HKLM\\ or HKCU\\",
+                self.key
+            ));
+        };
+        let key = format!("{}\0", subkey);
+        let value = format!("{}\0", self.value);
+        let found = [RRF_SUBKEY_WOW6464KEY, RRF_SUBKEY_WOW6432KEY]
+            .into_iter()
+            .find_map(|view| registry_text(root.hkey(), &key, &value, view));
+
+        let problem = match (found, &self.equals) {
+            (None, _) => format!("the registry value {} is not set", self.name_in_key()),
+            (Some(_), None) => return PrereqResult::Met,
+            (Some(found), Some(expected)) if found.eq_ignore_ascii_case(expected) => return PrereqResult::Met,
+            (Some(found), Some(expected)) => format!(
+                "the registry value {} is {:?}, not {:?}",
+                self.name_in_key(),
+                found,
+                expected
+            ),
+        };
+        missing(self.required, with_url(problem, self.url.as_deref()))
+    }
+}
+
+impl RegistryValue {
+    fn name_in_key(&self) -> String {
+        format!("{} in {}", self.value, self.key)
+    }
+}
+
+/// Splits `HKLM\SOFTWARE\Vendor` into its hive and the path below it.
+pub fn parse_registry_key(key: &str) -> Option<(RegistryRoot, &str)> {
+    let (root, subkey) = key.split_once('\\')?;
+    let root = match root.to_ascii_uppercase().as_str() {
+        "HKLM" | "HKEY_LOCAL_MACHINE" => RegistryRoot::LocalMachine,
+        "HKCU" | "HKEY_CURRENT_USER" => RegistryRoot::CurrentUser,
+        _ => return None,
+    };
+    (!subkey.is_empty()).then_some((root, subkey))
+}
+
+/// A file that must exist, such as a driver or a shared runtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileExists {
+    /// Absolute path; `%VAR%` environment variables are expanded.
+    pub path: String,
+    #[serde(default = "required_by_default")]
+    pub required: bool,
+    /// Where to get what installs the file, named in the failure.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl PrerequisiteCheck for FileExists {
+    fn name(&self) -> String {
+        format!("file {}", self.path)
+    }
+
+    fn check(&self) -> PrereqResult {
+        let path = match paths::expand_env_vars(&self.path, |name| std::env::var(name).ok()) {
+            Ok(path) => path,
+            Err(e) => return missing(self.required, with_url(format!("{:#}", e), self.url.as_deref())),
+        };
+        if Path::new(&path).exists() {
+            return PrereqResult::Met;
+        }
+        missing(self.required, with_url(format!("{} does not exist", path), self.url.as_deref()))
+    }
+}
+
+/// Free space the release needs on a volume besides its own install, e.g. for data it
+/// creates on first start.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskSpace {
+    /// A directory on the volume; `%VAR%` environment variables are expanded.
+    #[serde(default = "system_drive")]
+    pub path: String,
+    pub min_bytes: u64,
+}
+
+fn system_drive() -> String {
+    "%SystemDrive%\\".to_string()
+}
+
+impl PrerequisiteCheck for DiskSpace {
+    fn name(&self) -> String {
+        format!("{} free on {}", format_bytes(self.min_bytes), self.path)
+    }
+
+    fn check(&self) -> PrereqResult {
+        let path = match paths::expand_env_vars(&self.path, |name| std::env::var(name).ok()) {
+            Ok(path) => path,
+            Err(e) => return PrereqResult::Failed(format!("{:#}", e)),
+        };
+        match free_space(Path::new(&path)) {
+            Ok(available) if available >= self.min_bytes => PrereqResult::Met,
+            Ok(available) => PrereqResult::Failed(format!(
+                "This release needs {} free on {}, but only {} is available",
+                format_bytes(self.min_bytes),
+                path,
+                format_bytes(available)
+            )),
+            Err(e) => PrereqResult::Failed(format!("{:#}", e)),
+        }
+    }
+}
+
+/// A check of a type this bootstrapper does not know.
+struct UnknownCheck(String);
+
+impl PrerequisiteCheck for UnknownCheck {
+    fn name(&self) -> String {
+        format!("prerequisite of type {:?}", self.0)
+    }
+
+    fn check(&self) -> PrereqResult {
+        PrereqResult::Warning(
+            "unknown prerequisite type, not checked; a newer bootstrapper may know it".to_string(),
+        )
+    }
+}
+
+/// A string value from the registry; `key` and `value` are NUL-terminated.
+fn registry_string(root: HKEY, key: &str, value: &str, view: REG_ROUTINE_FLAGS) -> Option<String> {
+    let mut buffer = [0u8; 512];
+    let mut size = buffer.len() as u32;
+    unsafe {
+        RegGetValueA(
+            root,
+            PCSTR(key.as_ptr()),
+            PCSTR(value.as_ptr()),
+            RRF_RT_REG_SZ | view,
+            None,
+            Some(buffer.as_mut_ptr().cast()),
+            Some(&mut size),
+        )
+    }
+    .ok()?;
+
+    let text = &buffer[..(size as usize).saturating_sub(1)];
+    std::str::from_utf8(text).ok().map(str::to_string)
+}
+
+/// A DWORD value from the registry; `key` and `value` are NUL-terminated.
+fn registry_dword(root: HKEY, key: &str, value: &str, view: REG_ROUTINE_FLAGS) -> Option<u32> {
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        RegGetValueA(
+            root,
+            PCSTR(key.as_ptr()),
+            PCSTR(value.as_ptr()),
+            RRF_RT_REG_DWORD | view,
+            None,
+            Some(&mut data as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    }
+    .ok()?;
+    Some(data)
+}
+
+/// A string or DWORD value from the registry as text.
+fn registry_text(root: HKEY, key: &str, value: &str, view: REG_ROUTINE_FLAGS) -> Option<String> {
+    registry_string(root, key, value, view)
+        .or_else(|| registry_dword(root, key, value, view).map(|data| data.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Windows 11 23H2.
+    const WINDOWS_11: WindowsVersion = WindowsVersion { major: 10, minor: 0, build: 22631 };
+    /// Windows 8.1, which `CurrentVersion` claims even on Windows 11.
+    const WINDOWS_8_1: WindowsVersion = WindowsVersion { major: 6, minor: 3, build: 9600 };
+
+    #[test]
+    fn full_versions_compare_part_by_part() {
+        assert!(meets_windows_minimum("10.0.19041", WINDOWS_11).unwrap());
+        assert!(meets_windows_minimum("10.0.22631", WINDOWS_11).unwrap());
+        assert!(!meets_windows_minimum("10.0.22632", WINDOWS_11).unwrap());
+        assert!(!meets_windows_minimum("10.0.19041", WINDOWS_8_1).unwrap());
+        // A higher build of an older major version is still older.
+        assert!(!meets_windows_minimum("10.0.0", WindowsVersion { major: 6, minor: 3, build: 30000 }).unwrap());
+        assert!(meets_windows_minimum("6.1.7601", WINDOWS_8_1).unwrap());
+    }
+
+    #[test]
+    fn missing_parts_are_0() {
+        assert!(meets_windows_minimum("10.0", WINDOWS_11).unwrap());
+        assert!(meets_windows_minimum("6.3", WINDOWS_8_1).unwrap());
+        assert!(!meets_windows_minimum("10.0", WINDOWS_8_1).unwrap());
+        assert!(!meets_windows_minimum("10.1", WINDOWS_11).unwrap());
+    }
+
+    #[test]
+    fn plain_build_numbers_compare_with_the_build() {
+        assert!(meets_windows_minimum("19041", WINDOWS_11).unwrap());
+        assert!(meets_windows_minimum(" 22631 ", WINDOWS_11).unwrap());
+        assert!(!meets_windows_minimum("22635", WINDOWS_11).unwrap());
+        assert!(!meets_windows_minimum("10240", WINDOWS_8_1).unwrap());
+    }
+
+    #[test]
+    fn invalid_minimums_fail() {
+        for minimum in ["", "10.0.x", "Windows 10", "10.0.19041.1", "-1"] {
+            let e = meets_windows_minimum(minimum, WINDOWS_11).unwrap_err();
+            assert_eq!(
+                e.to_string(),
+                format!("Invalid windows_version_min {:?}, expected e.g. \"10.0.19041\" or \"19041\"", minimum)
+            );
+        }
+    }
+
+    #[test]
+    fn windows_versions_display_dotted() {
+        assert_eq!(WINDOWS_11.to_string(), "10.0.22631");
+    }
+
+    const VC_REDIST_URL: &str = "https://aka.ms/vs/17/release/vc_redist.x64.exe";
+
+    fn vc_redist(required: bool, min_version: Option<&str>) -> VcRedist {
+        VcRedist {
+            required,
+            url: VC_REDIST_URL.to_string(),
+            min_version: min_version.map(str::to_string),
+        }
+    }
+
+    fn runtime(version: Option<&str>) -> VcRuntime {
+        VcRuntime { version: version.map(str::to_string) }
+    }
+
+    #[test]
+    fn vc_runtime_keys_follow_the_architecture() {
+        assert_eq!(vc_runtime_key("x86_64"), "SOFTWARE\\Microsoft\\VisualStudio\\14.0\\VC\\Runtimes\\x64");
+        assert_eq!(vc_runtime_key("aarch64"), "SOFTWARE\\Microsoft\\VisualStudio\\14.0\\VC\\Runtimes\\arm64");
+    }
+
+    #[test]
+    fn any_installed_vc_runtime_does_without_a_minimum() {
+        assert_eq!(check_vc_redist(&vc_redist(true, None), "x86_64", Some(&runtime(None))), PrereqResult::Met);
+        assert_eq!(
+            check_vc_redist(&vc_redist(true, None), "aarch64", Some(&runtime(Some("v14.20.27508.01")))),
+            PrereqResult::Met
+        );
+    }
+
+    #[test]
+    fn missing_vc_runtime_fails_with_the_url_when_required() {
+        assert_eq!(
+            check_vc_redist(&vc_redist(true, Some("14.38")), "aarch64", None),
+            PrereqResult::Failed(format!(
+                "This release needs the Visual C++ runtime: the Visual C++ runtime for aarch64 is not installed. \
+                 Install it from {}",
+                VC_REDIST_URL
+            ))
+        );
+        assert_eq!(
+            check_vc_redist(&vc_redist(false, None), "x86_64", None),
+            PrereqResult::Warning(format!(
+                "the Visual C++ runtime for x86_64 is not installed; the release may not start without it ({})",
+                VC_REDIST_URL
+            ))
+        );
+    }
+
+    #[test]
+    fn vc_runtime_versions_compare_numerically() {
+        let spec = vc_redist(true, Some("14.38"));
+        for version in ["v14.38.33135.00", "14.38", "v14.38.0.0", "v14.40.33810.00", "v15.0.0.0"] {
+            assert_eq!(check_vc_redist(&spec, "x86_64", Some(&runtime(Some(version)))), PrereqResult::Met, "{}", version);
+        }
+        assert_eq!(
+            check_vc_redist(&spec, "x86_64", Some(&runtime(Some("v14.9.30000.00")))),
+            PrereqResult::Failed(format!(
+                "This release needs the Visual C++ runtime: the Visual C++ runtime for x86_64 is version \
+                 14.9.30000.00, older than the required 14.38. Install it from {}",
+                VC_REDIST_URL
+            ))
+        );
+    }
+
+    #[test]
+    fn unknown_vc_runtime_versions_do_not_meet_a_minimum() {
+        for version in [None, Some("unknown")] {
+            let PrereqResult::Warning(warning) =
+                check_vc_redist(&vc_redist(false, Some("14.38")), "x86_64", Some(&runtime(version)))
+            else {
+                panic!("{:?} should only warn", version);
+            };
+            assert!(warning.starts_with("the "), "{}", warning);
+        }
+        assert_eq!(
+            check_vc_redist(&vc_redist(true, Some("14.38")), "x86_64", Some(&runtime(None))),
+            PrereqResult::Failed(format!(
+                "This release needs the Visual C++ runtime: the version of the Visual C++ runtime for x86_64 is \
+                 unknown, 14.38 is required. Install it from {}",
+                VC_REDIST_URL
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_vc_redist_minimums_fail() {
+        assert_eq!(
+            check_vc_redist(&vc_redist(false, Some("latest")), "x86_64", Some(&runtime(Some("v14.38.33135.00")))),
+            PrereqResult::Failed("Invalid vc_redist min_version \"latest\", expected e.g. \"14.38\"".to_string())
+        );
+    }
+
+    const DOTNET_URL: &str = "https://dotnet.microsoft.com/download/dotnet/8.0";
+
+    fn dotnet(required: bool, min_version: &str) -> DotnetRuntime {
+        DotnetRuntime {
+            required,
+            min_version: min_version.to_string(),
+            url: DOTNET_URL.to_string(),
+        }
+    }
+
+    fn runtimes(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|version| version.to_string()).collect()
+    }
+
+    #[test]
+    fn dotnet_runtimes_are_the_version_folders() {
+        let root = tempfile::tempdir().unwrap();
+        let shared = root.path().join("shared");
+        for dir in ["Microsoft.NETCore.App/6.0.25", "Microsoft.NETCore.App/8.0.4", "Microsoft.NETCore.App/9.0.0-preview.3.24172.9"] {
+            fs::create_dir_all(shared.join(dir)).unwrap();
+        }
+        // Neither other frameworks, stray files nor folders that are no version count.
+        fs::create_dir_all(shared.join("Microsoft.AspNetCore.App").join("7.0.0")).unwrap();
+        fs::create_dir_all(shared.join("Microsoft.NETCore.App").join("backup")).unwrap();
+        fs::write(shared.join("Microsoft.NETCore.App").join("7.0.0"), "").unwrap();
+
+        let mut found = dotnet_runtimes_in(root.path()).unwrap();
+        found.sort();
+        assert_eq!(found, ["6.0.25", "8.0.4", "9.0.0-preview.3.24172.9"]);
+    }
+
+    #[test]
+    fn dotnet_roots_without_runtimes() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(dotnet_runtimes_in(root.path()), None);
+        fs::create_dir_all(root.path().join("shared").join("Microsoft.NETCore.App")).unwrap();
+        assert_eq!(dotnet_runtimes_in(root.path()), Some(Vec::new()));
+    }
+
+    #[test]
+    fn list_runtimes_output_gives_the_app_runtimes() {
+        let output = "Microsoft.AspNetCore.App 8.0.4 [C:\\Program Files\\dotnet\\shared\\Microsoft.AspNetCore.App]\r\n\
+                      Microsoft.NETCore.App 6.0.25 [C:\\Program Files\\dotnet\\shared\\Microsoft.NETCore.App]\r\n\
+                      Microsoft.NETCore.App 8.0.4 [C:\\Program Files\\dotnet\\shared\\Microsoft.NETCore.App]\r\n\
+                      Microsoft.WindowsDesktop.App 8.0.4 [C:\\Program Files\\dotnet\\shared\\Microsoft.WindowsDesktop.App]\r\n";
+        assert_eq!(parse_list_runtimes(output), ["6.0.25", "8.0.4"]);
+        assert!(parse_list_runtimes("").is_empty());
+        assert!(parse_list_runtimes("'dotnet' is not recognized as an internal or external command").is_empty());
+    }
+
+    #[test]
+    fn dotnet_versions_compare_semantically() {
+        let v = |text| DotnetVersion::parse(text).unwrap();
+        assert!(v("8.0.10") > v("8.0.4"));
+        assert!(v("8.0") == v("8.0.0"));
+        assert!(v("8") == v("8.0.0"));
+        assert!(v("9.0.0-preview.3") < v("9.0.0"));
+        assert!(v("9.0.0-preview.3") > v("8.0.99"));
+        assert!(v("9.0.0-preview.3") < v("9.0.0-rc.1"));
+        for invalid in ["", "eight", "8.x", "8.0.0.1"] {
+            assert!(DotnetVersion::parse(invalid).is_none(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn dotnet_needs_the_same_major_version() {
+        let spec = dotnet(true, "8.0.2");
+        assert_eq!(check_dotnet(&spec, &runtimes(&["6.0.25", "8.0.4"])), PrereqResult::Met);
+        assert_eq!(check_dotnet(&spec, &runtimes(&["8.0.2"])), PrereqResult::Met);
+        assert_eq!(
+            check_dotnet(&spec, &runtimes(&["8.0.1", "9.0.0"])),
+            PrereqResult::Failed(format!(
+                "This release needs the .NET runtime: the installed .NET runtimes (8.0.1, 9.0.0) do not include \
+                 8.0.2 or a later 8.x. Install it from {}",
+                DOTNET_URL
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_dotnet_fails_only_when_required() {
+        assert_eq!(
+            check_dotnet(&dotnet(true, "8.0"), &[]),
+            PrereqResult::Failed(format!(
+                "This release needs the .NET runtime: no .NET runtime is installed, 8.0 is required. Install it from {}",
+                DOTNET_URL
+            ))
+        );
+        assert_eq!(
+            check_dotnet(&dotnet(false, "8.0"), &[]),
+            PrereqResult::Warning(format!(
+                "no .NET runtime is installed, 8.0 is required; parts of the release may not work ({})",
+                DOTNET_URL
+            ))
+        );
+        assert_eq!(
+            check_dotnet(&dotnet(true, "net8"), &[]),
+            PrereqResult::Failed("Invalid dotnet min_version \"net8\", expected e.g. \"8.0\"".to_string())
+        );
+    }
+
+    /// Downloads by writing `contents`, and records what it was asked to install.
+    struct FakeRemediator {
+        contents: &'static [u8],
+        installing: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl FakeRemediator {
+        fn new(contents: &'static [u8]) -> Self {
+            FakeRemediator { contents, installing: Default::default() }
+        }
+    }
+
+    impl Remediator for FakeRemediator {
+        fn dry_run(&self) -> bool {
+            false
+        }
+
+        fn installing(&self, name: &str) {
+            self.installing.borrow_mut().push(name.to_string());
+        }
+
+        fn download(&self, _url: &str, destination: &Path) -> Result<()> {
+            Ok(fs::write(destination, self.contents)?)
+        }
+    }
+
+    /// Missing before and after it is installed.
+    struct NeverInstalled {
+        required: bool,
+    }
+
+    impl PrerequisiteCheck for NeverInstalled {
+        fn name(&self) -> String {
+            "the runtime".to_string()
+        }
+
+        fn check(&self) -> PrereqResult {
+            missing(self.required, "the runtime is not installed".to_string())
+        }
+
+        fn can_remediate(&self) -> bool {
+            true
+        }
+
+        fn remediate(&self, _remediator: &dyn Remediator) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn required_prerequisites_missing_after_installing_still_fail() {
+        let remediator = FakeRemediator::new(b"");
+        let checks: Vec<Box<dyn PrerequisiteCheck>> = vec![Box::new(NeverInstalled { required: true })];
+        let e = run_all(&checks, Some(&remediator)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "the runtime is not installed (still missing after installing the runtime)"
+        );
+        assert_eq!(*remediator.installing.borrow(), ["the runtime"]);
+
+        let checks: Vec<Box<dyn PrerequisiteCheck>> = vec![Box::new(NeverInstalled { required: false })];
+        run_all(&checks, Some(&remediator)).unwrap();
+    }
+
+    #[test]
+    fn webview2_bootstrappers_need_a_hash() {
+        let spec = serde_json::json!({
+            "type": "webview2",
+            "required": true,
+            "url": "https://go.microsoft.com/fwlink/p/?LinkId=2124703"
+        });
+        assert_eq!(
+            PrerequisiteSpec::from_value(spec).unwrap_err(),
+            "invalid webview2 prerequisite: missing field `sha256`"
+        );
+    }
+
+    #[test]
+    fn webview2_bootstrappers_with_another_hash_are_not_run() {
+        use sha2::Digest;
+
+        let webview2 = WebView2 {
+            required: true,
+            url: "https://go.microsoft.com/fwlink/p/?LinkId=2124703".to_string(),
+            sha256: format!("{}\n", "AB".repeat(32)),
+        };
+        let e = webview2.remediate(&FakeRemediator::new(b"not the bootstrapper")).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "the bootstrapper from {} has SHA256 {}, expected {}",
+                webview2.url,
+                hex::encode(sha2::Sha256::digest(b"not the bootstrapper")),
+                "AB".repeat(32)
+            )
+        );
+    }
+}
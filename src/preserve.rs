@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Whether `path`, relative to the install directory, is one of the manifest's `preserve`
+/// patterns or inside a directory that is. Patterns are relative paths in which `*` and
+/// `?` match within a name and `**` matches any number of directories; case is ignored,
+/// as on Windows.
+pub fn is_preserved(path: &str, patterns: &[String]) -> bool {
+    let path = path.replace('\\', "/");
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.replace('\\', "/");
+        let pattern: Vec<&str> = pattern.split('/').filter(|segment| !segment.is_empty()).collect();
+        (1..=segments.len()).any(|len| match_segments(&pattern, &segments[..len]))
+    })
+}
+
+/// The preserved files and directories in `dir`, relative to it, without descending
+/// into a preserved directory.
+pub fn find(dir: &Path, patterns: &[String]) -> Result<Vec<String>> {
+    let mut found = Vec::new();
+    if !patterns.is_empty() {
+        find_in(dir, "", patterns, &mut found)?;
+    }
+    Ok(found)
+}
+
+fn find_in(dir: &Path, prefix: &str, patterns: &[String], found: &mut Vec<String>) -> Result<()> {
+    let entries = fs::read_dir(dir.join(prefix))
+        .with_context(|| format!("Failed to read {}", dir.join(prefix).display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let relative = join(prefix, &entry.file_name().to_string_lossy());
+        if is_preserved(&relative, patterns) {
+            found.push(relative);
+        } else if entry.file_type()?.is_dir() {
+            find_in(dir, &relative, patterns, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the preserved files and directories of `old_dir`, the installation being
+/// replaced, into `new_dir`. The user's copy wins over a file the release ships at the
+/// same path. Returns the relative paths copied.
+pub fn restore(old_dir: &Path, new_dir: &Path, patterns: &[String]) -> Result<Vec<String>> {
+    let mut restored = Vec::new();
+    if !patterns.is_empty() {
+        restore_dir(old_dir, new_dir, "", patterns, &mut restored)?;
+    }
+    Ok(restored)
+}
+
+fn restore_dir(old_dir: &Path, new_dir: &Path, prefix: &str, patterns: &[String], restored: &mut Vec<String>) -> Result<()> {
+    let entries = fs::read_dir(old_dir.join(prefix))
+        .with_context(|| format!("Failed to read {}", old_dir.join(prefix).display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let relative = join(prefix, &entry.file_name().to_string_lossy());
+        let preserved = is_preserved(&relative, patterns);
+        if entry.file_type()?.is_dir() {
+            if preserved {
+                fs::create_dir_all(new_dir.join(&relative))
+                    .with_context(|| format!("Failed to create {}", relative))?;
+            }
+            restore_dir(old_dir, new_dir, &relative, patterns, restored)?;
+        } else if preserved {
+            let destination = new_dir.join(&relative);
+            if destination.exists() {
+                log::info!("Keeping the user's {} instead of the one in the release", relative);
+            }
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            fs::copy(entry.path(), &destination).with_context(|| format!("Failed to keep {}", relative))?;
+            restored.push(relative);
+        }
+    }
+    Ok(())
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    match prefix {
+        "" => name.to_string(),
+        prefix => format!("{}/{}", prefix, name),
+    }
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((segment, rest)) => {
+            !path.is_empty() && match_name(segment, path[0]) && match_segments(rest, &path[1..])
+        }
+    }
+}
+
+/// Matches one path segment against a pattern segment with `*` and `?`.
+fn match_name(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    // Where the last `*` was and the position in `name` it matched up to.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn patterns_match_paths_and_what_is_inside_them() {
+        let patterns = patterns(&["settings.json", "saves/", "config/*.ini", "**/*.sav"]);
+        assert!(is_preserved("settings.json", &patterns));
+        assert!(is_preserved("Settings.JSON", &patterns));
+        assert!(is_preserved("saves/slot1/world.dat", &patterns));
+        assert!(is_preserved("config/keys.ini", &patterns));
+        assert!(!is_preserved("config/defaults/keys.ini", &patterns));
+        assert!(is_preserved("profiles/alice/quick.sav", &patterns));
+        assert!(is_preserved("quick.sav", &patterns));
+        assert!(!is_preserved("paradise.exe", &patterns));
+        assert!(!is_preserved("saves.txt", &patterns));
+    }
+
+    #[test]
+    fn preserved_files_win_over_the_release() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        fs::create_dir_all(old.path().join("saves/slot1")).unwrap();
+        fs::create_dir_all(old.path().join("saves/empty")).unwrap();
+        fs::write(old.path().join("saves/slot1/world.dat"), "mine").unwrap();
+        fs::write(old.path().join("settings.json"), "{\"volume\": 3}").unwrap();
+        fs::write(old.path().join("paradise.exe"), "old").unwrap();
+        fs::write(new.path().join("settings.json"), "{}").unwrap();
+        fs::write(new.path().join("paradise.exe"), "new").unwrap();
+
+        let mut restored = restore(old.path(), new.path(), &patterns(&["settings.json", "saves"])).unwrap();
+        restored.sort();
+        assert_eq!(restored, ["saves/slot1/world.dat", "settings.json"]);
+        assert_eq!(fs::read_to_string(new.path().join("settings.json")).unwrap(), "{\"volume\": 3}");
+        assert_eq!(fs::read_to_string(new.path().join("saves/slot1/world.dat")).unwrap(), "mine");
+        assert!(new.path().join("saves/empty").is_dir());
+        assert_eq!(fs::read_to_string(new.path().join("paradise.exe")).unwrap(), "new");
+
+        let mut found = find(old.path(), &patterns(&["settings.json", "**/world.dat"])).unwrap();
+        found.sort();
+        assert_eq!(found, ["saves/slot1/world.dat", "settings.json"]);
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::PWSTR;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows::Win32::System::Threading::{
+    GetCurrentProcessId, OpenProcess, QueryFullProcessImageNameW, TerminateProcess, PROCESS_NAME_WIN32,
+    PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_TERMINATE,
+};
+
+/// How often [`wait_for_exit`] looks again.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A running program that holds files of the install open.
+#[derive(Debug, Clone)]
+pub struct RunningProcess {
+    pub pid: u32,
+    pub path: PathBuf,
+}
+
+impl RunningProcess {
+    /// The program's file name and process id, e.g. `paradise.exe (pid 4242)`.
+    pub fn describe(&self) -> String {
+        let name = self.path.file_name().unwrap_or(self.path.as_os_str());
+        format!("{} (pid {})", name.to_string_lossy(), self.pid)
+    }
+}
+
+/// Running programs started from `install_dir`, or at one of `names` in it, the
+/// manifest's `close_processes`. Programs whose path cannot be read, such as those of
+/// other users, are found by the file names of `names` alone. The bootstrapper itself is
+/// left out.
+pub fn find(install_dir: &Path, names: &[String]) -> Result<Vec<RunningProcess>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
+        .context("Failed to list the running processes")?;
+    let own = unsafe { GetCurrentProcessId() };
+
+    let mut running = Vec::new();
+    let mut entry = PROCESSENTRY32W {
+        dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+        ..Default::default()
+    };
+    let mut next = unsafe { Process32FirstW(snapshot, &mut entry) };
+    while next.is_ok() {
+        if entry.th32ProcessID != own {
+            // Processes of other users or protected ones cannot be opened, but one of
+            // the listed programs run by another user still holds the files open.
+            let found = match image_path(entry.th32ProcessID) {
+                Some(path) => is_match(&path, install_dir, names).then_some(path),
+                None => {
+                    let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+                    let exe = String::from_utf16_lossy(&entry.szExeFile[..len]);
+                    is_listed_name(&exe, names).then(|| PathBuf::from(exe))
+                }
+            };
+            if let Some(path) = found {
+                running.push(RunningProcess { pid: entry.th32ProcessID, path });
+            }
+        }
+        next = unsafe { Process32NextW(snapshot, &mut entry) };
+    }
+    let _ = unsafe { CloseHandle(snapshot) };
+    Ok(running)
+}
+
+/// Whether to end the running programs rather than wait for them to exit: always with
+/// `--kill-running`, unattended only when the manifest allows it, and otherwise when
+/// the user agrees to `ask`.
+pub fn should_end(
+    kill_running: bool,
+    interactive: bool,
+    kill_running_unattended: bool,
+    ask: impl FnOnce() -> Result<bool>,
+) -> Result<bool> {
+    if kill_running {
+        return Ok(true);
+    }
+    if !interactive {
+        return Ok(kill_running_unattended);
+    }
+    ask()
+}
+
+/// Ends `process` without giving it a chance to save.
+pub fn terminate(process: &RunningProcess) -> Result<()> {
+    let handle = unsafe { OpenProcess(PROCESS_TERMINATE, false, process.pid) }
+        .with_context(|| format!("Failed to open {}", process.describe()))?;
+    let result = unsafe { TerminateProcess(handle, 1) };
+    let _ = unsafe { CloseHandle(handle) };
+    result.with_context(|| format!("Failed to end {}", process.describe()))
+}
+
+/// Waits up to `timeout` for the programs [`find`] reports to exit, and returns those
+/// still running then.
+pub fn wait_for_exit(install_dir: &Path, names: &[String], timeout: Duration) -> Result<Vec<RunningProcess>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let running = find(install_dir, names)?;
+        if running.is_empty() || Instant::now() >= deadline {
+            return Ok(running);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn image_path(pid: u32) -> Option<PathBuf> {
+    let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+    let mut buffer = [0u16; 1024];
+    let mut size = buffer.len() as u32;
+    let result = unsafe {
+        QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut size)
+    };
+    let _ = unsafe { CloseHandle(handle) };
+    result.ok()?;
+    Some(PathBuf::from(String::from_utf16_lossy(&buffer[..size as usize])))
+}
+
+/// Whether the program at `path` is inside `install_dir` or is one of `names`, relative
+/// to it, compared the way Windows compares paths.
+fn is_match(path: &Path, install_dir: &Path, names: &[String]) -> bool {
+    let normalize = |path: &str| path.replace('/', "\\").to_lowercase();
+    let path = normalize(&path.to_string_lossy());
+    let dir = normalize(&install_dir.to_string_lossy());
+    let dir = dir.trim_end_matches('\\');
+    if path.starts_with(&format!("{}\\", dir)) {
+        return true;
+    }
+    names
+        .iter()
+        .any(|name| path == format!("{}\\{}", dir, normalize(name).trim_start_matches('\\')))
+}
+
+/// Whether `exe`, a program file name without its directory, is the file name of one
+/// of `names`.
+fn is_listed_name(exe: &str, names: &[String]) -> bool {
+    names.iter().any(|name| {
+        let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        file_name.eq_ignore_ascii_case(exe)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_inside_the_install_dir_or_on_the_list_match() {
+        let dir = Path::new(r"C:\Games\paradise\");
+        let names = vec!["tools/crash-reporter.exe".to_string()];
+        assert!(is_match(Path::new(r"C:\Games\Paradise\paradise.exe"), dir, &names));
+        assert!(is_match(Path::new(r"C:\Games\paradise\bin\helper.exe"), dir, &names));
+        assert!(is_match(Path::new(r"C:/Games/paradise/Tools/Crash-Reporter.exe"), dir, &names));
+        assert!(!is_match(Path::new(r"C:\Temp\crash-reporter.exe"), dir, &names));
+        assert!(!is_match(Path::new(r"C:\Games\paradise-old\paradise.exe"), dir, &names));
+        assert!(!is_match(Path::new(r"C:\Windows\explorer.exe"), dir, &names));
+    }
+
+    #[test]
+    fn unreadable_programs_match_by_listed_file_name() {
+        let names = vec!["paradise.exe".to_string(), r"tools\crash-reporter.exe".to_string()];
+        assert!(is_listed_name("Paradise.exe", &names));
+        assert!(is_listed_name("crash-reporter.exe", &names));
+        assert!(!is_listed_name("tools", &names));
+        assert!(!is_listed_name("helper.exe", &names));
+        assert!(!is_listed_name("paradise.exe", &[]));
+    }
+
+    #[test]
+    fn programs_are_ended_when_allowed_or_agreed_to() {
+        let never = || -> Result<bool> { panic!("asked without a console") };
+        assert!(should_end(true, false, false, never).unwrap());
+        assert!(should_end(true, true, false, never).unwrap());
+        assert!(should_end(false, false, true, never).unwrap());
+        assert!(!should_end(false, false, false, never).unwrap());
+        assert!(should_end(false, true, false, || Ok(true)).unwrap());
+        assert!(!should_end(false, true, true, || Ok(false)).unwrap());
+        assert!(should_end(false, true, false, || anyhow::bail!("no console")).is_err());
+    }
+}
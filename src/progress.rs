@@ -0,0 +1,172 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::time::{Duration, Instant};
+
+use crate::disk::format_bytes;
+
+/// How often progress is logged when the total size is unknown.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// Window the peak transfer rate is measured over.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// A multi-bar display shared by downloads running at the same time, and the label of one
+/// of them.
+#[derive(Clone)]
+pub struct ProgressGroup {
+    bars: MultiProgress,
+    label: String,
+}
+
+impl ProgressGroup {
+    pub fn new() -> Self {
+        Self {
+            bars: MultiProgress::new(),
+            label: String::new(),
+        }
+    }
+
+    /// The same display, for the download called `label`.
+    pub fn for_download(&self, label: &str) -> Self {
+        Self {
+            bars: self.bars.clone(),
+            label: label.to_string(),
+        }
+    }
+}
+
+/// Download progress: a bar on the console when `visible`, plus log lines every ~10%.
+pub struct DownloadProgress {
+    bar: Option<ProgressBar>,
+    total: Option<u64>,
+    position: u64,
+    next_percent: u64,
+    last_log: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+    peak_rate: u64,
+}
+
+impl DownloadProgress {
+    /// In a `group` the bar joins its multi-bar display, labelled with the download's name.
+    pub fn new(total: Option<u64>, visible: bool, group: Option<&ProgressGroup>) -> Self {
+        let prefix = if group.is_some() { "{prefix:24} " } else { "" };
+        let bar = visible.then(|| {
+            let bar = match total {
+                Some(total) => {
+                    let bar = ProgressBar::new(total);
+                    bar.set_style(
+                        ProgressStyle::with_template(&format!(
+                            "{}[{{bar:40}}] {{percent:>3}}% {{bytes}}/{{total_bytes}} {{bytes_per_sec}} eta {{eta}}",
+                            prefix
+                        ))
+                        .expect("valid progress template")
+                        .progress_chars("=> "),
+                    );
+                    bar
+                }
+                None => {
+                    let bar = ProgressBar::new_spinner();
+                    bar.set_style(
+                        ProgressStyle::with_template(&format!(
+                            "{}{{spinner}} {{bytes}} {{bytes_per_sec}}",
+                            prefix
+                        ))
+                        .expect("valid progress template"),
+                    );
+                    bar.enable_steady_tick(Duration::from_millis(120));
+                    bar
+                }
+            };
+            match group {
+                Some(group) => {
+                    let bar = group.bars.add(bar);
+                    bar.set_prefix(group.label.clone());
+                    bar
+                }
+                None => bar,
+            }
+        });
+
+        Self {
+            bar,
+            total,
+            position: 0,
+            next_percent: 10,
+            last_log: Instant::now(),
+            window_start: Instant::now(),
+            window_bytes: 0,
+            peak_rate: 0,
+        }
+    }
+
+    /// Starts from `position` bytes already on disk, without counting them as transferred.
+    pub fn resume_at(&mut self, position: u64) {
+        self.position = position;
+        if let Some(bar) = &self.bar {
+            bar.set_position(position);
+        }
+    }
+
+    /// Highest rate seen over any one-second window, in bytes per second.
+    pub fn peak_rate(&self) -> u64 {
+        let elapsed = self.window_start.elapsed().as_secs_f64();
+        let current = if elapsed > 0.0 {
+            (self.window_bytes as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        self.peak_rate.max(current)
+    }
+
+    pub fn advance(&mut self, bytes: u64) {
+        self.position += bytes;
+        if let Some(bar) = &self.bar {
+            bar.set_position(self.position);
+        }
+
+        self.window_bytes += bytes;
+        let window = self.window_start.elapsed();
+        if window >= RATE_WINDOW {
+            let rate = (self.window_bytes as f64 / window.as_secs_f64()) as u64;
+            self.peak_rate = self.peak_rate.max(rate);
+            self.window_start = Instant::now();
+            self.window_bytes = 0;
+        }
+
+        match self.total {
+            Some(total) if total > 0 => {
+                let percent = self.position.saturating_mul(100) / total;
+                if percent >= self.next_percent {
+                    log::info!(
+                        "Downloaded {}% ({} of {})",
+                        percent,
+                        format_bytes(self.position),
+                        format_bytes(total)
+                    );
+                    self.next_percent = (percent / 10 + 1) * 10;
+                }
+            }
+            _ => {
+                if self.last_log.elapsed() >= LOG_INTERVAL {
+                    log::info!("Downloaded {} so far", format_bytes(self.position));
+                    self.last_log = Instant::now();
+                }
+            }
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
+    }
+}
+
+impl Drop for DownloadProgress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            if !bar.is_finished() {
+                bar.abandon();
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use std::io::{self, Write};
+
+use crate::strings;
+
+pub fn confirm(question: &str, default: bool) -> Result<bool> {
+    let hint = if default {
+        strings::text("prompt.hint_yes")
+    } else {
+        strings::text("prompt.hint_no")
+    };
+    print!("{} {} ", question, hint);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        anyhow::bail!("No answer to {:?}: input is closed", question);
+    }
+
+    let answer = input.trim().to_lowercase();
+    let is_one_of = |key| strings::text(key).split('|').any(|word| word == answer);
+
+    if answer.is_empty() {
+        Ok(default)
+    } else if is_one_of("prompt.yes") {
+        Ok(true)
+    } else if is_one_of("prompt.no") {
+        Ok(false)
+    } else {
+        println!("{}", strings::text("prompt.retry"));
+        confirm(question, default)
+    }
+}
+
+/// Reads one line of text after `question`, trimmed.
+pub fn ask(question: &str) -> Result<String> {
+    print!("{} ", question);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        anyhow::bail!("No answer to {:?}: input is closed", question);
+    }
+    Ok(input.trim().to_string())
+}
@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use reqwest::{NoProxy, Proxy};
+use std::env;
+use std::fmt;
+
+use crate::http::redact;
+
+/// Proxy URL schemes reqwest can connect through; `socks5h` resolves names on the proxy.
+const SUPPORTED_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// Where the proxy for this run was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxySource {
+    /// `--proxy`, `PARADISE_PROXY`.
+    Configured,
+    /// `HTTPS_PROXY` / `HTTP_PROXY` / `ALL_PROXY`.
+    Environment,
+    /// The proxy set in Windows Internet Options.
+    WindowsSettings,
+}
+
+impl fmt::Display for ProxySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxySource::Configured => write!(f, "the proxy setting"),
+            ProxySource::Environment => write!(f, "the HTTP(S)_PROXY / ALL_PROXY environment"),
+            ProxySource::WindowsSettings => write!(f, "Windows Internet Options"),
+        }
+    }
+}
+
+/// The proxy to use, per scheme, and the hosts that bypass it.
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub source: ProxySource,
+    pub http: Option<String>,
+    pub https: Option<String>,
+    /// Comma-separated, in `NO_PROXY` syntax.
+    pub bypass: Option<String>,
+}
+
+impl ProxyConfig {
+    /// The proxy for this run: `configured` when given, else the environment, else Windows.
+    pub fn detect(configured: Option<&str>) -> Option<Self> {
+        match configured {
+            Some(url) => Some(ProxyConfig {
+                source: ProxySource::Configured,
+                http: Some(url.to_string()),
+                https: Some(url.to_string()),
+                bypass: env_var("NO_PROXY"),
+            }),
+            None => Self::from_environment().or_else(Self::from_windows),
+        }
+    }
+
+    fn from_environment() -> Option<Self> {
+        let all = env_var("ALL_PROXY");
+        let http = env_var("HTTP_PROXY").or_else(|| all.clone());
+        let https = env_var("HTTPS_PROXY").or(all);
+        if http.is_none() && https.is_none() {
+            return None;
+        }
+
+        Some(ProxyConfig {
+            source: ProxySource::Environment,
+            http,
+            https,
+            bypass: env_var("NO_PROXY"),
+        })
+    }
+
+    fn from_windows() -> Option<Self> {
+        let (proxy, bypass) = match windows_proxy() {
+            Ok(Some(settings)) => settings,
+            Ok(None) => return None,
+            Err(e) => {
+                log::warn!("Could not read the Windows proxy settings: {:#}", e);
+                return None;
+            }
+        };
+
+        // Either "host:port" for every scheme or "http=host:port;https=host:port".
+        let (mut http, mut https) = (None, None);
+        for entry in proxy.split(';').map(str::trim).filter(|entry| !entry.is_empty()) {
+            match entry.split_once('=') {
+                Some(("http", address)) => http = Some(with_scheme(address)),
+                Some(("https", address)) => https = Some(with_scheme(address)),
+                Some(("socks", _)) => {
+                    log::info!("Ignoring the SOCKS proxy in the Windows settings, only SOCKS5 URLs are supported");
+                }
+                Some(_) => {}
+                None => {
+                    http = Some(with_scheme(entry));
+                    https = Some(with_scheme(entry));
+                }
+            }
+        }
+        if http.is_none() && https.is_none() {
+            return None;
+        }
+
+        // "<local>;*.corp.example;10.0.0.1" in Windows, "corp.example, 10.0.0.1" for reqwest.
+        let bypass = bypass.map(|bypass| {
+            bypass
+                .split(';')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty() && *entry != "<local>")
+                .map(|entry| entry.trim_start_matches('*'))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+
+        Some(ProxyConfig {
+            source: ProxySource::WindowsSettings,
+            http,
+            https,
+            bypass: bypass.filter(|bypass| !bypass.is_empty()),
+        })
+    }
+
+    /// The reqwest proxies for this configuration.
+    pub fn proxies(&self) -> Result<Vec<Proxy>> {
+        let no_proxy = || self.bypass.as_deref().and_then(NoProxy::from_string);
+        let mut proxies = Vec::new();
+
+        for url in [&self.https, &self.http].into_iter().flatten() {
+            check_scheme(url)?;
+        }
+
+        if let Some(url) = &self.https {
+            proxies.push(
+                Proxy::https(url)
+                    .with_context(|| format!("Invalid proxy URL {}", redact(url)))?
+                    .no_proxy(no_proxy()),
+            );
+        }
+        if let Some(url) = &self.http {
+            proxies.push(
+                Proxy::http(url)
+                    .with_context(|| format!("Invalid proxy URL {}", redact(url)))?
+                    .no_proxy(no_proxy()),
+            );
+        }
+
+        Ok(proxies)
+    }
+
+    /// The proxy URLs without credentials, for logs and errors.
+    pub fn describe(&self) -> String {
+        match (&self.https, &self.http) {
+            (Some(https), Some(http)) if https == http => redact(https),
+            (Some(https), Some(http)) => format!("{} (https), {} (http)", redact(https), redact(http)),
+            (Some(https), None) => format!("{} (https only)", redact(https)),
+            (None, Some(http)) => format!("{} (http only)", redact(http)),
+            (None, None) => "none".to_string(),
+        }
+    }
+}
+
+/// Refuses proxy URLs reqwest would not connect through, e.g. `socks4://`.
+fn check_scheme(url: &str) -> Result<()> {
+    let scheme = url.split_once("://").map_or("http", |(scheme, _)| scheme);
+    if SUPPORTED_SCHEMES.contains(&scheme.to_lowercase().as_str()) {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "Unsupported proxy {}, use an http://, https://, socks5:// or socks5h:// URL",
+        redact(url)
+    )
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name)
+        .or_else(|_| env::var(name.to_lowercase()))
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+fn with_scheme(address: &str) -> String {
+    if address.contains("://") {
+        address.to_string()
+    } else {
+        format!("http://{}", address)
+    }
+}
+
+/// The manual proxy and bypass list from Windows Internet Options, if one is set.
+#[cfg(windows)]
+fn windows_proxy() -> Result<Option<(String, Option<String>)>> {
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{GlobalFree, HGLOBAL};
+    use windows::Win32::Networking::WinHttp::{
+        WinHttpGetIEProxyConfigForCurrentUser, WINHTTP_CURRENT_USER_IE_PROXY_CONFIG,
+    };
+
+    let mut config = WINHTTP_CURRENT_USER_IE_PROXY_CONFIG::default();
+    unsafe { WinHttpGetIEProxyConfigForCurrentUser(&mut config) }
+        .context("WinHttpGetIEProxyConfigForCurrentUser failed")?;
+
+    // The strings are allocated for the caller and must be freed with GlobalFree.
+    let take = |value: PWSTR| -> Option<String> {
+        if value.is_null() {
+            return None;
+        }
+        let text = unsafe { value.to_string() }.ok();
+        let _ = unsafe { GlobalFree(HGLOBAL(value.0.cast())) };
+        text.filter(|text| !text.is_empty())
+    };
+
+    let proxy = take(config.lpszProxy);
+    let bypass = take(config.lpszProxyBypass);
+    let _ = take(config.lpszAutoConfigUrl);
+    if config.fAutoDetect.as_bool() {
+        log::info!("Windows proxy auto-detection is enabled but not supported, ignoring it");
+    }
+
+    Ok(proxy.map(|proxy| (proxy, bypass)))
+}
+
+/// Elsewhere, where only the tests run, there are no Windows settings.
+#[cfg(not(windows))]
+fn windows_proxy() -> Result<Option<(String, Option<String>)>> {
+    Ok(None)
+}
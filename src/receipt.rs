@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::{Product, RegistryRoot, Uninstall, DEFAULT_CHANNEL};
+use crate::version::Version;
+
+pub const RECEIPT_FILE: &str = "install_receipt.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallReceipt {
+    pub version: Version,
+    /// The product installed; receipts from before the field are of the default one.
+    #[serde(default)]
+    pub product: Product,
+    #[serde(default = "default_channel")]
+    pub channel: String,
+    #[serde(default)]
+    pub pinned: bool,
+    /// The version a `rollback` replaced; `update` asks before moving forward again.
+    #[serde(default)]
+    pub rolled_back_from: Option<Version>,
+    #[serde(default)]
+    pub prereq_check_skipped: bool,
+    pub installed_at: String,
+    pub files: Vec<ReceiptFile>,
+    #[serde(default)]
+    pub shortcuts: Vec<PathBuf>,
+    /// The components installed, when the manifest has any. Receipts written before
+    /// components existed have none.
+    #[serde(default)]
+    pub components: Option<Vec<InstalledComponent>>,
+    /// The EULA the user accepted, when the manifest has one.
+    #[serde(default)]
+    pub eula: Option<AcceptedEula>,
+    /// What the install added to the environment, for uninstall to take out again.
+    #[serde(default)]
+    pub environment: Option<InstalledEnvironment>,
+    /// Registry values the install wrote.
+    #[serde(default)]
+    pub registry: Vec<WrittenRegistryValue>,
+    /// The manifest's `preserve` patterns: user data that `verify` does not check and
+    /// `uninstall` offers to keep.
+    #[serde(default)]
+    pub preserve: Vec<String>,
+    /// The manifest's `uninstall` section as it was at install time. Uninstall goes by
+    /// this, never by a newer manifest that may list things this install did not create.
+    #[serde(default)]
+    pub uninstall: Uninstall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptedEula {
+    pub sha256: String,
+    pub accepted_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledComponent {
+    pub name: String,
+    #[serde(default)]
+    pub required: bool,
+    pub files: Vec<String>,
+}
+
+/// PATH entries and variables an install set, in the user's or the machine's environment.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstalledEnvironment {
+    #[serde(default)]
+    pub machine: bool,
+    /// Directories added to PATH; ones that were there already are not listed.
+    #[serde(default)]
+    pub path: Vec<String>,
+    #[serde(default)]
+    pub variables: Vec<SetVariable>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetVariable {
+    pub name: String,
+    pub value: String,
+    /// The value the variable had before, restored on uninstall.
+    #[serde(default)]
+    pub previous: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrittenRegistryValue {
+    pub root: RegistryRoot,
+    pub path: String,
+    pub name: String,
+    /// Whether the install created the key, so uninstall removes it once it is empty.
+    #[serde(default)]
+    pub created_key: bool,
+    /// What the value held before the install overwrote it, put back on uninstall. None
+    /// when the install created the value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<RegistryValueData>,
+}
+
+/// A registry value's type, one of the `REG_*` constants, and its raw data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryValueData {
+    pub kind: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptFile {
+    pub name: String,
+    pub sha256: String,
+    /// Installed from an optional `files` entry; optional files the release did not
+    /// have are not listed.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+fn default_channel() -> String {
+    DEFAULT_CHANNEL.to_string()
+}
+
+impl InstallReceipt {
+    /// Names of the installed components.
+    pub fn component_names(&self) -> Vec<String> {
+        self.components
+            .iter()
+            .flatten()
+            .map(|component| component.name.clone())
+            .collect()
+    }
+
+    pub fn load(install_dir: &Path) -> Result<Option<Self>> {
+        let path = install_dir.join(RECEIPT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let text = fs::read_to_string(&path)
+            .context("Failed to read install receipt")?;
+        let receipt = serde_json::from_str(&text)
+            .context("Failed to parse install receipt")?;
+
+        Ok(Some(receipt))
+    }
+
+    pub fn save(&self, install_dir: &Path) -> Result<()> {
+        let path = install_dir.join(RECEIPT_FILE);
+        let text = serde_json::to_string_pretty(self)
+            .context("Failed to serialize install receipt")?;
+
+        fs::write(&path, text)
+            .context("Failed to write install receipt")?;
+
+        log::info!("Install receipt written to {:?}", path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receipts_from_older_versions_still_load() {
+        let text = r#"{
+            "version": "1.0.0",
+            "installed_at": "2023-04-01T12:00:00Z",
+            "files": [{ "name": "paradise.exe", "sha256": "abc" }],
+            "shortcuts": ["C:\\Users\\alice\\Desktop\\paradise.lnk"]
+        }"#;
+        let receipt: InstallReceipt = serde_json::from_str(text).unwrap();
+        assert_eq!(receipt.version, "1.0.0");
+        assert_eq!(receipt.product, Product::default());
+        assert_eq!(receipt.channel, DEFAULT_CHANNEL);
+        assert!(!receipt.files[0].optional);
+        assert!(receipt.components.is_none());
+        assert!(receipt.registry.is_empty());
+        assert!(receipt.preserve.is_empty());
+        assert_eq!(receipt.uninstall, Uninstall::default());
+        assert!(receipt.uninstall.offer_user_data_removal);
+    }
+
+    #[test]
+    fn the_uninstall_section_survives_a_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = r#"{
+            "version": "1.2.0",
+            "installed_at": "2024-06-01T12:00:00Z",
+            "files": [],
+            "uninstall": {
+                "remove_paths": ["%LOCALAPPDATA%\\paradise\\cache"],
+                "registry_keys": [{ "root": "HKCU", "path": "Software\\paradise" }],
+                "offer_user_data_removal": false
+            }
+        }"#;
+        let receipt: InstallReceipt = serde_json::from_str(text).unwrap();
+        receipt.save(dir.path()).unwrap();
+
+        let loaded = InstallReceipt::load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.uninstall, receipt.uninstall);
+        assert_eq!(loaded.uninstall.remove_paths, [r"%LOCALAPPDATA%\paradise\cache"]);
+        assert_eq!(loaded.uninstall.registry_keys[0].root, RegistryRoot::CurrentUser);
+        assert!(!loaded.uninstall.offer_user_data_removal);
+    }
+
+    #[test]
+    fn overwritten_registry_data_survives_a_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = r#"{
+            "version": "1.2.0",
+            "installed_at": "2024-06-01T12:00:00Z",
+            "files": [],
+            "registry": [
+                { "root": "HKCU", "path": "Software\\paradise", "name": "Path", "created_key": true },
+                { "root": "HKCU", "path": "Software\\Classes\\.para", "name": "", "previous": { "kind": 1, "data": [111, 0, 0, 0] } }
+            ]
+        }"#;
+        let receipt: InstallReceipt = serde_json::from_str(text).unwrap();
+        assert_eq!(receipt.registry[0].previous, None);
+        receipt.save(dir.path()).unwrap();
+
+        let loaded = InstallReceipt::load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.registry, receipt.registry);
+        assert_eq!(
+            loaded.registry[1].previous,
+            Some(RegistryValueData { kind: 1, data: vec![111, 0, 0, 0] })
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use windows::core::{HSTRING, PCWSTR, PWSTR};
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, HANDLE};
+use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegCreateKeyExW, RegDeleteKeyValueW, RegDeleteKeyW, RegDeleteTreeW, RegOpenKeyExW,
+    RegQueryInfoKeyW, RegQueryValueExW, RegSetKeyValueW, RegSetValueExW, HKEY, KEY_QUERY_VALUE, KEY_SET_VALUE,
+    REG_CREATED_NEW_KEY, REG_CREATE_KEY_DISPOSITION, REG_DWORD, REG_OPTION_NON_VOLATILE, REG_SZ, REG_VALUE_TYPE,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+use crate::manifest::{Product, RegistryData, RegistryEntry, RegistryKey, RegistryRoot};
+use crate::receipt::{RegistryValueData, WrittenRegistryValue};
+
+/// Stand for the install directory and the product's names in string values.
+const INSTALL_DIR_TOKEN: &str = "${install_dir}";
+const DISPLAY_NAME_TOKEN: &str = "${display_name}";
+const PUBLISHER_TOKEN: &str = "${publisher}";
+
+/// Whether the bootstrapper runs elevated, i.e. may write to `HKLM` and the machine's
+/// environment.
+pub fn is_elevated() -> bool {
+    let mut token = HANDLE::default();
+    let mut elevation = TOKEN_ELEVATION::default();
+    let mut size = 0u32;
+    unsafe {
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token).is_ok()
+            && GetTokenInformation(
+                token,
+                TokenElevation,
+                Some(&mut elevation as *mut TOKEN_ELEVATION as *mut _),
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut size,
+            )
+            .is_ok()
+            && elevation.TokenIsElevated != 0
+    }
+}
+
+/// Refuses entries under `HKLM` unless the bootstrapper is elevated, before anything is
+/// downloaded.
+pub fn check_access(entries: &[RegistryEntry]) -> Result<()> {
+    if let Some(entry) = entries.iter().find(|entry| entry.root == RegistryRoot::LocalMachine) {
+        if !is_elevated() {
+            anyhow::bail!(
+                "The manifest writes HKLM\\{}, which needs the bootstrapper to run as administrator",
+                entry.path
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes `entries` for the install of `product` in `install_dir`, recording the data of
+/// values they overwrite. When one fails, those written before it are undone again.
+pub fn write_all(entries: &[RegistryEntry], install_dir: &Path, product: &Product) -> Result<Vec<WrittenRegistryValue>> {
+    let mut written = Vec::new();
+    for entry in entries {
+        match write(entry, install_dir, product) {
+            Ok(value) => written.push(value),
+            Err(e) => {
+                remove_all(&written);
+                return Err(e);
+            }
+        }
+    }
+    Ok(written)
+}
+
+/// Undoes the values an install wrote: those it overwrote get their earlier data back, the
+/// rest are removed, and the keys it created once they are empty. Failures are logged, the
+/// rest is still undone.
+pub fn remove_all(written: &[WrittenRegistryValue]) {
+    for value in written.iter().rev() {
+        if let Err(e) = remove(value) {
+            log::warn!("Failed to remove registry value {}: {:#}", describe(value), e);
+        }
+    }
+}
+
+/// Removes `key` with its values and subkeys. Returns false when it did not exist.
+pub fn remove_key(key: &RegistryKey) -> Result<bool> {
+    let path = HSTRING::from(key.path.trim_matches('\\'));
+    match unsafe { RegDeleteTreeW(key.root.hkey(), &path) } {
+        Ok(()) => {}
+        Err(e) if e.code() == ERROR_FILE_NOT_FOUND.to_hresult() => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("Failed to remove registry key {}", describe_key(key))),
+    }
+    // RegDeleteTreeW empties the key but leaves it in place.
+    unsafe { RegDeleteKeyW(key.root.hkey(), &path) }
+        .with_context(|| format!("Failed to remove registry key {}", describe_key(key)))?;
+    log::info!("Removed registry key {}", describe_key(key));
+    Ok(true)
+}
+
+fn write(entry: &RegistryEntry, install_dir: &Path, product: &Product) -> Result<WrittenRegistryValue> {
+    let path = entry.path.trim_matches('\\').to_string();
+    let (kind, data) = match &entry.value {
+        RegistryData::Text(text) => {
+            let text = text
+                .replace(INSTALL_DIR_TOKEN, &install_dir.display().to_string())
+                .replace(DISPLAY_NAME_TOKEN, &product.display_name)
+                .replace(PUBLISHER_TOKEN, &product.publisher);
+            let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+            (REG_SZ, wide.iter().flat_map(|c| c.to_le_bytes()).collect::<Vec<u8>>())
+        }
+        RegistryData::Number(number) => (REG_DWORD, number.to_le_bytes().to_vec()),
+    };
+
+    let mut key = HKEY::default();
+    let mut disposition = REG_CREATE_KEY_DISPOSITION::default();
+    unsafe {
+        RegCreateKeyExW(
+            entry.root.hkey(),
+            &HSTRING::from(path.as_str()),
+            0,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_SET_VALUE | KEY_QUERY_VALUE,
+            None,
+            &mut key,
+            Some(&mut disposition),
+        )
+    }
+    .with_context(|| format!("Failed to open registry key {}", path))?;
+    let name = HSTRING::from(entry.name.as_str());
+    let result = read(key, &name).and_then(|previous| {
+        unsafe { RegSetValueExW(key, &name, 0, kind, Some(&data)) }?;
+        Ok(previous)
+    });
+    let _ = unsafe { RegCloseKey(key) };
+
+    let mut value = WrittenRegistryValue {
+        root: entry.root,
+        path,
+        name: entry.name.clone(),
+        created_key: disposition == REG_CREATED_NEW_KEY,
+        previous: None,
+    };
+    value.previous = match result {
+        Ok(previous) => previous,
+        Err(e) => {
+            let path = HSTRING::from(value.path.as_str());
+            if value.created_key && is_empty(value.root.hkey(), &path) {
+                let _ = unsafe { RegDeleteKeyW(value.root.hkey(), &path) };
+            }
+            return Err(e).with_context(|| format!("Failed to write registry value {}", describe(&value)));
+        }
+    };
+    log::info!("Wrote registry value {}", describe(&value));
+    Ok(value)
+}
+
+/// The type and data of value `name` of the open `key`, if it has one.
+fn read(key: HKEY, name: &HSTRING) -> windows::core::Result<Option<RegistryValueData>> {
+    let mut kind = REG_VALUE_TYPE::default();
+    let mut size = 0u32;
+    match unsafe { RegQueryValueExW(key, name, None, Some(&mut kind), None, Some(&mut size)) } {
+        Err(e) if e.code() == ERROR_FILE_NOT_FOUND.to_hresult() => return Ok(None),
+        result => result?,
+    }
+    let mut data = vec![0u8; size as usize];
+    unsafe { RegQueryValueExW(key, name, None, Some(&mut kind), Some(data.as_mut_ptr()), Some(&mut size)) }?;
+    data.truncate(size as usize);
+    Ok(Some(RegistryValueData { kind: kind.0, data }))
+}
+
+fn remove(value: &WrittenRegistryValue) -> Result<()> {
+    let root = value.root.hkey();
+    let path = HSTRING::from(value.path.as_str());
+    let name = HSTRING::from(value.name.as_str());
+    if let Some(previous) = &value.previous {
+        unsafe {
+            RegSetKeyValueW(
+                root,
+                &path,
+                &name,
+                previous.kind,
+                Some(previous.data.as_ptr().cast()),
+                previous.data.len() as u32,
+            )
+        }?;
+        log::info!("Restored registry value {}", describe(value));
+        return Ok(());
+    }
+    match unsafe { RegDeleteKeyValueW(root, &path, &name) } {
+        Err(e) if e.code() != ERROR_FILE_NOT_FOUND.to_hresult() => return Err(e.into()),
+        _ => {}
+    }
+    log::info!("Removed registry value {}", describe(value));
+    if value.created_key && is_empty(root, &path) && unsafe { RegDeleteKeyW(root, &path) }.is_ok() {
+        log::info!("Removed registry key {}", value.path);
+    }
+    Ok(())
+}
+
+/// Whether the key has neither values nor subkeys, which `RegDeleteKeyW` would take along.
+fn is_empty(root: HKEY, path: &HSTRING) -> bool {
+    let mut key = HKEY::default();
+    if unsafe { RegOpenKeyExW(root, path, 0, KEY_QUERY_VALUE, &mut key) }.is_err() {
+        return false;
+    }
+    let (mut subkeys, mut values) = (0u32, 0u32);
+    let result = unsafe {
+        RegQueryInfoKeyW(
+            key,
+            PWSTR::null(),
+            None,
+            None,
+            Some(&mut subkeys),
+            None,
+            None,
+            Some(&mut values),
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+    let _ = unsafe { RegCloseKey(key) };
+    result.is_ok() && subkeys == 0 && values == 0
+}
+
+pub fn describe(value: &WrittenRegistryValue) -> String {
+    let root = root_name(value.root);
+    match value.name.as_str() {
+        "" => format!("{}\\{} (default)", root, value.path),
+        name => format!("{}\\{}\\{}", root, value.path, name),
+    }
+}
+
+pub fn describe_key(key: &RegistryKey) -> String {
+    format!("{}\\{}", root_name(key.root), key.path.trim_matches('\\'))
+}
+
+fn root_name(root: RegistryRoot) -> &'static str {
+    match root {
+        RegistryRoot::CurrentUser => "HKCU",
+        RegistryRoot::LocalMachine => "HKLM",
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+
+use crate::http::{HttpClient, StatusError};
+use crate::manifest::{local_path, Manifest};
+
+/// Lines of notes shown; the rest is left to the full notes.
+const MAX_LINES: usize = 40;
+
+/// Characters a single line is cut to.
+const MAX_LINE_CHARS: usize = 200;
+
+/// Bytes of notes read; a server sending more does not get it all buffered.
+const MAX_BYTES: u64 = 256 * 1024;
+
+/// The release notes of `manifest`: `release_notes`, or what `release_notes_url` points at.
+pub fn fetch(http: &HttpClient, manifest: &Manifest) -> Result<Option<String>> {
+    if let Some(text) = &manifest.release_notes {
+        return Ok(Some(text.clone()));
+    }
+    let Some(url) = &manifest.release_notes_url else {
+        return Ok(None);
+    };
+
+    log::info!("Fetching the release notes from {}", url);
+    if let Some(path) = local_path(url) {
+        let file = fs::File::open(&path)
+            .with_context(|| format!("Failed to read the release notes {}", path.display()))?;
+        return read_capped(file).map(Some);
+    }
+    let response = http
+        .send(http.get(url)?)
+        .context("Failed to fetch the release notes")?;
+    if !response.status().is_success() {
+        return Err(StatusError::new("Release notes fetch", &response).into());
+    }
+    read_capped(response).map(Some)
+}
+
+/// Reads at most [`MAX_BYTES`] of `notes`, as UTF-8 with anything invalid replaced.
+fn read_capped(notes: impl Read) -> Result<String> {
+    let mut bytes = Vec::new();
+    notes
+        .take(MAX_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .context("Failed to read the release notes")?;
+    if bytes.len() as u64 > MAX_BYTES {
+        log::info!("The release notes are longer than {} bytes, reading only that much", MAX_BYTES);
+        bytes.truncate(MAX_BYTES as usize);
+        // A character cut in half would show up as garbage.
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            if e.error_len().is_none() {
+                bytes.truncate(e.valid_up_to());
+            }
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Turns markdown notes into plain console lines: headings lose their `#`, list items get
+/// a bullet, emphasis and code marks are dropped and links show their URL. At most
+/// [`MAX_LINES`] lines are kept, the last one saying how many were left out.
+pub fn render(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim_end();
+        let stripped = trimmed.trim_start();
+        let indent = &trimmed[..trimmed.len() - stripped.len()];
+
+        let rendered = if stripped.starts_with("```") {
+            continue;
+        } else if let Some(heading) = heading(stripped) {
+            inline(heading).to_uppercase()
+        } else if let Some(item) = stripped
+            .strip_prefix("- ")
+            .or_else(|| stripped.strip_prefix("* "))
+            .or_else(|| stripped.strip_prefix("+ "))
+        {
+            format!("{}  - {}", indent, inline(item))
+        } else {
+            format!("{}{}", indent, inline(stripped))
+        };
+
+        // Blank lines collapse to one.
+        if rendered.is_empty() && lines.last().is_none_or(String::is_empty) {
+            continue;
+        }
+        lines.push(truncate(rendered));
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+
+    if lines.len() > MAX_LINES {
+        let left_out = lines.len() - (MAX_LINES - 1);
+        lines.truncate(MAX_LINES - 1);
+        lines.push(format!("... ({} more lines)", left_out));
+    }
+    lines
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    (text.len() < line.len() && (text.is_empty() || text.starts_with(' '))).then(|| text.trim())
+}
+
+/// Drops `**`, `__` and `` ` `` marks and writes `[text](url)` as `text (url)`.
+fn inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let link = after.find("](").and_then(|close| {
+            let url_end = after[close + 2..].find(')')?;
+            Some((close, close + 2 + url_end))
+        });
+        match link {
+            Some((close, url_end)) => {
+                out.push_str(&format!("{} ({})", &after[..close], &after[close + 2..url_end]));
+                rest = &after[url_end + 1..];
+            }
+            None => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out.replace("**", "").replace("__", "").replace('`', "")
+}
+
+fn truncate(line: String) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_is_rendered_as_plain_text() {
+        let notes = "# Paradise 1.3\n\n\n## Fixes\n- **Crash** on start with `--safe`\n  * see [the issue](https://example.com/1)\n\n```\nlog\n```\n";
+        assert_eq!(
+            render(notes),
+            [
+                "PARADISE 1.3",
+                "",
+                "FIXES",
+                "  - Crash on start with --safe",
+                "    - see the issue (https://example.com/1)",
+                "",
+                "log",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_notes_are_capped() {
+        let notes: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let lines = render(&notes);
+        assert_eq!(lines.len(), MAX_LINES);
+        assert_eq!(lines[MAX_LINES - 1], "... (61 more lines)");
+    }
+
+    #[test]
+    fn only_the_start_of_huge_notes_is_read() {
+        let notes = read_capped("# Notes\n".as_bytes()).unwrap();
+        assert_eq!(notes, "# Notes\n");
+
+        let huge = format!("x{}", "\u{e9}".repeat(MAX_BYTES as usize));
+        let notes = read_capped(huge.as_bytes()).unwrap();
+        assert_eq!(notes.len(), MAX_BYTES as usize - 1);
+        assert!(notes.ends_with('\u{e9}'));
+    }
+}
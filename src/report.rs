@@ -0,0 +1,137 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// Line-delimited JSON events on stdout; human text only goes to the log file
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ManifestFetched {
+        version: String,
+        channel: String,
+        url: String,
+    },
+    /// The manifest host was unreachable and the last fetched manifest is used.
+    StaleManifest {
+        fetched_at: String,
+        age_secs: u64,
+    },
+    InstallDirectory {
+        path: PathBuf,
+    },
+    DownloadStarted {
+        url: String,
+    },
+    DownloadFinished {
+        url: String,
+        bytes: u64,
+        elapsed_secs: f64,
+        avg_bytes_per_sec: u64,
+        peak_bytes_per_sec: u64,
+        retries: u32,
+    },
+    Extracted {
+        files: usize,
+        bytes: u64,
+        elapsed_secs: f64,
+        files_per_sec: f64,
+        bytes_per_sec: u64,
+    },
+    Verification {
+        target: String,
+        passed: bool,
+    },
+    PrerequisitesSkipped {
+        failure: Option<String>,
+    },
+    ShortcutCreated {
+        path: PathBuf,
+    },
+    UpdateCheck {
+        installed: Option<String>,
+        available: String,
+        up_to_date: bool,
+        /// The installed version is older than the manifest's `min_supported_version`.
+        update_required: bool,
+    },
+    FileChecked {
+        name: String,
+        status: String,
+    },
+    Removed {
+        path: PathBuf,
+    },
+    Plan {
+        version: String,
+        channel: String,
+        download_url: String,
+        download_size: Option<u64>,
+        /// Installed version a delta update would patch, if one would be used.
+        delta_from: Option<String>,
+        patch_size: Option<u64>,
+        url_status: Option<u16>,
+        accepts_ranges: Option<bool>,
+        install_dir: PathBuf,
+        files: Vec<String>,
+        /// Components that would be installed, required ones included.
+        components: Vec<String>,
+        /// The first of `shortcuts`, as the field was before there could be several.
+        shortcut: Option<PathBuf>,
+        shortcuts: Vec<PathBuf>,
+    },
+    Finished {
+        command: String,
+        dry_run: bool,
+        status: String,
+    },
+    Failed {
+        command: String,
+        stage: Option<String>,
+        /// Kind of network failure, when it was one.
+        category: Option<String>,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reporter {
+    format: OutputFormat,
+}
+
+impl Reporter {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Prints a line for humans, or only logs it when the output is JSON.
+    pub fn say(&self, text: &str) {
+        match self.format {
+            OutputFormat::Text => println!("{}", text),
+            OutputFormat::Json => log::info!("{}", text),
+        }
+    }
+
+    /// Writes a structured event to stdout when the output is JSON.
+    pub fn event(&self, event: Event) {
+        if !self.is_json() {
+            return;
+        }
+
+        match serde_json::to_string(&event) {
+            Ok(line) => println!("{}", line),
+            Err(e) => log::warn!("Failed to serialize output event: {}", e),
+        }
+    }
+}
@@ -4,7 +4,15 @@ use windows::core::*;
 use windows::Win32::System::Com::*;
 use windows::Win32::UI::Shell::*;
 
-pub fn create_shortcut(exe_path: &Path, shortcut_path: &Path) -> Result<()> {
+/// What a shortcut sets beyond its target.
+#[derive(Debug, Default)]
+pub struct ShortcutOptions<'a> {
+    pub arguments: Option<&'a str>,
+    pub icon: Option<(&'a Path, i32)>,
+    pub description: Option<&'a str>,
+}
+
+pub fn create_shortcut(exe_path: &Path, shortcut_path: &Path, options: &ShortcutOptions) -> Result<()> {
     log::info!(
         "Creating shortcut: {:?} -> {:?}",
         shortcut_path,
@@ -47,6 +55,27 @@ pub fn create_shortcut(exe_path: &Path, shortcut_path: &Path) -> Result<()> {
             .ok()
             .context("Failed to set working directory")?;
 
+        if let Some(arguments) = options.arguments {
+            shell_link
+                .SetArguments(&HSTRING::from(arguments))
+                .ok()
+                .context("Failed to set shortcut arguments")?;
+        }
+
+        if let Some((icon, index)) = options.icon {
+            shell_link
+                .SetIconLocation(&HSTRING::from(icon.to_string_lossy().as_ref()), index)
+                .ok()
+                .context("Failed to set shortcut icon")?;
+        }
+
+        if let Some(description) = options.description {
+            shell_link
+                .SetDescription(&HSTRING::from(description))
+                .ok()
+                .context("Failed to set shortcut description")?;
+        }
+
         let persist_file: IPersistFile = shell_link.cast()
             .context("Failed to get IPersistFile interface")?;
 
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::trust;
+use key::ALGORITHM_PURE;
+
+mod key;
+
+/// Signature over the BLAKE2b-512 of the file, what `minisign -S` writes by default.
+const ALGORITHM_PREHASHED: [u8; 2] = *b"ED";
+
+/// A minisign Ed25519 public key.
+pub struct PublicKey {
+    id: [u8; 8],
+    key: VerifyingKey,
+}
+
+impl PublicKey {
+    /// Parses the base64 line of a minisign `.pub` file, or the whole file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let (id, key) = key::parse(text).map_err(anyhow::Error::msg)?;
+        Ok(Self { id, key })
+    }
+}
+
+/// The key release signatures are checked against, if this build has one; see
+/// [`trust::signing_key`].
+pub fn signing_key() -> Result<Option<PublicKey>> {
+    match trust::signing_key() {
+        Some(key) => PublicKey::parse(key)
+            .context("Invalid built-in signing key")
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Checks `signature`, the text of a minisign `.minisig` file, over the file at `path`.
+///
+/// Both the signature over the file and the one binding its trusted comment must verify.
+pub fn verify_file(key: &PublicKey, path: &Path, signature: &str) -> Result<()> {
+    let mut lines = signature
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("untrusted comment:"));
+    let signature_line = lines.next().ok_or_else(|| anyhow::anyhow!("Signature file is empty"))?;
+    let trusted_comment = lines
+        .next()
+        .and_then(|line| line.strip_prefix("trusted comment: "))
+        .ok_or_else(|| anyhow::anyhow!("Signature file has no trusted comment"))?;
+    let global_line = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Signature file has no global signature"))?;
+
+    let bytes = STANDARD
+        .decode(signature_line)
+        .context("Signature is not base64")?;
+    if bytes.len() != 2 + 8 + 64 {
+        anyhow::bail!("Not a minisign Ed25519 signature");
+    }
+    let (algorithm, rest) = bytes.split_at(2);
+    let (key_id, signature) = rest.split_at(8);
+    if key_id != key.id {
+        anyhow::bail!(
+            "Signed with key {}, but the trusted key is {}",
+            key_id_hex(key_id),
+            key_id_hex(&key.id)
+        );
+    }
+    let signature = Signature::from_slice(signature).context("Malformed signature")?;
+
+    let message = match [algorithm[0], algorithm[1]] {
+        ALGORITHM_PURE => fs::read(path).context("Failed to read the signed file")?,
+        ALGORITHM_PREHASHED => {
+            let mut file = fs::File::open(path).context("Failed to read the signed file")?;
+            let mut hasher = Blake2b512::new();
+            io::copy(&mut file, &mut hasher).context("Failed to read the signed file")?;
+            hasher.finalize().to_vec()
+        }
+        _ => anyhow::bail!("Unsupported signature algorithm"),
+    };
+    key.key
+        .verify_strict(&message, &signature)
+        .map_err(|_| anyhow::anyhow!("The signature does not match the file"))?;
+
+    let global = STANDARD
+        .decode(global_line)
+        .context("Global signature is not base64")?;
+    let global = Signature::from_slice(&global).context("Malformed global signature")?;
+    let mut signed_comment = signature.to_bytes().to_vec();
+    signed_comment.extend_from_slice(trusted_comment.as_bytes());
+    key.key
+        .verify_strict(&signed_comment, &global)
+        .map_err(|_| anyhow::anyhow!("The trusted comment was altered"))?;
+
+    log::info!("Signature verified for {:?} ({})", path, trusted_comment);
+    Ok(())
+}
+
+/// Key IDs as `minisign` prints them: little-endian, upper-case hex.
+fn key_id_hex(id: &[u8]) -> String {
+    id.iter().rev().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const KEY_ID: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn public_key(signing: &SigningKey, id: [u8; 8]) -> String {
+        let mut bytes = ALGORITHM_PURE.to_vec();
+        bytes.extend_from_slice(&id);
+        bytes.extend_from_slice(signing.verifying_key().as_bytes());
+        format!("untrusted comment: minisign public key\n{}\n", STANDARD.encode(bytes))
+    }
+
+    /// A `.minisig` file as `minisign -S` writes it, over the BLAKE2b-512 of `contents`.
+    fn minisig(signing: &SigningKey, contents: &[u8], comment: &str) -> String {
+        let signature = signing.sign(&Blake2b512::digest(contents));
+        let mut line = ALGORITHM_PREHASHED.to_vec();
+        line.extend_from_slice(&KEY_ID);
+        line.extend_from_slice(&signature.to_bytes());
+
+        let mut signed_comment = signature.to_bytes().to_vec();
+        signed_comment.extend_from_slice(comment.as_bytes());
+        let global = signing.sign(&signed_comment);
+        format!(
+            "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: {}\n{}\n",
+            STANDARD.encode(line),
+            comment,
+            STANDARD.encode(global.to_bytes())
+        )
+    }
+
+    fn signed_file(contents: &[u8]) -> (tempfile::TempDir, std::path::PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("release.zip");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn public_keys_are_read_from_pub_files() {
+        let key = PublicKey::parse(&public_key(&signing_key(), KEY_ID)).unwrap();
+        assert_eq!(key.id, KEY_ID);
+        assert_eq!(key.key, signing_key().verifying_key());
+
+        let error = |text: &str| PublicKey::parse(text).err().unwrap().to_string();
+        assert_eq!(error("untrusted comment: nothing else\n"), "Public key is empty");
+        assert_eq!(error("not base64!"), "Public key is not base64");
+        assert_eq!(error(&STANDARD.encode(b"Ed")), "Not a minisign Ed25519 public key");
+        assert_eq!(error(&STANDARD.encode([b'E', b'D', 0, 0])), "Not a minisign Ed25519 public key");
+    }
+
+    #[test]
+    fn signatures_over_the_file_verify() {
+        let (_dir, path) = signed_file(b"release");
+        let key = PublicKey::parse(&public_key(&signing_key(), KEY_ID)).unwrap();
+        verify_file(&key, &path, &minisig(&signing_key(), b"release", "timestamp:1 file:release.zip")).unwrap();
+    }
+
+    #[test]
+    fn tampered_files_and_comments_are_refused() {
+        let (_dir, path) = signed_file(b"tampered");
+        let key = PublicKey::parse(&public_key(&signing_key(), KEY_ID)).unwrap();
+        let signature = minisig(&signing_key(), b"release", "file:release.zip");
+        let e = verify_file(&key, &path, &signature).unwrap_err();
+        assert_eq!(e.to_string(), "The signature does not match the file");
+
+        fs::write(&path, b"release").unwrap();
+        let altered = signature.replace("file:release.zip", "file:other.zip");
+        let e = verify_file(&key, &path, &altered).unwrap_err();
+        assert_eq!(e.to_string(), "The trusted comment was altered");
+    }
+
+    #[test]
+    fn signatures_by_another_key_are_refused() {
+        let (_dir, path) = signed_file(b"release");
+        let other = SigningKey::from_bytes(&[9; 32]);
+        let signature = minisig(&other, b"release", "file:release.zip");
+
+        let key = PublicKey::parse(&public_key(&signing_key(), [8; 8])).unwrap();
+        let e = verify_file(&key, &path, &signature).unwrap_err();
+        assert_eq!(e.to_string(), "Signed with key 0807060504030201, but the trusted key is 0808080808080808");
+
+        let key = PublicKey::parse(&public_key(&signing_key(), KEY_ID)).unwrap();
+        let e = verify_file(&key, &path, &signature).unwrap_err();
+        assert_eq!(e.to_string(), "The signature does not match the file");
+    }
+}
@@ -0,0 +1,38 @@
+//! Reading minisign public keys. `build.rs` includes this file too, to check the built-in
+//! key the same way it is read at run time.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::VerifyingKey;
+
+/// The algorithm of an Ed25519 key, and of a signature over the file itself.
+pub const ALGORITHM_PURE: [u8; 2] = *b"Ed";
+
+/// The base64 line of a minisign `.pub` file, or of the whole file; empty when there is none.
+pub fn key_line(text: &str) -> &str {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+        .unwrap_or_default()
+}
+
+/// The key ID and key of a minisign `.pub` file, or of its base64 line.
+pub fn parse(text: &str) -> Result<([u8; 8], VerifyingKey), &'static str> {
+    let line = key_line(text);
+    if line.is_empty() {
+        return Err("Public key is empty");
+    }
+    let bytes = STANDARD.decode(line).map_err(|_| "Public key is not base64")?;
+
+    let [a, b, rest @ ..] = bytes.as_slice() else {
+        return Err("Public key is too short");
+    };
+    if [*a, *b] != ALGORITHM_PURE || rest.len() != 8 + 32 {
+        return Err("Not a minisign Ed25519 public key");
+    }
+    let (id, key) = rest.split_at(8);
+    let key = VerifyingKey::from_bytes(key.try_into().expect("32 bytes"))
+        .map_err(|_| "Public key is not a valid Ed25519 key")?;
+
+    Ok((id.try_into().expect("8 bytes"), key))
+}
@@ -0,0 +1,33 @@
+//! What this build of the bootstrapper trusts, fixed when it is built so a fork can ship
+//! its own bootstrapper without changing the code:
+//!
+//! `PARADISE_MANIFEST_URL=https://example.com/installer.json PARADISE_SIGNING_PUBKEY=<base64
+//! line of minisign.pub> cargo build --release`
+//!
+//! `build.rs` checks the values and fails the build on a bad one.
+
+/// The manifest fetched when neither `--manifest-url`, `PARADISE_MANIFEST_URL` at run
+/// time nor the config file names another.
+pub const MANIFEST_URL: &str = env!("PARADISE_TRUSTED_MANIFEST_URL");
+
+/// Whether releases without a signature are installed: by default only in builds without
+/// a signing key. Builds that refuse them always have one.
+pub const ALLOW_UNSIGNED: bool = matches!(env!("PARADISE_TRUSTED_ALLOW_UNSIGNED").as_bytes(), b"true");
+
+const SIGNING_KEY: &str = env!("PARADISE_TRUSTED_SIGNING_PUBKEY");
+
+/// The base64 minisign public key release zips are signed with, if this build has one.
+pub fn signing_key() -> Option<&'static str> {
+    Some(SIGNING_KEY).filter(|key| !key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_built_in_manifest_url_is_usable() {
+        crate::manifest::validate_manifest_url(MANIFEST_URL).unwrap();
+        assert!(ALLOW_UNSIGNED || signing_key().is_some());
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup::list_backups;
+use crate::error::InstallError;
+use crate::paths;
+use crate::preserve::is_preserved;
+
+/// Removes an installation directory, its backups and the given shortcuts. Paths matching
+/// `keep`, `preserve` patterns, stay in the install directory along with the directories
+/// leading to them.
+///
+/// Returns every path that was actually deleted. The logs directory is never removed:
+/// an install directory that contains it is refused.
+pub fn remove_installation(install_dir: &Path, shortcuts: &[PathBuf], keep: &[String]) -> Result<Vec<PathBuf>> {
+    if !install_dir.is_dir() {
+        return Err(InstallError::NotInstalled(install_dir.to_path_buf()).into());
+    }
+
+    let logs_dir = paths::logs_dir()?;
+    if logs_dir.starts_with(install_dir) {
+        anyhow::bail!(
+            "Refusing to uninstall from {}: it contains the logs directory",
+            install_dir.display()
+        );
+    }
+
+    let mut removed = Vec::new();
+
+    for shortcut in shortcuts {
+        if shortcut.exists() {
+            fs::remove_file(shortcut).context("Failed to remove shortcut")?;
+            log::info!("Removed shortcut {:?}", shortcut);
+            removed.push(shortcut.clone());
+        }
+    }
+
+    if keep.is_empty() {
+        fs::remove_dir_all(install_dir).context("Failed to remove install directory")?;
+        log::info!("Removed install directory {:?}", install_dir);
+        removed.push(install_dir.to_path_buf());
+    } else {
+        remove_all_but(install_dir, "", keep, &mut removed)?;
+        log::info!("Removed install directory {:?}, keeping the preserved files", install_dir);
+    }
+
+    let mut backups = list_backups(install_dir)?;
+    let legacy_backup = install_dir.with_extension("backup");
+    if legacy_backup.is_dir() {
+        backups.push(legacy_backup);
+    }
+    for backup in backups {
+        fs::remove_dir_all(&backup).context("Failed to remove backup directory")?;
+        log::info!("Removed backup directory {:?}", backup);
+        removed.push(backup);
+    }
+
+    Ok(removed)
+}
+
+/// Removes what is in `install_dir`/`prefix` except the paths matching `keep`, and the
+/// directories left empty.
+fn remove_all_but(install_dir: &Path, prefix: &str, keep: &[String], removed: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(install_dir.join(prefix)).context("Failed to read install directory")? {
+        let entry = entry?;
+        let relative = match prefix {
+            "" => entry.file_name().to_string_lossy().into_owned(),
+            prefix => format!("{}/{}", prefix, entry.file_name().to_string_lossy()),
+        };
+        if is_preserved(&relative, keep) {
+            log::info!("Keeping {}", relative);
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            remove_all_but(install_dir, &relative, keep, removed)?;
+            if fs::remove_dir(&path).is_ok() {
+                removed.push(path);
+            }
+        } else {
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {}", relative))?;
+            removed.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Removes `files`, given relative to `install_dir`, and the directories they leave empty.
+/// Nothing is removed if any of them is not inside `install_dir`.
+///
+/// Returns every file that was actually deleted; files already gone are skipped.
+pub fn remove_files(install_dir: &Path, files: &[String]) -> Result<Vec<PathBuf>> {
+    // The names come from the receipt, which anyone can edit.
+    if let Some(file) = files.iter().find(|file| {
+        file.is_empty()
+            || !Path::new(file)
+                .components()
+                .all(|component| matches!(component, std::path::Component::Normal(_)))
+    }) {
+        anyhow::bail!("Refusing to remove {:?}: it is not inside {}", file, install_dir.display());
+    }
+
+    let mut removed = Vec::new();
+
+    for file in files {
+        let path = install_dir.join(file);
+        if !path.is_file() {
+            log::debug!("{:?} is already gone", path);
+            continue;
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", file))?;
+        log::info!("Removed {:?}", path);
+
+        // remove_dir only succeeds on empty directories.
+        for dir in path.ancestors().skip(1).take_while(|dir| *dir != install_dir) {
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+            log::info!("Removed empty directory {:?}", dir);
+        }
+        removed.push(path);
+    }
+
+    Ok(removed)
+}
+
+/// Removes the manifest's `uninstall.remove_paths`, `%VAR%` references expanded, whole.
+/// Failures are logged, the rest is still removed.
+///
+/// Returns every path that was actually deleted; paths already gone are skipped.
+pub fn remove_paths(paths: &[String]) -> Vec<PathBuf> {
+    let mut removed = Vec::new();
+    for path in paths {
+        let path = match paths::expand_env_vars(path, |name| std::env::var(name).ok()) {
+            Ok(expanded) => PathBuf::from(expanded),
+            Err(e) => {
+                log::warn!("Not removing {}: {:#}", path, e);
+                continue;
+            }
+        };
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else if path.exists() {
+            fs::remove_file(&path)
+        } else {
+            log::debug!("{:?} is already gone", path);
+            continue;
+        };
+        match result {
+            Ok(()) => {
+                log::info!("Removed {:?}", path);
+                removed.push(path);
+            }
+            Err(e) => log::warn!("Failed to remove {:?}: {}", path, e),
+        }
+    }
+    removed
+}
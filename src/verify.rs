@@ -1,20 +1,25 @@
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
 use std::fs;
+use std::io;
 use std::path::Path;
+use std::time::Instant;
 
 pub fn verify_sha256(file_path: &Path, expected_hash: &str) -> Result<bool> {
     log::info!("Verifying SHA256 for {:?}", file_path);
 
-    let file_data = fs::read(file_path)
-        .context("Failed to read file for verification")?;
+    let started = Instant::now();
+    let computed_hash = compute_sha256(file_path)?;
+    log::debug!("Hashed {:?} in {:?}: {}", file_path, started.elapsed(), computed_hash);
 
-    let mut hasher = Sha256::new();
-    hasher.update(&file_data);
-    let computed_hash = hex::encode(hasher.finalize());
+    Ok(sha256_matches(file_path, &computed_hash, expected_hash))
+}
+
+/// Compares a hash computed elsewhere, e.g. while downloading, and logs the result.
+/// `expected_hash` is lowercase, as the manifest normalizes its hashes.
+pub fn sha256_matches(file_path: &Path, computed_hash: &str, expected_hash: &str) -> bool {
+    let matches = computed_hash == expected_hash;
 
-    let matches = computed_hash.to_lowercase() == expected_hash.to_lowercase();
-    
     if matches {
         log::info!("SHA256 verification passed for {:?}", file_path);
     } else {
@@ -26,15 +31,20 @@ pub fn verify_sha256(file_path: &Path, expected_hash: &str) -> Result<bool> {
         );
     }
 
-    Ok(matches)
+    matches
 }
 
 pub fn compute_sha256(file_path: &Path) -> Result<String> {
-    let file_data = fs::read(file_path)
+    let file = fs::File::open(file_path)
         .context("Failed to read file for hash computation")?;
 
+    sha256_of(file).context("Failed to read file for hash computation")
+}
+
+/// Hashes everything `reader` yields, e.g. an entry of a zip.
+pub fn sha256_of(mut reader: impl io::Read) -> io::Result<String> {
     let mut hasher = Sha256::new();
-    hasher.update(&file_data);
+    io::copy(&mut reader, &mut hasher)?;
     Ok(hex::encode(hasher.finalize()))
 }
 
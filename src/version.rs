@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A release version as the manifest and the receipt give it. It compares as semver, so
+/// 1.10.0 is newer than 1.9.0, and is written back out exactly as it was read.
+///
+/// Two-component versions like `1.2` are read as `1.2.0`. Versions that are not semver at
+/// all are still read, so an old receipt stays usable, but [`Version::check`] refuses them
+/// and they only equal the same text.
+#[derive(Debug, Clone)]
+pub struct Version {
+    text: String,
+    semver: Option<semver::Version>,
+}
+
+impl Version {
+    /// Reads `text`, failing unless it is a semver version.
+    pub fn parse(text: &str) -> Result<Self> {
+        let version = Self::from(text);
+        version
+            .check()
+            .with_context(|| format!("{:?} is not a semver version", text))?;
+        Ok(version)
+    }
+
+    /// Fails unless the version is semver.
+    pub fn check(&self) -> Result<(), semver::Error> {
+        match &self.semver {
+            Some(_) => Ok(()),
+            None => lenient_parse(&self.text).map(|_| ()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+}
+
+/// Parses `text` as semver, adding the missing patch number to `1.2`, `1.2-beta` or
+/// `1.2+build`.
+fn lenient_parse(text: &str) -> Result<semver::Version, semver::Error> {
+    let core_end = text.find(['-', '+']).unwrap_or(text.len());
+    let (core, rest) = text.split_at(core_end);
+    match core.matches('.').count() {
+        1 => semver::Version::parse(&format!("{}.0{}", core, rest)),
+        _ => semver::Version::parse(text),
+    }
+}
+
+impl From<&str> for Version {
+    fn from(text: &str) -> Self {
+        Self { text: text.to_string(), semver: lenient_parse(text).ok() }
+    }
+}
+
+impl From<String> for Version {
+    fn from(text: String) -> Self {
+        let semver = lenient_parse(&text).ok();
+        Self { text, semver }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialEq<str> for Version {
+    fn eq(&self, other: &str) -> bool {
+        self.cmp(&Version::from(other)) == Ordering::Equal
+    }
+}
+
+impl PartialEq<&str> for Version {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Semver order; versions that are not semver sort before all others, by their text.
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (&self.semver, &other.semver) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => self.text.cmp(&other.text),
+        }
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Version::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_as_semver() {
+        let version = |text| Version::parse(text).unwrap();
+        assert!(version("1.10.0") > version("1.9.0"));
+        assert!(version("1.0.0-rc.1") < version("1.0.0"));
+        assert!(version("1.0.0-alpha") < version("1.0.0-alpha.1"));
+        assert!(version("1.0.0-beta.2") < version("1.0.0-beta.11"));
+        assert_eq!(version("1.2"), version("1.2.0"));
+        assert!(version("1.2-beta") < version("1.2"));
+        assert_eq!(version("1.2.0+build.7").to_string(), "1.2.0+build.7");
+        assert_ne!(version("1.2.0+build.7"), version("1.2.0+build.8"));
+    }
+
+    #[test]
+    fn the_original_text_is_kept() {
+        let version: Version = serde_json::from_str(r#""1.2""#).unwrap();
+        assert_eq!(serde_json::to_string(&version).unwrap(), r#""1.2""#);
+        assert_eq!(version, "1.2.0");
+
+        let odd: Version = serde_json::from_str(r#""2024.05.1""#).unwrap();
+        assert_eq!(odd, "2024.05.1");
+        assert_eq!(
+            format!("{:#}", Version::parse(odd.as_str()).unwrap_err()),
+            "\"2024.05.1\" is not a semver version: invalid leading zero in minor version number"
+        );
+    }
+}